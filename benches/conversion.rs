@@ -0,0 +1,56 @@
+//! Times parsing, patching, writing and whole conversions of the packs in
+//! `benches/fixtures` and of the golden test materials, run with `cargo
+//! bench`. Each is also timed scaled up to a pack of 200 materials. Compare
+//! against a baseline with `cargo bench -- --save-baseline before` and
+//! `--baseline before`
+
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use material_updater::{
+    bench::{self, Fixture},
+    MVersion,
+};
+
+/// Materials in the pack the fixtures are scaled up to
+const SCALED_MATERIALS: usize = 200;
+
+/// Every pack and material.bin in `benches/fixtures`, and the golden test
+/// materials, which are small enough to time on every run
+fn fixtures() -> Vec<Fixture> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut paths = bench::fixture_paths(&root.join("benches").join("fixtures"), &[".mcpack", ".zip", ".material.bin"]);
+    paths.extend(bench::fixture_paths(&root.join("tests").join("golden").join("fixtures"), &[".material.bin"]));
+    if paths.is_empty() {
+        eprintln!("No packs in benches/fixtures or tests/golden/fixtures, there is nothing to time");
+    }
+    paths
+        .iter()
+        .map(|path| Fixture::load(path).unwrap_or_else(|err| panic!("{}: {err:#}", path.display())))
+        .collect()
+}
+
+fn phases(c: &mut Criterion) {
+    let target = MVersion::V1_21_110;
+    for fixture in fixtures() {
+        let material_bytes: usize = fixture.materials.iter().map(|(_, data)| data.len()).sum();
+        let mut group = c.benchmark_group(&fixture.name);
+        group.sample_size(10);
+        group.throughput(Throughput::Bytes(material_bytes as u64));
+        group.bench_function("parse", |b| b.iter(|| bench::parse(&fixture).unwrap()));
+        let parsed = bench::parse(&fixture).unwrap();
+        group.bench_function("patch", |b| {
+            b.iter_batched(|| parsed.clone(), |mut materials| bench::patch(&mut materials, &target).unwrap(), BatchSize::LargeInput)
+        });
+        group.bench_function("write", |b| b.iter(|| bench::write(&parsed, &target).unwrap()));
+        group.throughput(Throughput::Bytes(fixture.pack.len() as u64));
+        group.bench_function("pipeline", |b| b.iter(|| bench::pipeline(&fixture, &target).unwrap()));
+        let scaled = fixture.scaled(SCALED_MATERIALS).unwrap();
+        group.throughput(Throughput::Bytes(scaled.pack.len() as u64));
+        group.bench_function(format!("pipeline {SCALED_MATERIALS} materials"), |b| b.iter(|| bench::pipeline(&scaled, &target).unwrap()));
+        group.finish();
+    }
+}
+
+criterion_group!(benches, phases);
+criterion_main!(benches);
@@ -0,0 +1,41 @@
+#ifndef MATERIAL_UPDATER_H
+#define MATERIAL_UPDATER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define MU_OK 0
+#define MU_ERR_INVALID_ARGUMENT 1
+#define MU_ERR_IO 2
+#define MU_ERR_CONVERSION 3
+#define MU_ERR_PANIC 4
+/* No binary version could read a material */
+#define MU_ERR_PARSE 5
+/* A shader patch failed */
+#define MU_ERR_PATCH 6
+/* A material can't be written for the target version */
+#define MU_ERR_INCOMPATIBLE 7
+/* The input is over a size or entry count limit, e.g. a zip bomb */
+#define MU_ERR_LIMIT 8
+
+/* Convert the pack or .material.bin at input_path to target (e.g. "1.21.110") */
+int mu_convert_file(const char *input_path, const char *output_path, const char *target);
+
+/* Convert an in-memory pack, free *output with mu_free_buffer */
+int mu_convert_buffer(const uint8_t *input, size_t input_len, const char *target,
+                      uint8_t **output, size_t *output_len);
+void mu_free_buffer(uint8_t *buffer, size_t len);
+
+/* Last error on the calling thread, the message is valid until the next call */
+int mu_last_error_code(void);
+const char *mu_last_error_message(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
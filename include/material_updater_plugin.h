@@ -0,0 +1,44 @@
+#ifndef MATERIAL_UPDATER_PLUGIN_H
+#define MATERIAL_UPDATER_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Loaded with `material-updater --plugin path/to/plugin.so` */
+
+#define MU_PLUGIN_ABI_VERSION 1
+
+#define MU_PLUGIN_UNCHANGED 0
+#define MU_PLUGIN_CHANGED 1
+#define MU_PLUGIN_ERROR -1
+
+/* Where the shader being patched lives, strings are only valid during the call */
+typedef struct {
+    const char *material;
+    const char *pass;
+    const char *platform;
+    const char *stage;
+} mu_shader_info;
+
+/* Must return MU_PLUGIN_ABI_VERSION */
+uint32_t mu_plugin_abi_version(void);
+
+/* Name shown in list-patches and accepted by --disable-patch */
+const char *mu_plugin_name(void);
+
+/* Called for the code of every shader. To change it, store a buffer allocated by
+ * the plugin in *output / *output_len and return MU_PLUGIN_CHANGED, the buffer is
+ * handed back through mu_plugin_free. */
+int32_t mu_plugin_patch_shader(const mu_shader_info *info, const uint8_t *code, size_t code_len,
+                               uint8_t **output, size_t *output_len);
+void mu_plugin_free(uint8_t *buffer, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,117 @@
+# English messages of material-updater, the fallback for every other language.
+# To translate, copy this file to <lang>.ftl (e.g. es.ftl or pt_BR.ftl) and
+# translate the text after each `=`, keeping the { $placeholders }. Put it in
+# the material-updater/locales folder of your config directory to try it out,
+# and send it in to have it bundled.
+
+# Conversion
+processing-input = Processing input { $name }
+processing-zip = Processing input zip { $name }
+processing-file = Processing file { $name }
+downloading = Downloading { $url }
+extracting = Extracting { $name }
+loaded-plugin = Loaded plugin { $name } from { $path }
+detected-game = Detected Minecraft { $game }, targeting { $target }
+resolved-target = Resolved target version { $requested } to { $target }
+default-target = No target version specified, updating to latest stable: { $target }
+auto-output = No output name specified, using { $path }
+flattened = Flattened subpack { $tier }: { $promoted } files moved to the main pack ({ $replaced } replaced), { $dropped } files of other subpacks dropped
+tag-vanilla = [vanilla, stripped]
+tag-unreadable = [unreadable, copied unchanged]
+tag-cached = [cached { $targets }]
+progress-left = { $eta } left
+
+# Problems with materials
+unfixed-shaders = { $count } shaders could not be fixed for { $target }, the output may render wrong
+unpatchable-shaders = { $count } compiled (non-GLSL) shaders cannot be patched automatically for { $target }
+read-as-other-source = Can't be written as read for { $source } ({ $error }), read as { $retried } instead
+read-as-other-source-short = Read as { $retried } instead, { $source } couldn't be written
+incompatible-kept = Incompatible with { $target } ({ $issue }), kept the original
+incompatible-downgraded = Incompatible with { $target } ({ $issue }), written as { $version }
+patch-failed-ignored = Copying the material unchanged because patching it failed
+compat-ignored = Ignoring materialbin because of compatibility error:
+
+# Problems with entries
+duplicates-hidden = { $count } entries share their name with a later one and were dropped
+duplicates-hidden-later = { $count } entries share their name with an earlier one and were dropped
+duplicates-first-unreadable = The central directory of this archive can't be rewritten, so of entries with exactly the same name the last is kept despite --duplicates first
+entry-escapes = Left out { $entry }, its name escapes the pack
+entry-duplicate = Dropped { $entry }, a duplicate of { $name }
+entry-copied-anyway = { $error }, copied anyway
+json-material-unchanged = Copied { $entry } unchanged: { $error }
+
+# Summary
+summary-entry = Entry
+summary-source = Source
+summary-patches = Patches
+summary-result = Result
+summary-size = Size
+result-converted = converted
+result-cached = cached
+result-copied = copied
+result-downgraded = downgraded to { $version }
+result-skipped = skipped
+result-failed = failed, copied unchanged
+result-vanilla = vanilla, stripped
+result-other-subpack = other subpack, unchanged
+materials-for = Materials for { $target }:
+subpack = Subpack { $tier }
+main-pack = Main pack
+subpack-summary = { $tier }: { $count } materials ({ $results })
+other-files-copied = { $count } other files copied
+paths-normalized = { $count } entry paths normalized
+zip-names-decoded = { $count } entry names read as { $encoding }
+zip-names-invalid = { $count } entry names aren't valid { $encoding }, pick their encoding with --zip-encoding
+budget-fits = { $path } is { $size }, within the size budget of { $budget }
+budget-over = { $path } is { $size }, { $over } over the size budget of { $budget }
+budget-largest-entries = Largest entries:
+budget-largest-folders = Largest folders:
+dedup-none = No material of { $path } stores a shader twice
+dedup-summary = { $path }: { $duplicates } of { $shaders } shaders are copies of another in the same material, { $wasted } of { $total } of shader code
+dedup-group = { $copies } copies of { $size } in { $entry }:
+dedup-more-copies = and { $count } more
+materials-renamed = { $count } materials renamed
+mixed-sources = Materials were compiled for several versions: { $versions }
+warnings-count = { $count } warnings while updating ({ $codes })
+ported = Ported { $count } materials in zip to version { $target }
+took = Took { $seconds }s, output is { $size }
+pack-up-to-date = Pack is already up to date for { $target }
+material-up-to-date = Material is already up to date for { $target }
+
+# Outputs
+skipping-output = Skipping output { $path }
+dry-run = Dry run, nothing written: { $path } would have been written for { $target }
+dry-run-output-ignored = --yeet is a dry run, nothing is written to { $path }
+dry-run-install = Dry run, nothing installed: the pack would have been extracted to { $path }
+verified = Verified { $path }
+verified-entries = Verified { $count } entries in { $path }
+installed-pack = Installed { $path }
+updated-in-place = Updated { $path } in place: { $replaced } replaced, { $added } added, { $removed } removed, { $kept } kept
+wrote-debug-pack = Wrote debug pack { $path } ({ $count } materials in both versions)
+wrote-delta = Wrote delta { $path } ({ $added } added, { $replaced } replaced, { $removed } removed)
+wrote-profile = Wrote profile to { $path }
+wrote-checksums = Wrote checksums to { $path }
+wrote-run-summary = Wrote run summary to { $path }
+
+# Several inputs
+failed = Failed
+input-failed = { $failed } { $input }: { $error }
+task-started = { $counter } Started { $input } for { $target }
+task-finished = { $counter } Finished { $input } for { $target } in { $seconds }s
+task-failed = { $counter } { $failed } { $input } for { $target }: { $error }
+tasks-input = Input
+tasks-target = Target
+tasks-time = Time
+tasks-result = Result
+
+# Questions when started without arguments
+interactive-intro = No arguments given, answer a few questions instead (Ctrl-C to quit)
+prompt-input = Shader pack, material.bin or url to update
+prompt-no-such-file = No such file
+prompt-target = Target version
+prompt-output = Output path (leave empty to name it automatically)
+press-enter = Press Enter to exit
+error = Error: { $error }
+stopping = Stopping before the next entry, press Ctrl-C again to quit right away
+stopping-entry = Stopping after { $entry }, press Ctrl-C again to quit right away
+crashed-entry = Crashed while converting { $entry }
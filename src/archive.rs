@@ -0,0 +1,127 @@
+//! Readers for archive formats other than zip. Their files are repacked into a
+//! zip so the normal zip pipeline can convert them.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use flate2::read::GzDecoder;
+use zip::{
+    write::{ExtendedFileOptions, FileOptions},
+    CompressionMethod, ZipWriter,
+};
+
+use crate::{
+    limits::{normalize_entry_name, Limits},
+    UpdateError,
+};
+
+/// Source of archive files that can be walked once
+pub trait ArchiveSource {
+    /// Call `each` with the name and contents of every regular file
+    fn for_each_file(
+        &mut self,
+        each: &mut dyn FnMut(&str, &mut dyn Read) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()>;
+}
+
+pub struct TarGz<R: Read>(pub R);
+
+impl<R: Read> ArchiveSource for TarGz<R> {
+    fn for_each_file(
+        &mut self,
+        each: &mut dyn FnMut(&str, &mut dyn Read) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let mut archive = tar::Archive::new(GzDecoder::new(&mut self.0));
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = entry.path()?.to_string_lossy().replace('\\', "/");
+            each(&name, &mut entry)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct SevenZ<R: Read + Seek>(pub R);
+
+impl<R: Read + Seek> ArchiveSource for SevenZ<R> {
+    fn for_each_file(
+        &mut self,
+        each: &mut dyn FnMut(&str, &mut dyn Read) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let len = self.0.seek(SeekFrom::End(0))?;
+        self.0.rewind()?;
+        let mut archive =
+            sevenz_rust::SevenZReader::new(&mut self.0, len, sevenz_rust::Password::empty())?;
+        // The callback has to return 7z errors, so ours are smuggled out
+        let mut failure = None;
+        archive.for_each_entries(|entry, reader| {
+            if entry.is_directory() {
+                return Ok(true);
+            }
+            match each(entry.name(), reader) {
+                Ok(()) => Ok(true),
+                Err(err) => {
+                    failure = Some(err);
+                    Ok(false)
+                }
+            }
+        })?;
+        failure.map_or(Ok(()), Err)
+    }
+}
+
+/// Pick a reader from the file name, `None` for zips and unknown formats
+pub fn source_for_name<'a, R: Read + Seek + 'a>(
+    name: &str,
+    reader: R,
+) -> Option<Box<dyn ArchiveSource + 'a>> {
+    let name = name.to_ascii_lowercase();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(Box::new(TarGz(reader)))
+    } else if name.ends_with(".7z") {
+        Some(Box::new(SevenZ(reader)))
+    } else {
+        None
+    }
+}
+
+/// Suffixes of the archive formats handled here
+pub const ARCHIVE_SUFFIXES: &[&str] = &[".tar.gz", ".tgz", ".7z"];
+
+/// Copy every file of `source` into an uncompressed zip written to `output`,
+/// failing on archives over `limits` and leaving out files whose names would
+/// escape the pack
+pub fn repack_to_zip<W: Write + Seek>(
+    source: &mut dyn ArchiveSource,
+    output: W,
+    limits: &Limits,
+) -> anyhow::Result<W> {
+    let mut zip = ZipWriter::new(output);
+    let mut entries = 0;
+    source.for_each_file(&mut |name, reader| {
+        let Some(name) = normalize_entry_name(name, false) else {
+            log::warn!("{name}: entry name escapes the pack, left out");
+            return Ok(());
+        };
+        entries += 1;
+        limits.check_entries(entries)?;
+        let options = FileOptions::<ExtendedFileOptions>::default()
+            .compression_method(CompressionMethod::Stored)
+            .large_file(true);
+        zip.start_file(name.as_str(), options)?;
+        let copied = io::copy(&mut reader.take(limits.max_entry_size + 1), &mut zip)?;
+        if copied > limits.max_entry_size {
+            return Err(UpdateError::LimitExceeded {
+                entry: Some(name),
+                limit: "entry size",
+                size: copied,
+                max: limits.max_entry_size,
+            }
+            .into());
+        }
+        Ok(())
+    })?;
+    Ok(zip.finish()?)
+}
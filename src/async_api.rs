@@ -0,0 +1,73 @@
+//! Conversions for async hosts such as tokio services. The work runs on the
+//! runtime's blocking pool, so it never stalls the async workers
+
+use std::panic;
+
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    task,
+};
+
+use crate::{convert_bytes_with, ConversionReport, ConvertOptions, MVersion, UpdateError};
+
+/// [`convert_bytes_with`](crate::convert_bytes_with) on the blocking pool.
+/// Needs a tokio runtime. A conversion that panics panics the caller, like the
+/// blocking call would, and one whose task is cancelled is
+/// [`UpdateError::Interrupted`]
+pub async fn convert_bytes_async(
+    input: Vec<u8>,
+    version: MVersion,
+    options: ConvertOptions<'static>,
+) -> Result<(Vec<u8>, ConversionReport), UpdateError> {
+    let converted = task::spawn_blocking(move || convert_bytes_with(&input, &version, &options)).await;
+    match converted {
+        Ok(result) => result,
+        Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+        Err(_) => Err(UpdateError::Interrupted { entry: None }),
+    }
+}
+
+/// [`convert_bytes_async`], then the whole output written to `output`, e.g.
+/// an HTTP response body. Nothing is streamed: archives are written with their
+/// directory at the end, which needs seeking, so the output is complete in
+/// memory before the first byte is sent
+pub async fn convert_and_write<W>(
+    input: Vec<u8>,
+    version: MVersion,
+    options: ConvertOptions<'static>,
+    output: &mut W,
+) -> Result<ConversionReport, UpdateError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let (converted, report) = convert_bytes_async(input, version, options).await?;
+    output.write_all(&converted).await?;
+    output.flush().await?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+    use super::*;
+
+    #[test]
+    fn writes_the_converted_pack() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("manifest.json", SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"{}").unwrap();
+        let pack = zip.finish().unwrap().into_inner();
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let mut output = Vec::new();
+        let report = runtime
+            .block_on(convert_and_write(pack, MVersion::V1_21_110, ConvertOptions::default(), &mut output))
+            .unwrap();
+        assert_eq!(report.translated_materials, 0);
+        let converted = ZipArchive::new(Cursor::new(output)).unwrap();
+        assert_eq!(converted.file_names().collect::<Vec<_>>(), ["manifest.json"]);
+    }
+}
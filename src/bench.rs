@@ -0,0 +1,236 @@
+//! The phases of a conversion run on their own, so they can be timed: by the
+//! criterion suite in `benches/` and by the hidden `bench` subcommand, which
+//! measures the device it runs on
+
+use std::{
+    fs,
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use materialbin::{CompiledMaterialDefinition, MinecraftVersion};
+use serde::Serialize;
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use crate::{
+    detect_material, encode_material, file_update,
+    limits::Limits,
+    patches::{patch_material, PatchSelection},
+    zip_update, ConversionReport, ConvertOptions, MVersion,
+};
+
+/// A pack to time the phases on, with its materials read out once
+pub struct Fixture {
+    pub name: String,
+    pub pack: Vec<u8>,
+    /// Entry name and data of every material
+    pub materials: Vec<(String, Vec<u8>)>,
+}
+
+impl Fixture {
+    /// The pack or material.bin at `path`
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let pack = std::fs::read(path).with_context(|| format!("Error while reading {}", path.display()))?;
+        let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned();
+        Self::from_bytes(name, pack)
+    }
+
+    pub fn from_bytes(name: String, pack: Vec<u8>) -> anyhow::Result<Self> {
+        if !pack.starts_with(b"PK\x03\x04") {
+            let materials = vec![(name.clone(), pack.clone())];
+            return Ok(Self { name, pack, materials });
+        }
+        let limits = Limits::default();
+        let mut zip = ZipArchive::new(Cursor::new(pack.as_slice()))?;
+        let mut materials = Vec::new();
+        for index in 0..zip.len() {
+            let mut file = zip.by_index(index)?;
+            if !file.name().ends_with(".material.bin") {
+                continue;
+            }
+            let entry = file.name().to_string();
+            let mut data = Vec::new();
+            limits.read_entry(&entry, &mut file, &mut data)?;
+            materials.push((entry, data));
+        }
+        anyhow::ensure!(!materials.is_empty(), "{name} has no materials to time");
+        Ok(Self { name, pack, materials })
+    }
+
+    pub fn is_pack(&self) -> bool {
+        self.pack.starts_with(b"PK\x03\x04")
+    }
+
+    /// A pack of `count` materials, those of this fixture over and over under
+    /// new names, to time packs bigger than the ones at hand
+    pub fn scaled(&self, count: usize) -> anyhow::Result<Self> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (index, (_, data)) in self.materials.iter().cycle().take(count).enumerate() {
+            zip.start_file(format!("renderer/materials/Scaled{index}.material.bin"), SimpleFileOptions::default())?;
+            zip.write_all(data)?;
+        }
+        let pack = zip.finish()?.into_inner();
+        Self::from_bytes(format!("{} x{count}", self.name), pack)
+    }
+}
+
+/// The files in `dir` whose names end in one of `suffixes`, sorted, none when
+/// `dir` doesn't exist. Shared by the benches and the golden tests
+pub fn fixture_paths(dir: &Path, suffixes: &[&str]) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| entries.filter_map(Result::ok).map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+    paths.retain(|path| suffixes.iter().any(|suffix| path.to_string_lossy().ends_with(suffix)));
+    paths.sort();
+    paths
+}
+
+/// Parse every material of `fixture`
+pub fn parse(fixture: &Fixture) -> anyhow::Result<Vec<(CompiledMaterialDefinition, MinecraftVersion)>> {
+    fixture
+        .materials
+        .iter()
+        .map(|(name, data)| detect_material(data).with_context(|| format!("Error while reading {name}")))
+        .collect()
+}
+
+/// Apply the default patches for `target` to parsed materials, returning how
+/// many changed
+pub fn patch(materials: &mut [(CompiledMaterialDefinition, MinecraftVersion)], target: &MVersion) -> anyhow::Result<usize> {
+    let selection = PatchSelection::default();
+    let mut changed = 0;
+    for (material, source) in materials {
+        changed += usize::from(patch_material(material, *source, target, &selection)?.changed);
+    }
+    Ok(changed)
+}
+
+/// Serialize parsed materials for `target`, returning how many bytes that took
+pub fn write(materials: &[(CompiledMaterialDefinition, MinecraftVersion)], target: &MVersion) -> anyhow::Result<usize> {
+    let mut written = 0;
+    for (material, _) in materials {
+        written += encode_material(material, target).with_context(|| format!("Error while writing {}", material.name))?.len();
+    }
+    Ok(written)
+}
+
+/// Convert the whole of `fixture` for `target` in memory, like `convert` does
+/// without the disk
+pub fn pipeline(fixture: &Fixture, target: &MVersion) -> anyhow::Result<(Vec<u8>, ConversionReport)> {
+    let mut output = Cursor::new(Vec::new());
+    let report = if fixture.is_pack() {
+        zip_update(&mut Cursor::new(fixture.pack.as_slice()), &mut output, target, None, &ConvertOptions::default())?
+    } else {
+        file_update(&mut Cursor::new(fixture.pack.as_slice()), &mut output, target, &ConvertOptions::default())?
+    };
+    Ok((output.into_inner(), report))
+}
+
+/// A timed phase
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    Parse,
+    Patch,
+    Write,
+    Pipeline,
+}
+
+/// How long a phase took over every iteration
+#[derive(Clone, Debug, Serialize)]
+pub struct PhaseTiming {
+    pub phase: Phase,
+    pub iterations: usize,
+    pub mean_secs: f64,
+    pub min_secs: f64,
+    pub max_secs: f64,
+    /// Material bytes read or written per second, from the mean
+    pub bytes_per_sec: f64,
+}
+
+impl PhaseTiming {
+    fn new(phase: Phase, times: &[Duration], bytes: usize) -> Self {
+        let total: Duration = times.iter().sum();
+        let mean = total.as_secs_f64() / times.len().max(1) as f64;
+        Self {
+            phase,
+            iterations: times.len(),
+            mean_secs: mean,
+            min_secs: times.iter().min().copied().unwrap_or_default().as_secs_f64(),
+            max_secs: times.iter().max().copied().unwrap_or_default().as_secs_f64(),
+            bytes_per_sec: if mean > 0.0 { bytes as f64 / mean } else { 0.0 },
+        }
+    }
+}
+
+/// Time every phase on `fixture` `iterations` times, returning the timings and
+/// the report of the last full conversion
+pub fn measure(fixture: &Fixture, target: &MVersion, iterations: usize) -> anyhow::Result<(Vec<PhaseTiming>, ConversionReport)> {
+    let iterations = iterations.max(1);
+    let input_bytes: usize = fixture.materials.iter().map(|(_, data)| data.len()).sum();
+    let (mut parsing, mut patching, mut writing, mut converting) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let mut written = 0;
+    let mut last_report = None;
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let mut materials = parse(fixture)?;
+        parsing.push(start.elapsed());
+
+        let start = Instant::now();
+        patch(&mut materials, target)?;
+        patching.push(start.elapsed());
+
+        let start = Instant::now();
+        written = write(&materials, target)?;
+        writing.push(start.elapsed());
+
+        let start = Instant::now();
+        let (_, report) = pipeline(fixture, target)?;
+        converting.push(start.elapsed());
+        last_report = Some(report);
+    }
+    let timings = vec![
+        PhaseTiming::new(Phase::Parse, &parsing, input_bytes),
+        PhaseTiming::new(Phase::Patch, &patching, input_bytes),
+        PhaseTiming::new(Phase::Write, &writing, written),
+        PhaseTiming::new(Phase::Pipeline, &converting, fixture.pack.len()),
+    ];
+    Ok((timings, last_report.expect("at least one iteration")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::*;
+
+    #[test]
+    fn loads_the_materials_of_a_pack() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in [("manifest.json", "{}"), ("renderer/materials/Sky.material.bin", "sky")] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        let pack = zip.finish().unwrap().into_inner();
+        let fixture = Fixture::from_bytes("pack.mcpack".to_string(), pack).unwrap();
+        assert!(fixture.is_pack());
+        assert_eq!(fixture.materials, [("renderer/materials/Sky.material.bin".to_string(), b"sky".to_vec())]);
+
+        let timing = PhaseTiming::new(Phase::Parse, &[Duration::from_secs(1), Duration::from_secs(3)], 100);
+        assert_eq!((timing.mean_secs, timing.min_secs, timing.max_secs, timing.bytes_per_sec), (2.0, 1.0, 3.0, 50.0));
+    }
+
+    #[test]
+    fn scales_fixtures_up() {
+        let fixture = Fixture::from_bytes("a.material.bin".to_string(), b"material".to_vec()).unwrap();
+        let scaled = fixture.scaled(200).unwrap();
+        assert!(scaled.is_pack());
+        assert_eq!(scaled.name, "a.material.bin x200");
+        assert_eq!(scaled.materials.len(), 200);
+        assert!(scaled.materials.iter().all(|(_, data)| data == b"material"));
+    }
+}
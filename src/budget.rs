@@ -0,0 +1,106 @@
+//! `--size-budget`: whether a converted pack fits the size the Marketplace or a
+//! phone will import, and what takes the most space when it doesn't
+
+use std::{
+    collections::HashMap,
+    io::{Read, Seek, SeekFrom},
+};
+
+use owo_colors::OwoColorize;
+use zip::ZipArchive;
+
+use crate::{progress::format_bytes, tr};
+
+/// How many entries and folders the breakdown lists
+const LISTED: usize = 10;
+
+/// An output measured against the budget
+pub struct BudgetReport {
+    pub size: u64,
+    pub budget: u64,
+    /// Largest entries by compressed size, biggest first
+    pub largest: Vec<(String, u64)>,
+    /// Compressed size of the entries directly in each folder, biggest first
+    pub folders: Vec<(String, u64)>,
+}
+
+impl BudgetReport {
+    pub fn over(&self) -> bool {
+        self.size > self.budget
+    }
+}
+
+/// Measure `output`, a pack or a single material, against `budget` bytes
+pub fn measure<R: Read + Seek>(output: &mut R, budget: u64) -> zip::result::ZipResult<BudgetReport> {
+    let size = output.seek(SeekFrom::End(0))?;
+    output.rewind()?;
+    let mut report = BudgetReport { size, budget, largest: Vec::new(), folders: Vec::new() };
+    let Ok(mut zip) = ZipArchive::new(output) else {
+        return Ok(report);
+    };
+    let mut folders: HashMap<String, u64> = HashMap::new();
+    for index in 0..zip.len() {
+        let file = zip.by_index_raw(index)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_string();
+        let folder = name.rsplit_once('/').map_or("", |(folder, _)| folder);
+        *folders.entry(folder.to_string()).or_default() += file.compressed_size();
+        report.largest.push((name, file.compressed_size()));
+    }
+    report.largest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    report.largest.truncate(LISTED);
+    report.folders = folders.into_iter().collect();
+    report.folders.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    report.folders.truncate(LISTED);
+    Ok(report)
+}
+
+/// Say whether the output fits, with the breakdown when it doesn't
+pub fn print_budget(report: &BudgetReport, path: &str) {
+    let size = format_bytes(report.size);
+    let budget = format_bytes(report.budget);
+    if !report.over() {
+        println!("{}", tr!("budget-fits", path = path, size = size, budget = budget).dimmed());
+        return;
+    }
+    let over = format_bytes(report.size - report.budget);
+    println!("{}", tr!("budget-over", path = path, size = size, budget = budget, over = over).yellow());
+    if !report.largest.is_empty() {
+        println!("{}", tr!("budget-largest-entries"));
+        for (name, size) in &report.largest {
+            println!("  {:>10}  {name}", format_bytes(*size));
+        }
+        println!("{}", tr!("budget-largest-folders"));
+        for (folder, size) in &report.folders {
+            let folder = if folder.is_empty() { "." } else { folder };
+            println!("  {:>10}  {folder}/", format_bytes(*size));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+    use super::*;
+
+    #[test]
+    fn breaks_down_the_biggest() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        for (name, size) in [("manifest.json", 10), ("textures/a.png", 300), ("textures/b.png", 200), ("sounds/c.ogg", 400)] {
+            zip.start_file(name, stored).unwrap();
+            zip.write_all(&vec![0; size]).unwrap();
+        }
+        let mut pack = zip.finish().unwrap();
+        let report = measure(&mut pack, 1000).unwrap();
+        assert!(report.over());
+        assert_eq!(report.largest[0], ("sounds/c.ogg".to_string(), 400));
+        assert_eq!(report.folders[0], ("textures".to_string(), 500));
+        assert!(!measure(&mut Cursor::new(vec![0; 100]), 1000).unwrap().over());
+    }
+}
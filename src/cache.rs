@@ -0,0 +1,167 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufReader, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zip::{result::ZipResult, ZipArchive, ZipWriter};
+
+use crate::{checksums::hex, MVersion};
+
+pub const CACHE_FILE_NAME: &str = ".material-updater-cache.json";
+
+/// On disk layout: output file name -> (zip entry name -> entry key)
+#[derive(Default, Serialize, Deserialize)]
+struct CacheFile {
+    outputs: HashMap<String, HashMap<String, String>>,
+}
+
+impl CacheFile {
+    fn read(path: &Path) -> Self {
+        match File::open(path) {
+            Ok(f) => serde_json::from_reader(BufReader::new(f)).unwrap_or_else(|err| {
+                log::warn!("Ignoring unreadable cache {}: {err}", path.display());
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+}
+
+/// Remembers which input entries produced the previous output, so unchanged
+/// materials can be copied from it instead of being converted again
+pub struct ConversionCache {
+    path: PathBuf,
+    output_name: String,
+    /// What besides the input and target changes the result, see [`key`](Self::key)
+    settings: String,
+    previous_entries: HashMap<String, String>,
+    entries: HashMap<String, String>,
+    previous: Option<ZipArchive<BufReader<File>>>,
+}
+
+impl ConversionCache {
+    /// Load the cache that sits next to `output`, opening the previous output if
+    /// there is one. `settings` describes everything besides the target version
+    /// that changes a converted entry, like the patches and renames, so entries
+    /// converted with others aren't reused
+    pub fn load(output: &Path, settings: String) -> anyhow::Result<Self> {
+        let dir = output.parent().unwrap_or(Path::new(""));
+        let path = dir.join(CACHE_FILE_NAME);
+        let output_name = output
+            .file_name()
+            .with_context(|| "Output path has no file name")?
+            .to_string_lossy()
+            .into_owned();
+        let mut file = CacheFile::read(&path);
+        // Only trust the old entries if the output they describe is still around
+        let previous = File::open(output)
+            .ok()
+            .and_then(|f| ZipArchive::new(BufReader::new(f)).ok());
+        let previous_entries = match previous {
+            Some(_) => file.outputs.remove(&output_name).unwrap_or_default(),
+            None => HashMap::new(),
+        };
+        Ok(Self {
+            path,
+            output_name,
+            settings,
+            previous_entries,
+            entries: HashMap::new(),
+            previous,
+        })
+    }
+
+    /// Key for a material entry, derived from its bytes, the target version and
+    /// the settings
+    pub fn key(&self, data: &[u8], version: &MVersion) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.settings.as_bytes());
+        hasher.update([0]);
+        hasher.update(version.to_string().as_bytes());
+        hasher.update([0]);
+        hasher.update(data);
+        hex(&hasher.finalize())
+    }
+
+    /// Copy the previously converted `name` into `output` if it was last converted
+    /// from the same key, returns whether it did so
+    pub fn copy_cached<W: Write + Seek>(
+        &mut self,
+        name: &str,
+        key: &str,
+        output: &mut ZipWriter<W>,
+    ) -> ZipResult<bool> {
+        if self.previous_entries.get(name).map(String::as_str) != Some(key) {
+            return Ok(false);
+        }
+        let Some(previous) = self.previous.as_mut() else {
+            return Ok(false);
+        };
+        match previous.by_name(name) {
+            Ok(entry) => output.raw_copy_file(entry)?,
+            Err(_) => return Ok(false),
+        }
+        self.record(name, key.to_string());
+        Ok(true)
+    }
+
+    /// Remember that `name` in the new output was produced from `key`
+    pub fn record(&mut self, name: &str, key: String) {
+        self.entries.insert(name.to_string(), key);
+    }
+
+    /// The cache file, shared by every output in the directory
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write the entries of this output to the cache file. It is read again
+    /// first, keeping what other outputs saved since this one was loaded, so
+    /// runs sharing it only need to hold a lock on [`path`](Self::path) around
+    /// the save
+    pub fn save(self) -> anyhow::Result<()> {
+        let mut file = CacheFile::read(&self.path);
+        file.outputs.insert(self.output_name, self.entries);
+        let tmp = self.path.with_extension("json.tmp");
+        let writer = File::create(&tmp).with_context(|| "Error while writing cache file")?;
+        serde_json::to_writer_pretty(writer, &file)?;
+        fs::rename(&tmp, &self.path).with_context(|| "Error while writing cache file")?;
+        Ok(())
+    }
+}
+
+/// Converted material entries kept across runs, outputs and machines, looked
+/// up by the input entry and target version (see `--cache-dir`)
+pub trait EntryStore: Sync {
+    /// The entry converted from `data` for `version` in an earlier run
+    fn load(&self, data: &[u8], version: &MVersion) -> Option<Vec<u8>>;
+
+    /// Keep `encoded`, converted from `data` for `version`. Failing to keep it
+    /// only costs a conversion next time, so it isn't an error
+    fn save(&self, data: &[u8], version: &MVersion, encoded: &[u8]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saving_keeps_other_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        // Loaded together, as for several targets
+        let mut first = ConversionCache::load(&dir.path().join("first.mcpack"), String::new()).unwrap();
+        let mut second = ConversionCache::load(&dir.path().join("second.mcpack"), String::new()).unwrap();
+        first.record("a.material.bin", "1".to_string());
+        second.record("b.material.bin", "2".to_string());
+        first.save().unwrap();
+        second.save().unwrap();
+        let file = CacheFile::read(&dir.path().join(CACHE_FILE_NAME));
+        assert_eq!(file.outputs["first.mcpack"]["a.material.bin"], "1");
+        assert_eq!(file.outputs["second.mcpack"]["b.material.bin"], "2");
+    }
+}
@@ -0,0 +1,49 @@
+//! Stopping conversions early, e.g. on Ctrl-C. Conversions check between
+//! entries and fail like on any other error, so their outputs are never written
+//! half done
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+use crate::UpdateError;
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+static CURRENT_ENTRY: Mutex<Option<String>> = Mutex::new(None);
+
+/// Make running and later conversions stop before their next entry
+pub fn cancel() {
+    CANCELLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::Relaxed)
+}
+
+/// Entry a conversion started on last, to say where it stopped or crashed.
+/// With conversions running at once it is the entry of any of them
+pub fn current_entry() -> Option<String> {
+    CURRENT_ENTRY.lock().map_or(None, |entry| entry.clone())
+}
+
+/// Note that `entry` is being converted, failing instead if conversions were
+/// cancelled
+pub(crate) fn check(entry: &str) -> Result<(), UpdateError> {
+    if is_cancelled() {
+        return Err(UpdateError::Interrupted {
+            entry: Some(entry.to_string()),
+        });
+    }
+    if let Ok(mut current) = CURRENT_ENTRY.lock() {
+        *current = Some(entry.to_string());
+    }
+    Ok(())
+}
+
+/// Note that the conversion is done with its entries
+pub(crate) fn finished() {
+    if let Ok(mut current) = CURRENT_ENTRY.lock() {
+        *current = None;
+    }
+}
@@ -0,0 +1,86 @@
+//! What each target version changed for shader packs, shown by `explain` to
+//! answer "why is my pack pink" without digging through release notes
+
+use crate::MVersion;
+
+/// The changes of one version that break packs made for the ones before it
+pub struct VersionNotes {
+    pub version: MVersion,
+    /// What changed and how packs made for older versions break on it
+    pub changes: &'static [&'static str],
+    /// What converting can't fix
+    pub limitations: &'static [&'static str],
+}
+
+/// Shaders that are compiled bytecode can't be edited by text patches
+const BYTECODE: &str = "Only GLSL and ESSL shaders are patched. Direct3D and Vulkan bytecode (Windows packs) is converted without the fixes, get a pack made for this version for those";
+
+/// One entry per target version, newest first
+pub const VERSION_NOTES: &[VersionNotes] = &[
+    VersionNotes {
+        version: MVersion::V26_10,
+        changes: &[
+            "Vertex shaders get the lightmap uv (a_texcoord1) packed differently. Packs that unpack it the old way look up the wrong light levels, so lighting comes out wrong",
+            "Fragment shaders that unpack the lightmap uv they sample with unpack it a second time",
+            "Materials are still read in the 1.21.110 binary format",
+        ],
+        limitations: &[
+            BYTECODE,
+            "Packs that compute the lightmap uv in an unusual way aren't recognized, the patches then report shaders they found nothing to fix in (W002)",
+        ],
+    },
+    VersionNotes {
+        version: MVersion::V26_0_24,
+        changes: &[
+            "New material.bin binary format. The game can't load materials in an older one, so blocks and entities using them turn pink or invisible",
+        ],
+        limitations: &[BYTECODE],
+    },
+    VersionNotes {
+        version: MVersion::V1_21_110,
+        changes: &["New material.bin binary format, older materials don't load and show up pink or invisible"],
+        limitations: &[BYTECODE],
+    },
+    VersionNotes {
+        version: MVersion::V1_21_20,
+        changes: &["New material.bin binary format, older materials don't load and show up pink or invisible"],
+        limitations: &[],
+    },
+    VersionNotes {
+        version: MVersion::V1_20_80,
+        changes: &["New material.bin binary format, older materials don't load and show up pink or invisible"],
+        limitations: &[],
+    },
+    VersionNotes {
+        version: MVersion::V1_19_60,
+        changes: &["New material.bin binary format, older materials don't load and show up pink or invisible"],
+        limitations: &[],
+    },
+    VersionNotes {
+        version: MVersion::V1_18_30,
+        changes: &["Oldest material.bin binary format the tool writes"],
+        limitations: &["Packs can't be converted to versions before it"],
+    },
+];
+
+/// Notes of `version`
+pub fn notes(version: &MVersion) -> &'static VersionNotes {
+    VERSION_NOTES
+        .iter()
+        .find(|notes| notes.version == *version)
+        .expect("every version has VERSION_NOTES")
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ValueEnum;
+
+    use super::*;
+
+    #[test]
+    fn every_version_has_notes() {
+        for version in MVersion::value_variants() {
+            assert_eq!(VERSION_NOTES.iter().filter(|notes| notes.version == *version).count(), 1, "{version:?}");
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Seek},
+};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use zip::ZipArchive;
+
+#[derive(Serialize)]
+pub struct Checksums {
+    pub algorithm: &'static str,
+    /// Entry name -> lowercase hex digest of its uncompressed contents
+    pub files: BTreeMap<String, String>,
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Lowercase hex SHA-256 of everything `reader` yields
+pub fn sha256_reader<R: Read>(mut reader: R) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// SHA-256 of every file in a zip archive
+pub fn archive_checksums<R: Read + Seek>(input: R) -> anyhow::Result<Checksums> {
+    let mut zip = ZipArchive::new(input)?;
+    let mut files = BTreeMap::new();
+    for index in 0..zip.len() {
+        let file = zip.by_index(index)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_string();
+        files.insert(name, sha256_reader(file)?);
+    }
+    Ok(Checksums {
+        algorithm: "sha256",
+        files,
+    })
+}
+
+/// SHA-256 of a single file stored under `name`
+pub fn file_checksums<R: Read>(name: &str, input: R) -> anyhow::Result<Checksums> {
+    Ok(Checksums {
+        algorithm: "sha256",
+        files: BTreeMap::from([(name.to_string(), sha256_reader(input)?)]),
+    })
+}
@@ -0,0 +1,245 @@
+//! Detection of the locally installed Minecraft Bedrock client version
+
+#[cfg(any(windows, target_os = "android"))]
+use std::process::{Command, Output};
+
+#[cfg(any(windows, target_os = "android"))]
+use anyhow::Context;
+
+#[cfg(target_os = "android")]
+const PACKAGE: &str = "com.mojang.minecraftpe";
+
+/// Fail with what `command` printed unless it succeeded
+#[cfg(any(windows, target_os = "android"))]
+fn check_status(command: &str, output: &Output) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        output.status.success(),
+        "{command} failed ({}): {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(())
+}
+
+/// Version of the installed client, e.g. "1.21.111.01", read from the
+/// manifest of its APK
+#[cfg(target_os = "android")]
+pub fn installed_version() -> anyhow::Result<String> {
+    use std::{fs::File, io::Read};
+
+    let output = Command::new("pm")
+        .args(["path", PACKAGE])
+        .output()
+        .with_context(|| "Could not run pm to query the Minecraft package")?;
+    check_status(&format!("pm path {PACKAGE}"), &output)?;
+    let paths = String::from_utf8_lossy(&output.stdout);
+    let mut apks = paths.lines().filter_map(|line| line.trim().strip_prefix("package:"));
+    // Split installs list every part, the manifest with the version is in the base
+    let apk = apks
+        .clone()
+        .find(|path| path.ends_with("/base.apk"))
+        .or_else(|| apks.next())
+        .with_context(|| format!("Minecraft ({PACKAGE}) does not seem to be installed"))?;
+    let file = File::open(apk).with_context(|| format!("Error while opening {apk}"))?;
+    let mut zip = zip::ZipArchive::new(file).with_context(|| format!("{apk} is not a readable APK"))?;
+    let mut manifest = Vec::new();
+    zip.by_name("AndroidManifest.xml")
+        .with_context(|| format!("{apk} has no manifest"))?
+        .read_to_end(&mut manifest)?;
+    manifest_version_name(&manifest).with_context(|| format!("The manifest of {apk} has no versionName"))
+}
+
+/// Version of the installed client, e.g. "1.21.111.01"
+#[cfg(windows)]
+pub fn installed_version() -> anyhow::Result<String> {
+    let output = Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-Command",
+            "(Get-AppxPackage -Name Microsoft.MinecraftUWP).Version",
+        ])
+        .output()
+        .with_context(|| "Could not run powershell to query the Minecraft package")?;
+    check_status("powershell Get-AppxPackage", &output)?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    anyhow::ensure!(!version.is_empty(), "Minecraft for Windows does not seem to be installed");
+    Ok(uwp_to_game_version(&version))
+}
+
+/// Package versions look like "1.21.11101.0", where the third part is patch * 100 + revision
+#[cfg(any(windows, test))]
+fn uwp_to_game_version(version: &str) -> String {
+    let parts: Vec<&str> = version.split('.').collect();
+    match parts.as_slice() {
+        [major, minor, build, ..] => match build.parse::<u32>() {
+            Ok(build) => format!("{major}.{minor}.{}.{:02}", build / 100, build % 100),
+            Err(_) => version.to_string(),
+        },
+        _ => version.to_string(),
+    }
+}
+
+#[cfg(not(any(windows, target_os = "android")))]
+pub fn installed_version() -> anyhow::Result<String> {
+    anyhow::bail!("Detecting the installed Minecraft version is only supported on Android and Windows")
+}
+
+/// Resource id of `android:versionName`, which stands in for the attribute
+/// name when an APK's build stripped it
+#[cfg(any(target_os = "android", test))]
+const VERSION_NAME_ID: u32 = 0x0101_021c;
+
+#[cfg(any(target_os = "android", test))]
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+#[cfg(any(target_os = "android", test))]
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Strings of a binary XML string pool chunk, in UTF-8 or UTF-16
+#[cfg(any(target_os = "android", test))]
+fn string_pool(chunk: &[u8]) -> Option<Vec<String>> {
+    let header = usize::from(u16_at(chunk, 2)?);
+    let count = u32_at(chunk, 8)? as usize;
+    let utf8 = u32_at(chunk, 16)? & 0x100 != 0;
+    let start = u32_at(chunk, 20)? as usize;
+    (0..count)
+        .map(|index| {
+            let at = start + u32_at(chunk, header + index * 4)? as usize;
+            if utf8 {
+                // The length in UTF-16 units, then in bytes, each in one or two bytes
+                let length = |at: usize| {
+                    let first = usize::from(*chunk.get(at)?);
+                    if first & 0x80 == 0 {
+                        Some((first, at + 1))
+                    } else {
+                        Some((((first & 0x7f) << 8) | usize::from(*chunk.get(at + 1)?), at + 2))
+                    }
+                };
+                let (_, at) = length(at)?;
+                let (bytes, at) = length(at)?;
+                String::from_utf8(chunk.get(at..at + bytes)?.to_vec()).ok()
+            } else {
+                let first = usize::from(u16_at(chunk, at)?);
+                let (units, at) = if first & 0x8000 == 0 {
+                    (first, at + 2)
+                } else {
+                    (((first & 0x7fff) << 16) | usize::from(u16_at(chunk, at + 2)?), at + 4)
+                };
+                let units: Vec<u16> = chunk
+                    .get(at..at + units * 2)?
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                    .collect();
+                String::from_utf16(&units).ok()
+            }
+        })
+        .collect()
+}
+
+/// `versionName` of the `<manifest>` element of a binary AndroidManifest.xml
+#[cfg(any(target_os = "android", test))]
+fn manifest_version_name(xml: &[u8]) -> Option<String> {
+    let mut strings = Vec::new();
+    let mut resource_ids = Vec::new();
+    let mut chunk = usize::from(u16_at(xml, 2)?);
+    while chunk < xml.len() {
+        let header = usize::from(u16_at(xml, chunk + 2)?);
+        let size = u32_at(xml, chunk + 4)? as usize;
+        let body = xml.get(chunk..chunk + size)?;
+        match u16_at(xml, chunk)? {
+            0x0001 => strings = string_pool(body)?,
+            0x0180 => resource_ids = (header..size).step_by(4).map(|at| u32_at(body, at)).collect::<Option<_>>()?,
+            // The first element, the manifest
+            0x0102 => {
+                if strings.get(u32_at(body, 20)? as usize)? != "manifest" {
+                    return None;
+                }
+                let start = header + usize::from(u16_at(body, 24)?);
+                let attribute_size = usize::from(u16_at(body, 26)?);
+                return (0..usize::from(u16_at(body, 28)?)).find_map(|index| {
+                    let attribute = start + index * attribute_size;
+                    let name = u32_at(body, attribute + 4)? as usize;
+                    if strings.get(name)? != "versionName" && resource_ids.get(name) != Some(&VERSION_NAME_ID) {
+                        return None;
+                    }
+                    // The raw string, or the typed value when that is a string
+                    let value = match u32_at(body, attribute + 8)? {
+                        u32::MAX if body.get(attribute + 15) == Some(&0x03) => u32_at(body, attribute + 16)?,
+                        raw => raw,
+                    };
+                    strings.get(value as usize).cloned()
+                });
+            }
+            _ => {}
+        }
+        if size == 0 {
+            return None;
+        }
+        chunk += size;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_uwp_versions() {
+        assert_eq!(uwp_to_game_version("1.21.11101.0"), "1.21.111.01");
+        assert_eq!(uwp_to_game_version("1.21.2002.0"), "1.21.20.02");
+        assert_eq!(uwp_to_game_version("1.21"), "1.21");
+        assert_eq!(uwp_to_game_version("1.21.x.0"), "1.21.x.0");
+    }
+
+    /// A binary manifest with the UTF-16 `strings` and a `<manifest>` element
+    /// whose one attribute is named and valued by string indices
+    fn binary_manifest(strings: &[&str], name: u32, value: u32) -> Vec<u8> {
+        let mut pool = Vec::new();
+        let mut offsets = Vec::new();
+        for string in strings {
+            offsets.extend((pool.len() as u32).to_le_bytes());
+            let units: Vec<u16> = string.encode_utf16().collect();
+            pool.extend((units.len() as u16).to_le_bytes());
+            pool.extend(units.iter().chain([&0]).flat_map(|unit| unit.to_le_bytes()));
+        }
+        let start = 28 + offsets.len() as u32;
+        let mut chunk = [1u16.to_le_bytes(), 28u16.to_le_bytes()].concat();
+        chunk.extend((start + pool.len() as u32).to_le_bytes());
+        for field in [strings.len() as u32, 0, 0, start, 0] {
+            chunk.extend(field.to_le_bytes());
+        }
+        chunk.extend(offsets);
+        chunk.extend(pool);
+
+        let mut element = [0x0102u16.to_le_bytes(), 16u16.to_le_bytes()].concat();
+        element.extend(56u32.to_le_bytes());
+        for field in [1, u32::MAX, u32::MAX, 0] {
+            element.extend(field.to_le_bytes());
+        }
+        for field in [20u16, 20, 1, 0, 0, 0] {
+            element.extend(field.to_le_bytes());
+        }
+        for field in [u32::MAX, name, value, 0x0300_0008, value] {
+            element.extend(field.to_le_bytes());
+        }
+        let mut xml = [3u16.to_le_bytes(), 8u16.to_le_bytes()].concat();
+        xml.extend((8 + chunk.len() as u32 + element.len() as u32).to_le_bytes());
+        xml.extend(chunk);
+        xml.extend(element);
+        xml
+    }
+
+    #[test]
+    fn reads_the_version_from_binary_manifests() {
+        let xml = binary_manifest(&["manifest", "versionName", "1.21.111.01"], 1, 2);
+        assert_eq!(manifest_version_name(&xml).as_deref(), Some("1.21.111.01"));
+        let xml = binary_manifest(&["manifest", "versionCode", "1.21.111.01"], 1, 2);
+        assert_eq!(manifest_version_name(&xml), None);
+        assert_eq!(manifest_version_name(&xml[..20]), None);
+    }
+}
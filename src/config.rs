@@ -0,0 +1,188 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+pub const CONFIG_FILE_NAME: &str = "material-updater.toml";
+
+/// Defaults for command line options, flags given on the command line win
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub target_version: Option<String>,
+    pub zip_compression: Option<u32>,
+    /// Directory for automatically named outputs instead of next to the input
+    pub output_dir: Option<PathBuf>,
+    /// Name template for automatically named outputs, see `--output-template`
+    pub output_template: Option<String>,
+    pub cache: Option<bool>,
+    /// Directory of converted entries shared between runs, see `--cache-dir`
+    pub cache_dir: Option<PathBuf>,
+    /// Extra or overridden target version aliases, e.g. `stable = "1.21.110"`
+    pub aliases: HashMap<String, String>,
+    /// Uniform and sampler renames on top of the built-in ones, e.g. `s_OldTexture = "s_NewTexture"`
+    pub uniform_renames: HashMap<String, String>,
+    /// Material name globs the lightmap fixes are limited to, see `--lightmap-materials`
+    pub lightmap_materials: Option<Vec<String>>,
+    /// Patches to run or skip, unless the command line names them, see `--enable-patch`
+    pub enable_patch: Vec<String>,
+    pub disable_patch: Vec<String>,
+    /// Rule files for `--patch-file`, relative to the config file
+    pub patch_files: Vec<PathBuf>,
+    /// Vanilla materials for `compare-vanilla`: a directory, pack or url
+    pub vanilla_materials: Option<String>,
+    /// The game's `com.mojang` directory for `--install`, see `--install-dir`
+    pub install_dir: Option<PathBuf>,
+    /// Named bundles of options for `--preset`, on top of the built-in ones
+    pub presets: HashMap<String, Preset>,
+}
+
+/// Options `--preset` sets, unless they are given on the command line
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    pub description: Option<String>,
+    /// Comma separated like `--target-version`
+    pub target_version: Option<String>,
+    pub zip_compression: Option<u32>,
+    pub output_template: Option<String>,
+    pub enable_patch: Vec<String>,
+    pub disable_patch: Vec<String>,
+    pub minify_shaders: bool,
+    pub strip_conflicting_patches: bool,
+    /// `300` or `310`, see `--add-essl`
+    pub add_essl: Option<String>,
+    /// See `--on-incompat`
+    pub on_incompat: Option<String>,
+    pub lightmap_materials: Option<Vec<String>>,
+}
+
+/// Presets for the usual ways packs are published, in the format of the
+/// config file's `[presets]` tables
+const BUILTIN_PRESETS: &str = r#"
+[android-stable]
+description = "Current release on Android, minified to keep downloads small"
+target_version = "stable"
+output_template = "{stem}-android-{target}{ext}"
+zip_compression = 9
+minify_shaders = true
+
+[android-preview]
+description = "Current preview on Android, minified to keep downloads small"
+target_version = "preview"
+output_template = "{stem}-android-preview-{target}{ext}"
+zip_compression = 9
+minify_shaders = true
+
+[ios-stable]
+description = "Current release on iOS, whose Metal shaders are left as they are"
+target_version = "stable"
+output_template = "{stem}-ios-{target}{ext}"
+zip_compression = 9
+"#;
+
+/// The built-in presets, by name
+pub fn builtin_presets() -> HashMap<String, Preset> {
+    toml::from_str(BUILTIN_PRESETS).expect("built-in presets are valid")
+}
+
+/// Per-user config, e.g. `~/.config/material-updater/config.toml`
+fn user_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("material-updater").join("config.toml"))
+}
+
+impl Config {
+    /// Load `path` if given, otherwise the per-user config overlaid with
+    /// `material-updater.toml` from the working directory
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        if let Some(path) = path {
+            return Self::read(path);
+        }
+        let mut config = Self::default();
+        for path in user_config_path()
+            .into_iter()
+            .chain([PathBuf::from(CONFIG_FILE_NAME)])
+        {
+            if path.exists() {
+                log::info!("Using config file {}", path.display());
+                config = config.overlay(Self::read(&path)?);
+            }
+        }
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Error while reading config file {}", path.display()))?;
+        let mut config: Self = toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for file in &mut config.patch_files {
+            *file = dir.join(&*file);
+        }
+        Ok(config)
+    }
+
+    /// Every preset by name, the config's replacing built-in ones of the same name
+    pub fn presets(&self) -> BTreeMap<String, Preset> {
+        let mut presets: BTreeMap<_, _> = builtin_presets().into_iter().collect();
+        presets.extend(self.presets.clone());
+        presets
+    }
+
+    pub fn preset(&self, name: &str) -> anyhow::Result<Preset> {
+        self.presets()
+            .remove(name)
+            .with_context(|| format!("Unknown preset {name}, see `material-updater list-presets`"))
+    }
+
+    /// Values set in `other` replace the ones in `self`
+    fn overlay(mut self, other: Self) -> Self {
+        self.aliases.extend(other.aliases);
+        self.uniform_renames.extend(other.uniform_renames);
+        self.presets.extend(other.presets);
+        Self {
+            target_version: other.target_version.or(self.target_version),
+            zip_compression: other.zip_compression.or(self.zip_compression),
+            output_dir: other.output_dir.or(self.output_dir),
+            output_template: other.output_template.or(self.output_template),
+            cache: other.cache.or(self.cache),
+            cache_dir: other.cache_dir.or(self.cache_dir),
+            aliases: self.aliases,
+            uniform_renames: self.uniform_renames,
+            lightmap_materials: other.lightmap_materials.or(self.lightmap_materials),
+            enable_patch: if other.enable_patch.is_empty() { self.enable_patch } else { other.enable_patch },
+            disable_patch: if other.disable_patch.is_empty() { self.disable_patch } else { other.disable_patch },
+            patch_files: if other.patch_files.is_empty() { self.patch_files } else { other.patch_files },
+            vanilla_materials: other.vanilla_materials.or(self.vanilla_materials),
+            install_dir: other.install_dir.or(self.install_dir),
+            presets: self.presets,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_presets_replace_builtin_ones() {
+        let config: Config = toml::from_str("[presets.ios-stable]\ntarget_version = \"1.21.110\"").unwrap();
+        let presets = config.presets();
+        assert!(presets.contains_key("android-preview"));
+        assert_eq!(presets["ios-stable"].target_version.as_deref(), Some("1.21.110"));
+        assert!(config.preset("nope").is_err());
+    }
+
+    #[test]
+    fn later_files_replace_patch_settings() {
+        let user: Config = toml::from_str("enable_patch = [\"a\"]\ndisable_patch = [\"b\"]").unwrap();
+        let project: Config = toml::from_str("disable_patch = [\"c\"]").unwrap();
+        let config = user.overlay(project);
+        assert_eq!(config.enable_patch, ["a"]);
+        assert_eq!(config.disable_patch, ["c"]);
+    }
+}
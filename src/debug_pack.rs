@@ -0,0 +1,177 @@
+//! Debug packs for A/B testing patches in game: every patched material is
+//! kept as it was next to its patched version, and two subpacks let testers
+//! switch between them in the pack settings without converting again
+
+use std::{
+    collections::HashSet,
+    io::{Read, Seek, Write},
+};
+
+use serde_json::{json, Value};
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use crate::subpack::subpack_of;
+
+/// Suffix of the patched copies, replacing `.material.bin`
+pub const PATCHED_SUFFIX: &str = ".patched.material.bin";
+/// Subpack holding nothing, so the pack uses the original materials
+pub const ORIGINAL_TIER: &str = "original";
+/// Subpack holding the patched materials under their usual names
+pub const PATCHED_TIER: &str = "patched";
+
+/// What [`make_debug_pack`] did
+pub struct DebugPack {
+    /// Materials kept in both versions
+    pub patched: usize,
+    /// Whether the manifest got the subpacks switching between them
+    pub toggle: bool,
+}
+
+/// Write to `output` the converted pack with every material in `patched` (by
+/// entry name) replaced by its entry of `original`, the converted one being
+/// written next to it with [`PATCHED_SUFFIX`] and in the
+/// [`PATCHED_TIER`] subpack. Materials the conversion renamed or that don't
+/// exist in `original` are kept as converted
+pub fn make_debug_pack<O, C, W>(original: O, converted: C, output: W, patched: &HashSet<&str>) -> anyhow::Result<DebugPack>
+where
+    O: Read + Seek,
+    C: Read + Seek,
+    W: Write + Seek,
+{
+    let mut original = ZipArchive::new(original)?;
+    let mut converted = ZipArchive::new(converted)?;
+    let mut output = ZipWriter::new(output);
+    let manifest = converted
+        .file_names()
+        .filter(|name| name.rsplit('/').next() == Some("manifest.json") && subpack_of(name).is_none())
+        .min_by_key(|name| name.matches('/').count())
+        .map(str::to_string);
+    let pack = manifest.as_deref().map_or("", |name| &name[..name.len() - "manifest.json".len()]);
+    let toggle_manifest = match &manifest {
+        Some(name) => {
+            let mut text = String::new();
+            converted.by_name(name)?.read_to_string(&mut text)?;
+            match with_toggle(&text) {
+                Ok(Some(manifest)) => Some(manifest),
+                Ok(None) => {
+                    log::warn!("{name} already declares subpacks, the debug pack has no switch between the versions");
+                    None
+                }
+                Err(err) => {
+                    log::warn!("{name} can't be read ({err}), the debug pack has no switch between the versions");
+                    None
+                }
+            }
+        }
+        None => {
+            log::warn!("The pack has no manifest.json, the debug pack has no switch between the versions");
+            None
+        }
+    };
+
+    let mut debug = DebugPack {
+        patched: 0,
+        toggle: toggle_manifest.is_some(),
+    };
+    let mut toggled = Vec::new();
+    for index in 0..converted.len() {
+        let file = converted.by_index_raw(index)?;
+        let name = file.name().to_string();
+        if Some(&name) == manifest.as_ref() {
+            if let Some(manifest) = &toggle_manifest {
+                output.start_file(name, SimpleFileOptions::default())?;
+                output.write_all(manifest.as_bytes())?;
+                continue;
+            }
+        }
+        let before = original.index_for_name(&name);
+        let (Some(stem), Some(before)) = (name.strip_suffix(".material.bin"), before) else {
+            output.raw_copy_file(file)?;
+            continue;
+        };
+        if !patched.contains(name.as_str()) {
+            output.raw_copy_file(file)?;
+            continue;
+        }
+        drop(file);
+        output.raw_copy_file(original.by_index_raw(before)?)?;
+        output.raw_copy_file_rename(converted.by_index_raw(index)?, format!("{stem}{PATCHED_SUFFIX}"))?;
+        debug.patched += 1;
+        if let Some(path) = name.strip_prefix(pack).filter(|_| subpack_of(&name).is_none()) {
+            toggled.push((index, format!("{pack}subpacks/{PATCHED_TIER}/{path}")));
+        }
+    }
+    if debug.toggle {
+        output.add_directory(format!("{pack}subpacks/{ORIGINAL_TIER}/"), SimpleFileOptions::default())?;
+        for (index, name) in toggled {
+            output.raw_copy_file_rename(converted.by_index_raw(index)?, name)?;
+        }
+    }
+    output.finish()?;
+    Ok(debug)
+}
+
+/// `manifest` with subpacks for the original and patched materials, `None`
+/// if it already has subpacks of its own
+fn with_toggle(manifest: &str) -> anyhow::Result<Option<String>> {
+    let mut manifest: Value = serde_json::from_str(manifest)?;
+    let object = manifest.as_object_mut().ok_or_else(|| anyhow::anyhow!("not an object"))?;
+    if object.contains_key("subpacks") {
+        return Ok(None);
+    }
+    object.insert(
+        "subpacks".to_string(),
+        json!([
+            { "folder_name": ORIGINAL_TIER, "name": "Original shaders", "memory_tier": 0 },
+            { "folder_name": PATCHED_TIER, "name": "Patched shaders", "memory_tier": 0 },
+        ]),
+    );
+    Ok(Some(serde_json::to_string_pretty(&manifest)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn pack(entries: &[(&str, &str)]) -> Cursor<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        Cursor::new(zip.finish().unwrap().into_inner())
+    }
+
+    fn read(zip: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut contents = String::new();
+        zip.by_name(name).unwrap().read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn keeps_both_versions() {
+        let original = pack(&[
+            ("manifest.json", r#"{"header": {}}"#),
+            ("renderer/materials/Sky.material.bin", "old sky"),
+            ("renderer/materials/Water.material.bin", "old water"),
+        ]);
+        let converted = pack(&[
+            ("manifest.json", r#"{"header": {}}"#),
+            ("renderer/materials/Sky.material.bin", "new sky"),
+            ("renderer/materials/Water.material.bin", "new water"),
+        ]);
+        let mut output = Cursor::new(Vec::new());
+        let patched = HashSet::from(["renderer/materials/Sky.material.bin"]);
+        let debug = make_debug_pack(original, converted, &mut output, &patched).unwrap();
+        assert_eq!((debug.patched, debug.toggle), (1, true));
+
+        let mut zip = ZipArchive::new(output).unwrap();
+        assert_eq!(read(&mut zip, "renderer/materials/Sky.material.bin"), "old sky");
+        assert_eq!(read(&mut zip, "renderer/materials/Sky.patched.material.bin"), "new sky");
+        assert_eq!(read(&mut zip, "subpacks/patched/renderer/materials/Sky.material.bin"), "new sky");
+        assert_eq!(read(&mut zip, "renderer/materials/Water.material.bin"), "new water");
+        assert!(read(&mut zip, "manifest.json").contains("Patched shaders"));
+    }
+}
@@ -0,0 +1,115 @@
+//! `--dedup-report`: shader blobs a material stores more than once. Variants
+//! often have byte-identical shaders, yet each keeps its own copy, which is
+//! where much of a pack's size goes. The format has no way for variants to
+//! share one, so for now duplicates are only reported, for authors to collapse
+//! the flags that make no difference to the code
+
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    io::{Read, Seek},
+};
+
+use materialbin::CompiledMaterialDefinition;
+use sha2::{Digest, Sha256};
+
+use crate::for_each_material;
+
+/// How many groups the report lists, the most wasteful
+pub const LISTED_GROUPS: usize = 10;
+/// How many copies of a group the report lists
+pub const LISTED_COPIES: usize = 5;
+
+/// Copies of one shader blob in a material
+pub struct DuplicateGroup {
+    /// Zip entry (or file) the material was read from
+    pub entry: String,
+    pub bytes: usize,
+    /// Where each copy is, as `pass/variant platform stage`
+    pub copies: Vec<String>,
+}
+
+impl DuplicateGroup {
+    /// What keeping only one copy would save
+    pub fn wasted_bytes(&self) -> usize {
+        self.bytes * (self.copies.len() - 1)
+    }
+}
+
+#[derive(Default)]
+pub struct DedupReport {
+    pub shaders: usize,
+    /// Shaders left when every material keeps one copy of each blob
+    pub unique: usize,
+    pub code_bytes: usize,
+    /// Blobs stored more than once, most wasteful first
+    pub groups: Vec<DuplicateGroup>,
+}
+
+impl DedupReport {
+    pub fn wasted_bytes(&self) -> usize {
+        self.groups.iter().map(DuplicateGroup::wasted_bytes).sum()
+    }
+
+    fn add_material(&mut self, entry: &str, material: &CompiledMaterialDefinition) {
+        let shaders = material.passes.iter().flat_map(|(pass_name, pass)| {
+            pass.variants.iter().enumerate().flat_map(move |(index, variant)| {
+                variant.shader_codes.iter().map(move |(stage, code)| {
+                    (format!("{pass_name}/{index} {} {}", stage.platform_name, stage.stage_name), code.bgfx_shader_data.as_slice())
+                })
+            })
+        });
+        self.add_shaders(entry, shaders);
+    }
+
+    /// Add the shaders of one material, by where they are and their blob
+    fn add_shaders<'a>(&mut self, entry: &str, shaders: impl IntoIterator<Item = (String, &'a [u8])>) {
+        let mut blobs: HashMap<[u8; 32], DuplicateGroup> = HashMap::new();
+        for (location, data) in shaders {
+            self.shaders += 1;
+            self.code_bytes += data.len();
+            let group = blobs.entry(Sha256::digest(data).into()).or_insert_with(|| DuplicateGroup {
+                entry: entry.to_string(),
+                bytes: data.len(),
+                copies: Vec::new(),
+            });
+            group.copies.push(location);
+        }
+        self.unique += blobs.len();
+        self.groups.extend(blobs.into_values().filter(|group| group.copies.len() > 1));
+    }
+}
+
+/// Find the duplicated shaders of every material in a zip, or of a single
+/// material.bin
+pub fn dedup_report<R: Read + Seek>(input: &mut R) -> anyhow::Result<DedupReport> {
+    let mut report = DedupReport::default();
+    for_each_material(input, |entry, material, _| {
+        report.add_material(entry, &material);
+        Ok(())
+    })?;
+    report.groups.sort_by_key(|group| (Reverse(group.wasted_bytes()), group.entry.clone(), group.copies[0].clone()));
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_copies_per_material() {
+        let mut report = DedupReport::default();
+        let shaders = |blobs: &[&'static [u8]]| -> Vec<(String, &'static [u8])> {
+            blobs.iter().enumerate().map(|(index, blob)| (format!("Opaque/{index} ESSL_300 Vertex"), *blob)).collect()
+        };
+        report.add_shaders("a.material.bin", shaders(&[b"same", b"same", b"other", b"same"]));
+        // The same blob in another material is not a copy
+        report.add_shaders("b.material.bin", shaders(&[b"other"]));
+        assert_eq!((report.shaders, report.unique, report.code_bytes), (5, 3, 22));
+        assert_eq!(report.groups.len(), 1);
+        let group = &report.groups[0];
+        assert_eq!(group.entry, "a.material.bin");
+        assert_eq!(group.copies, ["Opaque/0 ESSL_300 Vertex", "Opaque/1 ESSL_300 Vertex", "Opaque/3 ESSL_300 Vertex"]);
+        assert_eq!((group.wasted_bytes(), report.wasted_bytes()), (8, 8));
+    }
+}
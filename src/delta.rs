@@ -0,0 +1,212 @@
+//! Update patches between an original pack and its conversion: a zip holding
+//! only the entries that changed, plus `delta.json` describing them
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{Read, Seek, Write},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use zip::{read::ZipFile, write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use crate::checksums::{archive_checksums, sha256_reader};
+
+pub const DELTA_SCHEMA_VERSION: u32 = 1;
+/// Name of the manifest inside a delta
+pub const DELTA_MANIFEST: &str = "delta.json";
+/// Directory of the delta holding the changed entries
+pub const DELTA_ENTRIES: &str = "entries/";
+
+#[derive(Serialize, Deserialize)]
+pub struct DeltaManifest {
+    pub schema: u32,
+    /// Target version the conversion was for
+    pub target: String,
+    /// SHA-256 of every entry of the original pack, which the delta only
+    /// applies to unchanged
+    pub original: BTreeMap<String, String>,
+    /// Entries that are new or different, in the order of the converted pack
+    pub changed: Vec<DeltaEntry>,
+    /// Entries of the original the conversion left out
+    pub removed: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeltaEntry {
+    pub name: String,
+    /// SHA-256 of the entry after the conversion
+    pub sha256: String,
+}
+
+/// What a delta changes, in entries
+#[derive(Default)]
+pub struct DeltaSummary {
+    pub added: usize,
+    pub replaced: usize,
+    pub removed: usize,
+}
+
+/// Write to `output` a delta turning `original` into `converted`
+pub fn make_delta<O, C, W>(original: O, converted: C, output: W, target: &str) -> anyhow::Result<DeltaSummary>
+where
+    O: Read + Seek,
+    C: Read + Seek,
+    W: Write + Seek,
+{
+    let original = archive_checksums(original)?.files;
+    let mut converted = ZipArchive::new(converted)?;
+    let mut delta = ZipWriter::new(output);
+    let mut summary = DeltaSummary::default();
+    let mut manifest = DeltaManifest {
+        schema: DELTA_SCHEMA_VERSION,
+        target: target.to_string(),
+        original: original.clone(),
+        changed: Vec::new(),
+        removed: Vec::new(),
+    };
+    let mut kept = HashSet::new();
+    for index in 0..converted.len() {
+        let file = converted.by_index(index)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_string();
+        let sha256 = sha256_reader(file)?;
+        match original.get(&name) {
+            Some(before) if *before == sha256 => {
+                kept.insert(name);
+                continue;
+            }
+            Some(_) => summary.replaced += 1,
+            None => summary.added += 1,
+        }
+        // Copied as stored, the delta has the same compression as the output
+        delta.raw_copy_file_rename(converted.by_index_raw(index)?, format!("{DELTA_ENTRIES}{name}"))?;
+        manifest.changed.push(DeltaEntry { name: name.clone(), sha256 });
+        kept.insert(name);
+    }
+    manifest.removed = original.keys().filter(|name| !kept.contains(*name)).cloned().collect();
+    summary.removed = manifest.removed.len();
+    delta.start_file(DELTA_MANIFEST, SimpleFileOptions::default())?;
+    serde_json::to_writer_pretty(&mut delta, &manifest)?;
+    delta.finish()?;
+    Ok(summary)
+}
+
+/// Rebuild the converted pack from `original` and a delta made by
+/// [`make_delta`]. Fails before writing anything if `original` isn't the pack
+/// the delta was made from or the delta's entries don't match its manifest
+pub fn apply_delta<O, D, W>(mut original: O, delta: D, output: W) -> anyhow::Result<DeltaSummary>
+where
+    O: Read + Seek,
+    D: Read + Seek,
+    W: Write + Seek,
+{
+    let mut delta = ZipArchive::new(delta)?;
+    let manifest: DeltaManifest = serde_json::from_reader(
+        delta
+            .by_name(DELTA_MANIFEST)
+            .map_err(|_| anyhow::anyhow!("Not a delta, it has no {DELTA_MANIFEST}"))?,
+    )
+    .with_context(|| format!("Invalid {DELTA_MANIFEST}"))?;
+    anyhow::ensure!(
+        manifest.schema == DELTA_SCHEMA_VERSION,
+        "Unsupported delta schema {}, this version reads schema {DELTA_SCHEMA_VERSION}",
+        manifest.schema
+    );
+
+    let found = archive_checksums(&mut original)?.files;
+    original.rewind()?;
+    for (name, sha256) in &manifest.original {
+        match found.get(name) {
+            Some(actual) if actual == sha256 => {}
+            Some(_) => anyhow::bail!("{name} differs from the pack the delta was made from"),
+            None => anyhow::bail!("{name} is missing, the delta was made from a different pack"),
+        }
+    }
+    if let Some(name) = found.keys().find(|name| !manifest.original.contains_key(*name)) {
+        anyhow::bail!("{name} wasn't in the pack the delta was made from");
+    }
+    for entry in &manifest.changed {
+        let stored = delta
+            .by_name(&format!("{DELTA_ENTRIES}{}", entry.name))
+            .with_context(|| format!("The delta lacks {}", entry.name))?;
+        anyhow::ensure!(
+            sha256_reader(stored)? == entry.sha256,
+            "{} in the delta doesn't match its checksum",
+            entry.name
+        );
+    }
+
+    let changed: HashMap<&str, &DeltaEntry> = manifest.changed.iter().map(|entry| (entry.name.as_str(), entry)).collect();
+    let mut original = ZipArchive::new(original)?;
+    let mut output = ZipWriter::new(output);
+    let mut summary = DeltaSummary::default();
+    let mut written = HashSet::new();
+    for index in 0..original.len() {
+        let file = original.by_index_raw(index)?;
+        let name = file.name().to_string();
+        if manifest.removed.contains(&name) {
+            summary.removed += 1;
+            continue;
+        }
+        if changed.contains_key(name.as_str()) {
+            drop(file);
+            output.raw_copy_file_rename(stored_entry(&mut delta, &name)?, &name)?;
+            summary.replaced += 1;
+        } else {
+            output.raw_copy_file(file)?;
+        }
+        written.insert(name);
+    }
+    for entry in &manifest.changed {
+        if written.insert(entry.name.clone()) {
+            output.raw_copy_file_rename(stored_entry(&mut delta, &entry.name)?, &entry.name)?;
+            summary.added += 1;
+        }
+    }
+    output.finish()?;
+    Ok(summary)
+}
+
+/// The delta's copy of `name`, as stored
+fn stored_entry<'a, D: Read + Seek>(delta: &'a mut ZipArchive<D>, name: &str) -> anyhow::Result<ZipFile<'a>> {
+    let index = delta
+        .index_for_name(&format!("{DELTA_ENTRIES}{name}"))
+        .with_context(|| format!("The delta lacks {name}"))?;
+    Ok(delta.by_index_raw(index)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn pack(entries: &[(&str, &str)]) -> Cursor<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        Cursor::new(zip.finish().unwrap().into_inner())
+    }
+
+    #[test]
+    fn applied_delta_rebuilds_the_conversion() {
+        let original = pack(&[("manifest.json", "{}"), ("a.material.bin", "old"), ("gone.txt", "x")]);
+        let converted = pack(&[("manifest.json", "{}"), ("a.material.bin", "new"), ("b.material.bin", "added")]);
+        let mut delta = Cursor::new(Vec::new());
+        make_delta(original.clone(), converted.clone(), &mut delta, "1.21.110").unwrap();
+
+        let mut rebuilt = Cursor::new(Vec::new());
+        let summary = apply_delta(original, Cursor::new(delta.get_ref().clone()), &mut rebuilt).unwrap();
+        assert_eq!((summary.added, summary.replaced, summary.removed), (1, 1, 1));
+        rebuilt.rewind().unwrap();
+        assert_eq!(archive_checksums(rebuilt).unwrap().files, archive_checksums(converted).unwrap().files);
+
+        let other = pack(&[("manifest.json", "{}"), ("a.material.bin", "edited"), ("gone.txt", "x")]);
+        assert!(apply_delta(other, delta, Cursor::new(Vec::new())).is_err());
+    }
+}
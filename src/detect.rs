@@ -0,0 +1,198 @@
+//! Working out which game version a pack is currently built for, from the
+//! binary format of its materials and the fixes already in their shaders
+
+use std::io::{Read, Seek};
+
+use materialbin::{bgfx_shader::BgfxShader, CompiledMaterialDefinition, MinecraftVersion};
+use scroll::Pread;
+use zip::ZipArchive;
+
+use crate::{
+    detect_material,
+    limits::Limits,
+    patches::{count_fingerprint, ShaderLanguage, PATCHES},
+    MVersion, VERSION_TABLE,
+};
+
+/// What a material entry says about the version it was made for
+pub struct DetectedMaterial {
+    pub entry: String,
+    /// `None` if the material can't be read
+    pub name: Option<String>,
+    pub version: Option<MinecraftVersion>,
+    /// Built-in patches whose fingerprint some shader contains
+    pub fixes: Vec<&'static str>,
+    /// Whether every shader is ESSL code
+    pub essl_only: bool,
+}
+
+impl DetectedMaterial {
+    pub(crate) fn new(entry: &str, material: &CompiledMaterialDefinition, version: MinecraftVersion) -> Self {
+        let mut languages = material
+            .passes
+            .values()
+            .flat_map(|pass| &pass.variants)
+            .flat_map(|variant| variant.shader_codes.keys())
+            .map(|stage| ShaderLanguage::of(&stage.platform))
+            .peekable();
+        let essl_only = languages.peek().is_some() && languages.all(|language| language == ShaderLanguage::Essl);
+        Self {
+            entry: entry.to_string(),
+            name: Some(material.name.clone()),
+            version: Some(version),
+            fixes: PATCHES
+                .iter()
+                .filter(|patch| patch.fingerprint().is_some_and(|fingerprint| count_fingerprint(material, fingerprint) != 0))
+                .map(|patch| patch.name())
+                .collect(),
+            essl_only,
+        }
+    }
+
+    pub(crate) fn unreadable(entry: &str) -> Self {
+        Self {
+            entry: entry.to_string(),
+            name: None,
+            version: None,
+            fixes: Vec::new(),
+            essl_only: false,
+        }
+    }
+}
+
+/// Most passes a real material has, by far
+const MAX_PASSES: usize = 64;
+/// Most variants a real pass has, by far
+const MAX_VARIANTS: usize = 8192;
+/// Text shaders looked at by [`parse_score`], enough to tell code from garbage
+const SAMPLED_SHADERS: usize = 8;
+
+/// How plausible `material` is as the result of parsing `len` bytes, of which
+/// the parser consumed `consumed`. A wrong version sometimes parses without
+/// error into garbage, which this tells apart: a right parse uses up the whole
+/// input, has a sane number of passes and variants, and its GLSL, ESSL and
+/// Metal shaders are text. Higher is better, [`MAX_PARSE_SCORE`] is the best
+pub fn parse_score(material: &CompiledMaterialDefinition, consumed: usize, len: usize) -> u32 {
+    let mut score = 0;
+    if consumed == len {
+        score += 4;
+    }
+    let sane_counts = (1..=MAX_PASSES).contains(&material.passes.len())
+        && material.passes.values().all(|pass| pass.variants.len() <= MAX_VARIANTS);
+    if sane_counts {
+        score += 2;
+    }
+    if !material.name.is_empty() && material.name.chars().all(|c| c.is_ascii_graphic()) {
+        score += 1;
+    }
+    let mut texts = material
+        .passes
+        .values()
+        .flat_map(|pass| &pass.variants)
+        .flat_map(|variant| &variant.shader_codes)
+        .filter(|(stage, _)| matches!(ShaderLanguage::of(&stage.platform), ShaderLanguage::Glsl | ShaderLanguage::Essl | ShaderLanguage::Metal))
+        .take(SAMPLED_SHADERS)
+        .map(|(_, code)| code.bgfx_shader_data.pread::<BgfxShader>(0).is_ok_and(|bgfx| is_source_text(&bgfx.code)))
+        .peekable();
+    // Materials without text shaders (e.g. only Direct3D) can't lose points here
+    if texts.peek().is_none() || texts.all(|text| text) {
+        score += 2;
+    }
+    score
+}
+
+/// Score of a parse nothing speaks against
+pub const MAX_PARSE_SCORE: u32 = 9;
+
+/// Whether `code` looks like shader source: printable UTF-8 apart from the
+/// usual whitespace, up to the NUL padding
+fn is_source_text(code: &[u8]) -> bool {
+    let end = code.iter().rposition(|&c| c != 0).map_or(0, |end| end + 1);
+    end != 0
+        && std::str::from_utf8(&code[..end])
+            .is_ok_and(|text| text.chars().all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t')))
+}
+
+/// Read every material of a pack, or a single material.bin
+pub fn detect_materials<R: Read + Seek>(input: &mut R) -> anyhow::Result<Vec<DetectedMaterial>> {
+    let mut materials = Vec::new();
+    let mut data = Vec::new();
+    let mut magic = [0; 4];
+    let is_zip = input.read_exact(&mut magic).is_ok() && magic == *b"PK\x03\x04";
+    input.rewind()?;
+    if !is_zip {
+        input.read_to_end(&mut data)?;
+        materials.push(match detect_material(&data) {
+            Ok((material, version)) => DetectedMaterial::new("input", &material, version),
+            Err(_) => DetectedMaterial::unreadable("input"),
+        });
+        return Ok(materials);
+    }
+    let mut zip = ZipArchive::new(input)?;
+    for index in 0..zip.len() {
+        let mut file = zip.by_index(index)?;
+        let name = file.name().to_string();
+        if !name.ends_with(".material.bin") {
+            continue;
+        }
+        data.clear();
+        Limits::default().read_entry(&name, &mut file, &mut data)?;
+        materials.push(match detect_material(&data) {
+            Ok((material, version)) => DetectedMaterial::new(&name, &material, version),
+            Err(_) => DetectedMaterial::unreadable(&name),
+        });
+    }
+    Ok(materials)
+}
+
+/// Target versions the materials most likely come from, most likely first.
+/// Versions sharing the most common binary format are narrowed down by the
+/// fixes found: a fix only made for some versions points at those, and a
+/// version whose fix is missing everywhere is less likely
+pub fn likely_versions(materials: &[DetectedMaterial]) -> Vec<MVersion> {
+    let mut formats: Vec<(MinecraftVersion, usize)> = Vec::new();
+    for version in materials.iter().filter_map(|material| material.version) {
+        match formats.iter_mut().find(|(format, _)| *format == version) {
+            Some((_, count)) => *count += 1,
+            None => formats.push((version, 1)),
+        }
+    }
+    let Some((format, _)) = formats.into_iter().max_by_key(|(_, count)| *count) else {
+        return Vec::new();
+    };
+    let candidates: Vec<MVersion> = VERSION_TABLE
+        .iter()
+        .filter(|info| info.binary == format)
+        .map(|info| info.version.clone())
+        .collect();
+    let found = |name: &str| materials.iter().any(|material| material.fixes.contains(&name));
+    let mut scored: Vec<(usize, MVersion)> = candidates
+        .into_iter()
+        .map(|version| {
+            let score = PATCHES
+                .iter()
+                .filter(|patch| patch.fingerprint().is_some())
+                .filter(|patch| {
+                    let meant_for = patch.targets().is_none_or(|targets| targets.contains(&version));
+                    meant_for == found(patch.name())
+                })
+                .count();
+            (score, version)
+        })
+        .collect();
+    // Stable, so equally likely versions stay newest first
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().map(|(_, version)| version).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_source_from_garbage() {
+        assert!(is_source_text(b"#version 300 es\nvoid main() {\n\tgl_FragColor = vec4(1.0);\n}\n\0\0"));
+        assert!(!is_source_text(b"\x03\x00\xfe\x12main"));
+        assert!(!is_source_text(b"\0\0"));
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
+
+/// Free bytes on the file system holding `dir`, `None` if it can't be told
+#[cfg(unix)]
+fn available_space(dir: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: path is NUL terminated and stat is a valid out pointer
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(windows)]
+fn available_space(dir: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let path: Vec<u16> = dir.as_os_str().encode_wide().chain([0]).collect();
+    let mut available = 0u64;
+    // SAFETY: path is NUL terminated, the other out pointers may be null
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(path.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut())
+    };
+    (ok != 0).then_some(available)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn available_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+/// Temporary files outputs are being written to, removed by
+/// [`remove_unfinished`] when the run is killed before they are dropped
+static UNFINISHED: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Keeps a path in [`UNFINISHED`] while alive
+struct Unfinished(PathBuf);
+
+impl Unfinished {
+    fn new(path: &Path) -> Self {
+        if let Ok(mut unfinished) = UNFINISHED.lock() {
+            unfinished.push(path.to_path_buf());
+        }
+        Self(path.to_path_buf())
+    }
+}
+
+impl Drop for Unfinished {
+    fn drop(&mut self) {
+        if let Ok(mut unfinished) = UNFINISHED.lock() {
+            unfinished.retain(|path| *path != self.0);
+        }
+    }
+}
+
+/// Remove the temporary files of outputs still being written, for exiting
+/// without unwinding
+pub fn remove_unfinished() {
+    if let Ok(unfinished) = UNFINISHED.lock() {
+        for path in unfinished.iter() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Refuse to clobber an existing `output` unless `overwrite` is set
+pub fn check_overwrite(output: &Path, overwrite: bool) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        overwrite || !output.exists(),
+        "Output {} already exists, pass --force to overwrite it",
+        output.display()
+    );
+    Ok(())
+}
+
+/// Exclusive lock on a path shared with other runs of the tool, held until
+/// dropped. Only material-updater honors it, it doesn't stop other programs
+pub struct PathLock {
+    path: PathBuf,
+    _file: Option<File>,
+}
+
+/// Lock file for `path`, kept in the cache directory so outputs don't get
+/// company. Named after the absolute path, so every run picks the same one
+fn lock_file(path: &Path) -> anyhow::Result<PathBuf> {
+    let absolute = std::path::absolute(path).with_context(|| format!("Can't resolve {}", path.display()))?;
+    let name: String = Sha256::digest(absolute.as_os_str().as_encoded_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    let dir = match dirs::cache_dir() {
+        Some(dir) => dir.join("material-updater").join("locks"),
+        None => absolute.parent().unwrap_or(Path::new(".")).to_path_buf(),
+    };
+    fs::create_dir_all(&dir).with_context(|| format!("Error while creating {}", dir.display()))?;
+    Ok(dir.join(format!("{name}.lock")))
+}
+
+/// Wait until no other run is writing `path`, then keep others out until the
+/// lock is dropped. File systems without locks only get a warning
+pub fn lock(path: &Path) -> anyhow::Result<PathLock> {
+    let lock_path = lock_file(path)?;
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("Error while opening lock file {}", lock_path.display()))?;
+    let locked = match file.try_lock() {
+        Ok(()) => Ok(()),
+        Err(TryLockError::WouldBlock) => {
+            log::warn!("Waiting for another run writing {}", path.display());
+            file.lock()
+        }
+        Err(TryLockError::Error(err)) => Err(err),
+    };
+    let file = match locked {
+        Ok(()) => Some(file),
+        Err(err) => {
+            log::warn!("Can't lock {}, parallel runs may race on it: {err}", path.display());
+            None
+        }
+    };
+    Ok(PathLock {
+        path: path.to_path_buf(),
+        _file: file,
+    })
+}
+
+impl PathLock {
+    /// [`write_output`] to the locked path, for callers that need the lock
+    /// around more than the write
+    pub fn replace<R: Read>(&self, contents: R, size: u64) -> anyhow::Result<()> {
+        replace(&self.path, contents, size)
+    }
+}
+
+/// Write `size` bytes from `contents` to `output` without ever leaving a
+/// half-written file behind: the data goes to a uniquely named temporary file
+/// next to it which replaces `output` only once everything reached the disk.
+/// Runs writing the same output at once take turns, see [`lock`]
+pub fn write_output<R: Read>(output: &Path, contents: R, size: u64) -> anyhow::Result<()> {
+    lock(output)?.replace(contents, size)
+}
+
+fn replace<R: Read>(output: &Path, mut contents: R, size: u64) -> anyhow::Result<()> {
+    let dir = match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Some(available) = available_space(dir) {
+        anyhow::ensure!(
+            available >= size,
+            "Not enough disk space for {}: needs {size} bytes, {available} available",
+            output.display()
+        );
+    }
+    // Removed again when dropped on error
+    let mut tmp = NamedTempFile::new_in(dir)
+        .with_context(|| format!("Error while creating a temporary file in {}", dir.display()))?;
+    let _unfinished = Unfinished::new(tmp.path());
+    io::copy(&mut contents, &mut tmp)
+        .and_then(|_| tmp.as_file().sync_all())
+        .with_context(|| format!("Error while writing {}", output.display()))?;
+    tmp.persist(output)
+        .with_context(|| format!("Error while replacing {}", output.display()))?;
+    Ok(())
+}
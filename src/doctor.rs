@@ -0,0 +1,253 @@
+//! Checks for the usual reasons a pack doesn't work, each with what to do
+//! about it, run by `doctor`
+
+use std::io::{Read, Seek};
+
+use materialbin::MinecraftVersion;
+use serde::Deserialize;
+use zip::ZipArchive;
+
+use crate::{
+    detect::DetectedMaterial,
+    detect_material,
+    limits::Limits,
+    lint::{lint_material, LintLevel, LintLevels},
+    patches::PATCHES,
+    summary::format_source_versions,
+    MVersion,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Might be fine, depending on where the pack is used
+    Warning,
+    /// The pack is broken or won't work for the target
+    Problem,
+}
+
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+    pub suggestion: String,
+}
+
+impl Finding {
+    fn new(severity: Severity, message: String, suggestion: impl Into<String>) -> Self {
+        Self {
+            severity,
+            message,
+            suggestion: suggestion.into(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    header: ManifestHeader,
+}
+
+#[derive(Deserialize)]
+struct ManifestHeader {
+    min_engine_version: Option<Vec<u32>>,
+}
+
+/// Run every check on a pack or material.bin, linting materials with
+/// `levels`. With `target`, patches and versions are checked against
+/// converting to it
+pub fn diagnose<R: Read + Seek>(input: &mut R, target: Option<&MVersion>, levels: &LintLevels) -> anyhow::Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    let mut materials = Vec::new();
+    let mut manifest = None;
+    let mut data = Vec::new();
+    let mut magic = [0; 4];
+    let is_zip = input.read_exact(&mut magic).is_ok() && magic == *b"PK\x03\x04";
+    input.rewind()?;
+    if is_zip {
+        let mut zip = ZipArchive::new(input)?;
+        for index in 0..zip.len() {
+            let mut file = zip.by_index(index)?;
+            let name = file.name().to_string();
+            data.clear();
+            if name.ends_with(".material.bin") {
+                Limits::default().read_entry(&name, &mut file, &mut data)?;
+                read_material(&name, &data, levels, &mut materials, &mut findings);
+            } else if name
+                .rsplit('/')
+                .next()
+                .is_some_and(|file_name| file_name.eq_ignore_ascii_case("manifest.json"))
+            {
+                Limits::default().read_entry(&name, &mut file, &mut data)?;
+                // The one closest to the root belongs to the pack, deeper ones to subpacks
+                let depth = name.matches('/').count();
+                if manifest.as_ref().is_none_or(|(other, _)| depth < *other) {
+                    manifest = Some((depth, data.clone()));
+                }
+            }
+        }
+    } else {
+        input.read_to_end(&mut data)?;
+        read_material("input", &data, levels, &mut materials, &mut findings);
+    }
+
+    check_render_chunk(is_zip, &materials, &mut findings);
+    check_patches(&materials, target, &mut findings);
+    check_platforms(&materials, &mut findings);
+    check_versions(&materials, manifest.map(|(_, data)| data), target, &mut findings);
+    findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+    Ok(findings)
+}
+
+fn read_material(
+    name: &str,
+    data: &[u8],
+    levels: &LintLevels,
+    materials: &mut Vec<DetectedMaterial>,
+    findings: &mut Vec<Finding>,
+) {
+    match detect_material(data) {
+        Ok((material, version)) => {
+            for lint in lint_material(&material, levels) {
+                let severity = match lint.level {
+                    LintLevel::Deny => Severity::Problem,
+                    _ => Severity::Warning,
+                };
+                findings.push(Finding::new(
+                    severity,
+                    format!("{name}: {} {} ({})", lint.location, lint.message, lint.code),
+                    lint.code.suggestion(),
+                ));
+            }
+            materials.push(DetectedMaterial::new(name, &material, version));
+        }
+        Err(err) => findings.push(Finding::new(
+            Severity::Problem,
+            format!("{name} can't be read: {err}"),
+            "Take the material from the pack's original download again, `validate` shows what is broken",
+        )),
+    }
+}
+
+fn check_render_chunk(is_zip: bool, materials: &[DetectedMaterial], findings: &mut Vec<Finding>) {
+    if is_zip && !materials.is_empty() && !materials.iter().any(|material| material.name.as_deref() == Some("RenderChunk")) {
+        findings.push(Finding::new(
+            Severity::Warning,
+            "The pack has no RenderChunk material, terrain will look vanilla".to_string(),
+            "Most shader packs change RenderChunk, check that the pack is complete and its materials are in renderer/materials/",
+        ));
+    }
+}
+
+fn check_patches(materials: &[DetectedMaterial], target: Option<&MVersion>, findings: &mut Vec<Finding>) {
+    for patch in PATCHES {
+        let carrying: Vec<&str> = materials
+            .iter()
+            .filter(|material| material.fixes.contains(&patch.name()))
+            .filter_map(|material| material.name.as_deref())
+            .collect();
+        if carrying.is_empty() {
+            continue;
+        }
+        let meant_for = patch.targets().map_or("every version".to_string(), |targets| {
+            targets.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+        });
+        let message = format!(
+            "{} materials already carry the {} fix for {meant_for}: {}",
+            carrying.len(),
+            patch.name(),
+            carrying.join(", ")
+        );
+        match target {
+            Some(target) if patch.targets().is_some_and(|targets| !targets.contains(target)) => {
+                findings.push(Finding::new(
+                    Severity::Problem,
+                    message,
+                    format!("It breaks lighting on {target}, convert with --strip-conflicting-patches to remove it"),
+                ))
+            }
+            Some(_) => {}
+            None => findings.push(Finding::new(
+                Severity::Warning,
+                message,
+                "Only use the pack on those versions, or convert it with --strip-conflicting-patches for others",
+            )),
+        }
+    }
+}
+
+fn check_platforms(materials: &[DetectedMaterial], findings: &mut Vec<Finding>) {
+    if !materials.is_empty() && materials.iter().all(|material| material.essl_only) {
+        findings.push(Finding::new(
+            Severity::Warning,
+            "Every shader is ESSL, the pack only works on Android and other OpenGL ES devices".to_string(),
+            "On Windows, iOS and consoles use the pack's build for that platform, converting can't add Direct3D or Metal shaders",
+        ));
+    }
+}
+
+fn check_versions(
+    materials: &[DetectedMaterial],
+    manifest: Option<Vec<u8>>,
+    target: Option<&MVersion>,
+    findings: &mut Vec<Finding>,
+) {
+    let mut sources: Vec<(MinecraftVersion, usize)> = Vec::new();
+    for source in materials.iter().filter_map(|material| material.version) {
+        match sources.iter_mut().find(|(version, _)| *version == source) {
+            Some((_, count)) => *count += 1,
+            None => sources.push((source, 1)),
+        }
+    }
+    if sources.len() > 1 {
+        findings.push(Finding::new(
+            Severity::Problem,
+            format!("Materials were compiled for several versions: {}", format_source_versions(&sources)),
+            "The pack was probably assembled from different releases, convert it so every material matches",
+        ));
+    }
+    if let Some(target) = target {
+        if sources.iter().any(|(version, _)| *version != target.as_version()) {
+            findings.push(Finding::new(
+                Severity::Warning,
+                format!("Materials aren't in the {target} format yet"),
+                format!("Convert the pack with -t {target}"),
+            ));
+        }
+    }
+
+    let Some(manifest) = manifest else {
+        return;
+    };
+    let engine = match serde_json::from_slice::<Manifest>(&manifest) {
+        Ok(manifest) => manifest.header.min_engine_version,
+        Err(err) => {
+            findings.push(Finding::new(
+                Severity::Warning,
+                format!("manifest.json can't be read: {err}"),
+                "The game tolerates some things strict JSON doesn't, like comments. If the pack doesn't show up in the game, fix the manifest",
+            ));
+            return;
+        }
+    };
+    let Some(engine) = engine.filter(|engine| !engine.is_empty()) else {
+        return;
+    };
+    let engine = engine.iter().map(ToString::to_string).collect::<Vec<_>>().join(".");
+    let Some(expected) = MVersion::from_game_version(&engine) else {
+        return;
+    };
+    let mismatched: Vec<(MinecraftVersion, usize)> = sources
+        .iter()
+        .copied()
+        .filter(|(version, _)| *version != expected.as_version())
+        .collect();
+    if !mismatched.is_empty() {
+        findings.push(Finding::new(
+            Severity::Warning,
+            format!(
+                "manifest.json asks for game {engine} or newer, which reads {expected} materials, but the pack has {}",
+                format_source_versions(&mismatched)
+            ),
+            format!("Convert the pack for the version it is meant for, or set min_engine_version to match (e.g. -t {expected})"),
+        ));
+    }
+}
@@ -0,0 +1,44 @@
+use std::io::{self, Seek};
+
+use anyhow::Context;
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::temp::Scratch;
+
+pub fn is_url(input: &str) -> bool {
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+/// File name of the last url path segment, without query or fragment
+fn url_file_name(url: &str) -> Option<&str> {
+    let path = url.split(['?', '#']).next()?;
+    path.rsplit('/').next().filter(|name| !name.is_empty())
+}
+
+/// Download `url` into a temporary file (or memory with `--no-temp`), returning
+/// the remote file name and the rewound file
+pub fn download(url: &str) -> anyhow::Result<(String, Scratch)> {
+    let name = url_file_name(url)
+        .with_context(|| "Url does not end with a file name")?
+        .to_string();
+    let response = ureq::get(url)
+        .call()
+        .with_context(|| format!("Error while downloading {url}"))?;
+    let bar = match response
+        .header("Content-Length")
+        .and_then(|len| len.parse().ok())
+    {
+        Some(len) => ProgressBar::new(len).with_style(
+            ProgressStyle::with_template("{bar:40.green} {bytes}/{total_bytes} ({bytes_per_sec})")
+                .unwrap(),
+        ),
+        None => ProgressBar::new_spinner().with_style(
+            ProgressStyle::with_template("{spinner:.green} {bytes} ({bytes_per_sec})").unwrap(),
+        ),
+    };
+    let mut file = Scratch::new()?;
+    io::copy(&mut bar.wrap_read(response.into_reader()), &mut file)?;
+    bar.finish_and_clear();
+    file.rewind()?;
+    Ok((name, file))
+}
@@ -0,0 +1,139 @@
+use std::{fmt, io};
+
+use materialbin::{MinecraftVersion, WriteError};
+use zip::result::ZipError;
+
+use crate::MVersion;
+
+/// Why converting a pack or material failed, for callers that need to react
+/// to the kind of failure rather than show the message
+#[derive(Debug)]
+pub enum UpdateError {
+    /// No binary version could read the material
+    ParseFailed {
+        /// Entry name, `None` for a single material.bin
+        material: Option<String>,
+        tried_versions: Vec<MinecraftVersion>,
+    },
+    /// A patch, or renaming identifiers, failed on a material
+    PatchFailed {
+        patch: &'static str,
+        material: String,
+        /// Pass and variant flags of the shader it failed on, if known
+        variant: Option<String>,
+        source: anyhow::Error,
+    },
+    /// The material can't be written for the target and the incompatibility
+    /// strategy gave no way around it
+    CompatSkipped {
+        material: String,
+        target: MVersion,
+        issue: &'static str,
+    },
+    /// An archive entry doesn't decompress or doesn't match its CRC
+    CorruptEntry { entry: String, source: io::Error },
+    /// The input is over one of the [`Limits`](crate::limits::Limits)
+    LimitExceeded {
+        /// Entry that is too large, `None` for the archive as a whole
+        entry: Option<String>,
+        limit: &'static str,
+        size: u64,
+        max: u64,
+    },
+    /// Stopped by [`cancel`](crate::cancel::cancel) before the entry it would
+    /// have converted next
+    Interrupted { entry: Option<String> },
+    Zip(ZipError),
+    Io(io::Error),
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ParseFailed {
+                material,
+                tried_versions,
+            } => {
+                let tried: Vec<String> = tried_versions.iter().map(ToString::to_string).collect();
+                match material {
+                    Some(material) => write!(f, "Material file {material} is invalid")?,
+                    None => f.write_str("Material file is invalid")?,
+                }
+                write!(f, " for all versions ({})", tried.join(", "))
+            }
+            Self::PatchFailed {
+                patch,
+                material,
+                variant,
+                ..
+            } => match variant {
+                Some(variant) => write!(f, "Patch {patch} failed on {material} {variant}"),
+                None => write!(f, "Patch {patch} failed on {material}"),
+            },
+            Self::CompatSkipped {
+                material,
+                target,
+                issue,
+            } => write!(f, "Material {material} is incompatible with {target}: {issue}"),
+            Self::CorruptEntry { entry, .. } => write!(f, "Entry {entry} is corrupt"),
+            Self::LimitExceeded {
+                entry,
+                limit,
+                size,
+                max,
+            } => {
+                if let Some(entry) = entry {
+                    write!(f, "{entry}: ")?;
+                }
+                write!(f, "{size} is over the {limit} limit of {max}")
+            }
+            Self::Interrupted { entry } => match entry {
+                Some(entry) => write!(f, "Interrupted before converting {entry}"),
+                None => f.write_str("Interrupted"),
+            },
+            Self::Zip(err) => write!(f, "Zip error: {err}"),
+            Self::Io(err) => write!(f, "IO error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for UpdateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::PatchFailed { source, .. } => Some(source.as_ref()),
+            Self::CorruptEntry { source, .. } => Some(source),
+            Self::Zip(err) => Some(err),
+            Self::Io(err) => Some(err),
+            Self::ParseFailed { .. } | Self::CompatSkipped { .. } | Self::LimitExceeded { .. } | Self::Interrupted { .. } => {
+                None
+            }
+        }
+    }
+}
+
+impl From<io::Error> for UpdateError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<ZipError> for UpdateError {
+    fn from(err: ZipError) -> Self {
+        Self::Zip(err)
+    }
+}
+
+impl UpdateError {
+    /// For encoding errors other than compatibility ones, which depend on the
+    /// incompatibility strategy and are handled where the material is written
+    pub(crate) fn from_write(err: WriteError, material: &str, target: &MVersion) -> Self {
+        match err {
+            WriteError::Compat(issue) => Self::CompatSkipped {
+                material: material.to_string(),
+                target: target.clone(),
+                issue,
+            },
+            WriteError::IoError(err) => Self::Io(err),
+        }
+    }
+}
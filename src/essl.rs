@@ -0,0 +1,194 @@
+//! Filling in missing ESSL variants from the other ESSL version a variant
+//! ships. ESSL 3.10 accepts ESSL 3.00 code as is, so going up only changes the
+//! `#version` line; going down is only done for shaders that use nothing 3.10
+//! added. naga can't read ESSL, and what it writes renames the uniforms bgfx
+//! binds by name, so the translation stays textual
+
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use clap::ValueEnum;
+use materialbin::{
+    bgfx_shader::BgfxShader,
+    pass::{PlatformShaderStage, ShaderCodePlatform, ShaderStage},
+    CompiledMaterialDefinition,
+};
+use regex::bytes::Regex;
+use scroll::Pread;
+
+use crate::patches::{Patch, PatchOutcome};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EsslVersion {
+    #[value(name = "300")]
+    Essl300,
+    #[value(name = "310")]
+    Essl310,
+}
+
+impl EsslVersion {
+    fn platform(self) -> ShaderCodePlatform {
+        match self {
+            Self::Essl300 => ShaderCodePlatform::Essl300,
+            Self::Essl310 => ShaderCodePlatform::Essl310,
+        }
+    }
+
+    fn platform_name(self) -> &'static str {
+        match self {
+            Self::Essl300 => "ESSL_300",
+            Self::Essl310 => "ESSL_310",
+        }
+    }
+
+    fn number(self) -> &'static [u8] {
+        match self {
+            Self::Essl300 => b"300",
+            Self::Essl310 => b"310",
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            Self::Essl300 => Self::Essl310,
+            Self::Essl310 => Self::Essl300,
+        }
+    }
+}
+
+/// Adds shaders of its ESSL version to every variant that only has the other
+/// one, run when `--add-essl` is given
+pub struct AddEsslVariants(pub EsslVersion);
+
+impl Patch for AddEsslVariants {
+    fn name(&self) -> &'static str {
+        match self.0 {
+            EsslVersion::Essl300 => "add-essl-300",
+            EsslVersion::Essl310 => "add-essl-310",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        "translate ESSL shaders to the other ESSL version for variants missing it"
+    }
+
+    fn apply(&self, material: &mut CompiledMaterialDefinition) -> anyhow::Result<PatchOutcome> {
+        let wanted = self.0;
+        let mut outcome = PatchOutcome::default();
+        for (pass_name, pass) in material.passes.iter_mut() {
+            for variant in pass.variants.iter_mut() {
+                let missing: Vec<_> = variant
+                    .shader_codes
+                    .iter()
+                    .filter(|(stage, _)| stage.platform == wanted.other().platform())
+                    .filter(|(stage, _)| {
+                        !variant
+                            .shader_codes
+                            .keys()
+                            .any(|other| other.platform == wanted.platform() && other.stage == stage.stage)
+                    })
+                    .map(|(stage, scode)| (stage.clone(), scode.clone()))
+                    .collect();
+                for (stage, mut scode) in missing {
+                    let mut bgfx: BgfxShader = match scode.bgfx_shader_data.pread(0) {
+                        Ok(s) => s,
+                        Err(_) => continue,
+                    };
+                    if let Err(reason) = translate(&mut bgfx.code, &stage.stage, wanted) {
+                        log::warn!(
+                            "{}/{pass_name}: {:?} shader left without an {} variant: {reason}",
+                            material.name,
+                            stage.stage,
+                            wanted.platform_name()
+                        );
+                        outcome.missing += 1;
+                        continue;
+                    }
+                    scode.bgfx_shader_data.clear();
+                    bgfx.write(&mut scode.bgfx_shader_data)
+                        .with_context(|| format!("Error while writing the {} shader", wanted.platform_name()))?;
+                    let stage = PlatformShaderStage {
+                        platform_name: wanted.platform_name().to_string(),
+                        platform: wanted.platform(),
+                        ..stage
+                    };
+                    variant.shader_codes.insert(stage, scode);
+                    outcome.patched += 1;
+                }
+            }
+        }
+        Ok(outcome)
+    }
+}
+
+/// Rewrite the ESSL `code` of a `stage` shader for `wanted`, the error says
+/// why it can't be
+fn translate(code: &mut Vec<u8>, stage: &ShaderStage, wanted: EsslVersion) -> Result<(), &'static str> {
+    static VERSION: OnceLock<Regex> = OnceLock::new();
+    let version = VERSION.get_or_init(|| Regex::new(r"(?m)^[ \t]*#[ \t]*version[ \t]+(3[01]0)[ \t]+es\b").expect("valid regex"));
+    let number = version
+        .captures(code)
+        .and_then(|captures| captures.get(1))
+        .ok_or("no ESSL 3 #version line")?
+        .range();
+    if wanted == EsslVersion::Essl300 {
+        if *stage == ShaderStage::Compute {
+            return Err("ESSL 3.00 has no compute shaders");
+        }
+        if let Some(feature) = essl_310_feature(code, stage) {
+            return Err(feature);
+        }
+    }
+    code.splice(number, wanted.number().iter().copied());
+    Ok(())
+}
+
+/// Something in `code` ESSL 3.00 doesn't have
+fn essl_310_feature(code: &[u8], stage: &ShaderStage) -> Option<&'static str> {
+    static FEATURES: OnceLock<[(Regex, &str); 4]> = OnceLock::new();
+    static VARYING_LOCATION: OnceLock<[Regex; 2]> = OnceLock::new();
+    let features = FEATURES.get_or_init(|| {
+        [
+            (r"\b(buffer|shared|coherent|readonly|writeonly)\b", "uses storage buffers or shared memory"),
+            (r"\b[iu]?image(2D|3D|Cube|2DArray)\b|\bimage(Load|Store|Size)\b|\batomic\w*\(", "uses images or atomics"),
+            (r"\btextureGather(Offset)?s?\b|\bgl_(SampleID|SamplePosition|SampleMaskIn)\b", "uses ESSL 3.10 built-ins"),
+            (r"layout\s*\([^)]*\b(binding|std430|offset)\b", "uses ESSL 3.10 layout qualifiers"),
+        ]
+        .map(|(regex, reason)| (Regex::new(regex).expect("valid regex"), reason))
+    });
+    if let Some((_, reason)) = features.iter().find(|(regex, _)| regex.is_match(code)) {
+        return Some(reason);
+    }
+    // ESSL 3.00 only allows locations on vertex inputs and fragment outputs
+    let [vertex, fragment] = VARYING_LOCATION.get_or_init(|| {
+        [r"\bout\b", r"\bin\b"].map(|direction| {
+            Regex::new(&format!(r"layout\s*\([^)]*\blocation\b[^)]*\)[\w\s]*?{direction}")).expect("valid regex")
+        })
+    });
+    let varying = match stage {
+        ShaderStage::Vertex => vertex,
+        ShaderStage::Fragment => fragment,
+        _ => return None,
+    };
+    varying.is_match(code).then_some("has locations on varyings")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_between_essl_versions() {
+        let mut code = b"#version 300 es\nprecision highp float;\nout vec4 color;\nvoid main() {}\n".to_vec();
+        assert!(translate(&mut code, &ShaderStage::Fragment, EsslVersion::Essl310).is_ok());
+        assert!(code.starts_with(b"#version 310 es\n"));
+        assert!(translate(&mut code, &ShaderStage::Fragment, EsslVersion::Essl300).is_ok());
+        assert!(code.starts_with(b"#version 300 es\n"));
+
+        let mut code = b"#version 310 es\nlayout(std430, binding = 0) buffer Data { vec4 d[]; };\nvoid main() {}\n".to_vec();
+        assert!(translate(&mut code, &ShaderStage::Fragment, EsslVersion::Essl300).is_err());
+        let mut code = b"#version 310 es\nlayout(location = 0) out vec2 v_texcoord0;\nvoid main() {}\n".to_vec();
+        assert!(translate(&mut code, &ShaderStage::Vertex, EsslVersion::Essl300).is_err());
+        assert!(translate(&mut code, &ShaderStage::Fragment, EsslVersion::Essl300).is_ok());
+    }
+}
@@ -0,0 +1,95 @@
+//! Pulling a single material out of a pack and putting it back
+
+use std::io::{Read, Seek, Write};
+
+use anyhow::Context;
+use zip::{
+    write::{ExtendedFileOptions, FileOptions},
+    ZipArchive, ZipWriter,
+};
+
+use crate::{detect_material, limits::Limits};
+
+/// Entry of the pack `wanted` refers to: its full path, or a file name only one
+/// entry has (ignoring ASCII case)
+pub fn find_entry<R: Read + Seek>(zip: &ZipArchive<R>, wanted: &str) -> anyhow::Result<String> {
+    let wanted = wanted.replace('\\', "/");
+    if zip.index_for_name(&wanted).is_some() {
+        return Ok(wanted);
+    }
+    let mut matches: Vec<&str> = zip
+        .file_names()
+        .filter(|name| {
+            name.rsplit('/')
+                .next()
+                .is_some_and(|file_name| file_name.eq_ignore_ascii_case(&wanted))
+        })
+        .collect();
+    matches.sort_unstable();
+    match matches.as_slice() {
+        [] => anyhow::bail!("The pack has no entry called {wanted}"),
+        [name] => Ok(name.to_string()),
+        names => anyhow::bail!(
+            "{wanted} matches several entries, give the full path of one: {}",
+            names.join(", ")
+        ),
+    }
+}
+
+/// Read the entry `wanted` out of a pack, returns its full path and data
+pub fn extract<R: Read + Seek>(input: R, wanted: &str) -> anyhow::Result<(String, Vec<u8>)> {
+    let mut zip = ZipArchive::new(input)?;
+    let name = find_entry(&zip, wanted)?;
+    let mut data = Vec::new();
+    Limits::default().read_entry(&name, zip.by_name(&name)?, &mut data)?;
+    Ok((name, data))
+}
+
+/// Copy a pack to `output` with the entry `wanted` replaced by the material
+/// `data`, every other entry is copied without recompressing. Returns the path
+/// of the replaced entry
+pub fn inject<R, W>(
+    input: R,
+    output: W,
+    wanted: &str,
+    data: &[u8],
+    compression_level: Option<u32>,
+) -> anyhow::Result<String>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let (material, version) = detect_material(data).with_context(|| "The material to inject can't be read")?;
+    let mut zip = ZipArchive::new(input)?;
+    let name = find_entry(&zip, wanted)?;
+
+    let mut replaced = Vec::new();
+    Limits::default().read_entry(&name, zip.by_name(&name)?, &mut replaced)?;
+    match detect_material(&replaced) {
+        Ok((old, old_version)) => {
+            if old.name != material.name {
+                log::warn!("{name} held material {}, replacing it with {}", old.name, material.name);
+            }
+            if old_version != version {
+                log::warn!("{name} was a {old_version} material, the replacement is {version}");
+            }
+        }
+        Err(_) => log::warn!("{name} wasn't a readable material"),
+    }
+
+    let mut output_zip = ZipWriter::new(output);
+    for index in 0..zip.len() {
+        let file = zip.by_index_raw(index)?;
+        if file.name() != name {
+            output_zip.raw_copy_file(file)?;
+            continue;
+        }
+        drop(file);
+        let file_options = FileOptions::<ExtendedFileOptions>::default()
+            .compression_level(compression_level.map(|v| v.into()));
+        output_zip.start_file(name.as_str(), file_options)?;
+        output_zip.write_all(data)?;
+    }
+    output_zip.finish()?;
+    Ok(name)
+}
@@ -0,0 +1,160 @@
+//! Stable C ABI for embedding the converter, see `include/material_updater.h`
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{catch_unwind, UnwindSafe},
+    ptr, slice,
+};
+
+use clap::ValueEnum;
+
+use crate::{MVersion, UpdateError};
+
+pub const MU_OK: c_int = 0;
+pub const MU_ERR_INVALID_ARGUMENT: c_int = 1;
+pub const MU_ERR_IO: c_int = 2;
+pub const MU_ERR_CONVERSION: c_int = 3;
+pub const MU_ERR_PANIC: c_int = 4;
+pub const MU_ERR_PARSE: c_int = 5;
+pub const MU_ERR_PATCH: c_int = 6;
+pub const MU_ERR_INCOMPATIBLE: c_int = 7;
+pub const MU_ERR_LIMIT: c_int = 8;
+
+struct FfiError {
+    code: c_int,
+    message: String,
+}
+
+impl FfiError {
+    fn new(code: c_int, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<(c_int, Option<CString>)> = const { RefCell::new((MU_OK, None)) };
+}
+
+fn set_last_error(code: c_int, message: Option<String>) {
+    let message = message.map(|m| CString::new(m.replace('\0', " ")).unwrap_or_default());
+    LAST_ERROR.with(|last| *last.borrow_mut() = (code, message));
+}
+
+/// Run `f`, storing its error (or panic) as the thread's last error and returning the code
+fn guard<F: FnOnce() -> Result<(), FfiError> + UnwindSafe>(f: F) -> c_int {
+    let (code, message) = match catch_unwind(f) {
+        Ok(Ok(())) => (MU_OK, None),
+        Ok(Err(err)) => (err.code, Some(err.message)),
+        Err(_) => (MU_ERR_PANIC, Some("panic during conversion".to_string())),
+    };
+    set_last_error(code, message);
+    code
+}
+
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, FfiError> {
+    if ptr.is_null() {
+        return Err(FfiError::new(MU_ERR_INVALID_ARGUMENT, format!("{name} is null")));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| FfiError::new(MU_ERR_INVALID_ARGUMENT, format!("{name} is not valid UTF-8")))
+}
+
+fn parse_target(target: &str) -> Result<MVersion, FfiError> {
+    MVersion::from_str(target, true).map_err(|err| FfiError::new(MU_ERR_INVALID_ARGUMENT, err))
+}
+
+fn conversion_error(err: UpdateError) -> FfiError {
+    let code = match err {
+        UpdateError::ParseFailed { .. } => MU_ERR_PARSE,
+        UpdateError::PatchFailed { .. } => MU_ERR_PATCH,
+        UpdateError::CompatSkipped { .. } => MU_ERR_INCOMPATIBLE,
+        UpdateError::LimitExceeded { .. } => MU_ERR_LIMIT,
+        UpdateError::Io(_) => MU_ERR_IO,
+        UpdateError::CorruptEntry { .. } | UpdateError::Interrupted { .. } | UpdateError::Zip(_) => MU_ERR_CONVERSION,
+    };
+    FfiError::new(code, format!("{:#}", anyhow::Error::new(err)))
+}
+
+/// Convert the pack or material.bin at `input_path` to `target` and write it to `output_path`.
+///
+/// # Safety
+/// All arguments must be valid NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn mu_convert_file(
+    input_path: *const c_char,
+    output_path: *const c_char,
+    target: *const c_char,
+) -> c_int {
+    guard(|| {
+        let input_path = str_arg(input_path, "input_path")?;
+        let output_path = str_arg(output_path, "output_path")?;
+        let version = parse_target(str_arg(target, "target")?)?;
+        let input = std::fs::read(input_path)
+            .map_err(|err| FfiError::new(MU_ERR_IO, format!("{input_path}: {err}")))?;
+        let output = crate::convert_bytes(&input, &version, None).map_err(conversion_error)?;
+        std::fs::write(output_path, output)
+            .map_err(|err| FfiError::new(MU_ERR_IO, format!("{output_path}: {err}")))
+    })
+}
+
+/// Convert `input_len` bytes at `input` to `target`. On success `*output` and
+/// `*output_len` describe a buffer that must be released with [`mu_free_buffer`].
+///
+/// # Safety
+/// `input` must point to `input_len` readable bytes, `target` must be a valid NUL
+/// terminated string and `output`/`output_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn mu_convert_buffer(
+    input: *const u8,
+    input_len: usize,
+    target: *const c_char,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> c_int {
+    guard(|| {
+        if input.is_null() || output.is_null() || output_len.is_null() {
+            return Err(FfiError::new(MU_ERR_INVALID_ARGUMENT, "null buffer argument"));
+        }
+        let version = parse_target(str_arg(target, "target")?)?;
+        let input = slice::from_raw_parts(input, input_len);
+        let converted = crate::convert_bytes(input, &version, None).map_err(conversion_error)?;
+        let converted = Box::into_raw(converted.into_boxed_slice());
+        *output_len = converted.len();
+        *output = converted.cast();
+        Ok(())
+    })
+}
+
+/// Release a buffer returned by [`mu_convert_buffer`].
+///
+/// # Safety
+/// `buffer` and `len` must come from a single successful [`mu_convert_buffer`] call.
+#[no_mangle]
+pub unsafe extern "C" fn mu_free_buffer(buffer: *mut u8, len: usize) {
+    if !buffer.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buffer, len)));
+    }
+}
+
+/// Error code of the last call made on this thread, `MU_OK` if it succeeded
+#[no_mangle]
+pub extern "C" fn mu_last_error_code() -> c_int {
+    LAST_ERROR.with(|last| last.borrow().0)
+}
+
+/// Message of the last error on this thread, or null. The pointer stays valid
+/// until the next converter call on the same thread.
+#[no_mangle]
+pub extern "C" fn mu_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .1
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
@@ -0,0 +1,120 @@
+use std::{
+    io::{Read, Seek, Write},
+    str::FromStr,
+};
+
+use materialbin::CompiledMaterialDefinition;
+use zip::{
+    write::{ExtendedFileOptions, FileOptions},
+    ZipArchive, ZipWriter,
+};
+
+use crate::detect_material;
+
+/// Change to the flags of material variants
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FlagEdit {
+    /// `KEY=VALUE`: set the flag on every variant that has it
+    Set(String, String),
+    /// `KEY`: remove the flag from every variant
+    Remove(String),
+}
+
+impl FromStr for FlagEdit {
+    type Err = String;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        match arg.split_once('=') {
+            Some(("", _)) => Err(format!("Flag {arg:?} has no name")),
+            Some((key, value)) => Ok(Self::Set(key.to_string(), value.to_string())),
+            None if arg.is_empty() => Err("Flag name is empty".to_string()),
+            None => Ok(Self::Remove(arg.to_string())),
+        }
+    }
+}
+
+/// Apply `edits` to every variant of `material`, returns how many variants changed
+pub fn apply_flag_edits(material: &mut CompiledMaterialDefinition, edits: &[FlagEdit]) -> usize {
+    let mut changed = 0;
+    for variant in material.passes.values_mut().flat_map(|pass| &mut pass.variants) {
+        let mut variant_changed = false;
+        for edit in edits {
+            match edit {
+                FlagEdit::Set(key, value) => {
+                    if let Some(old) = variant.flags.get_mut(key) {
+                        if old != value {
+                            *old = value.clone();
+                            variant_changed = true;
+                        }
+                    }
+                }
+                FlagEdit::Remove(key) => {
+                    variant_changed |= variant.flags.shift_remove(key).is_some();
+                }
+            }
+        }
+        if variant_changed {
+            changed += 1;
+        }
+    }
+    changed
+}
+
+/// Rewrite a zip or a single material.bin with `edits` applied, keeping every
+/// material in the binary version it was read as. Returns how many variants changed.
+pub fn edit_flags<R, W>(
+    input: &mut R,
+    output: &mut W,
+    edits: &[FlagEdit],
+    compression_level: Option<u32>,
+) -> anyhow::Result<usize>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let mut data = Vec::new();
+    let mut magic = [0; 4];
+    let is_zip = input.read_exact(&mut magic).is_ok() && magic == *b"PK\x03\x04";
+    input.rewind()?;
+    if !is_zip {
+        input.read_to_end(&mut data)?;
+        let (mut material, version) = detect_material(&data)?;
+        let changed = apply_flag_edits(&mut material, edits);
+        // Serialized in memory first, the writer makes many tiny writes
+        data.clear();
+        material.write(&mut data, version)?;
+        output.write_all(&data)?;
+        return Ok(changed);
+    }
+
+    let mut input_zip = ZipArchive::new(input)?;
+    let mut output_zip = ZipWriter::new(output);
+    let mut changed = 0;
+    for index in 0..input_zip.len() {
+        let mut file = input_zip.by_index(index)?;
+        if !file.name().ends_with(".material.bin") {
+            output_zip.raw_copy_file(file)?;
+            continue;
+        }
+        let name = file.name().to_string();
+        data.clear();
+        file.read_to_end(&mut data)?;
+        drop(file);
+        let (mut material, version) = detect_material(&data)?;
+        let material_changed = apply_flag_edits(&mut material, edits);
+        if material_changed == 0 {
+            output_zip.raw_copy_file(input_zip.by_index(index)?)?;
+            continue;
+        }
+        println!("Edited {material_changed} variants in {name}");
+        changed += material_changed;
+        let file_options = FileOptions::<ExtendedFileOptions>::default()
+            .compression_level(compression_level.map(|v| v.into()));
+        output_zip.start_file(name.as_str(), file_options)?;
+        data.clear();
+        material.write(&mut data, version)?;
+        output_zip.write_all(&data)?;
+    }
+    output_zip.finish()?;
+    Ok(changed)
+}
@@ -0,0 +1,73 @@
+//! Type checking of patched GLSL/ESSL with glslang, through shaderc. Only built
+//! with the `validate-glsl` feature, which needs shaderc's native library
+
+use std::cell::RefCell;
+
+use materialbin::pass::{ShaderCodePlatform, ShaderStage};
+use shaderc::{CompileOptions, Compiler, GlslProfile, ShaderKind, TargetEnv};
+
+thread_local! {
+    static COMPILER: RefCell<Option<Compiler>> = const { RefCell::new(None) };
+}
+
+/// Version and profile glslang checks code for `platform` as. glslang only
+/// takes ESSL 310 and up, which accepts ESSL 300 code too. ESSL 100 and
+/// GLSL 120 aren't checked
+fn checked_version(platform: &ShaderCodePlatform) -> Option<(u32, GlslProfile)> {
+    match platform {
+        ShaderCodePlatform::Glsl430 => Some((430, GlslProfile::Core)),
+        ShaderCodePlatform::Essl300 | ShaderCodePlatform::Essl310 => Some((310, GlslProfile::Es)),
+        _ => None,
+    }
+}
+
+fn compile(code: &str, kind: ShaderKind, version: u32, profile: GlslProfile) -> Result<(), String> {
+    COMPILER.with(|compiler| {
+        let mut compiler = compiler.borrow_mut();
+        if compiler.is_none() {
+            *compiler = Compiler::new();
+        }
+        let (Some(compiler), Some(mut options)) = (compiler.as_mut(), CompileOptions::new()) else {
+            return Err("shaderc couldn't be initialized".to_string());
+        };
+        options.set_forced_version_profile(version, profile);
+        options.set_target_env(TargetEnv::OpenGL, 0);
+        options.set_auto_map_locations(true);
+        options.set_auto_bind_uniforms(true);
+        compiler
+            .compile_into_spirv(code, kind, "shader", "main", Some(&options))
+            .map(drop)
+            .map_err(|err| err.to_string())
+    })
+}
+
+/// Fail if `patched` doesn't compile while `original`, the same shader before
+/// patching, does. glslang is stricter than most drivers, so shaders it already
+/// rejected aren't blamed on the patch
+pub(crate) fn check_patched(
+    platform: &ShaderCodePlatform,
+    stage: &ShaderStage,
+    original: &[u8],
+    patched: &[u8],
+) -> anyhow::Result<()> {
+    let Some((version, profile)) = checked_version(platform) else {
+        return Ok(());
+    };
+    let kind = match stage {
+        ShaderStage::Vertex => ShaderKind::Vertex,
+        ShaderStage::Fragment => ShaderKind::Fragment,
+        ShaderStage::Compute => ShaderKind::Compute,
+        ShaderStage::Unknown => return Ok(()),
+    };
+    let (Ok(original), Ok(patched)) = (std::str::from_utf8(original), std::str::from_utf8(patched)) else {
+        return Ok(());
+    };
+    if let Err(errors) = compile(patched, kind, version, profile) {
+        if let Err(original_errors) = compile(original, kind, version, profile) {
+            log::debug!("glslang rejects the shader before patching too: {original_errors}");
+            return Ok(());
+        }
+        anyhow::bail!("Patched {platform:?} {stage:?} shader doesn't compile: {errors}");
+    }
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::io::{Read, Seek};
+
+use materialbin::bgfx_shader::BgfxShader;
+use scroll::Pread;
+
+use crate::{find_subsequence, for_each_material};
+
+/// A line of shader code containing the searched text
+pub struct ShaderMatch {
+    pub entry: String,
+    pub material: String,
+    pub pass: String,
+    /// Flags of the variant the shader belongs to, as `key=value` pairs
+    pub flags: Vec<String>,
+    pub stage: String,
+    pub platform: String,
+    /// 1-based line in the shader code
+    pub line_number: usize,
+    pub line: String,
+}
+
+/// Call `each` for every shader code line in `input` that contains `pattern`
+pub fn grep_shaders<R, F>(
+    input: &mut R,
+    pattern: &str,
+    ignore_case: bool,
+    mut each: F,
+) -> anyhow::Result<()>
+where
+    R: Read + Seek,
+    F: FnMut(ShaderMatch),
+{
+    anyhow::ensure!(!pattern.is_empty(), "Search pattern is empty");
+    let needle = if ignore_case {
+        pattern.to_ascii_lowercase()
+    } else {
+        pattern.to_string()
+    };
+    for_each_material(input, |entry, material, _| {
+        for (pass_name, pass) in &material.passes {
+            for variant in &pass.variants {
+                for (stage, code) in &variant.shader_codes {
+                    let shader: BgfxShader = match code.bgfx_shader_data.pread(0) {
+                        Ok(shader) => shader,
+                        Err(err) => {
+                            log::warn!("Skipping unreadable shader in {entry} {pass_name}: {err}");
+                            continue;
+                        }
+                    };
+                    for (index, line) in shader.code.split(|b| *b == b'\n').enumerate() {
+                        let found = if ignore_case {
+                            find_subsequence(&line.to_ascii_lowercase(), needle.as_bytes())
+                        } else {
+                            find_subsequence(line, needle.as_bytes())
+                        };
+                        if found.is_none() {
+                            continue;
+                        }
+                        each(ShaderMatch {
+                            entry: entry.to_string(),
+                            material: material.name.clone(),
+                            pass: pass_name.clone(),
+                            flags: variant
+                                .flags
+                                .iter()
+                                .map(|(key, value)| format!("{key}={value}"))
+                                .collect(),
+                            stage: stage.stage_name.clone(),
+                            platform: stage.platform_name.clone(),
+                            line_number: index + 1,
+                            line: String::from_utf8_lossy(line).trim().to_string(),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    })
+}
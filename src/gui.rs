@@ -0,0 +1,242 @@
+use std::{
+    ffi::OsString,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use clap::Parser;
+use eframe::egui;
+use log::{LevelFilter, Log, Metadata, Record};
+use material_updater::{
+    patches::{default_patches, PATCHES},
+    VERSION_TABLE,
+};
+
+use crate::{config::Config, convert, Command, Options};
+
+/// Log records shown in the window instead of a terminal
+struct PaneLogger(Arc<Mutex<Vec<String>>>);
+
+impl Log for PaneLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let line = format!("{:<5} {}", record.level(), record.args());
+            self.0.lock().unwrap().push(line);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Conversion running in the background
+struct Job {
+    total: usize,
+    done: Arc<AtomicUsize>,
+    handle: JoinHandle<()>,
+}
+
+struct App {
+    files: Vec<PathBuf>,
+    /// Index into VERSION_TABLE
+    target: usize,
+    /// Whether each of PATCHES runs, starts out as the defaults of the target
+    patches: Vec<bool>,
+    overwrite: bool,
+    log: Arc<Mutex<Vec<String>>>,
+    job: Option<Job>,
+}
+
+/// Which of PATCHES run by default for VERSION_TABLE[target]
+fn default_selection(target: usize) -> Vec<bool> {
+    let version = &VERSION_TABLE[target].version;
+    PATCHES
+        .iter()
+        .map(|patch| default_patches(version).any(|default| default.name() == patch.name()))
+        .collect()
+}
+
+impl App {
+    fn new(log: Arc<Mutex<Vec<String>>>) -> Self {
+        let target = material_updater::MVersion::resolve("stable")
+            .and_then(|stable| VERSION_TABLE.iter().position(|info| info.version == stable))
+            .unwrap_or(0);
+        Self {
+            files: Vec::new(),
+            target,
+            patches: default_selection(target),
+            overwrite: false,
+            log,
+            job: None,
+        }
+    }
+
+    /// The choices made in the window as `convert` arguments, without the input
+    fn args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![
+            env!("CARGO_BIN_NAME").into(),
+            "convert".into(),
+            "--target-version".into(),
+            VERSION_TABLE[self.target].name.into(),
+        ];
+        for ((patch, &on), default) in PATCHES.iter().zip(&self.patches).zip(default_selection(self.target)) {
+            if on != default {
+                args.push(if on { "--enable-patch" } else { "--disable-patch" }.into());
+                args.push(patch.name().into());
+            }
+        }
+        if self.overwrite {
+            args.push("--force".into());
+        }
+        args
+    }
+
+    fn running(&self) -> bool {
+        self.job.as_ref().is_some_and(|job| !job.handle.is_finished())
+    }
+
+    /// Convert every selected file on a worker thread, one after the other
+    fn start(&mut self, ctx: &egui::Context) {
+        let args = self.args();
+        let files = self.files.clone();
+        let done = Arc::new(AtomicUsize::new(0));
+        let ctx = ctx.clone();
+        let counter = done.clone();
+        let handle = thread::spawn(move || {
+            let config = match Config::load(None) {
+                Ok(config) => config,
+                Err(err) => {
+                    log::error!("{err:#}");
+                    return;
+                }
+            };
+            for file in files {
+                log::info!("Converting {}", file.display());
+                let mut file_args = args.clone();
+                file_args.push(file.clone().into());
+                let result = Options::try_parse_from(file_args)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|opts| match opts.command {
+                        Some(Command::Convert(convert_args)) => convert(*convert_args, &config),
+                        _ => unreachable!("the arguments start with convert"),
+                    });
+                match result {
+                    Ok(()) => log::info!("Finished {}", file.display()),
+                    Err(err) => log::error!("{}: {err:#}", file.display()),
+                }
+                counter.fetch_add(1, Ordering::Relaxed);
+                ctx.request_repaint();
+            }
+        });
+        self.job = Some(Job {
+            total: self.files.len(),
+            done,
+            handle,
+        });
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let running = self.running();
+        ctx.input(|input| {
+            if !running {
+                self.files
+                    .extend(input.raw.dropped_files.iter().filter_map(|file| file.path.clone()));
+            }
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.add_enabled_ui(!running, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Add packs…").clicked() {
+                        let picked = rfd::FileDialog::new()
+                            .add_filter("Shader packs and materials", &["mcpack", "zip", "7z", "gz", "bin"])
+                            .pick_files();
+                        self.files.extend(picked.unwrap_or_default());
+                    }
+                    if ui.button("Clear").clicked() {
+                        self.files.clear();
+                    }
+                });
+                if self.files.is_empty() {
+                    ui.weak("Add packs or drop them on this window, outputs are written next to them");
+                }
+                for file in &self.files {
+                    ui.monospace(file.display().to_string());
+                }
+                ui.separator();
+
+                let previous = self.target;
+                egui::ComboBox::from_label("Target version")
+                    .selected_text(VERSION_TABLE[self.target].name)
+                    .show_ui(ui, |ui| {
+                        for (index, info) in VERSION_TABLE.iter().enumerate() {
+                            ui.selectable_value(&mut self.target, index, info.name);
+                        }
+                    });
+                if self.target != previous {
+                    self.patches = default_selection(self.target);
+                }
+                for (patch, on) in PATCHES.iter().zip(&mut self.patches) {
+                    ui.checkbox(on, patch.name()).on_hover_text(patch.description());
+                }
+                ui.checkbox(&mut self.overwrite, "Overwrite existing outputs");
+            });
+
+            let ready = !running && !self.files.is_empty();
+            if ui.add_enabled(ready, egui::Button::new("Convert")).clicked() {
+                self.start(ctx);
+            }
+            if let Some(job) = &self.job {
+                let done = job.done.load(Ordering::Relaxed);
+                ui.add(
+                    egui::ProgressBar::new(done as f32 / job.total as f32)
+                        .text(format!("{done} / {}", job.total))
+                        .animate(running),
+                );
+            }
+            ui.separator();
+            egui::ScrollArea::vertical().stick_to_bottom(true).show(ui, |ui| {
+                for line in self.log.lock().unwrap().iter() {
+                    ui.monospace(line);
+                }
+            });
+        });
+        if running {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+    }
+}
+
+/// Open the window, returns once it is closed
+pub fn run() -> anyhow::Result<()> {
+    // Double clicked, the console window Windows opened along is not needed
+    #[cfg(windows)]
+    if crate::terminal::owns_console() {
+        // SAFETY: no console handles are held at this point
+        unsafe { windows_sys::Win32::System::Console::FreeConsole() };
+    }
+    let log = Arc::new(Mutex::new(Vec::new()));
+    log::set_boxed_logger(Box::new(PaneLogger(log.clone())))?;
+    log::set_max_level(LevelFilter::Info);
+
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([560.0, 560.0]),
+        ..Default::default()
+    };
+    eframe::run_native(
+        "Material Updater",
+        options,
+        Box::new(move |_| Ok(Box::new(App::new(log)))),
+    )
+    .map_err(|err| anyhow::anyhow!("Error while opening the window: {err}"))
+}
@@ -0,0 +1,130 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::disk;
+
+/// One conversion that wrote an output, a line of the journal
+#[derive(Serialize, Deserialize)]
+pub struct Conversion {
+    pub timestamp: String,
+    pub input: String,
+    pub input_sha256: String,
+    /// Absolute path of the output
+    pub output: PathBuf,
+    pub target: String,
+    /// Patches that changed at least one material
+    pub patches: Vec<String>,
+    /// Copy of the file the output replaced, `None` if there was none
+    pub backup: Option<PathBuf>,
+}
+
+/// Per-user directory with the journal and backups, e.g. `~/.local/share/material-updater`
+fn history_dir() -> anyhow::Result<PathBuf> {
+    let dir = dirs::data_local_dir().with_context(|| "No local data directory to keep the history in")?;
+    Ok(dir.join("material-updater"))
+}
+
+fn journal_path() -> anyhow::Result<PathBuf> {
+    Ok(history_dir()?.join("history.jsonl"))
+}
+
+/// `output` as recorded in the journal
+pub fn absolute(output: &Path) -> anyhow::Result<PathBuf> {
+    std::path::absolute(output).with_context(|| format!("Can't resolve {}", output.display()))
+}
+
+/// Copy what is at `output` before a conversion replaces it, returns where
+/// the copy went or `None` if there is nothing to keep
+pub fn backup(output: &Path) -> anyhow::Result<Option<PathBuf>> {
+    if !output.is_file() {
+        return Ok(None);
+    }
+    let dir = history_dir()?.join("backups");
+    fs::create_dir_all(&dir).with_context(|| format!("Error while creating {}", dir.display()))?;
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f");
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    // The process id keeps runs backing up the same name at once apart
+    let backup = dir.join(format!("{stamp}-{}-{name}", std::process::id()));
+    fs::copy(output, &backup).with_context(|| format!("Error while backing up {}", output.display()))?;
+    Ok(Some(backup))
+}
+
+/// Append a conversion to the journal
+pub fn record(conversion: &Conversion) -> anyhow::Result<()> {
+    let path = journal_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Keeps the line from going missing in a rollback rewriting the journal
+    let _lock = disk::lock(&path)?;
+    let mut journal = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Error while opening {}", path.display()))?;
+    // A single write, so parallel conversions can't interleave their lines
+    let line = serde_json::to_string(conversion)? + "\n";
+    journal.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+fn read_journal(path: &Path) -> anyhow::Result<Vec<Conversion>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let reader = BufReader::new(File::open(path)?);
+    let mut conversions = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let conversion = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid line {} in {}", number + 1, path.display()))?;
+        conversions.push(conversion);
+    }
+    Ok(conversions)
+}
+
+/// Undo the latest recorded conversion that wrote `output`: put the file it
+/// replaced back, or remove the output if it replaced nothing. Rolling back
+/// again goes one conversion further back
+pub fn rollback(output: &Path) -> anyhow::Result<Conversion> {
+    let path = journal_path()?;
+    // Output first, in the order conversions take them
+    let output_lock = disk::lock(output)?;
+    let journal_lock = disk::lock(&path)?;
+    let mut conversions = read_journal(&path)?;
+    let wanted = absolute(output)?;
+    let index = conversions
+        .iter()
+        .rposition(|conversion| conversion.output == wanted)
+        .with_context(|| format!("No recorded conversion wrote {}", output.display()))?;
+    let conversion = conversions.remove(index);
+    match &conversion.backup {
+        Some(backup) => {
+            let file = File::open(backup)
+                .with_context(|| format!("Backup {} is gone", backup.display()))?;
+            let size = file.metadata()?.len();
+            output_lock.replace(BufReader::new(file), size)?;
+            fs::remove_file(backup)?;
+        }
+        None if output.exists() => fs::remove_file(output)
+            .with_context(|| format!("Error while removing {}", output.display()))?,
+        None => {}
+    }
+
+    let mut journal = String::new();
+    for conversion in &conversions {
+        journal.push_str(&serde_json::to_string(conversion)?);
+        journal.push('\n');
+    }
+    journal_lock.replace(journal.as_bytes(), journal.len() as u64)?;
+    Ok(conversion)
+}
@@ -0,0 +1,135 @@
+//! Callbacks on a conversion as it runs, for GUIs and servers that show its
+//! progress live. The terminal progress bar is driven by them too, and what
+//! the command line prints comes as [`Message`]s, the library itself writes
+//! nothing to stdout. Set with
+//! [`ConvertOptionsBuilder::hooks`](crate::ConvertOptionsBuilder::hooks):
+//!
+//! ```
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//! use material_updater::hooks::{ConversionHooks, MaterialConverted};
+//!
+//! #[derive(Default)]
+//! struct Percent(AtomicU64);
+//!
+//! impl ConversionHooks for Percent {
+//!     fn on_material_converted(&self, converted: &MaterialConverted) {
+//!         let percent = converted.done_bytes * 100 / converted.total_bytes.max(1);
+//!         self.0.store(percent, Ordering::Relaxed);
+//!     }
+//! }
+//! ```
+
+use crate::{summary::EntrySummary, warnings::Warning, ConversionReport, MVersion};
+
+/// Every method does nothing by default, so only the ones of interest need
+/// implementing. They are called from the converting thread, in order
+pub trait ConversionHooks: Send + Sync {
+    /// A zip is about to be converted. Single materials have no start
+    fn on_archive_start(&self, _archive: &ArchiveStart) {}
+
+    /// A material was converted, copied or left out for a target, see the
+    /// entry's result
+    fn on_material_converted(&self, _converted: &MaterialConverted) {}
+
+    /// A conversion for `target` warned
+    fn on_warning(&self, _target: &MVersion, _warning: &Warning) {}
+
+    /// The conversion for a target is done. Not called when it fails
+    fn on_finish(&self, _report: &ConversionReport) {}
+
+    /// The entries of the output for `target`, once they are all done. The
+    /// command line prints them as a table
+    fn on_summary(&self, _target: &MVersion, _entries: &[EntrySummary]) {}
+
+    /// A line of what the conversion has to say, which the command line prints
+    fn on_message(&self, _message: &Message) {}
+}
+
+pub struct ArchiveStart<'a> {
+    /// Entries of the zip, materials or not
+    pub entries: usize,
+    /// Uncompressed size of the materials, which take the conversion time
+    pub material_bytes: u64,
+    pub targets: &'a [MVersion],
+}
+
+pub struct MaterialConverted<'a> {
+    pub target: &'a MVersion,
+    pub entry: &'a EntrySummary,
+    /// Bytes of the materials done so far, this one included
+    pub done_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// Localized output of a conversion, one or more lines
+pub struct Message<'a> {
+    pub kind: MessageKind,
+    pub text: &'a str,
+}
+
+/// What a message is about, which the command line colors it by
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MessageKind {
+    /// The entry table and the count of converted materials
+    Plain,
+    /// Heading of the output for one of several targets
+    Heading,
+    /// Details such as the lines per entry of `--verbose`
+    Detail,
+    /// Something was left out or not done as asked
+    Warning,
+    /// The input needed no conversion
+    Done,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Cursor, Write},
+        sync::Mutex,
+    };
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::*;
+    use crate::{zip_update, ConvertOptions};
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>, Mutex<Vec<(MessageKind, String)>>);
+
+    impl ConversionHooks for Recorder {
+        fn on_archive_start(&self, archive: &ArchiveStart) {
+            self.0.lock().unwrap().push(format!("start {} entries", archive.entries));
+        }
+
+        fn on_warning(&self, target: &MVersion, warning: &Warning) {
+            self.0.lock().unwrap().push(format!("{target} {}", warning.code.name()));
+        }
+
+        fn on_finish(&self, report: &ConversionReport) {
+            self.0.lock().unwrap().push(format!("finish {}", report.version));
+        }
+
+        fn on_message(&self, message: &Message) {
+            self.1.lock().unwrap().push((message.kind, message.text.to_string()));
+        }
+    }
+
+    #[test]
+    fn calls_the_hooks_in_order() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for name in ["manifest.json", "../outside.txt"] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(b"{}").unwrap();
+        }
+        let mut pack = zip.finish().unwrap();
+        pack.set_position(0);
+        let recorder = Recorder::default();
+        let options = ConvertOptions::builder().hooks(&recorder).build().unwrap();
+        zip_update(&mut pack, &mut Cursor::new(Vec::new()), &MVersion::V26_10, None, &options).unwrap();
+        assert_eq!(*recorder.0.lock().unwrap(), ["start 2 entries", "26.10 unsafe-path", "finish 26.10"]);
+        let messages = recorder.1.lock().unwrap();
+        assert_eq!(messages[0], (MessageKind::Warning, "Left out ../outside.txt, its name escapes the pack".to_string()));
+        assert!(messages.iter().any(|(kind, text)| *kind == MessageKind::Plain && text.starts_with("Ported 0 materials")));
+    }
+}
@@ -0,0 +1,175 @@
+//! Translations of the messages printed while converting. Messages live in
+//! `locales/<lang>.ftl` files written in a subset of the Fluent syntax: one
+//! `id = text` per line, with `{ $name }` placeholders. English is the
+//! fallback for ids a translation lacks
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{OnceLock, RwLock},
+};
+
+/// Translations built into the binary. A new one is a file in `locales/` and a
+/// line here
+const BUNDLED: &[(&str, &str)] = &[("en", include_str!("../locales/en.ftl"))];
+
+type Catalog = HashMap<String, String>;
+
+static SELECTED: RwLock<Option<Catalog>> = RwLock::new(None);
+
+fn english() -> &'static Catalog {
+    static ENGLISH: OnceLock<Catalog> = OnceLock::new();
+    ENGLISH.get_or_init(|| parse(BUNDLED[0].1))
+}
+
+/// The `id = text` lines of a catalog, skipping comments, blank lines and
+/// lines that aren't messages
+pub fn parse(text: &str) -> Catalog {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(id, message)| (id.trim().to_string(), message.trim().to_string()))
+        .collect()
+}
+
+/// Translate into `lang` (e.g. `pt_BR` or `pt-BR`, falling back to `pt`), or
+/// the language of the `LC_ALL`, `LC_MESSAGES` or `LANG` variables without
+/// one. A `<lang>.ftl` in the `material-updater/locales` directory of the
+/// config directory is preferred over the bundled one, for trying out or
+/// fixing translations. Fails if `lang` is asked for but doesn't exist, while
+/// an unknown language of the environment is just English
+pub fn set_language(lang: Option<&str>) -> anyhow::Result<()> {
+    let (requested, explicit) = match lang {
+        Some(lang) => (lang.to_string(), true),
+        None => match ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .find_map(|var| std::env::var(var).ok().filter(|value| !value.is_empty()))
+        {
+            Some(lang) => (lang, false),
+            None => return Ok(()),
+        },
+    };
+    // pt_BR.UTF-8@euro is pt_BR
+    let lang = requested.split(['.', '@']).next().unwrap_or_default().replace('-', "_");
+    let language = lang.split('_').next().unwrap_or_default();
+    let catalog = [lang.as_str(), language]
+        .into_iter()
+        .filter(|candidate| !candidate.is_empty())
+        .find_map(load);
+    match catalog {
+        Some(catalog) => *SELECTED.write().unwrap() = Some(catalog),
+        None if explicit => anyhow::bail!(
+            "No translation for {requested:?}, there are {}",
+            languages().join(", ")
+        ),
+        // C, POSIX or a language nobody translated to yet
+        None => *SELECTED.write().unwrap() = None,
+    }
+    Ok(())
+}
+
+fn load(lang: &str) -> Option<Catalog> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(dir) = user_dir() {
+        if let Ok(text) = std::fs::read_to_string(dir.join(format!("{lang}.ftl"))) {
+            return Some(parse(&text));
+        }
+    }
+    BUNDLED
+        .iter()
+        .find(|(bundled, _)| bundled.eq_ignore_ascii_case(lang))
+        .map(|(_, text)| parse(text))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn user_dir() -> Option<std::path::PathBuf> {
+    Some(dirs::config_dir()?.join("material-updater").join("locales"))
+}
+
+/// Languages there are translations for, bundled or in the config directory
+pub fn languages() -> Vec<String> {
+    let mut languages: Vec<String> = BUNDLED.iter().map(|(lang, _)| lang.to_string()).collect();
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(entries) = user_dir().and_then(|dir| std::fs::read_dir(dir).ok()) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|extension| extension == "ftl") {
+                if let Some(stem) = path.file_stem() {
+                    languages.push(stem.to_string_lossy().into_owned());
+                }
+            }
+        }
+    }
+    languages.sort();
+    languages.dedup();
+    languages
+}
+
+/// Message `id` in the selected language with its placeholders filled in from
+/// `args`, see [`tr!`](crate::tr)
+pub fn message(id: &str, args: &[(&str, &dyn Display)]) -> String {
+    let selected = SELECTED.read().unwrap();
+    let text = selected
+        .as_ref()
+        .and_then(|catalog| catalog.get(id))
+        .or_else(|| english().get(id))
+        .map_or(id, String::as_str);
+    fill(text, args)
+}
+
+/// `text` with every `{ $name }` replaced by the argument called `name`.
+/// Placeholders without an argument are left in
+fn fill(text: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut filled = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let placeholder = &rest[..=end];
+        let name = placeholder[1..placeholder.len() - 1].trim().trim_start_matches('$');
+        match args.iter().find(|(arg, _)| *arg == name) {
+            Some((_, value)) => filled.push_str(&value.to_string()),
+            None => filled.push_str(placeholder),
+        }
+        rest = &rest[end + 1..];
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// A message in the selected language: `tr!("ported", count = 3, target = version)`
+#[macro_export]
+macro_rules! tr {
+    ($id:literal $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::message($id, &[$((stringify!($name), &$value as &dyn ::std::fmt::Display)),*])
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_placeholders() {
+        let catalog = parse("# comment\nported = Ported { $count } materials to {$target}\n\nbad line\n");
+        assert_eq!(catalog.len(), 1);
+        let args: [(&str, &dyn Display); 2] = [("count", &3), ("target", &"1.21.110")];
+        assert_eq!(fill(&catalog["ported"], &args), "Ported 3 materials to 1.21.110");
+        assert_eq!(fill("{ $missing } {", &args), "{ $missing } {");
+    }
+
+    #[test]
+    fn english_has_every_message_once() {
+        let text = BUNDLED[0].1;
+        let ids: Vec<&str> = text
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_once('=').map(|(id, _)| id.trim()))
+            .collect();
+        assert_eq!(ids.len(), english().len());
+    }
+}
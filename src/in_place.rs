@@ -0,0 +1,375 @@
+//! `--in-place`: the entries a conversion changed written into the input
+//! archive itself. They are appended where the central directory was, and a
+//! new central directory points at them instead of their old versions, so the
+//! entries that didn't change are neither read nor written again. The old
+//! versions stay behind as unreferenced bytes
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+use anyhow::Context;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const DATA_DESCRIPTOR: u32 = 0x0807_4b50;
+
+/// What [`update_in_place`] did
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct InPlaceUpdate {
+    pub kept: usize,
+    pub replaced: usize,
+    pub added: usize,
+    pub removed: usize,
+    /// Bytes of replaced and removed entries nothing points at anymore
+    pub unreferenced: u64,
+    /// Length of the updated archive, which the caller truncates it to
+    pub length: u64,
+}
+
+impl InPlaceUpdate {
+    pub fn changed(&self) -> bool {
+        self.replaced + self.added + self.removed != 0
+    }
+}
+
+/// A central directory record
+struct Record {
+    name: Vec<u8>,
+    /// The whole record as stored
+    raw: Vec<u8>,
+    flags: u16,
+    crc32: u32,
+    compressed_size: u32,
+    size: u32,
+    offset: u32,
+}
+
+fn u16_at(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn u32_at(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+/// End of central directory record of `archive`: where the directory starts,
+/// how long it is and the archive comment
+fn end_of_directory<R: Read + Seek>(archive: &mut R) -> anyhow::Result<(u64, u64, Vec<u8>)> {
+    let length = archive.seek(SeekFrom::End(0))?;
+    let tail_length = length.min(22 + u64::from(u16::MAX));
+    archive.seek(SeekFrom::Start(length - tail_length))?;
+    let mut tail = vec![0; tail_length as usize];
+    archive.read_exact(&mut tail)?;
+    let start = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&at| u32_at(&tail, at) == END_OF_DIRECTORY && at + 22 + usize::from(u16_at(&tail, at + 20)) == tail.len())
+        .with_context(|| "Not a zip archive, it has no end of central directory")?;
+    let record = &tail[start..];
+    anyhow::ensure!(
+        start < 20 || u32_at(&tail, start - 20) != ZIP64_LOCATOR,
+        "Zip64 archives can't be updated in place"
+    );
+    anyhow::ensure!(u16_at(record, 4) == 0, "Archives split over several files can't be updated in place");
+    Ok((u64::from(u32_at(record, 16)), u64::from(u32_at(record, 12)), record[22..].to_vec()))
+}
+
+/// The central directory of `archive` and where it starts
+fn read_directory<R: Read + Seek>(archive: &mut R) -> anyhow::Result<(Vec<Record>, u64)> {
+    let (offset, size, _) = end_of_directory(archive)?;
+    archive.seek(SeekFrom::Start(offset))?;
+    let mut directory = vec![0; size as usize];
+    archive.read_exact(&mut directory).with_context(|| "The central directory is cut off")?;
+    let mut records = Vec::new();
+    let mut at = 0;
+    while at < directory.len() {
+        anyhow::ensure!(
+            at + 46 <= directory.len() && u32_at(&directory, at) == CENTRAL_HEADER,
+            "Broken central directory record at {}",
+            offset + at as u64
+        );
+        let header = &directory[at..];
+        let length = 46 + usize::from(u16_at(header, 28)) + usize::from(u16_at(header, 30)) + usize::from(u16_at(header, 32));
+        anyhow::ensure!(at + length <= directory.len(), "Broken central directory record at {}", offset + at as u64);
+        let record = Record {
+            name: header[46..46 + usize::from(u16_at(header, 28))].to_vec(),
+            raw: header[..length].to_vec(),
+            flags: u16_at(header, 8),
+            crc32: u32_at(header, 16),
+            compressed_size: u32_at(header, 20),
+            size: u32_at(header, 24),
+            offset: u32_at(header, 42),
+        };
+        anyhow::ensure!(
+            record.compressed_size != u32::MAX && record.size != u32::MAX && record.offset != u32::MAX,
+            "Zip64 archives can't be updated in place"
+        );
+        records.push(record);
+        at += length;
+    }
+    Ok((records, offset))
+}
+
+/// Length of the local header, data and data descriptor of `record`
+fn local_length<R: Read + Seek>(archive: &mut R, record: &Record) -> anyhow::Result<u64> {
+    let mut header = [0; 30];
+    archive.seek(SeekFrom::Start(u64::from(record.offset)))?;
+    archive.read_exact(&mut header)?;
+    anyhow::ensure!(u32_at(&header, 0) == LOCAL_HEADER, "Broken local header of {}", String::from_utf8_lossy(&record.name));
+    let data_end = 30 + u64::from(u16_at(&header, 26)) + u64::from(u16_at(&header, 28)) + u64::from(record.compressed_size);
+    if record.flags & 0x08 == 0 {
+        return Ok(data_end);
+    }
+    let mut signature = [0; 4];
+    archive.seek(SeekFrom::Start(u64::from(record.offset) + data_end))?;
+    archive.read_exact(&mut signature)?;
+    Ok(data_end + if u32::from_le_bytes(signature) == DATA_DESCRIPTOR { 16 } else { 12 })
+}
+
+/// End of central directory record for a directory of `count` records
+fn end_of_directory_record(count: u16, size: u32, offset: u32, comment: &[u8]) -> Vec<u8> {
+    let mut end = Vec::with_capacity(22 + comment.len());
+    end.extend(END_OF_DIRECTORY.to_le_bytes());
+    end.extend([0; 4]);
+    end.extend(count.to_le_bytes());
+    end.extend(count.to_le_bytes());
+    end.extend(size.to_le_bytes());
+    end.extend(offset.to_le_bytes());
+    end.extend((comment.len() as u16).to_le_bytes());
+    end.extend(comment);
+    end
+}
+
+/// A zip as the zip reader sees it once only the first of the entries with
+/// exactly the same name is left in its central directory, for `--duplicates
+/// first`. The reader itself keeps the last of them. Reads before the central
+/// directory go to the archive, the new directory is held in memory
+pub(crate) struct FirstDuplicates<R> {
+    archive: R,
+    /// Where the central directory starts and the one replacing it, with its
+    /// end record. `None` when nothing is dropped
+    replaced: Option<(u64, Vec<u8>)>,
+    dropped: usize,
+    position: u64,
+}
+
+impl<R: Read + Seek> FirstDuplicates<R> {
+    /// `archive`, with the later entries sharing a name dropped when
+    /// `keep_first`. Archives whose directory can't be read here, zip64 ones,
+    /// are left as they are
+    pub(crate) fn new(mut archive: R, keep_first: bool) -> io::Result<Self> {
+        let replaced = if keep_first {
+            match first_of_duplicates(&mut archive) {
+                Ok(replaced) => replaced,
+                Err(err) => {
+                    log::debug!("Can't keep the first of duplicate entries: {err:#}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        archive.rewind()?;
+        let (replaced, dropped) = replaced.map_or((None, 0), |(offset, directory, dropped)| (Some((offset, directory)), dropped));
+        Ok(Self { archive, replaced, dropped, position: 0 })
+    }
+
+    /// How many entries were dropped
+    pub(crate) fn dropped(&self) -> usize {
+        self.dropped
+    }
+}
+
+/// Where the central directory of `archive` starts and one without the later
+/// records sharing a name, with how many those are. `None` if there are none
+fn first_of_duplicates<R: Read + Seek>(archive: &mut R) -> anyhow::Result<Option<(u64, Vec<u8>, usize)>> {
+    let (records, offset) = read_directory(archive)?;
+    let (_, _, comment) = end_of_directory(archive)?;
+    let mut seen = HashSet::new();
+    let kept: Vec<&Record> = records.iter().filter(|record| seen.insert(record.name.as_slice())).collect();
+    if kept.len() == records.len() {
+        return Ok(None);
+    }
+    let mut directory: Vec<u8> = kept.iter().flat_map(|record| record.raw.iter().copied()).collect();
+    let end = end_of_directory_record(kept.len() as u16, directory.len() as u32, offset as u32, &comment);
+    directory.extend(end);
+    Ok(Some((offset, directory, records.len() - kept.len())))
+}
+
+impl<R: Read + Seek> Read for FirstDuplicates<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some((offset, directory)) = &self.replaced else {
+            return self.archive.read(buf);
+        };
+        let read = if self.position < *offset {
+            let wanted = buf.len().min((*offset - self.position) as usize);
+            self.archive.seek(SeekFrom::Start(self.position))?;
+            self.archive.read(&mut buf[..wanted])?
+        } else {
+            let rest = directory.get((self.position - offset) as usize..).unwrap_or_default();
+            let read = buf.len().min(rest.len());
+            buf[..read].copy_from_slice(&rest[..read]);
+            read
+        };
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for FirstDuplicates<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let Some((offset, directory)) = &self.replaced else {
+            return self.archive.seek(pos);
+        };
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::End(delta) => (offset + directory.len() as u64).checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        self.position = position.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start of the archive"))?;
+        Ok(self.position)
+    }
+}
+
+/// Make `archive` hold the entries of `converted`, in its order, writing only
+/// the ones that differ from those already in `archive`. Entries are the same
+/// when their name, CRC and size are. Nothing is written if none changed
+pub fn update_in_place<F, R>(archive: &mut F, converted: &mut R) -> anyhow::Result<InPlaceUpdate>
+where
+    F: Read + Write + Seek,
+    R: Read + Seek,
+{
+    let (old, old_directory) = read_directory(archive)?;
+    let (_, _, comment) = end_of_directory(archive)?;
+    let (new, _) = read_directory(converted)?;
+    anyhow::ensure!(new.len() <= usize::from(u16::MAX), "Too many entries to update in place");
+    let existing: HashMap<&[u8], &Record> = old.iter().map(|record| (record.name.as_slice(), record)).collect();
+    let mut update = InPlaceUpdate::default();
+    // Every entry of `converted` with the record of `archive` it can keep
+    let mut plan = Vec::with_capacity(new.len());
+    for record in &new {
+        let kept = match existing.get(record.name.as_slice()) {
+            Some(before) if before.crc32 == record.crc32 && before.size == record.size => {
+                update.kept += 1;
+                Some(*before)
+            }
+            Some(before) => {
+                update.unreferenced += u64::from(before.compressed_size);
+                update.replaced += 1;
+                None
+            }
+            None => {
+                update.added += 1;
+                None
+            }
+        };
+        plan.push((record, kept));
+    }
+    let names: HashSet<&[u8]> = new.iter().map(|record| record.name.as_slice()).collect();
+    for record in old.iter().filter(|record| !names.contains(record.name.as_slice())) {
+        update.unreferenced += u64::from(record.compressed_size);
+        update.removed += 1;
+    }
+    if !update.changed() {
+        update.length = archive.seek(SeekFrom::End(0))?;
+        return Ok(update);
+    }
+
+    // The directory is written again anyway, so the changed entries go where it was
+    let mut position = old_directory;
+    let mut directory = Vec::new();
+    for (record, kept) in plan {
+        if let Some(kept) = kept {
+            directory.extend_from_slice(&kept.raw);
+            continue;
+        }
+        let length = local_length(converted, record)?;
+        converted.seek(SeekFrom::Start(u64::from(record.offset)))?;
+        archive.seek(SeekFrom::Start(position))?;
+        io::copy(&mut converted.by_ref().take(length), archive)?;
+        let offset = u32::try_from(position).with_context(|| "The archive would grow past 4 GiB, which needs zip64")?;
+        let mut raw = record.raw.clone();
+        raw[42..46].copy_from_slice(&offset.to_le_bytes());
+        directory.extend(raw);
+        position += length;
+    }
+    let directory_offset = u32::try_from(position).with_context(|| "The archive would grow past 4 GiB, which needs zip64")?;
+    let end = end_of_directory_record(new.len() as u16, directory.len() as u32, directory_offset, &comment);
+    archive.write_all(&directory)?;
+    archive.write_all(&end)?;
+    archive.flush()?;
+    update.length = position + directory.len() as u64 + end.len() as u64;
+    Ok(update)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+    use super::*;
+
+    fn pack(entries: &[(&str, &str)]) -> Cursor<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        Cursor::new(zip.finish().unwrap().into_inner())
+    }
+
+    fn read(zip: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut contents = String::new();
+        zip.by_name(name).unwrap().read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn writes_only_what_changed() {
+        let mut archive = pack(&[("manifest.json", "{}"), ("Sky.material.bin", "old sky"), ("Old.material.bin", "gone")]);
+        let mut converted = pack(&[("manifest.json", "{}"), ("Sky.material.bin", "new sky"), ("New.material.bin", "new")]);
+        let update = update_in_place(&mut archive, &mut converted).unwrap();
+        assert_eq!((update.kept, update.replaced, update.added, update.removed), (1, 1, 1, 1));
+        let mut data = archive.into_inner();
+        data.truncate(update.length as usize);
+
+        let mut zip = ZipArchive::new(Cursor::new(data)).unwrap();
+        assert_eq!(zip.file_names().count(), 3);
+        assert_eq!(read(&mut zip, "Sky.material.bin"), "new sky");
+        assert_eq!(read(&mut zip, "New.material.bin"), "new");
+        assert_eq!(read(&mut zip, "manifest.json"), "{}");
+        assert!(zip.by_name("Old.material.bin").is_err());
+
+        let mut again = Cursor::new(zip.into_inner().into_inner());
+        let update = update_in_place(&mut again, &mut converted).unwrap();
+        assert!(!update.changed());
+    }
+
+    #[test]
+    fn keeps_the_first_of_duplicates() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in [("a.txt", "first"), ("b.txt", "b"), ("a.txt", "second")] {
+            // The writer refuses a name twice, so the second a.txt starts as c.txt
+            zip.start_file(if contents == "second" { "c.txt" } else { name }, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        let mut bytes = zip.finish().unwrap().into_inner();
+        for at in (0..bytes.len() - 4).filter(|&at| &bytes[at..at + 5] == b"c.txt").collect::<Vec<_>>() {
+            bytes[at] = b'a';
+        }
+        assert_eq!(read(&mut ZipArchive::new(Cursor::new(bytes.clone())).unwrap(), "a.txt"), "second");
+
+        let first = FirstDuplicates::new(Cursor::new(bytes.clone()), true).unwrap();
+        assert_eq!(first.dropped(), 1);
+        let mut zip = ZipArchive::new(first).unwrap();
+        assert_eq!(zip.len(), 2);
+        let mut contents = String::new();
+        zip.by_name("a.txt").unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "first");
+        assert_eq!(FirstDuplicates::new(Cursor::new(bytes), false).unwrap().dropped(), 0);
+    }
+}
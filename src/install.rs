@@ -0,0 +1,284 @@
+//! `--install`: converted packs extracted straight into the pack folders of the
+//! local game, so they needn't be imported
+
+use std::{
+    fs,
+    io::{self, Read, Seek},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::ValueEnum;
+use material_updater::detect_material;
+use materialbin::MinecraftVersion;
+use serde_json::Value;
+use zip::ZipArchive;
+
+/// Pack folder of the game to install into
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum PackFolder {
+    /// development_resource_packs, reloaded by the game without caching
+    Development,
+    /// resource_packs
+    Resource,
+}
+
+impl PackFolder {
+    pub const ALL: [Self; 2] = [Self::Development, Self::Resource];
+
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            Self::Development => "development_resource_packs",
+            Self::Resource => "resource_packs",
+        }
+    }
+}
+
+/// `com.mojang` directories of the places the game keeps its data, most
+/// likely first
+#[cfg(windows)]
+fn candidate_game_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(appdata) = dirs::data_dir() {
+        // Since 1.21.120 the game keeps its data in the roaming app data
+        dirs.push(appdata.join("Minecraft Bedrock").join("Users").join("Shared").join("games").join("com.mojang"));
+    }
+    if let Some(local) = dirs::data_local_dir() {
+        dirs.push(
+            local
+                .join("Packages")
+                .join("Microsoft.MinecraftUWP_8wekyb3d8bbwe")
+                .join("LocalState")
+                .join("games")
+                .join("com.mojang"),
+        );
+    }
+    dirs
+}
+
+#[cfg(target_os = "android")]
+fn candidate_game_dirs() -> Vec<PathBuf> {
+    vec![
+        PathBuf::from("/storage/emulated/0/Android/data/com.mojang.minecraftpe/files/games/com.mojang"),
+        // Where older versions and the "external" storage setting put it
+        PathBuf::from("/storage/emulated/0/games/com.mojang"),
+    ]
+}
+
+/// mcpelauncher, the usual way to run the game on Linux and macOS
+#[cfg(not(any(windows, target_os = "android")))]
+fn candidate_game_dirs() -> Vec<PathBuf> {
+    dirs::data_dir()
+        .map(|dir| dir.join("mcpelauncher").join("games").join("com.mojang"))
+        .into_iter()
+        .collect()
+}
+
+/// The game's `com.mojang` directory: `configured` if given, otherwise the
+/// first existing one of the usual places
+pub fn game_dir(configured: Option<&Path>) -> anyhow::Result<PathBuf> {
+    if let Some(dir) = configured {
+        anyhow::ensure!(dir.is_dir(), "{} doesn't exist", dir.display());
+        return Ok(dir.to_path_buf());
+    }
+    let candidates = candidate_game_dirs();
+    candidates.iter().find(|dir| dir.is_dir()).cloned().with_context(|| {
+        let looked: Vec<String> = candidates.iter().map(|dir| dir.display().to_string()).collect();
+        format!(
+            "Couldn't find the game's data (looked in {}), give its com.mojang directory with --install-dir",
+            if looked.is_empty() { "no known place".to_string() } else { looked.join(", ") }
+        )
+    })
+}
+
+/// `header.uuid` and `header.version` of a pack manifest
+pub fn manifest_header(manifest: &str) -> Option<(String, String)> {
+    let manifest: Value = serde_json::from_str(manifest).ok()?;
+    let header = manifest.get("header")?;
+    let uuid = header.get("uuid")?.as_str()?.to_string();
+    let version = match header.get("version")? {
+        Value::Array(parts) => parts.iter().map(ToString::to_string).collect::<Vec<_>>().join("."),
+        other => other.as_str().map_or_else(|| other.to_string(), str::to_string),
+    };
+    Some((uuid, version))
+}
+
+/// Extract the pack in `archive` to `folder/name`, the pack's own directory
+/// inside the archive if it has one being left out. An installed pack with the
+/// same name is only replaced if it has the same UUID or `replace` is set
+pub fn install_pack<R: Read + Seek>(archive: R, folder: &Path, name: &str, replace: bool) -> anyhow::Result<PathBuf> {
+    let mut zip = ZipArchive::new(archive)?;
+    let manifest = zip
+        .file_names()
+        .filter(|entry| entry.rsplit('/').next() == Some("manifest.json"))
+        .min_by_key(|entry| entry.matches('/').count())
+        .map(str::to_string)
+        .with_context(|| "The pack has no manifest.json, the game wouldn't load it")?;
+    let prefix = &manifest[..manifest.len() - "manifest.json".len()];
+    let mut text = String::new();
+    zip.by_name(&manifest)?.read_to_string(&mut text)?;
+    let uuid = manifest_header(&text).map(|(uuid, _)| uuid);
+
+    let destination = folder.join(name);
+    if destination.exists() && !replace {
+        let installed = fs::read_to_string(destination.join("manifest.json")).ok();
+        let same = installed.as_deref().and_then(manifest_header).map(|(uuid, _)| uuid);
+        anyhow::ensure!(
+            same.is_some() && same == uuid,
+            "{} holds another pack, pass --force to replace it",
+            destination.display()
+        );
+    }
+    fs::create_dir_all(folder).with_context(|| format!("Error while creating {}", folder.display()))?;
+    // Extracted next to the destination and moved in place when complete
+    let staging = tempfile::Builder::new()
+        .prefix(".material-updater-")
+        .tempdir_in(folder)
+        .with_context(|| format!("Error while creating a directory in {}", folder.display()))?;
+    for index in 0..zip.len() {
+        let mut file = zip.by_index(index)?;
+        let Some(path) = file.enclosed_name() else {
+            log::warn!("{} is left out, its path leaves the pack", file.name());
+            continue;
+        };
+        let Ok(path) = path.strip_prefix(prefix) else {
+            continue;
+        };
+        let path = staging.path().join(path);
+        if file.is_dir() {
+            fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = fs::File::create(&path).with_context(|| format!("Error while creating {}", path.display()))?;
+        io::copy(&mut file, &mut out).with_context(|| format!("Error while extracting {}", file.name()))?;
+    }
+    if destination.exists() {
+        fs::remove_dir_all(&destination).with_context(|| format!("Error while removing {}", destination.display()))?;
+    }
+    // Once moved, dropping `staging` has nothing left to clean up
+    fs::rename(staging.path(), &destination)
+        .with_context(|| format!("Error while moving the pack to {}", destination.display()))?;
+    Ok(destination)
+}
+
+/// A pack in one of the game's pack folders
+pub struct InstalledPack {
+    pub folder: PackFolder,
+    pub path: PathBuf,
+    /// `header.name`, which may be a localization key
+    pub name: String,
+    pub uuid: String,
+    pub version: String,
+    /// How many materials are compiled for each binary version, unreadable
+    /// ones left out
+    pub sources: Vec<(MinecraftVersion, usize)>,
+}
+
+/// Every resource pack installed in the game's `com.mojang` directory
+/// `game_dir`. Directories without a readable manifest aren't packs and are
+/// left out
+pub fn installed_packs(game_dir: &Path) -> anyhow::Result<Vec<InstalledPack>> {
+    let mut packs = Vec::new();
+    for folder in PackFolder::ALL {
+        let dir = game_dir.join(folder.dir_name());
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries {
+            let path = entry.with_context(|| format!("Error while reading {}", dir.display()))?.path();
+            let Ok(manifest) = fs::read_to_string(path.join("manifest.json")) else {
+                continue;
+            };
+            let Some((uuid, version)) = manifest_header(&manifest) else {
+                continue;
+            };
+            let name = serde_json::from_str::<Value>(&manifest)?["header"]["name"].as_str().unwrap_or_default().to_string();
+            let mut sources = Vec::new();
+            count_sources(&path, &mut sources)?;
+            packs.push(InstalledPack {
+                folder,
+                path,
+                name,
+                uuid,
+                version,
+                sources,
+            });
+        }
+    }
+    packs.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(packs)
+}
+
+/// Add the binary versions of the materials under `dir` to `sources`, in the
+/// order they are found
+fn count_sources(dir: &Path, sources: &mut Vec<(MinecraftVersion, usize)>) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Error while reading {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            count_sources(&path, sources)?;
+            continue;
+        }
+        if !path.to_string_lossy().ends_with(".material.bin") {
+            continue;
+        }
+        let Ok((_, version)) = detect_material(&fs::read(&path)?) else {
+            log::info!("{} can't be read", path.display());
+            continue;
+        };
+        match sources.iter_mut().find(|(found, _)| *found == version) {
+            Some((_, count)) => *count += 1,
+            None => sources.push((version, 1)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::*;
+
+    fn pack(uuid: &str) -> Cursor<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let manifest = format!(r#"{{"header": {{"uuid": "{uuid}", "version": [1, 0, 2]}}}}"#);
+        for (name, contents) in [
+            ("MyPack/manifest.json", manifest.as_str()),
+            ("MyPack/renderer/materials/Sky.material.bin", "sky"),
+        ] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        Cursor::new(zip.finish().unwrap().into_inner())
+    }
+
+    #[test]
+    fn installs_and_updates_the_same_pack() {
+        let folder = tempfile::tempdir().unwrap();
+        let installed = install_pack(pack("a"), folder.path(), "mine", false).unwrap();
+        assert_eq!(fs::read_to_string(installed.join("renderer/materials/Sky.material.bin")).unwrap(), "sky");
+        let manifest = fs::read_to_string(installed.join("manifest.json")).unwrap();
+        assert_eq!(manifest_header(&manifest), Some(("a".to_string(), "1.0.2".to_string())));
+
+        assert!(install_pack(pack("a"), folder.path(), "mine", false).is_ok());
+        assert!(install_pack(pack("b"), folder.path(), "mine", false).is_err());
+        assert!(install_pack(pack("b"), folder.path(), "mine", true).is_ok());
+    }
+
+    #[test]
+    fn lists_installed_packs() {
+        let game = tempfile::tempdir().unwrap();
+        let folder = game.path().join(PackFolder::Resource.dir_name());
+        fs::create_dir_all(folder.join("mine")).unwrap();
+        fs::write(folder.join("mine/manifest.json"), r#"{"header": {"name": "Mine", "uuid": "a", "version": [1, 0, 0]}}"#).unwrap();
+        fs::create_dir_all(folder.join("not a pack")).unwrap();
+        let packs = installed_packs(game.path()).unwrap();
+        assert_eq!(packs.len(), 1);
+        assert_eq!((packs[0].folder, packs[0].uuid.as_str()), (PackFolder::Resource, "a"));
+    }
+}
@@ -0,0 +1,53 @@
+use std::{ffi::OsString, path::Path};
+
+use dialoguer::{theme::ColorfulTheme, Input, Select};
+use material_updater::{tr, VERSION_TABLE};
+
+use crate::download;
+
+/// Ask for the input, target version and output, returning them as command
+/// line arguments so they go through the same parsing as typed ones
+pub fn prompt_args() -> anyhow::Result<Vec<OsString>> {
+    let theme = ColorfulTheme::default();
+    println!("{}\n", tr!("interactive-intro"));
+
+    let file: String = Input::with_theme(&theme)
+        .with_prompt(tr!("prompt-input"))
+        .validate_with(|input: &String| -> Result<(), String> {
+            let input = input.trim().trim_matches('"');
+            if download::is_url(input) || Path::new(input).is_file() {
+                Ok(())
+            } else {
+                Err(tr!("prompt-no-such-file"))
+            }
+        })
+        .interact_text()?;
+
+    let names: Vec<&str> = VERSION_TABLE.iter().map(|info| info.name).collect();
+    let stable = material_updater::MVersion::resolve("stable")
+        .and_then(|stable| VERSION_TABLE.iter().position(|info| info.version == stable))
+        .unwrap_or(0);
+    let target = Select::with_theme(&theme)
+        .with_prompt(tr!("prompt-target"))
+        .items(&names)
+        .default(stable)
+        .interact()?;
+
+    let output: String = Input::with_theme(&theme)
+        .with_prompt(tr!("prompt-output"))
+        .allow_empty(true)
+        .interact_text()?;
+
+    let mut args = vec![
+        std::env::args_os().next().unwrap_or_default(),
+        file.trim().trim_matches('"').into(),
+        "--target-version".into(),
+        names[target].into(),
+    ];
+    let output = output.trim().trim_matches('"');
+    if !output.is_empty() {
+        args.push("--output".into());
+        args.push(output.into());
+    }
+    Ok(args)
+}
@@ -0,0 +1,327 @@
+//! Material.bin to JSON and back, for diffing, version control and tools that
+//! don't read the binary format.
+//!
+//! Passes, variants, flags and shader code are spelled out. Everything else
+//! (samplers, properties, pass settings, the bgfx header of each shader) is
+//! kept as base64 encoded binary, so a material rebuilt from unchanged JSON is
+//! the same as the original:
+//!
+//! ```json
+//! {
+//!   "schema": 1,
+//!   "format": "1.21.110",
+//!   "name": "RenderChunk",
+//!   "parent_name": null,
+//!   "definition": "<base64>",
+//!   "passes": [{
+//!     "name": "Opaque",
+//!     "variants": [{
+//!       "supported": true,
+//!       "flags": [["Seasons", "Off"]],
+//!       "shaders": [{
+//!         "stage": "Vertex",
+//!         "platform": "ESSL_310",
+//!         "source_hash": 0,
+//!         "header": "<base64>",
+//!         "code": "#version 310 es\n..."
+//!       }]
+//!     }]
+//!   }]
+//! }
+//! ```
+//!
+//! Shader code is `code` when it is text, `code_base64` when it is bytecode,
+//! or `code_file`, a path relative to the JSON file, when exported with a
+//! shader directory.
+
+use std::path::Path;
+
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use materialbin::{
+    bgfx_shader::BgfxShader,
+    pass::{PlatformShaderStage, ShaderCode, ShaderCodePlatform, ShaderStage},
+    CompiledMaterialDefinition, MinecraftVersion,
+};
+use scroll::Pread;
+use serde::{Deserialize, Serialize};
+
+use crate::patches::ShaderLanguage;
+
+/// Version of the format below, bumped on incompatible changes
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct MaterialJson {
+    pub schema: u32,
+    /// Binary version the material was read as and is written back in
+    pub format: String,
+    pub name: String,
+    pub parent_name: Option<String>,
+    /// The material without its variants, holding what isn't spelled out here
+    pub definition: String,
+    pub passes: Vec<PassJson>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PassJson {
+    pub name: String,
+    pub variants: Vec<VariantJson>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VariantJson {
+    pub supported: bool,
+    /// Flag names and values, in the order the material stores them
+    pub flags: Vec<(String, String)>,
+    pub shaders: Vec<ShaderJson>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ShaderJson {
+    pub stage: String,
+    pub platform: String,
+    pub source_hash: u64,
+    /// The bgfx shader blob without its code
+    pub header: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_base64: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_file: Option<String>,
+}
+
+const PLATFORMS: &[(&str, ShaderCodePlatform)] = &[
+    ("Direct3D_SM40", ShaderCodePlatform::Direct3DSm40),
+    ("Direct3D_SM50", ShaderCodePlatform::Direct3DSm50),
+    ("Direct3D_SM60", ShaderCodePlatform::Direct3DSm60),
+    ("Direct3D_SM65", ShaderCodePlatform::Direct3DSm65),
+    ("Direct3D_XB1", ShaderCodePlatform::Direct3DXb1),
+    ("Direct3D_XB3", ShaderCodePlatform::Direct3DXb3),
+    ("GLSL_120", ShaderCodePlatform::Glsl120),
+    ("GLSL_430", ShaderCodePlatform::Glsl430),
+    ("ESSL_100", ShaderCodePlatform::Essl100),
+    ("ESSL_300", ShaderCodePlatform::Essl300),
+    ("ESSL_310", ShaderCodePlatform::Essl310),
+    ("Metal", ShaderCodePlatform::Metal),
+    ("Vulkan", ShaderCodePlatform::Vulkan),
+    ("Nvn", ShaderCodePlatform::Nvn),
+    ("Pssl", ShaderCodePlatform::Pssl),
+];
+
+const STAGES: &[(&str, ShaderStage)] = &[
+    ("Vertex", ShaderStage::Vertex),
+    ("Fragment", ShaderStage::Fragment),
+    ("Compute", ShaderStage::Compute),
+];
+
+fn platform_named(name: &str) -> ShaderCodePlatform {
+    PLATFORMS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(name))
+        .map_or(ShaderCodePlatform::Unknown, |(_, platform)| platform.clone())
+}
+
+fn stage_named(name: &str) -> ShaderStage {
+    STAGES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(name))
+        .map_or(ShaderStage::Unknown, |(_, stage)| stage.clone())
+}
+
+/// File name for a shader's code in the shader directory
+fn code_file_name(pass: &str, variant: usize, stage: &PlatformShaderStage) -> String {
+    let extension = ShaderLanguage::of(&stage.platform).extension();
+    format!("{pass}/{variant}/{}.{}.{extension}", stage.platform_name, stage.stage_name)
+}
+
+/// Describe `material`, read as `version`, as JSON. With `shader_dir`, code is
+/// written to files there instead of into the JSON, `shader_dir_name` being
+/// how the JSON refers to that directory
+pub fn export(
+    material: &CompiledMaterialDefinition,
+    version: MinecraftVersion,
+    shader_dir: Option<(&Path, &str)>,
+) -> anyhow::Result<MaterialJson> {
+    let mut definition = material.clone();
+    for pass in definition.passes.values_mut() {
+        pass.variants.truncate(1);
+        for variant in &mut pass.variants {
+            variant.shader_codes.clear();
+        }
+    }
+    let mut encoded = Vec::new();
+    definition
+        .write(&mut encoded, version)
+        .map_err(|err| anyhow::anyhow!("Error while encoding the material definition: {err}"))?;
+
+    let mut passes = Vec::new();
+    for (pass_name, pass) in &material.passes {
+        let mut variants = Vec::new();
+        for (index, variant) in pass.variants.iter().enumerate() {
+            let mut shaders = Vec::new();
+            for (stage, scode) in &variant.shader_codes {
+                let mut bgfx: BgfxShader = scode
+                    .bgfx_shader_data
+                    .pread(0)
+                    .map_err(|err| anyhow::anyhow!("Shader of {pass_name} doesn't parse: {err}"))?;
+                let code = std::mem::take(&mut bgfx.code);
+                let mut header = Vec::new();
+                bgfx.write(&mut header)?;
+                let mut shader = ShaderJson {
+                    stage: stage.stage_name.clone(),
+                    platform: stage.platform_name.clone(),
+                    source_hash: scode.source_hash,
+                    header: STANDARD.encode(header),
+                    code: None,
+                    code_base64: None,
+                    code_file: None,
+                };
+                if let Some((dir, dir_name)) = shader_dir {
+                    let file_name = code_file_name(pass_name, index, stage);
+                    let path = dir.join(&file_name);
+                    if let Some(parent) = path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::write(&path, &code).with_context(|| format!("Error while writing {}", path.display()))?;
+                    shader.code_file = Some(format!("{dir_name}/{file_name}"));
+                } else {
+                    match String::from_utf8(code) {
+                        Ok(text) => shader.code = Some(text),
+                        Err(err) => shader.code_base64 = Some(STANDARD.encode(err.into_bytes())),
+                    }
+                }
+                shaders.push(shader);
+            }
+            variants.push(VariantJson {
+                supported: variant.is_supported,
+                flags: variant.flags.iter().map(|(flag, value)| (flag.clone(), value.clone())).collect(),
+                shaders,
+            });
+        }
+        passes.push(PassJson {
+            name: pass_name.clone(),
+            variants,
+        });
+    }
+    Ok(MaterialJson {
+        schema: SCHEMA_VERSION,
+        format: version.to_string(),
+        name: material.name.clone(),
+        parent_name: material.parent_name.clone(),
+        definition: STANDARD.encode(encoded),
+        passes,
+    })
+}
+
+/// Rebuild a material from its JSON, `base_dir` being where `code_file` paths
+/// start. Returns the material and the binary version to write it in
+pub fn import(json: MaterialJson, base_dir: &Path) -> anyhow::Result<(CompiledMaterialDefinition, MinecraftVersion)> {
+    anyhow::ensure!(
+        json.schema == SCHEMA_VERSION,
+        "Unsupported schema {}, this version reads schema {SCHEMA_VERSION}",
+        json.schema
+    );
+    let version = materialbin::ALL_VERSIONS
+        .into_iter()
+        .find(|version| version.to_string() == json.format)
+        .with_context(|| format!("Unknown format {:?}", json.format))?;
+    let definition = STANDARD.decode(&json.definition).with_context(|| "Invalid definition")?;
+    let mut material: CompiledMaterialDefinition = definition
+        .pread_with(0, version)
+        .map_err(|err| anyhow::anyhow!("The definition doesn't parse as {version}: {err}"))?;
+    material.name = json.name;
+    material.parent_name = json.parent_name;
+
+    let mut templates = std::mem::take(&mut material.passes);
+    for pass_json in json.passes {
+        let mut pass = templates
+            .swap_remove(&pass_json.name)
+            .with_context(|| format!("Pass {} isn't in the definition, passes can't be added in JSON", pass_json.name))?;
+        let template = pass.variants.first().cloned();
+        pass.variants.clear();
+        for variant_json in pass_json.variants {
+            let mut variant = template
+                .clone()
+                .with_context(|| format!("Pass {} had no variants, so none can be added", pass_json.name))?;
+            variant.is_supported = variant_json.supported;
+            variant.flags = variant_json.flags.into_iter().collect();
+            variant.shader_codes.clear();
+            for shader in variant_json.shaders {
+                let stage = PlatformShaderStage {
+                    stage: stage_named(&shader.stage),
+                    platform: platform_named(&shader.platform),
+                    stage_name: shader.stage,
+                    platform_name: shader.platform,
+                };
+                let code = match (shader.code, shader.code_base64, shader.code_file) {
+                    (Some(code), None, None) => code.into_bytes(),
+                    (None, Some(code), None) => STANDARD.decode(code).with_context(|| "Invalid code_base64")?,
+                    (None, None, Some(file)) => {
+                        let path = base_dir.join(file);
+                        std::fs::read(&path).with_context(|| format!("Error while reading {}", path.display()))?
+                    }
+                    _ => anyhow::bail!(
+                        "Shader {} {} of {} needs exactly one of code, code_base64 and code_file",
+                        stage.platform_name,
+                        stage.stage_name,
+                        pass_json.name
+                    ),
+                };
+                let header = STANDARD.decode(&shader.header).with_context(|| "Invalid shader header")?;
+                let mut bgfx: BgfxShader = header
+                    .pread(0)
+                    .map_err(|err| anyhow::anyhow!("Shader header doesn't parse: {err}"))?;
+                bgfx.code = code;
+                let mut bgfx_shader_data = Vec::new();
+                bgfx.write(&mut bgfx_shader_data)?;
+                variant.shader_codes.insert(
+                    stage,
+                    ShaderCode {
+                        source_hash: shader.source_hash,
+                        bgfx_shader_data,
+                    },
+                );
+            }
+            pass.variants.push(variant);
+        }
+        material.passes.insert(pass_json.name, pass);
+    }
+    Ok((material, version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn material_json(schema: u32, format: &str) -> MaterialJson {
+        MaterialJson {
+            schema,
+            format: format.to_string(),
+            name: "RenderChunk".to_string(),
+            parent_name: None,
+            definition: String::new(),
+            passes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for (name, platform) in PLATFORMS {
+            assert_eq!(&platform_named(&name.to_lowercase()), platform);
+        }
+        for (name, stage) in STAGES {
+            assert_eq!(&stage_named(name), stage);
+        }
+        assert_eq!(platform_named("PS6"), ShaderCodePlatform::Unknown);
+    }
+
+    #[test]
+    fn rejects_other_schemas_and_formats() {
+        let err = import(material_json(SCHEMA_VERSION + 1, "1.21.110"), Path::new(".")).unwrap_err();
+        assert!(err.to_string().starts_with("Unsupported schema"));
+        let err = import(material_json(SCHEMA_VERSION, "0.1"), Path::new(".")).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown format \"0.1\"");
+    }
+}
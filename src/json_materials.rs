@@ -0,0 +1,193 @@
+//! `--json-materials`: the `*.json` files newer packs keep next to their
+//! binary materials, e.g. `renderer/materials/Sky.json`. Those in the format of
+//! [`json`](crate::json) are materials and can be converted like the binary
+//! ones. The game's own files are read with their comments, like the game
+//! does, and only get their version raised to the target
+
+use std::path::Path;
+
+use anyhow::Context;
+use materialbin::{CompiledMaterialDefinition, MinecraftVersion};
+use serde_json::Value;
+
+use crate::{
+    encode_material,
+    json::{self, MaterialJson},
+    migrate_material_layouts,
+    patches::{patch_material, PatchReport},
+    rename::rename_material_definition,
+    rename_material, ConvertOptions, JsonMaterials, MVersion,
+};
+
+/// Whether `name` is a JSON file in a `materials` directory
+pub fn is_json_material(name: &str) -> bool {
+    name.ends_with(".json")
+        && name.rsplit_once('/').is_some_and(|(dir, _)| dir == "materials" || dir.ends_with("/materials"))
+}
+
+/// Entry name of the material.bin `name` is compiled to, `Sky.json` and
+/// `Sky.material.json` both becoming `Sky.material.bin`
+pub fn compiled_name(name: &str) -> String {
+    let stem = name.strip_suffix(".json").unwrap_or(name);
+    let stem = stem.strip_suffix(".material").unwrap_or(stem);
+    format!("{stem}.material.bin")
+}
+
+/// Fields of the game's JSON files holding the version they are written for
+const VERSION_FIELDS: [&str; 2] = ["format_version", "version"];
+
+/// What a JSON file among the materials turned out to be
+pub enum JsonEntry {
+    /// A material, rebuilt, with the binary version it is in
+    Material(Box<CompiledMaterialDefinition>, MinecraftVersion),
+    /// Valid JSON of some other kind, the game's own files among them
+    Other(Value),
+}
+
+/// `text` without its `//` and `/* */` comments, which the game allows in
+/// its JSON files
+fn strip_comments(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            stripped.push(c);
+            match c {
+                '\\' => stripped.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('/', Some('/')) => while chars.next_if(|&c| c != '\n').is_some() {},
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                stripped.push(' ');
+            }
+            _ => {
+                in_string = c == '"';
+                stripped.push(c);
+            }
+        }
+    }
+    stripped
+}
+
+/// `version` as numbers, `None` if it isn't dotted numbers
+fn version_numbers(version: &str) -> Option<Vec<u32>> {
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// The game's JSON file `value` with its version fields raised to `target`,
+/// `None` if it has none older than that
+pub fn bumped(value: &Value, target: &MVersion) -> Option<Vec<u8>> {
+    let target_name = target.to_string();
+    let target_numbers = version_numbers(&target_name)?;
+    let mut value = value.clone();
+    let mut changed = false;
+    for field in VERSION_FIELDS {
+        let Some(version) = value.get_mut(field) else {
+            continue;
+        };
+        if version.as_str().and_then(version_numbers).is_some_and(|numbers| numbers < target_numbers) {
+            *version = Value::String(target_name.clone());
+            changed = true;
+        }
+    }
+    changed.then(|| serde_json::to_vec_pretty(&value).ok()).flatten()
+}
+
+/// Check `data` is JSON, and that it rebuilds if it is a material. Those are
+/// told apart by their `schema` field
+pub fn read(data: &[u8]) -> anyhow::Result<JsonEntry> {
+    let text = std::str::from_utf8(data).context("Not valid JSON")?;
+    let value: Value = serde_json::from_str(&strip_comments(text)).context("Not valid JSON")?;
+    if value.get("schema").is_none() {
+        return Ok(JsonEntry::Other(value));
+    }
+    let material: MaterialJson = serde_json::from_value(value).context("Not a valid material")?;
+    // Would be read from the disk of whoever converts the pack
+    let mut shaders = material.passes.iter().flat_map(|pass| &pass.variants).flat_map(|variant| &variant.shaders);
+    anyhow::ensure!(
+        shaders.all(|shader| shader.code_file.is_none()),
+        "Shader code in files next to the JSON can't be read from inside a pack"
+    );
+    let (material, version) = json::import(material, Path::new(""))?;
+    Ok(JsonEntry::Material(Box::new(material), version))
+}
+
+/// Convert a rebuilt material for `target` like a binary one, returning it in
+/// the form `mode` writes: JSON for [`JsonMaterials::Update`], material.bin
+/// for [`JsonMaterials::Compile`]
+pub fn convert(
+    material: &mut CompiledMaterialDefinition,
+    source: MinecraftVersion,
+    target: &MVersion,
+    options: &ConvertOptions,
+    mode: JsonMaterials,
+) -> anyhow::Result<(Vec<u8>, PatchReport)> {
+    let report = patch_material(material, source, target, &options.patches)?;
+    rename_material(material, target, &options.renames)?;
+    migrate_material_layouts(material, target)?;
+    rename_material_definition(material, &options.material_renames);
+    // Also checks a material only written as JSON is compatible with the target
+    let encoded = encode_material(material, target).map_err(|err| anyhow::anyhow!("Can't be written for {target}: {err}"))?;
+    let converted = match mode {
+        JsonMaterials::Compile => encoded,
+        _ => serde_json::to_vec_pretty(&json::export(material, target.as_version(), None)?)?,
+    };
+    Ok((converted, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_json_materials_apart() {
+        assert!(is_json_material("renderer/materials/Sky.json"));
+        assert!(is_json_material("MyPack/subpacks/high/renderer/materials/Sky.material.json"));
+        assert!(!is_json_material("renderer/materials/Sky.material.bin"));
+        assert!(!is_json_material("manifest.json"));
+        assert!(!is_json_material("texts/materials.json"));
+        assert_eq!(compiled_name("renderer/materials/Sky.json"), "renderer/materials/Sky.material.bin");
+        assert_eq!(compiled_name("renderer/materials/Sky.material.json"), "renderer/materials/Sky.material.bin");
+
+        assert!(matches!(read(br#"{"tint": [1, 0, 0]}"#), Ok(JsonEntry::Other(_))));
+        assert!(read(b"{ \"a\": 1 ").is_err());
+        assert!(read(br#"{"schema": 1, "format": "1.21.110"}"#).is_err());
+        let in_file = br#"{"schema": 1, "format": "1.21.110", "name": "Sky", "parent_name": null, "definition": "",
+            "passes": [{"name": "Opaque", "variants": [{"supported": true, "flags": [], "shaders": [
+            {"stage": "Vertex", "platform": "ESSL_310", "source_hash": 0, "header": "", "code_file": "/etc/passwd"}]}]}]}"#;
+        let err = read(in_file).err().unwrap();
+        assert!(err.to_string().contains("can't be read from inside a pack"));
+    }
+
+    #[test]
+    fn bumps_the_game_files() {
+        let game = br#"// Written by the game
+        {
+            "format_version": "1.21.20", /* raised */
+            "url": "https://example.com/a//b",
+            "version": "27.1"
+        }"#;
+        let Ok(JsonEntry::Other(value)) = read(game) else {
+            panic!("the game's file doesn't read");
+        };
+        assert_eq!(value["url"], "https://example.com/a//b");
+        let raised: Value = serde_json::from_slice(&bumped(&value, &MVersion::V26_10).unwrap()).unwrap();
+        assert_eq!(raised["format_version"], "26.10");
+        assert_eq!(raised["version"], "27.1");
+        let current = serde_json::json!({"format_version": "26.10", "materials": {"version": "1.0.0"}});
+        assert!(bumped(&current, &MVersion::V26_10).is_none());
+    }
+}
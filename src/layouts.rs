@@ -0,0 +1,204 @@
+//! Uniform blocks whose members a game update moved, padded or renamed. Text
+//! patches can't follow those, so converting across the update rewrites the
+//! block declaration in GLSL shaders and the material's uniform tables to the
+//! target's layout
+
+use anyhow::Context;
+use materialbin::{bgfx_shader::BgfxShader, CompiledMaterialDefinition};
+use regex::bytes::Regex;
+use scroll::Pread;
+
+use crate::{
+    patches::ShaderLanguage,
+    rename::{age, replace_identifier},
+    MVersion,
+};
+
+/// A uniform block whose layout changed in a game update
+struct LayoutMigration {
+    /// First version using `after`
+    since: MVersion,
+    block: &'static str,
+    /// Member declarations in order, e.g. "vec4 FogColor"
+    before: &'static [&'static str],
+    after: &'static [&'static str],
+    /// Members renamed by the update, as (name in `before`, name in `after`)
+    renames: &'static [(&'static str, &'static str)],
+}
+
+/// Layout changes of game updates, newest first. Converting to `since` or
+/// newer moves blocks laid out as `before` to `after`, converting to an older
+/// version moves them back. No update is known to have moved a block yet
+const BUILTIN_LAYOUTS: &[LayoutMigration] = &[];
+
+/// One direction of a [`LayoutMigration`]
+struct Migration<'a> {
+    block: &'a str,
+    from: &'a [&'a str],
+    to: &'a [&'a str],
+    renames: Vec<(&'a str, &'a str)>,
+    /// Finds the block declaration, its members in the first group
+    declaration: Regex,
+}
+
+impl<'a> Migration<'a> {
+    fn for_target(layout: &'a LayoutMigration, target: &MVersion) -> Self {
+        let (from, to, renames) = if age(target) <= age(&layout.since) {
+            (layout.before, layout.after, layout.renames.to_vec())
+        } else {
+            (layout.after, layout.before, layout.renames.iter().map(|&(before, after)| (after, before)).collect())
+        };
+        let declaration = Regex::new(&format!(r"\buniform\s+{}\s*\{{([^}}]*)\}}", regex::escape(layout.block)))
+            .expect("valid regex");
+        Self { block: layout.block, from, to, renames, declaration }
+    }
+
+    /// Rewrite the block in `code` if it is laid out as `from`, returns
+    /// whether it was. Blocks in neither layout are left alone with a warning
+    fn migrate_code(&self, code: &mut Vec<u8>) -> bool {
+        let Some(body) = self.declaration.captures(code).and_then(|captures| captures.get(1)) else {
+            return false;
+        };
+        let members: Vec<Vec<u8>> = body
+            .as_bytes()
+            .split(|&c| c == b';')
+            .filter_map(member_name)
+            .collect();
+        if !same_names(&members, self.from) {
+            if !same_names(&members, self.to) {
+                log::warn!("Uniform block {} has a layout of no known version, left as is", self.block);
+            }
+            return false;
+        }
+        let declarations: String = self.to.iter().map(|declaration| format!("\n    {declaration};")).collect();
+        code.splice(body.range(), format!("{declarations}\n").into_bytes());
+        for (from, to) in &self.renames {
+            replace_identifier(code, from.as_bytes(), to.as_bytes());
+        }
+        true
+    }
+
+    /// Rename the migrated members in `entries` and put them in the order of
+    /// `to`, in the slots they took up
+    fn migrate_table<V>(&self, entries: impl IntoIterator<Item = (String, V)>) -> Vec<(String, V)> {
+        let mut entries: Vec<(String, V)> = entries
+            .into_iter()
+            .map(|(name, value)| match self.renames.iter().find(|(from, _)| *from == name) {
+                Some((_, to)) => (to.to_string(), value),
+                None => (name, value),
+            })
+            .collect();
+        let position = |name: &str| {
+            self.to
+                .iter()
+                .position(|declaration| member_name(declaration.as_bytes()).as_deref() == Some(name.as_bytes()))
+        };
+        let mut members: Vec<usize> = (0..entries.len()).filter(|&i| position(&entries[i].0).is_some()).collect();
+        members.sort_by_key(|&i| position(&entries[i].0));
+        let mut taken: Vec<Option<(String, V)>> = entries.drain(..).map(Some).collect();
+        let mut moved: Vec<(String, V)> = members.iter().map(|&i| taken[i].take().expect("slot taken once")).collect();
+        moved.reverse();
+        taken
+            .into_iter()
+            .map(|entry| entry.unwrap_or_else(|| moved.pop().expect("one member per slot")))
+            .collect()
+    }
+}
+
+/// Name declared by a block member declaration like `highp vec4 Fog[2]`
+fn member_name(declaration: &[u8]) -> Option<Vec<u8>> {
+    let declaration = declaration.split(|&c| c == b'[').next()?.trim_ascii();
+    let name = declaration.rsplit(|c| c.is_ascii_whitespace()).next()?;
+    (!name.is_empty()).then(|| name.to_vec())
+}
+
+fn same_names(members: &[Vec<u8>], declarations: &[&str]) -> bool {
+    members.len() == declarations.len()
+        && members
+            .iter()
+            .zip(declarations)
+            .all(|(member, declaration)| member_name(declaration.as_bytes()).as_ref() == Some(member))
+}
+
+/// Move uniform blocks to their layout in `target`, see [`BUILTIN_LAYOUTS`].
+/// Returns whether anything changed
+pub fn migrate_layouts(material: &mut CompiledMaterialDefinition, target: &MVersion) -> anyhow::Result<bool> {
+    migrate_with(material, target, BUILTIN_LAYOUTS)
+}
+
+/// Move the uniform blocks of `layouts` to their layout in `target`. Only
+/// GLSL shaders declare their blocks in text, bytecode ones are left alone
+fn migrate_with(material: &mut CompiledMaterialDefinition, target: &MVersion, layouts: &[LayoutMigration]) -> anyhow::Result<bool> {
+    let mut any_changed = false;
+    for layout in layouts {
+        let migration = Migration::for_target(layout, target);
+        let mut migrated = false;
+        for (pass_name, pass) in material.passes.iter_mut() {
+            for variant in pass.variants.iter_mut() {
+                for (stage, scode) in variant.shader_codes.iter_mut() {
+                    if !ShaderLanguage::of(&stage.platform).is_glsl() {
+                        continue;
+                    }
+                    let mut bgfx: BgfxShader = match scode.bgfx_shader_data.pread(0) {
+                        Ok(s) => s,
+                        Err(_) => continue,
+                    };
+                    if !migration.migrate_code(&mut bgfx.code) {
+                        continue;
+                    }
+                    let uniforms = std::mem::take(&mut bgfx.uniforms);
+                    bgfx.uniforms = migration.migrate_table(uniforms).into_iter().collect();
+                    scode.bgfx_shader_data.clear();
+                    bgfx.write(&mut scode.bgfx_shader_data).with_context(|| {
+                        format!("Error while writing migrated shader of {}/{pass_name}", material.name)
+                    })?;
+                    migrated = true;
+                }
+            }
+        }
+        if migrated {
+            let properties = std::mem::take(&mut material.property_fields);
+            material.property_fields = migration.migrate_table(properties).into_iter().collect();
+            log::info!("{}: moved uniform block {} to the {target} layout", material.name, layout.block);
+            any_changed = true;
+        }
+    }
+    Ok(any_changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: LayoutMigration = LayoutMigration {
+        since: MVersion::V26_10,
+        block: "Fog",
+        before: &["vec4 FogColor", "vec4 FogControl"],
+        after: &["vec4 FogControl", "vec4 Padding", "vec4 FogTint"],
+        renames: &[("FogColor", "FogTint")],
+    };
+
+    #[test]
+    fn migrates_blocks_both_ways() {
+        let mut code = b"uniform Fog { vec4 FogColor; vec4 FogControl; };\nvoid main() { c = FogColor; }".to_vec();
+        let forward = Migration::for_target(&LAYOUT, &MVersion::V26_10);
+        assert!(forward.migrate_code(&mut code));
+        assert_eq!(
+            code,
+            b"uniform Fog {\n    vec4 FogControl;\n    vec4 Padding;\n    vec4 FogTint;\n};\nvoid main() { c = FogTint; }".to_vec()
+        );
+        assert!(!forward.migrate_code(&mut code));
+
+        let back = Migration::for_target(&LAYOUT, &MVersion::V1_21_110);
+        assert!(back.migrate_code(&mut code));
+        assert!(code.starts_with(b"uniform Fog {\n    vec4 FogColor;\n    vec4 FogControl;\n};"));
+    }
+
+    #[test]
+    fn reorders_tables_in_place() {
+        let forward = Migration::for_target(&LAYOUT, &MVersion::V26_10);
+        let table = [("A", 0), ("FogColor", 1), ("B", 2), ("FogControl", 3)].map(|(name, value)| (name.to_string(), value));
+        let names: Vec<String> = forward.migrate_table(table).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["A", "FogControl", "B", "FogTint"]);
+    }
+}
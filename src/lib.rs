@@ -0,0 +1,1741 @@
+use std::{
+    collections::HashMap,
+    io::{BufWriter, Cursor, Read, Seek, SeekFrom, Write},
+    time::Duration,
+};
+
+use anyhow::Context;
+use clap::ValueEnum;
+use materialbin::{CompiledMaterialDefinition, MinecraftVersion, WriteError};
+use scroll::Pread;
+use zip::{
+    write::{ExtendedFileOptions, FileOptions},
+    result::ZipResult,
+    ZipArchive, ZipWriter,
+};
+
+pub mod archive;
+#[cfg(feature = "async")]
+pub mod async_api;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bench;
+pub mod budget;
+pub mod cache;
+pub mod cancel;
+pub mod changelog;
+pub mod debug_pack;
+pub mod dedup;
+pub mod delta;
+pub mod detect;
+pub mod doctor;
+mod error;
+pub mod essl;
+pub mod extract;
+pub mod checksums;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod flags;
+#[cfg(feature = "validate-glsl")]
+mod glsl_check;
+pub mod grep;
+pub mod hooks;
+pub mod in_place;
+pub mod i18n;
+pub mod json;
+pub mod json_materials;
+pub mod layouts;
+pub mod limits;
+pub mod lint;
+pub mod merge;
+pub mod options;
+pub mod patches;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod plugin;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod post_process;
+pub mod preamble;
+pub mod profile;
+pub mod progress;
+pub mod pretty;
+pub mod rename;
+pub mod rules;
+pub mod stamp;
+pub mod stats;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod store;
+pub mod subpack;
+pub mod summary;
+pub mod vanilla;
+pub mod verify;
+pub mod warnings;
+pub mod zip_names;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+use cache::{ConversionCache, EntryStore};
+pub use error::UpdateError;
+pub use options::{ConvertOptions, ConvertOptionsBuilder};
+use layouts::migrate_layouts;
+use hooks::{ArchiveStart, ConversionHooks, MaterialConverted, Message, MessageKind};
+use in_place::FirstDuplicates;
+use json_materials::JsonEntry;
+use limits::{normalize_entry_name, Limits};
+use patches::{patch_material, PatchReport, PatchSelection};
+use rename::{rename_identifiers, rename_material_definition, rename_material_entry, renames_for, NameRename};
+use profile::{EntryTimings, Stopwatch};
+use progress::{format_bytes, Progress};
+use stamp::STAMP_ENTRY;
+use summary::{format_source_versions, EntryResult, EntrySummary};
+use vanilla::Baseline;
+use verify::{check_entry_crc, declared_entries};
+use warnings::{Warning, WarningCode};
+
+#[derive(ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum MVersion {
+    #[value(name = "26.10")]
+    V26_10,
+    #[value(name = "26.0.24")]
+    V26_0_24,
+    #[value(name = "1.21.110")]
+    V1_21_110,
+    #[value(name = "1.21.20")]
+    V1_21_20,
+    #[value(name = "1.20.80")]
+    V1_20_80,
+    #[value(name = "1.19.60")]
+    V1_19_60,
+    #[value(name = "1.18.30")]
+    V1_18_30,
+}
+
+/// What to do with a material that can't be written for the target version
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IncompatStrategy {
+    /// Copy the untouched source material, the pack still loads
+    KeepOriginal,
+    /// Leave the material out of the output
+    #[default]
+    Skip,
+    /// Write it for the newest older version it is compatible with
+    DowngradeNearest,
+}
+
+/// Which of several archive entries with the same name ends up in the output
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateEntries {
+    First,
+    #[default]
+    Last,
+}
+
+/// What happens to the `*.json` files next to a pack's binary materials, see
+/// [`json_materials`]
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JsonMaterials {
+    /// Copy them unchanged
+    #[default]
+    Copy,
+    /// Check they are valid JSON, and that the materials among them rebuild
+    Validate,
+    /// Convert the materials among them for the target, keeping them JSON
+    Update,
+    /// Convert the materials among them for the target and write them as
+    /// material.bin instead
+    Compile,
+}
+
+/// Everything known about a target version
+pub struct VersionInfo {
+    pub version: MVersion,
+    pub name: &'static str,
+    /// Binary format the materials are written in
+    pub binary: MinecraftVersion,
+}
+
+/// Single source of truth for the target versions, newest first
+pub const VERSION_TABLE: &[VersionInfo] = &[
+    VersionInfo {
+        version: MVersion::V26_10,
+        name: "26.10",
+        // No dedicated 26.10 writer yet, the 1.21.110 layout is what the game reads
+        binary: MinecraftVersion::V1_21_110,
+    },
+    VersionInfo {
+        version: MVersion::V26_0_24,
+        name: "26.0.24",
+        binary: MinecraftVersion::V26_0_24,
+    },
+    VersionInfo {
+        version: MVersion::V1_21_110,
+        name: "1.21.110",
+        binary: MinecraftVersion::V1_21_110,
+    },
+    VersionInfo {
+        version: MVersion::V1_21_20,
+        name: "1.21.20",
+        binary: MinecraftVersion::V1_21_20,
+    },
+    VersionInfo {
+        version: MVersion::V1_20_80,
+        name: "1.20.80",
+        binary: MinecraftVersion::V1_20_80,
+    },
+    VersionInfo {
+        version: MVersion::V1_19_60,
+        name: "1.19.60",
+        binary: MinecraftVersion::V1_19_60,
+    },
+    VersionInfo {
+        version: MVersion::V1_18_30,
+        name: "1.18.30",
+        binary: MinecraftVersion::V1_18_30,
+    },
+];
+
+impl std::fmt::Display for MVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.info().name)
+    }
+}
+
+impl MVersion {
+    pub fn info(&self) -> &'static VersionInfo {
+        VERSION_TABLE
+            .iter()
+            .find(|info| info.version == *self)
+            .expect("every version has a VERSION_TABLE entry")
+    }
+
+    pub fn as_version(&self) -> MinecraftVersion {
+        self.info().binary
+    }
+
+    /// Automatic shader fixes applied when converting to this version
+    pub fn fixes(&self) -> Vec<&'static str> {
+        patches::default_patches(self)
+            .map(|patch| patch.description())
+            .collect()
+    }
+
+    /// Newest supported version that a game client of version `game` (e.g. "1.21.111.01") can load
+    pub fn from_game_version(game: &str) -> Option<Self> {
+        let game = version_numbers(game)?;
+        Self::value_variants()
+            .iter()
+            .filter_map(|v| Some((version_numbers(&v.to_string())?, v)))
+            .filter(|(numbers, _)| *numbers <= game)
+            .max_by_key(|(numbers, _)| *numbers)
+            .map(|(_, v)| v.clone())
+    }
+}
+
+/// Built-in names for versions, a config file can override these
+pub const VERSION_ALIASES: &[(&str, MVersion)] = &[
+    ("latest", MVersion::V26_10),
+    ("preview", MVersion::V26_10),
+    ("stable", MVersion::V1_21_110),
+];
+
+impl MVersion {
+    /// Resolve an exact version, a built-in alias, or a prefix like "1.21" / "1.21.x"
+    /// to the newest matching version
+    pub fn resolve(name: &str) -> Option<Self> {
+        let name = name.trim();
+        if let Ok(version) = Self::from_str(name, true) {
+            return Some(version);
+        }
+        if let Some((_, version)) = VERSION_ALIASES
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+        {
+            return Some(version.clone());
+        }
+        let prefix: Vec<&str> = name
+            .trim_end_matches(".x")
+            .split('.')
+            .filter(|part| !part.is_empty())
+            .collect();
+        if prefix.is_empty() {
+            return None;
+        }
+        Self::value_variants()
+            .iter()
+            .filter(|v| {
+                let name = v.to_string();
+                name.split('.').zip(&prefix).all(|(a, b)| a == *b)
+                    && name.split('.').count() >= prefix.len()
+            })
+            .max_by_key(|v| version_numbers(&v.to_string()))
+            .cloned()
+    }
+}
+
+/// Leading major.minor.patch of a version string, missing parts count as 0
+fn version_numbers(version: &str) -> Option<[u32; 3]> {
+    let mut numbers = [0; 3];
+    let mut parts = version.trim().split('.');
+    numbers[0] = parts.next()?.parse().ok()?;
+    for slot in &mut numbers[1..] {
+        match parts.next() {
+            Some(part) => *slot = part.parse().ok()?,
+            None => break,
+        }
+    }
+    Some(numbers)
+}
+
+// INLINE SHADER PATCHES
+pub(crate) fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Outcome of converting an input to one target version
+pub struct ConversionReport {
+    pub version: MVersion,
+    pub translated_materials: usize,
+    pub warnings: Vec<Warning>,
+    /// Shaders a selected patch could not fix
+    pub unfixed_shaders: usize,
+    /// Shaders a selected patch could not edit because they are bytecode or
+    /// another language than it understands
+    pub unpatchable_shaders: usize,
+    /// Every material was already in the target format with all fixes applied
+    pub up_to_date: bool,
+    /// What happened to every material
+    pub entries: Vec<EntrySummary>,
+    /// Other entries changed on purpose, by their normalized input name: the
+    /// stamped manifest, a stamp replaced and the JSON materials converted
+    pub rewritten: Vec<String>,
+}
+
+impl ConversionReport {
+    /// Binary versions the materials were read as, more than one for packs
+    /// assembled from materials of different releases
+    pub fn source_versions(&self) -> Vec<(MinecraftVersion, usize)> {
+        summary::source_versions(&self.entries)
+    }
+}
+
+/// Warnings about the shaders `report`'s patches couldn't fix or edit in `entry`
+fn patch_warnings(report: &PatchReport, entry: Option<&str>) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    for (name, outcome) in &report.outcomes {
+        if outcome.missing != 0 {
+            let message = format!("{name} found nothing to fix in {} shaders", outcome.missing);
+            warnings.push(Warning::new(WarningCode::PatternMissing, entry, message));
+        }
+        if outcome.unsupported != 0 {
+            let message = format!("{name} can't edit {} compiled shaders", outcome.unsupported);
+            warnings.push(Warning::new(WarningCode::UnpatchableShaders, entry, message));
+        }
+    }
+    warnings
+}
+
+/// Pass a line of output to `hooks`, for the command line to print
+fn say(hooks: &[&dyn ConversionHooks], kind: MessageKind, text: impl AsRef<str>) {
+    let message = Message { kind, text: text.as_ref() };
+    for hook in hooks {
+        hook.on_message(&message);
+    }
+}
+
+/// Say what the patches did to a material, shaders that could not be fixed stand out
+fn say_patch_report(hooks: &[&dyn ConversionHooks], report: &PatchReport, target: &MVersion) {
+    for (name, outcome) in &report.outcomes {
+        let kind = if outcome.missing != 0 || outcome.unsupported != 0 { MessageKind::Warning } else { MessageKind::Detail };
+        say(hooks, kind, format!("    {name} for {target}: {outcome}"));
+    }
+}
+
+/// How many warnings there were, by code
+fn say_warning_count(hooks: &[&dyn ConversionHooks], warnings: &[Warning]) {
+    if warnings.is_empty() {
+        return;
+    }
+    let codes: Vec<String> = warnings::count_codes(warnings)
+        .into_iter()
+        .map(|(code, count)| format!("{code} ×{count}"))
+        .collect();
+    say(hooks, MessageKind::Warning, tr!("warnings-count", count = warnings.len(), codes = codes.join(", ")));
+}
+
+fn say_unfixed(hooks: &[&dyn ConversionHooks], unfixed: usize, unpatchable: usize, target: &MVersion) {
+    if unfixed != 0 {
+        say(hooks, MessageKind::Warning, tr!("unfixed-shaders", count = unfixed, target = target));
+    }
+    if unpatchable != 0 {
+        say(hooks, MessageKind::Warning, tr!("unpatchable-shaders", count = unpatchable, target = target));
+    }
+}
+
+/// Whether a material read as `source` needed no changes for `target`
+fn already_converted(source: MinecraftVersion, target: &MVersion, patched: bool) -> bool {
+    !patched && source.to_string() == target.as_version().to_string()
+}
+
+/// Convert the material.bin `input` for `version` into `output`
+pub fn file_update<R, W>(
+    input: &mut R,
+    output: &mut W,
+    version: &MVersion,
+    options: &ConvertOptions,
+) -> Result<ConversionReport, UpdateError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let mut data = Vec::new();
+    options.limits.read_entry("input", input, &mut data)?;
+    file_update_bytes(&data, output, version, options)
+}
+
+/// [`file_update`] for a material.bin that is already in memory, e.g. mapped.
+/// Files holding several concatenated materials (see [`split_materials`]) have
+/// each converted and written one after the other
+pub fn file_update_bytes<W>(
+    data: &[u8],
+    output: &mut W,
+    version: &MVersion,
+    options: &ConvertOptions,
+) -> Result<ConversionReport, UpdateError>
+where
+    W: Write + Seek,
+{
+    let parts = split_materials(data);
+    if let [data] = parts[..] {
+        let report = material_update(data, output, version, options, None)?;
+        report_material(options, &report, data.len() as u64);
+        return Ok(report);
+    }
+    log::info!("The file holds {} concatenated materials", parts.len());
+    let mut combined: Option<ConversionReport> = None;
+    for (index, part) in parts.into_iter().enumerate() {
+        let name = format!("input[{index}]");
+        let report = material_update(part, output, version, options, Some(&name))?;
+        match &mut combined {
+            Some(combined) => {
+                combined.translated_materials += report.translated_materials;
+                combined.warnings.extend(report.warnings);
+                combined.unfixed_shaders += report.unfixed_shaders;
+                combined.unpatchable_shaders += report.unpatchable_shaders;
+                combined.up_to_date &= report.up_to_date;
+                combined.entries.extend(report.entries);
+            }
+            None => combined = Some(report),
+        }
+    }
+    let combined = combined.expect("split_materials gives at least one part");
+    report_material(options, &combined, data.len() as u64);
+    Ok(combined)
+}
+
+/// Tell the hooks of `options` about the conversion of a material.bin of `size`
+/// bytes, done in one go
+fn report_material(options: &ConvertOptions, report: &ConversionReport, size: u64) {
+    let Some(hooks) = options.hooks else {
+        return;
+    };
+    for entry in &report.entries {
+        hooks.on_material_converted(&MaterialConverted { target: &report.version, entry, done_bytes: size, total_bytes: size });
+    }
+    for warning in &report.warnings {
+        hooks.on_warning(&report.version, warning);
+    }
+    hooks.on_finish(report);
+}
+
+/// [`file_update_bytes`] for one material, `entry_name` naming it in the
+/// report when it is part of a larger file
+fn material_update<W>(
+    data: &[u8],
+    output: &mut W,
+    version: &MVersion,
+    options: &ConvertOptions,
+    entry_name: Option<&str>,
+) -> Result<ConversionReport, UpdateError>
+where
+    W: Write + Seek,
+{
+    let ConvertOptions {
+        ref renames,
+        ref patches,
+        on_incompat,
+        ref limits,
+        ref material_renames,
+        hooks,
+        ..
+    } = *options;
+    let hooks = hooks.as_slice();
+    let mut timings = EntryTimings::default();
+    let stopwatch = Stopwatch::start();
+    let (mut material, source) = detect_material(data)?;
+    timings.parse = stopwatch.elapsed();
+    limits.check_material(entry_name.unwrap_or("input"), &material)?;
+    say(hooks, MessageKind::Detail, format!(" [{source}]"));
+    
+    let stopwatch = Stopwatch::start();
+    let report = patch_material(&mut material, source, version, patches)?;
+    say_patch_report(hooks, &report, version);
+    let patched = report.changed
+        | rename_material(&mut material, version, renames)?
+        | migrate_material_layouts(&mut material, version)?
+        | rename_material_definition(&mut material, material_renames);
+    timings.patch = stopwatch.elapsed();
+    let mut warnings = patch_warnings(&report, entry_name);
+    let mut entry = EntrySummary::new(entry_name.unwrap_or("input"), Some(source), EntryResult::Converted);
+    entry.patches = report
+        .outcomes
+        .iter()
+        .filter(|(_, outcome)| outcome.changed() || outcome.missing != 0)
+        .map(|(name, outcome)| (*name, outcome.missing))
+        .collect();
+    let stopwatch = Stopwatch::start();
+    let mut encoded = encode_material(&material, version);
+    if let Err(err) = &encoded {
+        if let Some((retried, retried_source, retried_encoded)) =
+            retry_other_sources(data, source, version, patches, renames, material_renames)
+        {
+            say(hooks, MessageKind::Warning, tr!("read-as-other-source", source = source, error = err, retried = retried_source));
+            let message = format!("read as {retried_source}, {source} couldn't be written ({err})");
+            warnings.push(Warning::new(WarningCode::SourceMisdetected, entry_name, message));
+            material = retried;
+            entry.source = Some(retried_source);
+            encoded = Ok(retried_encoded);
+        }
+    }
+    timings.encode = stopwatch.elapsed();
+    match encoded {
+        Ok(encoded) => {
+            let stopwatch = Stopwatch::start();
+            output.write_all(&encoded)?;
+            timings.write = stopwatch.elapsed();
+            entry.size_delta = encoded.len() as i64 - data.len() as i64;
+        }
+        Err(WriteError::Compat(issue)) => {
+            match on_incompat {
+                IncompatStrategy::KeepOriginal => {
+                    say(hooks, MessageKind::Warning, tr!("incompatible-kept", target = version, issue = issue));
+                    let message = format!("incompatible with {version} ({issue}), kept the original");
+                    warnings.push(Warning::new(WarningCode::CompatKeptOriginal, entry_name, message));
+                    output.write_all(data)?;
+                    entry.result = EntryResult::Copied;
+                }
+                IncompatStrategy::Skip => return Err(UpdateError::from_write(WriteError::Compat(issue), &material.name, version)),
+                IncompatStrategy::DowngradeNearest => match encode_downgraded(&material, version)
+                    .map_err(|err| UpdateError::from_write(err, &material.name, version))?
+                {
+                    Some((encoded, info)) => {
+                        say(hooks, MessageKind::Warning, tr!("incompatible-downgraded", target = version, issue = issue, version = info.name));
+                        let message = format!("incompatible with {version} ({issue}), written as {}", info.name);
+                        warnings.push(Warning::new(WarningCode::CompatDowngraded, entry_name, message));
+                        output.write_all(&encoded)?;
+                        entry.result = EntryResult::Downgraded(info.name);
+                        entry.size_delta = encoded.len() as i64 - data.len() as i64;
+                    }
+                    None => return Err(UpdateError::from_write(WriteError::Compat(issue), &material.name, version)),
+                },
+            }
+        }
+        Err(err) => return Err(UpdateError::from_write(err, &material.name, version)),
+    }
+    entry.timings = timings;
+    say_unfixed(hooks, report.missing(), report.unsupported(), version);
+    say_warning_count(hooks, &warnings);
+    
+    Ok(ConversionReport {
+        version: version.clone(),
+        translated_materials: 1,
+        warnings,
+        unfixed_shaders: report.missing(),
+        unpatchable_shaders: report.unsupported(),
+        up_to_date: already_converted(source, version, patched),
+        entries: vec![entry],
+        rewritten: Vec::new(),
+    })
+}
+
+/// Convert the zip `input` for `version` into `output`, copying what `cache`
+/// already holds converted
+pub fn zip_update<R, W>(
+    input: &mut R,
+    output: &mut W,
+    version: &MVersion,
+    cache: Option<&mut ConversionCache>,
+    options: &ConvertOptions,
+) -> Result<ConversionReport, UpdateError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let target = ZipTarget {
+        version: version.clone(),
+        output,
+        cache,
+        store: None,
+    };
+    let mut reports = zip_update_targets(input, vec![target], options)?;
+    Ok(reports.remove(0))
+}
+
+/// One output archive of [`zip_update_targets`]
+pub struct ZipTarget<'a, W: Write + Seek> {
+    pub version: MVersion,
+    pub output: W,
+    pub cache: Option<&'a mut ConversionCache>,
+    /// Converted entries shared with other runs, consulted after `cache`
+    pub store: Option<&'a dyn EntryStore>,
+}
+
+/// Buffer between the zip writer and the output. Deflate hands over its output
+/// in small pieces, which are slow to write one by one on flash storage
+const OUTPUT_BUFFER: usize = 256 * 1024;
+
+struct TargetState<'a, W: Write + Seek> {
+    version: MVersion,
+    zip: ZipWriter<BufWriter<W>>,
+    cache: Option<&'a mut ConversionCache>,
+    store: Option<&'a dyn EntryStore>,
+    translated_shaders: i32,
+    warnings: Vec<Warning>,
+    unfixed_shaders: usize,
+    unpatchable_shaders: usize,
+    up_to_date: bool,
+    entries: Vec<EntrySummary>,
+    rewritten: Vec<String>,
+    /// Entries and warnings the hooks were told about
+    reported_entries: usize,
+    reported_warnings: usize,
+}
+
+impl<W: Write + Seek> TargetState<'_, W> {
+    /// Log a warning about `entry` with its code and count it
+    fn warn(&mut self, code: WarningCode, entry: &str, message: String) {
+        log::warn!("{}: {entry}: {message}", code.code());
+        self.warnings.push(Warning::new(code, Some(entry), message));
+    }
+
+    /// Tell `hooks` about the entries and warnings added since the last call
+    fn report_new(&mut self, hooks: &[&dyn ConversionHooks], done_bytes: u64, total_bytes: u64) {
+        for entry in &self.entries[self.reported_entries..] {
+            let converted = MaterialConverted { target: &self.version, entry, done_bytes, total_bytes };
+            for hook in hooks {
+                hook.on_material_converted(&converted);
+            }
+        }
+        for warning in &self.warnings[self.reported_warnings..] {
+            for hook in hooks {
+                hook.on_warning(&self.version, warning);
+            }
+        }
+        self.reported_entries = self.entries.len();
+        self.reported_warnings = self.warnings.len();
+    }
+}
+
+/// Name an entry called `name` is written as, `None` if it escapes the pack
+fn output_name(name: &str, options: &ConvertOptions) -> Option<String> {
+    let name = normalize_entry_name(name, options.lowercase_paths)?;
+    Some(rename_material_entry(&name, &options.material_renames).unwrap_or(name))
+}
+
+/// Index of the entry written for every (normalized) name of `names`, the
+/// decoded names of a zip's entries
+pub(crate) fn kept_entries(names: &[String], options: &ConvertOptions) -> HashMap<String, usize> {
+    let mut kept = HashMap::new();
+    for (index, name) in names.iter().enumerate() {
+        let Some(name) = output_name(name, options) else {
+            continue;
+        };
+        match options.duplicates {
+            DuplicateEntries::First => {
+                kept.entry(name).or_insert(index);
+            }
+            DuplicateEntries::Last => {
+                kept.insert(name, index);
+            }
+        }
+    }
+    kept
+}
+
+/// Warning about the entries sharing their name with another one that the zip
+/// reader never sees, `hidden` of them
+fn hidden_duplicates_warning(hooks: &[&dyn ConversionHooks], hidden: u64, dropped_later: usize, duplicates: DuplicateEntries) -> Option<Warning> {
+    if hidden == 0 {
+        return None;
+    }
+    let message = if dropped_later != 0 {
+        say(hooks, MessageKind::Warning, tr!("duplicates-hidden-later", count = hidden));
+        format!("{hidden} entries share their name with an earlier one and were dropped")
+    } else {
+        say(hooks, MessageKind::Warning, tr!("duplicates-hidden", count = hidden));
+        if duplicates == DuplicateEntries::First {
+            say(hooks, MessageKind::Warning, tr!("duplicates-first-unreadable"));
+        }
+        format!("{hidden} entries share their name with a later one and were dropped")
+    };
+    log::warn!("{}: {message}", WarningCode::DuplicateHidden.code());
+    Some(Warning::new(WarningCode::DuplicateHidden, None, message))
+}
+
+/// Convert a zip into one output per target, reading and parsing every
+/// material only once. Of `options`, with `keep_going` materials that can't be
+/// read are copied unchanged instead of failing the whole archive. `renames`
+/// are applied on top of the built-in uniform renames of each target,
+/// `patches` adjusts which fixes run and `on_incompat` decides what happens to
+/// materials that can't be written for a target. Inputs over `limits` fail,
+/// entry names are normalized (lowercased too with `lowercase_paths`) and the
+/// ones that would escape the pack are left out. Of entries that end up with
+/// the same name only the one picked by `duplicates` is kept.
+/// `material_renames` rename material entries and the materials in them. With
+/// `only_subpack`, materials of other subpacks are copied unchanged.
+pub fn zip_update_targets<R, W>(
+    input: &mut R,
+    targets: Vec<ZipTarget<'_, W>>,
+    options: &ConvertOptions,
+) -> Result<Vec<ConversionReport>, UpdateError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let progress = Progress::new();
+    let hooks: Vec<&dyn ConversionHooks> = std::iter::once(&progress as &dyn ConversionHooks).chain(options.hooks).collect();
+    let declared = declared_entries(input)?;
+    let input = FirstDuplicates::new(input, options.duplicates == DuplicateEntries::First)?;
+    let dropped_later = input.dropped();
+    let mut input_zip = ZipArchive::new(input)?;
+    options.limits.check_entries(input_zip.len())?;
+    let entry_names = zip_names::entry_names(&mut input_zip, options.zip_encoding)?;
+    if let Some(encoding) = entry_names.decoded_as {
+        say(&hooks, MessageKind::Detail, tr!("zip-names-decoded", count = entry_names.decoded, encoding = encoding.name()));
+        if entry_names.invalid != 0 {
+            log::warn!("{} entry names aren't valid {}", entry_names.invalid, encoding.name());
+            say(&hooks, MessageKind::Warning, tr!("zip-names-invalid", count = entry_names.invalid, encoding = encoding.name()));
+        }
+    }
+    // Exact duplicates never reach us, the zip reader only keeps the last one
+    // and the first were left out of its central directory with --duplicates first
+    let hidden = declared.map_or(0, |declared| declared.saturating_sub(input_zip.len() as u64));
+    let warnings: Vec<Warning> = hidden_duplicates_warning(&hooks, hidden, dropped_later, options.duplicates).into_iter().collect();
+    let kept = kept_entries(&entry_names.names, options);
+    // Where the stamp goes, and the manifest that gets a line about it
+    let stamp_prefix = if options.stamp { stamp::pack_prefix(kept.keys().map(String::as_str)) } else { None };
+    if options.stamp && stamp_prefix.is_none() {
+        log::info!("The pack has no manifest.json, it isn't stamped");
+    }
+    let mut targets: Vec<_> = targets
+        .into_iter()
+        .map(|target| TargetState {
+            version: target.version,
+            zip: ZipWriter::new(BufWriter::with_capacity(OUTPUT_BUFFER, target.output)),
+            cache: target.cache,
+            store: target.store,
+            translated_shaders: 0,
+            warnings: warnings.clone(),
+            unfixed_shaders: 0,
+            unpatchable_shaders: 0,
+            // Decoded entry names are written in UTF-8, unlike the input, and
+            // hidden duplicates are left out
+            up_to_date: entry_names.decoded == 0 && hidden == 0,
+            entries: Vec::new(),
+            rewritten: Vec::new(),
+            reported_entries: 0,
+            reported_warnings: 0,
+        })
+        .collect();
+    let clock = Stopwatch::start();
+    // Uncompressed sizes of the material entries, which take the conversion time
+    let sizes = (0..input_zip.len())
+        .map(|index| {
+            let file = input_zip.by_index_raw(index)?;
+            Ok(if entry_names.names[index].ends_with(".material.bin") { file.size() } else { 0 })
+        })
+        .collect::<ZipResult<Vec<u64>>>()?;
+    let total = sizes.iter().sum();
+    let versions: Vec<MVersion> = targets.iter().map(|target| target.version.clone()).collect();
+    let start = ArchiveStart { entries: input_zip.len(), material_bytes: total, targets: &versions };
+    for hook in &hooks {
+        hook.on_archive_start(&start);
+    }
+    let mut run = ArchiveRun {
+        options,
+        hooks,
+        zip: input_zip,
+        names: entry_names.names,
+        kept,
+        stamp_name: stamp_prefix.as_ref().map(|prefix| format!("{prefix}{STAMP_ENTRY}")),
+        manifest_name: stamp_prefix.as_ref().map(|prefix| format!("{prefix}manifest.json")),
+        verbose: log::log_enabled!(log::Level::Info),
+        target_count: targets.len(),
+        clock,
+        data: Vec::new(),
+        copied_files: 0,
+        renamed_paths: 0,
+        renamed_materials: 0,
+    };
+    let mut done = 0;
+
+    for (index, size) in sizes.into_iter().enumerate() {
+        for target in &mut targets {
+            target.report_new(&run.hooks, done, total);
+        }
+        done += size;
+        run.entry(index, &mut targets)?;
+    }
+
+    for target in &mut targets {
+        target.report_new(&run.hooks, done, total);
+    }
+    progress.finish();
+    cancel::finished();
+    let elapsed = run.clock.elapsed();
+
+    let reports = targets
+        .into_iter()
+        .map(|target| run.finish_target(target, elapsed))
+        .collect::<Result<Vec<_>, _>>()?;
+    for report in &reports {
+        for hook in &run.hooks {
+            hook.on_finish(report);
+        }
+    }
+    Ok(reports)
+}
+
+/// One archive being converted by `zip_update_targets`, with what its entries share
+struct ArchiveRun<'a, Z: Read + Seek> {
+    options: &'a ConvertOptions<'a>,
+    hooks: Vec<&'a dyn ConversionHooks>,
+    zip: ZipArchive<Z>,
+    /// Decoded names of the entries
+    names: Vec<String>,
+    kept: HashMap<String, usize>,
+    /// Where the stamp goes, and the manifest that gets a line about it
+    stamp_name: Option<String>,
+    manifest_name: Option<String>,
+    /// The per-entry lines are only printed with --verbose, the summary table always
+    verbose: bool,
+    target_count: usize,
+    clock: Stopwatch,
+    /// The entry being converted
+    data: Vec<u8>,
+    copied_files: usize,
+    renamed_paths: usize,
+    renamed_materials: usize,
+}
+
+/// A material entry being converted for every target
+#[derive(Default)]
+struct MaterialEntry {
+    /// Said once what became of the entry is known
+    line: Option<String>,
+    /// Taken by the first target that converts the entry
+    read_time: Option<Duration>,
+    /// Kept for the targets after the one that parsed it
+    parsed: Option<(CompiledMaterialDefinition, MinecraftVersion)>,
+    converted: bool,
+    unreadable: bool,
+    /// Targets it was taken from a cache for
+    cached: Vec<String>,
+}
+
+impl<Z: Read + Seek> ArchiveRun<'_, Z> {
+    /// Write the entry at `index` to every target, converted, copied or left out
+    fn entry<W: Write + Seek>(&mut self, index: usize, targets: &mut [TargetState<'_, W>]) -> Result<(), UpdateError> {
+        let ConvertOptions { lowercase_paths, ref material_renames, ref only_subpack, json_materials, .. } = *self.options;
+        let original_name = self.names[index].clone();
+        cancel::check(&original_name)?;
+        let Some(normalized) = normalize_entry_name(&original_name, lowercase_paths) else {
+            log::warn!("{}: {original_name}: entry name escapes the pack, left out", WarningCode::UnsafePath.code());
+            say(&self.hooks, MessageKind::Warning, tr!("entry-escapes", entry = original_name));
+            for target in targets {
+                target.warnings.push(Warning::new(WarningCode::UnsafePath, Some(&original_name), "name escapes the pack, left out"));
+                target.up_to_date = false;
+            }
+            return Ok(());
+        };
+        let name = rename_material_entry(&normalized, material_renames).unwrap_or_else(|| normalized.clone());
+        if self.kept.get(&name) != Some(&index) {
+            log::warn!("{}: Dropped {original_name}, a duplicate of {name}", WarningCode::DuplicateEntry.code());
+            say(&self.hooks, MessageKind::Warning, tr!("entry-duplicate", entry = original_name, name = name));
+            for target in targets {
+                target.warnings.push(Warning::new(WarningCode::DuplicateEntry, Some(&original_name), format!("a duplicate of {name}, left out")));
+                target.up_to_date = false;
+                if name.ends_with(".material.bin") {
+                    target.entries.push(EntrySummary::new(&name, None, EntryResult::Skipped));
+                }
+            }
+            return Ok(());
+        }
+        if normalized != original_name {
+            log::debug!("{original_name}: written as {normalized}");
+            self.renamed_paths += 1;
+        }
+        if name != normalized {
+            log::info!("{normalized}: renamed to {name}");
+            self.renamed_materials += 1;
+        }
+        if name != original_name {
+            for target in targets.iter_mut() {
+                target.up_to_date = false;
+            }
+        }
+        let other_subpack = only_subpack.as_deref().is_some_and(|tier| subpack::subpack_of(&name).is_some_and(|other| other != tier));
+        if json_materials != JsonMaterials::Copy && !other_subpack && json_materials::is_json_material(&name) && self.json_entry(index, &name, targets)? {
+            return Ok(());
+        }
+        if !name.ends_with(".material.bin") || other_subpack {
+            return self.copy_entry(index, &name, other_subpack, targets);
+        }
+        self.material_entry(index, &name, targets)
+    }
+
+    /// Convert or bump the JSON material `name`, false if it's left to be
+    /// copied like any other file
+    fn json_entry<W: Write + Seek>(&mut self, index: usize, name: &str, targets: &mut [TargetState<'_, W>]) -> Result<bool, UpdateError> {
+        let ConvertOptions { compression_level, ref limits, json_materials, .. } = *self.options;
+        let mut file = self.zip.by_index(index)?;
+        self.data.clear();
+        limits.read_entry(name, &mut file, &mut self.data)?;
+        drop(file);
+        let compiled = json_materials::compiled_name(name);
+        let read = json_materials::read(&self.data).and_then(|entry| match entry {
+            JsonEntry::Material(..) if json_materials == JsonMaterials::Compile && self.kept.contains_key(&compiled) => {
+                anyhow::bail!("{compiled} is already in the pack")
+            }
+            entry => Ok(entry),
+        });
+        match read {
+            Err(err) => {
+                say(&self.hooks, MessageKind::Warning, tr!("json-material-unchanged", entry = name, error = format!("{err:#}")));
+                for target in targets {
+                    target.warn(WarningCode::JsonMaterialUnchanged, name, format!("{err:#}, copied unchanged"));
+                    target.up_to_date = false;
+                }
+                Ok(false)
+            }
+            Ok(JsonEntry::Material(material, source)) if json_materials != JsonMaterials::Validate => {
+                let written = if json_materials == JsonMaterials::Compile { compiled.as_str() } else { name };
+                for target in targets {
+                    target.up_to_date = false;
+                    let mut material = material.clone();
+                    match json_materials::convert(&mut material, source, &target.version, self.options, json_materials) {
+                        Ok((converted, report)) => {
+                            log::info!("{name}: converted for {} as {written}", target.version);
+                            write_entry(&mut target.zip, written, &converted, compression_level)?;
+                            target.rewritten.push(name.to_string());
+                            target.warnings.extend(patch_warnings(&report, Some(name)));
+                            target.translated_shaders += 1;
+                            let mut entry = EntrySummary::new(written, Some(source), EntryResult::Converted);
+                            entry.size_delta = converted.len() as i64 - self.data.len() as i64;
+                            target.entries.push(entry);
+                        }
+                        Err(err) => {
+                            say(&self.hooks, MessageKind::Warning, tr!("json-material-unchanged", entry = name, error = format!("{err:#}")));
+                            target.warn(WarningCode::JsonMaterialUnchanged, name, format!("{err:#}, copied unchanged"));
+                            target.zip.raw_copy_file_rename(self.zip.by_index(index)?, name)?;
+                            target.entries.push(EntrySummary::new(name, Some(source), EntryResult::Copied));
+                        }
+                    }
+                }
+                Ok(true)
+            }
+            Ok(JsonEntry::Other(value)) if matches!(json_materials, JsonMaterials::Update | JsonMaterials::Compile) => {
+                for target in targets {
+                    match json_materials::bumped(&value, &target.version) {
+                        Some(bumped) => {
+                            log::info!("{name}: version raised to {}", target.version);
+                            write_entry(&mut target.zip, name, &bumped, compression_level)?;
+                            target.rewritten.push(name.to_string());
+                            target.up_to_date = false;
+                        }
+                        None => target.zip.raw_copy_file_rename(self.zip.by_index(index)?, name)?,
+                    }
+                }
+                self.copied_files += 1;
+                Ok(true)
+            }
+            // Valid, copied like any other file
+            Ok(_) => Ok(false),
+        }
+    }
+
+    /// Copy `name`, which isn't a material or one of another subpack, to every
+    /// target. The manifest gets a line about the stamp and the old stamp is
+    /// left out
+    fn copy_entry<W: Write + Seek>(&mut self, index: usize, name: &str, other_subpack: bool, targets: &mut [TargetState<'_, W>]) -> Result<(), UpdateError> {
+        let ConvertOptions { compression_level, keep_going, ref limits, .. } = *self.options;
+        // Replaced by the new stamp
+        if self.stamp_name.as_deref() == Some(name) {
+            for target in targets {
+                target.rewritten.push(name.to_string());
+            }
+            return Ok(());
+        }
+        let size = self.zip.by_index_raw(index)?.size();
+        if size > limits.max_entry_size {
+            return Err(UpdateError::LimitExceeded {
+                entry: Some(name.to_string()),
+                limit: "entry size",
+                size,
+                max: limits.max_entry_size,
+            });
+        }
+        // Copied without decompressing, so check the data here
+        if let Err(err) = check_entry_crc(&mut self.zip, index) {
+            if !keep_going {
+                return Err(err);
+            }
+            log::warn!("{}: {err:#}", WarningCode::CorruptEntry.code());
+            say(&self.hooks, MessageKind::Warning, tr!("entry-copied-anyway", error = format!("{err:#}")));
+            for target in targets.iter_mut() {
+                target.warnings.push(Warning::new(WarningCode::CorruptEntry, Some(name), format!("{err:#}, copied anyway")));
+                target.up_to_date = false;
+            }
+        }
+        let manifest = if self.manifest_name.as_deref() == Some(name) {
+            self.data.clear();
+            self.zip.by_index(index)?.read_to_end(&mut self.data)?;
+            Some(self.data.as_slice())
+        } else {
+            None
+        };
+        for target in targets {
+            match manifest.and_then(|manifest| stamp::stamp_manifest(manifest, &target.version)) {
+                Some(stamped) => {
+                    write_entry(&mut target.zip, name, &stamped, compression_level)?;
+                    target.rewritten.push(name.to_string());
+                }
+                None => target.zip.raw_copy_file_rename(self.zip.by_index(index)?, name)?,
+            }
+            if other_subpack && name.ends_with(".material.bin") {
+                target.entries.push(EntrySummary::new(name, None, EntryResult::OtherSubpack));
+            }
+        }
+        if !other_subpack {
+            self.copied_files += 1;
+        }
+        Ok(())
+    }
+
+    /// Read the material `name` once and convert it for every target
+    fn material_entry<W: Write + Seek>(&mut self, index: usize, name: &str, targets: &mut [TargetState<'_, W>]) -> Result<(), UpdateError> {
+        let ConvertOptions { vanilla, ref limits, .. } = *self.options;
+        let mut entry = MaterialEntry { line: self.verbose.then(|| tr!("processing-file", name = name)), ..Default::default() };
+        let stopwatch = Stopwatch::start();
+        let mut file = self.zip.by_index(index)?;
+        self.data.clear();
+        // The claimed size is only a hint, a crafted one must not reserve gigabytes
+        self.data.reserve(usize::try_from(file.size().min(limits.max_entry_size)).unwrap_or(0));
+        limits.read_entry(name, &mut file, &mut self.data)?;
+        drop(file);
+        entry.read_time = Some(stopwatch.elapsed());
+
+        if let Some(vanilla) = vanilla {
+            if let Ok((material, source)) = detect_material(&self.data) {
+                if vanilla.compare(name, &material) == Baseline::Identical {
+                    if let Some(line) = entry.line.take() {
+                        say(&self.hooks, MessageKind::Detail, format!("{line} {}", tr!("tag-vanilla")));
+                    }
+                    for target in targets {
+                        target.up_to_date = false;
+                        target.entries.push(EntrySummary::new(name, Some(source), EntryResult::Vanilla));
+                    }
+                    return Ok(());
+                }
+            }
+        }
+
+        for (position, target) in targets.iter_mut().enumerate() {
+            self.material_for_target(index, name, &mut entry, target, position + 1 == self.target_count)?;
+        }
+        if let Some(line) = entry.line.filter(|_| !entry.converted) {
+            say(&self.hooks, MessageKind::Detail, format!("{line} {}", tr!("tag-cached", targets = entry.cached.join(", "))));
+        }
+        Ok(())
+    }
+
+    /// Write the material `name` for `target`, from a cache if it has it.
+    /// Only the `last` target may take the parsed material
+    fn material_for_target<W: Write + Seek>(
+        &mut self,
+        index: usize,
+        name: &str,
+        entry: &mut MaterialEntry,
+        target: &mut TargetState<'_, W>,
+        last: bool,
+    ) -> Result<(), UpdateError> {
+        let ConvertOptions { compression_level, keep_going, ref limits, .. } = *self.options;
+        let data = &self.data;
+        let target_started = self.clock.elapsed();
+        let mut timings = EntryTimings::default();
+        let mut cache_key = None;
+        if let Some(cache) = target.cache.as_deref_mut() {
+            let key = cache.key(data, &target.version);
+            if cache.copy_cached(name, &key, &mut target.zip)? {
+                entry.cached.push(target.version.to_string());
+                target.translated_shaders += 1;
+                // Says nothing about the input itself
+                target.up_to_date = false;
+                target.entries.push(EntrySummary::new(name, None, EntryResult::Cached));
+                return Ok(());
+            }
+            cache_key = Some(key);
+        }
+        if let Some(encoded) = target.store.and_then(|store| store.load(data, &target.version)) {
+            write_entry(&mut target.zip, name, &encoded, compression_level)?;
+            if let (Some(cache), Some(key)) = (target.cache.as_deref_mut(), cache_key) {
+                cache.record(name, key);
+            }
+            entry.cached.push(target.version.to_string());
+            target.translated_shaders += 1;
+            target.up_to_date = false;
+            let mut summary = EntrySummary::new(name, None, EntryResult::Cached);
+            summary.size_delta = encoded.len() as i64 - data.len() as i64;
+            target.entries.push(summary);
+            return Ok(());
+        }
+
+        if entry.unreadable {
+            target.zip.raw_copy_file_rename(self.zip.by_index(index)?, name)?;
+            target.warnings.push(Warning::new(WarningCode::UnreadableMaterial, Some(name), "invalid for all versions, copied unchanged"));
+            target.up_to_date = false;
+            target.entries.push(EntrySummary::new(name, None, EntryResult::Copied));
+            return Ok(());
+        }
+
+        // Only the last target may take the parsed material, the others get copies
+        let (material, source) = match entry.parsed.take() {
+            Some(material) if last => material,
+            Some(material) => {
+                let copy = material.clone();
+                entry.parsed = Some(material);
+                copy
+            }
+            None => {
+                let stopwatch = Stopwatch::start();
+                let (material, source) = match detect_material(data) {
+                    Ok(material) => material,
+                    Err(_) if keep_going => {
+                        if let Some(line) = entry.line.take() {
+                            say(&self.hooks, MessageKind::Warning, format!("{line} {}", tr!("tag-unreadable")));
+                        }
+                        target.warn(WarningCode::UnreadableMaterial, name, "invalid for all versions, copied unchanged".to_string());
+                        entry.unreadable = true;
+                        entry.converted = true;
+                        target.zip.raw_copy_file_rename(self.zip.by_index(index)?, name)?;
+                        target.up_to_date = false;
+                        target.entries.push(EntrySummary::new(name, None, EntryResult::Copied));
+                        return Ok(());
+                    }
+                    Err(UpdateError::ParseFailed { tried_versions, .. }) => {
+                        return Err(UpdateError::ParseFailed {
+                            material: Some(name.to_string()),
+                            tried_versions,
+                        });
+                    }
+                    Err(err) => return Err(err),
+                };
+                timings.parse = stopwatch.elapsed();
+                limits.check_material(name, &material)?;
+                if let Some(line) = entry.line.take() {
+                    say(&self.hooks, MessageKind::Detail, format!("{line} [{source}]"));
+                }
+                entry.converted = true;
+                if !last {
+                    entry.parsed = Some((material.clone(), source));
+                }
+                (material, source)
+            }
+        };
+        timings.read = entry.read_time.take().unwrap_or_default();
+        timings.started = target_started.saturating_sub(timings.read);
+        if let Some(encoded) = self.write_material(index, name, material, source, timings, target)? {
+            if let Some(store) = target.store {
+                store.save(&self.data, &target.version, &encoded);
+            }
+            if let (Some(cache), Some(key)) = (target.cache.as_deref_mut(), cache_key) {
+                cache.record(name, key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Patch and rename the parsed `material` and write it for `target`,
+    /// falling back on `on_incompat` when it can't be written for it. Returns
+    /// the converted material when it was written as such
+    fn write_material<W: Write + Seek>(
+        &mut self,
+        index: usize,
+        name: &str,
+        mut material: CompiledMaterialDefinition,
+        source: MinecraftVersion,
+        timings: EntryTimings,
+        target: &mut TargetState<'_, W>,
+    ) -> Result<Option<Vec<u8>>, UpdateError> {
+        let ConvertOptions { compression_level, ref renames, ref patches, on_incompat, ref material_renames, .. } = *self.options;
+        let data = &self.data;
+        let mut entry = EntrySummary::new(name, Some(source), EntryResult::Converted);
+        let stopwatch = Stopwatch::start();
+        let patched = patch_material(&mut material, source, &target.version, patches).and_then(|report| {
+            let renamed = rename_material(&mut material, &target.version, renames)?
+                | migrate_material_layouts(&mut material, &target.version)?
+                | rename_material_definition(&mut material, material_renames);
+            Ok((report, renamed))
+        });
+        entry.timings = timings;
+        entry.timings.patch = stopwatch.elapsed();
+        match patched {
+            Ok((report, renamed)) => {
+                if self.verbose {
+                    say_patch_report(&self.hooks, &report, &target.version);
+                }
+                entry.patches = report
+                    .outcomes
+                    .iter()
+                    .filter(|(_, outcome)| outcome.changed() || outcome.missing != 0)
+                    .map(|(name, outcome)| (*name, outcome.missing))
+                    .collect();
+                target.unfixed_shaders += report.missing();
+                target.unpatchable_shaders += report.unsupported();
+                target.warnings.extend(patch_warnings(&report, Some(name)));
+                target.up_to_date &= already_converted(source, &target.version, report.changed | renamed);
+            }
+            Err(err) => {
+                target.warn(WarningCode::PatchFailed, name, format!("{err:#}, copied unchanged"));
+                if self.verbose {
+                    say(&self.hooks, MessageKind::Warning, format!("{}:\n{err:#}", tr!("patch-failed-ignored")));
+                }
+                target.zip.raw_copy_file_rename(self.zip.by_index(index)?, name)?;
+                target.up_to_date = false;
+                entry.result = EntryResult::Failed;
+                target.entries.push(entry);
+                return Ok(None);
+            }
+        }
+
+        // Encoded up front so incompatible materials never leave a started entry behind
+        let stopwatch = Stopwatch::start();
+        let mut encoded = encode_material(&material, &target.version);
+        if let Err(err) = &encoded {
+            if let Some((retried, retried_source, retried_encoded)) =
+                retry_other_sources(data, source, &target.version, patches, renames, material_renames)
+            {
+                let message = format!("can't be written as read for {source} ({err}), read as {retried_source} instead");
+                target.warn(WarningCode::SourceMisdetected, name, message);
+                if self.verbose {
+                    say(&self.hooks, MessageKind::Warning, tr!("read-as-other-source-short", retried = retried_source, source = source));
+                }
+                target.up_to_date = false;
+                material = retried;
+                entry.source = Some(retried_source);
+                encoded = Ok(retried_encoded);
+            }
+        }
+        entry.timings.encode = stopwatch.elapsed();
+        let written = match encoded {
+            Ok(encoded) => {
+                let stopwatch = Stopwatch::start();
+                write_entry(&mut target.zip, name, &encoded, compression_level)?;
+                entry.timings.write = stopwatch.elapsed();
+                target.translated_shaders += 1;
+                entry.size_delta = encoded.len() as i64 - data.len() as i64;
+                Some(encoded)
+            }
+            Err(WriteError::Compat(issue)) => {
+                target.up_to_date = false;
+                let downgraded = match on_incompat {
+                    IncompatStrategy::DowngradeNearest => encode_downgraded(&material, &target.version)
+                        .map_err(|err| UpdateError::from_write(err, name, &target.version))?,
+                    _ => None,
+                };
+                if let Some((encoded, info)) = downgraded {
+                    let message = format!("incompatible with {} ({issue}), written as {}", target.version, info.name);
+                    target.warn(WarningCode::CompatDowngraded, name, message);
+                    write_entry(&mut target.zip, name, &encoded, compression_level)?;
+                    target.translated_shaders += 1;
+                    entry.result = EntryResult::Downgraded(info.name);
+                    entry.size_delta = encoded.len() as i64 - data.len() as i64;
+                } else if on_incompat == IncompatStrategy::KeepOriginal {
+                    target.warn(WarningCode::CompatKeptOriginal, name, format!("kept unchanged because of compatibility error: {issue}"));
+                    target.zip.raw_copy_file_rename(self.zip.by_index(index)?, name)?;
+                    entry.result = EntryResult::Copied;
+                } else {
+                    target.warn(WarningCode::CompatSkip, name, format!("skipped because of compatibility error: {issue}"));
+                    if self.verbose {
+                        say(&self.hooks, MessageKind::Warning, format!("{}:\n{issue}", tr!("compat-ignored")));
+                    }
+                    entry.result = EntryResult::Skipped;
+                    entry.size_delta = -(data.len() as i64);
+                }
+                None
+            }
+            Err(err) => return Err(UpdateError::from_write(err, name, &target.version)),
+        };
+        target.entries.push(entry);
+        Ok(written)
+    }
+
+    /// Write the stamp and finish the output of `target`, then tell the hooks
+    /// what became of it
+    fn finish_target<W: Write + Seek>(&self, mut target: TargetState<'_, W>, elapsed: Duration) -> Result<ConversionReport, UpdateError> {
+        let hooks = &self.hooks;
+        if let Some(stamp_name) = &self.stamp_name {
+            let stamp = stamp::stamp_json(&target.version, &target.entries);
+            write_entry(&mut target.zip, stamp_name, stamp.as_bytes(), self.options.compression_level)?;
+            target.up_to_date = false;
+        }
+        let mut output = target.zip.finish()?;
+        output.flush()?;
+        let output_size = output.get_mut().seek(SeekFrom::End(0))?;
+        if self.target_count > 1 {
+            say(hooks, MessageKind::Heading, tr!("materials-for", target = target.version));
+        }
+        for hook in hooks {
+            hook.on_summary(&target.version, &target.entries);
+        }
+        if self.copied_files != 0 {
+            say(hooks, MessageKind::Detail, tr!("other-files-copied", count = self.copied_files));
+        }
+        if self.renamed_paths != 0 {
+            say(hooks, MessageKind::Detail, tr!("paths-normalized", count = self.renamed_paths));
+        }
+        if self.renamed_materials != 0 {
+            say(hooks, MessageKind::Detail, tr!("materials-renamed", count = self.renamed_materials));
+        }
+        let sources = summary::source_versions(&target.entries);
+        if sources.len() > 1 {
+            say(hooks, MessageKind::Warning, tr!("mixed-sources", versions = format_source_versions(&sources)));
+            let message = format!("materials were compiled for several versions: {}", format_source_versions(&sources));
+            target.warnings.push(Warning::new(WarningCode::MixedSources, None, message));
+        }
+        say_warning_count(hooks, &target.warnings);
+        for warning in &target.warnings[target.reported_warnings..] {
+            for hook in hooks {
+                hook.on_warning(&target.version, warning);
+            }
+        }
+
+        say(hooks, MessageKind::Plain, tr!("ported", count = target.translated_shaders, target = target.version));
+        say_unfixed(hooks, target.unfixed_shaders, target.unpatchable_shaders, &target.version);
+        say(hooks, MessageKind::Detail, tr!("took", seconds = format!("{:.1}", elapsed.as_secs_f64()), size = format_bytes(output_size)));
+        if target.up_to_date {
+            say(hooks, MessageKind::Done, tr!("pack-up-to-date", target = target.version));
+        }
+        Ok(ConversionReport {
+            version: target.version,
+            translated_materials: target.translated_shaders.max(0) as usize,
+            warnings: target.warnings,
+            unfixed_shaders: target.unfixed_shaders,
+            unpatchable_shaders: target.unpatchable_shaders,
+            up_to_date: target.up_to_date,
+            entries: target.entries,
+            rewritten: target.rewritten,
+        })
+    }
+}
+
+/// Add `name` holding `data` to `zip`. The entry is compressed in memory and
+/// copied over complete, which takes the output a couple of writes and seeks
+/// instead of one per deflate block and five to patch up the header
+fn write_entry<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    name: &str,
+    data: &[u8],
+    compression_level: Option<u32>,
+) -> ZipResult<()> {
+    let file_options = FileOptions::<ExtendedFileOptions>::default()
+        .compression_level(compression_level.map(|v| v.into()));
+    let mut entry = ZipWriter::new(Cursor::new(Vec::new()));
+    entry.start_file(name, file_options)?;
+    entry.write_all(data)?;
+    let mut entry = ZipArchive::new(entry.finish()?)?;
+    let compressed = entry.by_index_raw(0)?;
+    zip.raw_copy_file(compressed)
+}
+
+/// Encode `material` for the newest binary format older than `target` that
+/// accepts it, `None` if none does
+fn encode_downgraded(
+    material: &CompiledMaterialDefinition,
+    target: &MVersion,
+) -> Result<Option<(Vec<u8>, &'static VersionInfo)>, WriteError> {
+    let binary = target.as_version().to_string();
+    let older = VERSION_TABLE
+        .iter()
+        .skip_while(|info| info.version != *target)
+        .filter(|info| info.binary.to_string() != binary);
+    for info in older {
+        match encode_material(material, &info.version) {
+            Ok(encoded) => return Ok(Some((encoded, info))),
+            Err(WriteError::Compat(_)) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(None)
+}
+
+/// Serialize `material` for `version` in memory, so compatibility errors
+/// surface before anything reaches the output
+pub fn encode_material(material: &CompiledMaterialDefinition, version: &MVersion) -> Result<Vec<u8>, WriteError> {
+    let mut encoded = Vec::new();
+    material.write(&mut encoded, version.as_version())?;
+    Ok(encoded)
+}
+
+pub fn read_material(data: &[u8]) -> anyhow::Result<CompiledMaterialDefinition> {
+    let parts = split_materials(data);
+    if parts.len() > 1 {
+        log::warn!("The file holds {} concatenated materials, only the first is read", parts.len());
+    }
+    let (material, version) = detect_material(parts[0])?;
+    log::debug!("Read as {version}");
+    Ok(material)
+}
+
+/// Rename identifiers for `target` and report a failure like a patch's
+fn rename_material(
+    material: &mut CompiledMaterialDefinition,
+    target: &MVersion,
+    renames: &[NameRename],
+) -> Result<bool, UpdateError> {
+    rename_identifiers(material, &renames_for(target, renames)).map_err(|source| UpdateError::PatchFailed {
+        patch: "rename-identifiers",
+        material: material.name.clone(),
+        variant: None,
+        source,
+    })
+}
+
+/// Move uniform blocks to their `target` layout and report a failure like a
+/// patch's
+fn migrate_material_layouts(material: &mut CompiledMaterialDefinition, target: &MVersion) -> Result<bool, UpdateError> {
+    migrate_layouts(material, target).map_err(|source| UpdateError::PatchFailed {
+        patch: "uniform-layouts",
+        material: material.name.clone(),
+        variant: None,
+        source,
+    })
+}
+
+/// Whether `data` starts like a material.bin, which names its format right after
+/// the magic number. Only sniffs, the material may still fail to parse
+pub fn looks_like_material(data: &[u8]) -> bool {
+    find_subsequence(&data[..data.len().min(128)], MATERIAL_FORMAT_NAME).is_some()
+}
+
+/// What a material.bin calls its format near its start
+const MATERIAL_FORMAT_NAME: &[u8] = b"RenderDragon.CompiledMaterialDefinition";
+
+/// The material definitions concatenated in `data`, as some tools write them.
+/// A part ends where a parse of it ends right before another material's
+/// header, so a single material, or one followed by anything else, is one part
+pub fn split_materials(data: &[u8]) -> Vec<&[u8]> {
+    let mut parts = Vec::new();
+    let mut rest = data;
+    // Only parsed when another header follows, single materials are the rule
+    while has_second_header(rest) {
+        let Some(length) = material_length(rest) else {
+            break;
+        };
+        parts.push(&rest[..length]);
+        rest = &rest[length..];
+    }
+    parts.push(rest);
+    parts
+}
+
+/// Whether the format name of a material comes up twice in `data`
+fn has_second_header(data: &[u8]) -> bool {
+    find_subsequence(data, MATERIAL_FORMAT_NAME)
+        .is_some_and(|first| find_subsequence(&data[first + MATERIAL_FORMAT_NAME.len()..], MATERIAL_FORMAT_NAME).is_some())
+}
+
+/// Length of the material at the start of `data` if another one follows it
+fn material_length(data: &[u8]) -> Option<usize> {
+    materialbin::ALL_VERSIONS.into_iter().find_map(|version| {
+        let mut consumed = 0;
+        data.gread_with::<CompiledMaterialDefinition>(&mut consumed, version).ok()?;
+        (consumed < data.len() && looks_like_material(&data[consumed..])).then_some(consumed)
+    })
+}
+
+/// Parse `data` with every binary version and keep the most plausible result,
+/// scored by [`detect::parse_score`]. Warns when several versions score the
+/// same, the newest of them is used then
+pub fn detect_material(
+    data: &[u8],
+) -> Result<(CompiledMaterialDefinition, MinecraftVersion), UpdateError> {
+    let mut parses = parse_candidates(data);
+    if parses.is_empty() {
+        return Err(UpdateError::ParseFailed {
+            material: None,
+            tried_versions: materialbin::ALL_VERSIONS.to_vec(),
+        });
+    }
+    let (score, material, version) = parses.remove(0);
+    let tied: Vec<String> = parses
+        .iter()
+        .filter(|(other, ..)| *other == score)
+        .map(|(_, _, other)| other.to_string())
+        .collect();
+    if !tied.is_empty() {
+        log::warn!(
+            "{}: reads equally well as {version} and {}, assuming {version}",
+            material.name,
+            tied.join(", ")
+        );
+    } else if score < detect::MAX_PARSE_SCORE {
+        log::info!("{}: best read as {version}, but only scored {score}", material.name);
+    }
+    Ok((material, version))
+}
+
+/// Every binary version `data` parses as with the [`detect::parse_score`] of
+/// the result, best first
+fn parse_candidates(data: &[u8]) -> Vec<(u32, CompiledMaterialDefinition, MinecraftVersion)> {
+    let mut parses = Vec::new();
+    for version in materialbin::ALL_VERSIONS {
+        let mut consumed = 0;
+        match data.gread_with::<CompiledMaterialDefinition>(&mut consumed, version) {
+            Ok(material) => {
+                let score = detect::parse_score(&material, consumed, data.len());
+                log::info!("Parsed [{version}], scored {score}");
+                parses.push((score, material, version));
+            }
+            Err(e) => {
+                log::info!("Failed [{version}] {}", &e);
+            }
+        }
+    }
+    // Stable, so equally scored versions stay newest first
+    parses.sort_by_key(|(score, ..)| std::cmp::Reverse(*score));
+    parses
+}
+
+/// Convert `data` again, read as one of the other binary versions it parses
+/// as, most plausible first, once the material read as `failed` couldn't be
+/// written for `target`. A wrong version sometimes parses without error and
+/// only fails once written. Returns the material, the version that worked and
+/// the encoded material
+fn retry_other_sources(
+    data: &[u8],
+    failed: MinecraftVersion,
+    target: &MVersion,
+    patches: &PatchSelection,
+    renames: &[NameRename],
+    material_renames: &[NameRename],
+) -> Option<(CompiledMaterialDefinition, MinecraftVersion, Vec<u8>)> {
+    let candidates = parse_candidates(data).into_iter().filter(|(_, _, source)| *source != failed);
+    for (_, mut material, source) in candidates {
+        let converted = patch_material(&mut material, source, target, patches).and_then(|_| {
+            rename_material(&mut material, target, renames)?;
+            migrate_material_layouts(&mut material, target)?;
+            rename_material_definition(&mut material, material_renames);
+            encode_material(&material, target).map_err(|err| UpdateError::from_write(err, &material.name, target))
+        });
+        match converted {
+            Ok(encoded) => return Some((material, source, encoded)),
+            Err(err) => log::info!("Retried as [{source}]: {err:#}"),
+        }
+    }
+    None
+}
+
+/// Binary versions of a material.bin, or of every material in a zip in the
+/// order they first appear. Leaves `input` rewound.
+pub fn detect_source_versions<R: Read + Seek>(input: &mut R) -> anyhow::Result<Vec<MinecraftVersion>> {
+    let mut versions = Vec::new();
+    let mut data = Vec::new();
+    let mut magic = [0; 4];
+    let is_zip = input.read_exact(&mut magic).is_ok() && magic == *b"PK\x03\x04";
+    input.rewind()?;
+    if is_zip {
+        let mut zip = ZipArchive::new(&mut *input)?;
+        for index in 0..zip.len() {
+            let mut file = zip.by_index(index)?;
+            let name = file.name().to_string();
+            if !name.ends_with(".material.bin") {
+                continue;
+            }
+            data.clear();
+            Limits::default().read_entry(&name, &mut file, &mut data)?;
+            if let Ok((_, version)) = detect_material(&data) {
+                if !versions.contains(&version) {
+                    versions.push(version);
+                }
+            }
+        }
+    } else {
+        input.read_to_end(&mut data)?;
+        versions.extend(detect_material(&data).ok().map(|(_, version)| version));
+    }
+    input.rewind()?;
+    Ok(versions)
+}
+
+/// Call `each` with the entry name, material and binary version of every
+/// material in a zip, or of a single material.bin
+pub fn for_each_material<R, F>(input: &mut R, mut each: F) -> anyhow::Result<()>
+where
+    R: Read + Seek,
+    F: FnMut(&str, CompiledMaterialDefinition, MinecraftVersion) -> anyhow::Result<()>,
+{
+    let mut data = Vec::new();
+    let mut magic = [0; 4];
+    let limits = Limits::default();
+    let is_zip = input.read_exact(&mut magic).is_ok() && magic == *b"PK\x03\x04";
+    input.rewind()?;
+    if !is_zip {
+        limits.read_entry("input", input, &mut data)?;
+        let (material, version) = detect_material(&data)?;
+        limits.check_material("input", &material)?;
+        let name = format!("{}.material.bin", material.name);
+        return each(&name, material, version);
+    }
+    let mut zip = ZipArchive::new(input)?;
+    limits.check_entries(zip.len())?;
+    for index in 0..zip.len() {
+        let mut file = zip.by_index(index)?;
+        if !file.name().ends_with(".material.bin") {
+            continue;
+        }
+        let name = file.name().to_string();
+        data.clear();
+        limits.read_entry(&name, &mut file, &mut data)?;
+        let (material, version) =
+            detect_material(&data).with_context(|| format!("Error while reading {name}"))?;
+        limits.check_material(&name, &material)?;
+        each(&name, material, version)?;
+    }
+    Ok(())
+}
+
+/// Convert a zip/mcpack or a single material.bin held in memory, telling them
+/// apart by the zip magic
+pub fn convert_bytes(
+    input: &[u8],
+    version: &MVersion,
+    compression_level: Option<u32>,
+) -> Result<Vec<u8>, UpdateError> {
+    let options = ConvertOptions {
+        compression_level,
+        ..ConvertOptions::default()
+    };
+    convert_bytes_with(input, version, &options).map(|(output, _)| output)
+}
+
+/// [`convert_bytes`] with every option, also returning the report
+pub fn convert_bytes_with(
+    input: &[u8],
+    version: &MVersion,
+    options: &ConvertOptions,
+) -> Result<(Vec<u8>, ConversionReport), UpdateError> {
+    let mut input = Cursor::new(input);
+    let mut output = Cursor::new(Vec::new());
+    let report = if input.get_ref().starts_with(b"PK\x03\x04") {
+        zip_update(&mut input, &mut output, version, None, options)?
+    } else {
+        file_update(&mut input, &mut output, version, options)?
+    };
+    Ok((output.into_inner(), report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_materials() {
+        let mut header = 0xA11DA1A_u64.to_le_bytes().to_vec();
+        header.extend((39_u64).to_le_bytes());
+        header.extend(b"RenderDragon.CompiledMaterialDefinition");
+        assert!(looks_like_material(&header));
+        assert!(!looks_like_material(b"PK\x03\x04"));
+        assert!(!looks_like_material(b""));
+        assert_eq!(split_materials(&header), vec![&header[..]]);
+    }
+
+    #[test]
+    fn every_version_has_one_table_entry() {
+        for version in MVersion::value_variants() {
+            let entries = VERSION_TABLE
+                .iter()
+                .filter(|info| info.version == *version)
+                .count();
+            assert_eq!(entries, 1, "{version:?}");
+        }
+        assert_eq!(VERSION_TABLE.len(), MVersion::value_variants().len());
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for version in MVersion::value_variants() {
+            let name = version.to_string();
+            let value = version.to_possible_value().unwrap();
+            assert_eq!(value.get_name(), name);
+            assert_eq!(MVersion::from_str(&name, false).as_ref(), Ok(version));
+            assert_eq!(MVersion::resolve(&name).as_ref(), Some(version));
+        }
+    }
+
+    #[test]
+    fn binary_formats() {
+        let expected = [
+            (MVersion::V26_10, MinecraftVersion::V1_21_110),
+            (MVersion::V26_0_24, MinecraftVersion::V26_0_24),
+            (MVersion::V1_21_110, MinecraftVersion::V1_21_110),
+            (MVersion::V1_21_20, MinecraftVersion::V1_21_20),
+            (MVersion::V1_20_80, MinecraftVersion::V1_20_80),
+            (MVersion::V1_19_60, MinecraftVersion::V1_19_60),
+            (MVersion::V1_18_30, MinecraftVersion::V1_18_30),
+        ];
+        assert_eq!(expected.len(), MVersion::value_variants().len());
+        for (version, binary) in expected {
+            assert_eq!(version.as_version().to_string(), binary.to_string(), "{version:?}");
+        }
+    }
+
+    #[test]
+    fn binary_formats_are_readable() {
+        for info in VERSION_TABLE {
+            let binary = info.binary.to_string();
+            assert!(
+                materialbin::ALL_VERSIONS
+                    .iter()
+                    .any(|v| v.to_string() == binary),
+                "{} writes {binary}, which can't be read back",
+                info.name
+            );
+        }
+    }
+
+    #[test]
+    fn table_is_newest_first() {
+        let numbers: Vec<_> = VERSION_TABLE
+            .iter()
+            .map(|info| version_numbers(info.name).unwrap())
+            .collect();
+        assert!(numbers.windows(2).all(|pair| pair[0] > pair[1]));
+    }
+
+    #[test]
+    fn resolve_aliases_and_prefixes() {
+        assert_eq!(MVersion::resolve("stable"), Some(MVersion::V1_21_110));
+        assert_eq!(MVersion::resolve("LATEST"), Some(MVersion::V26_10));
+        assert_eq!(MVersion::resolve("1.21"), Some(MVersion::V1_21_110));
+        assert_eq!(MVersion::resolve("1.21.x"), Some(MVersion::V1_21_110));
+        assert_eq!(MVersion::resolve("26"), Some(MVersion::V26_10));
+        assert_eq!(MVersion::resolve("1.21.1"), None);
+        assert_eq!(MVersion::resolve("nonsense"), None);
+    }
+
+    #[test]
+    fn game_versions() {
+        assert_eq!(MVersion::from_game_version("1.21.111.01"), Some(MVersion::V1_21_110));
+        assert_eq!(MVersion::from_game_version("1.21.50"), Some(MVersion::V1_21_20));
+        assert_eq!(MVersion::from_game_version("26.10.20"), Some(MVersion::V26_10));
+        assert_eq!(MVersion::from_game_version("1.16.0"), None);
+    }
+}
@@ -0,0 +1,157 @@
+//! Bounds on what an input may contain, so crafted archives (zip bombs,
+//! millions of entries, entry names escaping the output directory) fail or get
+//! left out instead of exhausting memory or writing outside where they should
+
+use std::{io::Read, path::PathBuf};
+
+use materialbin::CompiledMaterialDefinition;
+
+use crate::UpdateError;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// Largest decompressed size of one archive entry, whatever size it claims
+    pub max_entry_size: u64,
+    /// Most entries an archive may have
+    pub max_entries: usize,
+    /// Largest shader blob in a material
+    pub max_shader_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_entry_size: 256 << 20,
+            max_entries: 65_536,
+            max_shader_size: 16 << 20,
+        }
+    }
+}
+
+impl Limits {
+    pub fn check_entries(&self, entries: usize) -> Result<(), UpdateError> {
+        if entries > self.max_entries {
+            return Err(UpdateError::LimitExceeded {
+                entry: None,
+                limit: "entry count",
+                size: entries as u64,
+                max: self.max_entries as u64,
+            });
+        }
+        Ok(())
+    }
+
+    /// Read `reader` to the end into `data`, failing once it is over the entry
+    /// size limit rather than trusting the size the archive claims
+    pub fn read_entry<R: Read>(&self, name: &str, reader: R, data: &mut Vec<u8>) -> Result<(), UpdateError> {
+        let read = reader.take(self.max_entry_size + 1).read_to_end(data)?;
+        if read as u64 > self.max_entry_size {
+            return Err(UpdateError::LimitExceeded {
+                entry: Some(name.to_string()),
+                limit: "entry size",
+                size: read as u64,
+                max: self.max_entry_size,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn check_material(&self, name: &str, material: &CompiledMaterialDefinition) -> Result<(), UpdateError> {
+        let largest = material
+            .passes
+            .values()
+            .flat_map(|pass| &pass.variants)
+            .flat_map(|variant| variant.shader_codes.values())
+            .map(|scode| scode.bgfx_shader_data.len())
+            .max()
+            .unwrap_or(0);
+        if largest > self.max_shader_size {
+            return Err(UpdateError::LimitExceeded {
+                entry: Some(name.to_string()),
+                limit: "shader size",
+                size: largest as u64,
+                max: self.max_shader_size as u64,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// `name` with forward slashes and without a drive letter, leading slashes or
+/// `.` components, lowercased if `lowercase`. `None` for names with `..` or
+/// nothing left, which would escape the pack. The game's zip reader on Android
+/// only understands the normalized form
+pub fn normalize_entry_name(name: &str, lowercase: bool) -> Option<String> {
+    // Archives made on Windows may use either separator
+    let name = name.replace('\\', "/");
+    let bytes = name.as_bytes();
+    let name = match bytes {
+        [drive, b':', ..] if drive.is_ascii_alphabetic() => &name[2..],
+        _ => &name,
+    };
+    let mut parts = Vec::new();
+    for part in name.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => return None,
+            part => parts.push(part),
+        }
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    let mut normalized = parts.join("/");
+    if name.ends_with('/') {
+        normalized.push('/');
+    }
+    if lowercase {
+        normalized = normalized.to_lowercase();
+    }
+    Some(normalized)
+}
+
+/// `name` as a relative path that stays inside the directory it is extracted
+/// to, see [`normalize_entry_name`]
+pub fn safe_entry_path(name: &str) -> Option<PathBuf> {
+    normalize_entry_name(name, false).map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_entry_names() {
+        let normalized = |name| normalize_entry_name(name, false);
+        assert_eq!(normalized("renderer/materials/a.material.bin").as_deref(), Some("renderer/materials/a.material.bin"));
+        assert_eq!(normalized("renderer\\materials\\a.material.bin").as_deref(), Some("renderer/materials/a.material.bin"));
+        assert_eq!(normalized("./a/./b").as_deref(), Some("a/b"));
+        assert_eq!(normalized("/textures//a.png").as_deref(), Some("textures/a.png"));
+        assert_eq!(normalized("C:\\pack\\subpacks\\").as_deref(), Some("pack/subpacks/"));
+        assert_eq!(normalize_entry_name("Renderer/Materials/A.material.bin", true).as_deref(), Some("renderer/materials/a.material.bin"));
+    }
+
+    #[test]
+    fn rejects_escaping_entry_names() {
+        assert_eq!(safe_entry_path("../a"), None);
+        assert_eq!(safe_entry_path("a/../../b"), None);
+        assert_eq!(safe_entry_path("..\\a"), None);
+        assert_eq!(safe_entry_path("/"), None);
+        assert_eq!(safe_entry_path(""), None);
+    }
+
+    #[test]
+    fn stops_reading_over_the_size_limit() {
+        let limits = Limits {
+            max_entry_size: 4,
+            ..Limits::default()
+        };
+        let mut data = Vec::new();
+        assert!(limits.read_entry("a", &b"1234"[..], &mut data).is_ok());
+        data.clear();
+        assert!(matches!(
+            limits.read_entry("a", &b"12345678"[..], &mut data),
+            Err(UpdateError::LimitExceeded { size: 5, .. })
+        ));
+    }
+}
@@ -0,0 +1,212 @@
+//! Structural checks of materials, for defects that parse fine but crash the
+//! game, run by `validate` and `doctor`
+
+use std::{collections::HashSet, fmt, str::FromStr};
+
+use materialbin::{bgfx_shader::BgfxShader, pass::ShaderStage, CompiledMaterialDefinition};
+use scroll::Pread;
+
+/// A structural defect. The codes never change meaning and new ones are only
+/// added at the end
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LintCode {
+    /// A variant has a vertex shader for a platform but no fragment shader
+    MissingFragment,
+    /// Two variants of a pass have the same flags, the game picks either
+    DuplicateFlags,
+    /// A shader holds no code
+    EmptyShader,
+    /// A pass falls back to a pass the material doesn't have
+    MissingFallback,
+}
+
+impl LintCode {
+    pub const ALL: [Self; 4] = [Self::MissingFragment, Self::DuplicateFlags, Self::EmptyShader, Self::MissingFallback];
+
+    /// `L001` and so on
+    pub fn code(self) -> String {
+        let number = Self::ALL.iter().position(|code| *code == self).unwrap_or_default() + 1;
+        format!("L{number:03}")
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::MissingFragment => "missing-fragment",
+            Self::DuplicateFlags => "duplicate-flags",
+            Self::EmptyShader => "empty-shader",
+            Self::MissingFallback => "missing-fallback",
+        }
+    }
+
+    /// How bad it is unless `--lint` says otherwise
+    pub fn default_level(self) -> LintLevel {
+        match self {
+            Self::DuplicateFlags => LintLevel::Warn,
+            Self::MissingFragment | Self::EmptyShader | Self::MissingFallback => LintLevel::Deny,
+        }
+    }
+
+    /// What to do about it
+    pub fn suggestion(self) -> &'static str {
+        match self {
+            Self::MissingFragment => "The game crashes when it picks the variant, rebuild the material with both stages",
+            Self::DuplicateFlags => "Only one of the variants is ever used, remove the other",
+            Self::EmptyShader => "The game crashes compiling it, take the material from the pack's original download again",
+            Self::MissingFallback => "The game crashes on devices that need the fallback, fix the pass's fallback or add the pass",
+        }
+    }
+}
+
+/// `L001 missing-fragment`
+impl fmt::Display for LintCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.name())
+    }
+}
+
+/// Either the code (`L001`, case-insensitive) or the name (`missing-fragment`)
+impl FromStr for LintCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|code| code.code().eq_ignore_ascii_case(s) || code.name() == s)
+            .ok_or_else(|| format!("Unknown lint {s:?}, the lints are {}", Self::ALL.map(|code| code.to_string()).join(", ")))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintLevel {
+    /// Not reported
+    Allow,
+    /// Reported without failing
+    Warn,
+    /// Reported as a problem that fails the check
+    Deny,
+}
+
+impl FromStr for LintLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "allow" => Ok(Self::Allow),
+            "warn" => Ok(Self::Warn),
+            "deny" => Ok(Self::Deny),
+            _ => Err(format!("Unknown lint level {s:?}, use allow, warn or deny")),
+        }
+    }
+}
+
+/// Levels of the lints, the defaults with `--lint` overrides
+#[derive(Clone, Default)]
+pub struct LintLevels {
+    overrides: Vec<(LintCode, LintLevel)>,
+}
+
+impl LintLevels {
+    /// Levels from `--lint LEVEL CODE` pairs, later ones win
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut levels = Self::default();
+        for pair in args.chunks(2) {
+            let [level, code] = pair else {
+                return Err("--lint takes a level and a code, e.g. --lint deny L002".to_string());
+            };
+            levels.overrides.push((code.parse()?, level.parse()?));
+        }
+        Ok(levels)
+    }
+
+    pub fn level(&self, code: LintCode) -> LintLevel {
+        self.overrides
+            .iter()
+            .rev()
+            .find(|(overridden, _)| *overridden == code)
+            .map_or(code.default_level(), |(_, level)| *level)
+    }
+}
+
+/// A defect found in a material
+#[derive(Clone, Debug)]
+pub struct Lint {
+    pub code: LintCode,
+    pub level: LintLevel,
+    /// Where in the material, e.g. `Transparent [Fancy=On]`
+    pub location: String,
+    pub message: String,
+}
+
+/// Check `material` for structural defects, leaving out the allowed ones
+pub fn lint_material(material: &CompiledMaterialDefinition, levels: &LintLevels) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let mut report = |code: LintCode, location: String, message: String| {
+        let level = levels.level(code);
+        if level != LintLevel::Allow {
+            lints.push(Lint {
+                code,
+                level,
+                location,
+                message,
+            });
+        }
+    };
+    for (pass_name, pass) in &material.passes {
+        if !pass.fallback.is_empty() && !material.passes.contains_key(&pass.fallback) {
+            report(
+                LintCode::MissingFallback,
+                pass_name.clone(),
+                format!("falls back to pass {}, which the material doesn't have", pass.fallback),
+            );
+        }
+        let mut flag_sets = HashSet::new();
+        for variant in &pass.variants {
+            let mut flags: Vec<String> = variant.flags.iter().map(|(flag, value)| format!("{flag}={value}")).collect();
+            flags.sort();
+            let location = format!("{pass_name} [{}]", flags.join(", "));
+            if !flag_sets.insert(flags) {
+                report(LintCode::DuplicateFlags, location.clone(), "another variant has the same flags".to_string());
+            }
+            for (stage, code) in &variant.shader_codes {
+                let empty = code.bgfx_shader_data.is_empty()
+                    || code.bgfx_shader_data.pread::<BgfxShader>(0).is_ok_and(|shader| shader.code.iter().all(|&byte| byte == 0));
+                if empty {
+                    report(
+                        LintCode::EmptyShader,
+                        location.clone(),
+                        format!("{:?} {:?} shader has no code", stage.platform, stage.stage),
+                    );
+                }
+                let has_fragment = variant
+                    .shader_codes
+                    .keys()
+                    .any(|other| other.platform == stage.platform && other.stage == ShaderStage::Fragment);
+                if stage.stage == ShaderStage::Vertex && !has_fragment {
+                    report(
+                        LintCode::MissingFragment,
+                        location.clone(),
+                        format!("has a {:?} vertex shader but no fragment shader", stage.platform),
+                    );
+                }
+            }
+        }
+    }
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_and_codes() {
+        assert_eq!("L002".parse::<LintCode>(), Ok(LintCode::DuplicateFlags));
+        assert_eq!("empty-shader".parse::<LintCode>(), Ok(LintCode::EmptyShader));
+        let levels = LintLevels::from_args(&["deny".into(), "L002".into(), "allow".into(), "missing-fallback".into()]).unwrap();
+        assert_eq!(levels.level(LintCode::DuplicateFlags), LintLevel::Deny);
+        assert_eq!(levels.level(LintCode::MissingFallback), LintLevel::Allow);
+        assert_eq!(levels.level(LintCode::EmptyShader), LintLevel::Deny);
+        assert!(LintLevels::from_args(&["deny".into()]).is_err());
+        assert!(LintLevels::from_args(&["L002".into(), "deny".into()]).is_err());
+    }
+}
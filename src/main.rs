@@ -1,387 +1,2801 @@
 use std::{
-    fs::File,
-    io::{self, BufReader, Read, Seek, Write},
+    collections::HashSet,
+    ffi::{OsStr, OsString},
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, Cursor, IsTerminal, Seek, SeekFrom, Write},
+    panic::{self, AssertUnwindSafe},
     path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+    time::Instant,
+};
+
+mod client;
+mod config;
+mod disk;
+mod download;
+mod history;
+mod install;
+#[cfg(feature = "gui")]
+mod gui;
+mod interactive;
+#[cfg(windows)]
+mod register;
+mod run_summary;
+mod temp;
+mod terminal;
+
+use anyhow::Context;
+use clap::{
+    builder::{
+        styling::{AnsiColor, Style},
+        BoolishValueParser,
+        Styles,
+    },
+    ArgAction, Args, CommandFactory, Parser, Subcommand, ValueEnum,
+};
+use clap_complete::Shell;
+use config::{Config, Preset};
+use install::PackFolder;
+use log::LevelFilter;
+use material_updater::{
+    archive::{self, ARCHIVE_SUFFIXES},
+    bench, budget, dedup,
+    extract,
+    limits::Limits,
+    lint::{LintLevel, LintLevels},
+    cache::{ConversionCache, EntryStore},
+    cancel, changelog,
+    in_place,
+    profile,
+    progress,
+    debug_pack, delta,
+    checksums::{archive_checksums, file_checksums, sha256_reader, Checksums},
+    detect_source_versions, file_update_bytes, split_materials,
+    detect,
+    doctor::{self, Severity},
+    flags::{self, FlagEdit},
+    detect_material,
+    essl::{AddEsslVariants, EsslVersion},
+    for_each_material, grep, i18n, json, merge,
+    patches::{self, MinifyShaders, Patch, PatchSelection, PATCHES},
+    plugin::PluginPatch,
+    post_process::PostProcess,
+    rules::{self, PatchFile},
+    pretty::pretty_glsl,
+    rename::NameRename,
+    stats,
+    store::ZstdStore,
+    subpack,
+    summary::{format_source_versions, EntrySummary},
+    vanilla::{self, Baseline, VanillaMaterials},
+    verify::{validate_pack, verify_archive, verify_material},
+    warnings::WarningCode,
+    zip_names::ZipEncoding,
+    tr, zip_update_targets, ConversionReport, ConvertOptions, DuplicateEntries, IncompatStrategy, JsonMaterials, MVersion, ZipTarget,
+    VERSION_TABLE,
 };
+use materialbin::bgfx_shader::BgfxShader;
+use memmap2::Mmap;
+use owo_colors::OwoColorize;
+use run_summary::{BenchRecord, InputRecord, OutputRecord, ResolvedTarget, RunSummary};
+use scroll::Pread;
+use temp::Scratch;
+use tempfile::{NamedTempFile, SpooledTempFile};
+
+#[derive(Parser)]
+#[clap(name = "Material Updater", version = "0.1.13")]
+#[command(version, about, long_about = None, styles = get_style())]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Options {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Convert without a subcommand, same as `convert`
+    #[command(flatten)]
+    convert: ConvertArgs,
+
+    /// Increase log verbosity (-v info, -vv debug, -vvv trace)
+    #[clap(short, long, global = true, action = ArgAction::Count, env = "MATERIAL_UPDATER_VERBOSE")]
+    verbose: u8,
+
+    /// Only log errors
+    #[clap(short, long, global = true, conflicts_with = "verbose", value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_QUIET")]
+    quiet: bool,
+
+    /// Write log records to this file instead of stderr
+    #[arg(long, global = true, env = "MATERIAL_UPDATER_LOG_FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Config file to use instead of the per-user and per-project material-updater.toml
+    #[arg(long, global = true, env = "MATERIAL_UPDATER_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Convert up to this many inputs and targets at once, each as its own task,
+    /// and patch the variants of a material on this many threads. 0 runs one
+    /// per CPU
+    #[arg(short = 'j', long, global = true, visible_alias = "jobs", default_value_t = 1, env = "MATERIAL_UPDATER_THREADS")]
+    threads: usize,
+
+    /// Put temporary files in this directory instead of the system's
+    #[arg(long, global = true, value_name = "DIR", conflicts_with = "no_temp", env = "MATERIAL_UPDATER_TEMPDIR")]
+    tempdir: Option<PathBuf>,
+
+    /// Keep inputs, outputs and scratch data in memory instead of temporary
+    /// files, for small inputs or when no directory is writable
+    #[arg(long, global = true, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_NO_TEMP")]
+    no_temp: bool,
+
+    /// Language of the messages (e.g. es or pt_BR), by default that of LC_ALL,
+    /// LC_MESSAGES or LANG
+    #[arg(long, global = true, value_name = "LANG", env = "MATERIAL_UPDATER_LANG")]
+    lang: Option<String>,
+
+    /// Print a man page generated from these options and exit
+    #[arg(long, hide = true, exclusive = true)]
+    generate_man: bool,
+}
+
+// What to convert and how, taken by `convert` and by the bare command line. A
+// doc comment here would replace the about text of the whole program
+#[derive(Args)]
+struct ConvertArgs {
+    /// Shader packs (.mcpack, .zip, .7z, .tar.gz), material.bins or http(s) urls to update
+    #[clap(required_unless_present = "from_stdin_name")]
+    files: Vec<OsString>,
+
+    /// Read the input from stdin, treating it as a file with this name (e.g. pack.mcpack)
+    #[arg(long, value_name = "NAME", conflicts_with = "files")]
+    from_stdin_name: Option<OsString>,
+
+    /// Write the entries that changed into the input .mcpack or .zip itself
+    /// instead of a new output, backed up first unless --no-history is given.
+    /// Much faster for large packs where few materials change
+    #[arg(long, conflicts_with_all = ["output", "yeet", "cache", "emit_delta", "emit_debug_pack", "verify_output"], value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_IN_PLACE")]
+    in_place: bool,
+
+    /// Treat inputs as material.bins whatever their name, e.g. Sky.material.bin.bak.
+    /// Without it, inputs that aren't named like a pack or material are checked
+    /// for the material format
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_ASSUME_MATERIAL")]
+    assume_material: bool,
+
+    /// Write each material of a material.bin holding several concatenated ones
+    /// to its own output, named after the material. Without it they are
+    /// converted and concatenated again
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_SPLIT")]
+    split: bool,
+
+    /// Output zip compression level
+    #[clap(short, long, env = "MATERIAL_UPDATER_ZIP_COMPRESSION")]
+    zip_compression: Option<u32>,
+
+    /// Process the file, but dont write anything
+    #[clap(short, long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_YEET")]
+    yeet: bool,
+
+    /// Output version: a version (1.21.110), a prefix (1.21 or 1.21.x), an alias
+    /// (latest, preview, stable) or "auto" to match the installed Minecraft.
+    /// Repeat it or give a comma separated list to write one output per version
+    #[clap(short, long, value_delimiter = ',', env = "MATERIAL_UPDATER_TARGET_VERSION")]
+    target_version: Vec<String>,
+
+    /// Output path
+    #[arg(short, long, env = "MATERIAL_UPDATER_OUTPUT")]
+    output: Option<PathBuf>,
+
+    /// Take the target version, patches, compression and naming from a preset
+    /// (see list-presets), options given here win
+    #[arg(long, value_name = "NAME", env = "MATERIAL_UPDATER_PRESET")]
+    preset: Option<String>,
+
+    /// Name for outputs when --output is not given [default: {stem}_{target}{ext}].
+    /// Placeholders: {stem}, {ext}, {target}, {source} (binary version of the input,
+    /// "mixed" if its materials differ), {date}
+    #[arg(long, env = "MATERIAL_UPDATER_OUTPUT_TEMPLATE")]
+    output_template: Option<String>,
+
+    /// Copy materials that did not change since the last run from the previous output
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_CACHE")]
+    cache: bool,
+
+    /// Don't use --cache even though the config file turns it on
+    #[arg(long, conflicts_with = "cache", value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_NO_CACHE")]
+    no_cache: bool,
+
+    /// Keep converted materials in this directory and reuse them whenever the
+    /// same material is converted with the same settings again, by any run or
+    /// machine sharing the directory
+    #[arg(long, value_name = "DIR", env = "MATERIAL_UPDATER_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Copy materials that can't be read unchanged instead of aborting
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_KEEP_GOING")]
+    keep_going: bool,
+
+    /// What to do with materials that can't be written for the target version
+    #[arg(long, value_enum, default_value_t, env = "MATERIAL_UPDATER_ON_INCOMPAT")]
+    on_incompat: IncompatStrategy,
+
+    /// Don't write outputs for targets the input is already converted to
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_SKIP_UP_TO_DATE")]
+    skip_up_to_date: bool,
+
+    /// Write <output>.checksums.json with the SHA-256 of every output entry
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_EMIT_CHECKSUMS")]
+    emit_checksums: bool,
+
+    /// Record the tool version, source and target versions and applied patches
+    /// in the pack, as material_updater.json next to its manifest and a line at
+    /// the end of the manifest's description
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_STAMP")]
+    stamp: bool,
+
+    /// Print how long parsing, patching, encoding and writing took, in total
+    /// and for the slowest materials
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_PROFILE")]
+    profile: bool,
+
+    /// Also write the timings of --profile as Chrome trace events, which
+    /// chrome://tracing, Perfetto and speedscope show as a flame chart
+    #[arg(long, value_name = "PATH", env = "MATERIAL_UPDATER_PROFILE_JSON")]
+    profile_json: Option<PathBuf>,
+
+    /// Also write a zip of only the entries the conversion changed, for
+    /// publishing an update to the original pack (see `apply-delta`)
+    #[arg(long, value_name = "PATH", env = "MATERIAL_UPDATER_EMIT_DELTA")]
+    emit_delta: Option<PathBuf>,
+
+    /// Also write a pack for A/B testing the patches in game: patched
+    /// materials are kept unchanged, with the patched version next to them as
+    /// `.patched.material.bin` and in a subpack to switch to
+    #[arg(long, value_name = "PATH", env = "MATERIAL_UPDATER_EMIT_DEBUG_PACK")]
+    emit_debug_pack: Option<PathBuf>,
+
+    /// Also extract the converted pack into the game's
+    /// development_resource_packs folder, or resource_packs with
+    /// `--install resource`, so it needn't be imported. With --yeet it only
+    /// says where it would go
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "development", value_name = "FOLDER", env = "MATERIAL_UPDATER_INSTALL")]
+    install: Option<PackFolder>,
+
+    /// The game's com.mojang directory for --install, found automatically
+    /// if not given
+    #[arg(long, value_name = "DIR", env = "MATERIAL_UPDATER_INSTALL_DIR")]
+    install_dir: Option<PathBuf>,
+
+    /// Overwrite output files that already exist (implied by --cache, which
+    /// updates its previous outputs)
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_FORCE")]
+    force: bool,
+
+    /// Reopen every written output and check that all entries read back intact
+    /// and unchanged entries still match the input
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_VERIFY_OUTPUT")]
+    verify_output: bool,
+
+    /// Report whether each output fits this size (e.g. 500MB) and what takes the
+    /// most space when it doesn't, as Marketplace and mobile imports reject
+    /// bigger packs
+    #[arg(long, value_parser = parse_size, value_name = "SIZE", env = "MATERIAL_UPDATER_SIZE_BUDGET")]
+    size_budget: Option<u64>,
+
+    /// Fail instead of writing outputs over --size-budget
+    #[arg(long, requires = "size_budget", value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_STRICT")]
+    strict: bool,
+
+    /// List the shaders each output material stores more than once, largest
+    /// first, to see which variants could be collapsed
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_DEDUP_REPORT")]
+    dedup_report: bool,
+
+    /// Leave out materials that are the same as vanilla, see `compare-vanilla`
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_STRIP_VANILLA")]
+    strip_vanilla: bool,
+
+    /// Vanilla materials for --strip-vanilla, see `compare-vanilla --vanilla`.
+    /// Defaults to `vanilla_materials` from the config file
+    #[arg(long, requires = "strip_vanilla", env = "MATERIAL_UPDATER_VANILLA")]
+    vanilla: Option<OsString>,
+
+    /// Set from the global --threads
+    #[arg(skip)]
+    jobs: usize,
+
+    /// Don't record the conversion or back up replaced outputs for `rollback`
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_NO_HISTORY")]
+    no_history: bool,
+
+    /// Write a JSON record of the run: inputs, resolved targets, what happened
+    /// to every entry, patch counts, warnings, durations and output hashes
+    #[arg(long, value_name = "FILE", env = "MATERIAL_UPDATER_SUMMARY_FILE")]
+    summary_file: Option<PathBuf>,
+
+    /// Keep outputs in memory up to this size (e.g. 256M) before spooling them to
+    /// temporary files, by default outputs always go to temporary files
+    #[arg(long, value_parser = parse_size, env = "MATERIAL_UPDATER_MAX_MEMORY")]
+    max_memory: Option<u64>,
+
+    /// Lowercase entry paths in the output, besides always writing them with forward
+    /// slashes and without drive letters or leading slashes
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_NORMALIZE_PATHS")]
+    normalize_paths: bool,
+
+    /// Fail on packs whose materials were compiled for different versions, which
+    /// usually means the pack was put together wrong
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_REQUIRE_UNIFORM_SOURCE")]
+    require_uniform_source: bool,
+
+    /// Fail instead of writing outputs with this warning, by code or name (e.g.
+    /// W002 or pattern-missing), see `list-warnings`. Can be repeated
+    #[arg(long, value_delimiter = ',', value_name = "CODE", env = "MATERIAL_UPDATER_DENY")]
+    deny: Vec<WarningCode>,
+
+    /// Which entry to keep when several have the same path
+    #[arg(long, value_enum, default_value_t, env = "MATERIAL_UPDATER_DUPLICATES")]
+    duplicates: DuplicateEntries,
+
+    /// What to do with the `*.json` files next to the binary materials (e.g.
+    /// renderer/materials/Sky.json): copy them, check them, convert the materials
+    /// among them and keep them JSON, or compile those to material.bin
+    #[arg(long, value_enum, default_value_t, value_name = "MODE", env = "MATERIAL_UPDATER_JSON_MATERIALS")]
+    json_materials: JsonMaterials,
+
+    /// What entry names are in when the zip doesn't say they are UTF-8, e.g.
+    /// gbk or shift-jis for packs zipped on Chinese or Japanese Windows. They are
+    /// written as UTF-8
+    #[arg(long, value_enum, default_value_t, value_name = "ENCODING", env = "MATERIAL_UPDATER_ZIP_ENCODING")]
+    zip_encoding: ZipEncoding,
+
+    /// Only convert the materials of this subpack (e.g. high) besides the main
+    /// pack's, the other subpacks are copied unchanged
+    #[arg(long, value_name = "TIER", env = "MATERIAL_UPDATER_ONLY_SUBPACK")]
+    only_subpack: Option<String>,
+
+    /// Make a single-tier pack: move the files of this subpack (e.g. high) to the
+    /// main pack, drop the other subpacks and their manifest declarations, then
+    /// convert
+    #[arg(long, value_name = "TIER", conflicts_with = "only_subpack", env = "MATERIAL_UPDATER_FLATTEN_SUBPACK")]
+    flatten_subpack: Option<String>,
+
+    /// Refuse inputs with an entry that decompresses to more than this (e.g. 64M)
+    /// [default: 256M]
+    #[arg(long, value_parser = parse_size, value_name = "SIZE", env = "MATERIAL_UPDATER_MAX_ENTRY_SIZE")]
+    max_entry_size: Option<u64>,
+
+    /// Refuse archives with more entries than this [default: 65536]
+    #[arg(long, value_name = "COUNT", env = "MATERIAL_UPDATER_MAX_ENTRIES")]
+    max_entries: Option<usize>,
+
+    /// Refuse materials with a shader larger than this (e.g. 4M) [default: 16M]
+    #[arg(long, value_parser = parse_size, value_name = "SIZE", env = "MATERIAL_UPDATER_MAX_SHADER_SIZE")]
+    max_shader_size: Option<u64>,
+
+    /// Rename a uniform or sampler (OLD=NEW) in the material and its shader code,
+    /// on top of the renames built in for the target version. Can be repeated
+    #[arg(long, value_name = "OLD=NEW", env = "MATERIAL_UPDATER_RENAME_UNIFORM")]
+    rename_uniform: Vec<NameRename>,
+
+    /// Rename a material (OLD=NEW), both its name and its file in the pack. Can be repeated
+    #[arg(long, value_name = "OLD=NEW", env = "MATERIAL_UPDATER_RENAME")]
+    rename: Vec<NameRename>,
+
+    /// Run a patch even when the versions would not select it (see list-patches)
+    #[arg(long, value_delimiter = ',', value_name = "PATCH", env = "MATERIAL_UPDATER_ENABLE_PATCH")]
+    enable_patch: Vec<String>,
+
+    /// Don't run a patch (see list-patches)
+    #[arg(long, value_delimiter = ',', value_name = "PATCH", env = "MATERIAL_UPDATER_DISABLE_PATCH")]
+    disable_patch: Vec<String>,
+
+    /// Strip comments and whitespace from GLSL shaders to make the output smaller
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_MINIFY_SHADERS")]
+    minify_shaders: bool,
+
+    /// Run a shell command on every shader before repacking, e.g. an optimizer.
+    /// {shader_file} is replaced by a file holding the shader, which the command may rewrite
+    #[arg(long, value_name = "COMMAND", env = "MATERIAL_UPDATER_POST_PROCESS")]
+    post_process: Option<String>,
+
+    /// Give variants that only ship the other ESSL version shaders of this one,
+    /// for devices that need it
+    #[arg(long, value_name = "VERSION", env = "MATERIAL_UPDATER_ADD_ESSL")]
+    add_essl: Option<EsslVersion>,
+
+    /// Only apply the lightmap fixes to materials matching these globs, e.g.
+    /// "RenderChunk*,MyChunk" (`*` matches anything, `?` one character)
+    #[arg(long, value_delimiter = ',', value_name = "GLOBS", env = "MATERIAL_UPDATER_LIGHTMAP_MATERIALS")]
+    lightmap_materials: Vec<String>,
+
+    /// Undo patches found in the input that are not selected for the target,
+    /// e.g. the 26.10 lightmap fix when converting back to 1.21.110
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_STRIP_CONFLICTING_PATCHES")]
+    strip_conflicting_patches: bool,
+
+    /// Load a patch plugin (a dynamic library implementing
+    /// include/material_updater_plugin.h), only use plugins you trust. Can be repeated
+    #[arg(long, env = "MATERIAL_UPDATER_PLUGIN")]
+    plugin: Vec<PathBuf>,
+
+    /// Load regex rewrite rules from a TOML patch file ([[rule]] tables with name,
+    /// pattern, replacement and optional material, expect, expect_min, expect_max)
+    #[arg(long, env = "MATERIAL_UPDATER_PATCH_FILE")]
+    patch_file: Vec<PathBuf>,
+
+    /// Print every replacement made by patch file rules, without writing outputs
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "MATERIAL_UPDATER_PREVIEW_RULES")]
+    preview_rules: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Update shader packs or material.bins, what runs when no subcommand is given
+    Convert(Box<ConvertArgs>),
+    /// List supported target versions, their binary format and the fixes applied
+    ListVersions,
+    /// List the built-in patches and the target versions they run for
+    ListPatches,
+    /// List the warning codes, for --deny and allowlists in automation
+    ListWarnings,
+    /// List the presets --preset accepts, built in and from the config file
+    ListPresets,
+    /// Explain what a game version changed for shader packs, what converting
+    /// fixes and what it can't
+    Explain {
+        /// Target version, e.g. 1.21.110, 26.10 or stable
+        version: String,
+    },
+    /// Show what the materials in a pack are made of and which shaders take the most space
+    Stats {
+        /// Shader pack, material.bin or http(s) url to inspect
+        pack: OsString,
+
+        /// How many of the largest shaders to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Search the code of every shader in a pack for some text
+    Grep {
+        /// Shader pack, material.bin or http(s) url to search
+        pack: OsString,
+
+        /// Text to look for, e.g. v_lightmapUV
+        pattern: String,
+
+        /// Ignore ASCII case when matching
+        #[arg(short, long)]
+        ignore_case: bool,
+    },
+    /// List the passes and variants of every material with their flags
+    Inspect {
+        /// Shader pack, material.bin or http(s) url to inspect
+        pack: OsString,
+
+        /// Only show variants with this flag value (KEY=VALUE), can be repeated
+        #[arg(long, value_parser = parse_flag_filter)]
+        flag: Vec<(String, String)>,
+
+        /// Print the code of every shader, re-indented for reading
+        #[arg(long)]
+        code: bool,
+
+        /// With --code, print the code as stored instead of re-indenting it
+        #[arg(long, requires = "code")]
+        raw: bool,
+    },
+    /// Check that every entry of a pack reads back intact and every material parses
+    Validate {
+        /// Shader pack, material.bin or http(s) url to check
+        pack: OsString,
+
+        #[command(flatten)]
+        lint: LintArgs,
+    },
+    /// Tell which game version a pack is built for, from the format of its
+    /// materials and the fixes they already have
+    Detect {
+        /// Shader pack, material.bin or http(s) url to look at
+        pack: OsString,
+    },
+    /// Look for the usual reasons a pack doesn't work and suggest fixes
+    Doctor {
+        /// Shader pack, material.bin or http(s) url to check
+        pack: OsString,
+
+        /// Also check against converting to this version (same values as convert -t)
+        #[clap(short, long)]
+        target_version: Option<String>,
+
+        #[command(flatten)]
+        lint: LintArgs,
+    },
+    /// Set or remove variant flags, keeping each material in its binary version
+    EditFlags {
+        /// Shader pack or material.bin to edit
+        pack: OsString,
+
+        /// KEY=VALUE sets the flag on every variant that has it, KEY alone removes it
+        #[arg(required = true)]
+        edits: Vec<FlagEdit>,
+
+        /// Output path
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Output zip compression level
+        #[clap(short, long)]
+        zip_compression: Option<u32>,
+
+        /// Overwrite the output if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show which materials of a pack differ from vanilla and which are unmodified copies
+    CompareVanilla {
+        /// Shader pack, material.bin or http(s) url to compare
+        pack: OsString,
+
+        /// Vanilla materials of the pack's game version: a directory like the game's
+        /// data/renderer/materials, a zip of them or an http(s) url to one.
+        /// Defaults to `vanilla_materials` from the config file
+        #[arg(long)]
+        vanilla: Option<OsString>,
+    },
+    /// Copy one material out of a pack, e.g. to convert or edit it on its own
+    Extract {
+        /// Entry to extract: its path in the pack, or a file name only one entry has
+        material: String,
+
+        /// Shader pack or http(s) url to extract from
+        #[arg(long)]
+        from: OsString,
+
+        /// Output file, or directory to write it to under its own name
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+
+        /// Overwrite the output if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Put a material back into a pack, replacing the entry with the same name
+    Inject {
+        /// material.bin to put in the pack
+        material: PathBuf,
+
+        /// Shader pack to inject into
+        #[arg(long)]
+        into: OsString,
+
+        /// Entry to replace, by default the one named like the material file
+        #[arg(long)]
+        entry: Option<String>,
+
+        /// Output path
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Output zip compression level
+        #[clap(short, long)]
+        zip_compression: Option<u32>,
+
+        /// Overwrite the output if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Write a material.bin as JSON: its passes, variants, flags and shader code
+    ExportJson {
+        /// material.bin to export
+        material: PathBuf,
+
+        /// Output JSON file
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Write shader code to files in a directory next to the JSON instead of into it
+        #[arg(long)]
+        split_shaders: bool,
+
+        /// Overwrite the output if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Rebuild a material.bin from JSON written by `export-json`
+    ImportJson {
+        /// JSON file to import
+        json: PathBuf,
+
+        /// Output path
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Overwrite the output if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Rebuild a converted pack from the original and a delta written by --emit-delta
+    ApplyDelta {
+        /// The pack the delta was made from
+        original: OsString,
+
+        /// Delta zip
+        delta: OsString,
+
+        /// Output path
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Overwrite the output if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Merge the passes and variants of one material.bin into another, the overlay wins on conflicts
+    Merge {
+        /// Material the result starts from, e.g. a vanilla dump
+        base: PathBuf,
+
+        /// Material whose passes and variants replace or add to the base's
+        overlay: PathBuf,
+
+        /// Output path
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Overwrite the output if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print a shell completion script, e.g. `material-updater completions bash > /etc/bash_completion.d/material-updater`
+    Completions {
+        shell: Shell,
+    },
+    /// Add "Update shaders…" to the right-click menu of .mcpack files in Explorer
+    #[cfg(windows)]
+    Register {
+        /// Target version the menu entry converts to
+        #[arg(short, long, default_value = "auto")]
+        target_version: String,
+    },
+    /// Remove the menu entry added by `register`
+    #[cfg(windows)]
+    Unregister,
+    /// List or remove the resource packs installed in the game, e.g. by --install
+    Installed {
+        #[command(subcommand)]
+        action: InstalledAction,
+
+        /// The game's com.mojang directory, found automatically if not given
+        #[arg(long, global = true, value_name = "DIR")]
+        install_dir: Option<PathBuf>,
+    },
+    /// Undo the latest conversion that wrote an output, restoring the file it replaced
+    Rollback {
+        /// Output of the conversion to undo
+        output: PathBuf,
+    },
+    /// Open a window to pick packs, the target version and patches
+    #[cfg(feature = "gui")]
+    Gui,
+    /// Time parsing, patching, writing and whole conversions of packs on this
+    /// device, for reports about slow conversions
+    #[command(hide = true)]
+    Bench {
+        /// Shader packs or material.bins to time
+        #[arg(required = true)]
+        packs: Vec<PathBuf>,
+
+        /// Target version (same values as convert -t)
+        #[arg(short, long, default_value = "stable")]
+        target_version: String,
+
+        /// How many times to run every phase
+        #[arg(long, default_value_t = 5)]
+        iterations: usize,
+
+        /// Where to write the timings, in the --summary-file format
+        #[arg(long, value_name = "FILE", default_value = "bench-summary.json")]
+        summary_file: PathBuf,
+    },
+}
+
+#[derive(Args)]
+struct LintArgs {
+    /// Report a structural lint as a problem, a warning or not at all, e.g.
+    /// `--lint deny L002`. Levels are allow, warn and deny, lints L001
+    /// missing-fragment, L002 duplicate-flags, L003 empty-shader and L004
+    /// missing-fallback. Can be repeated
+    #[arg(long, num_args = 2, value_names = ["LEVEL", "CODE"])]
+    lint: Vec<String>,
+}
+
+impl LintArgs {
+    fn levels(&self) -> anyhow::Result<LintLevels> {
+        LintLevels::from_args(&self.lint).map_err(anyhow::Error::msg)
+    }
+}
+
+#[derive(Subcommand)]
+enum InstalledAction {
+    /// List the installed packs with their UUID, version and the binary
+    /// version of their materials
+    List,
+    /// Remove installed packs, by directory name or manifest UUID
+    Remove {
+        /// Directory name or UUID of the pack
+        pack: String,
+
+        /// Remove every pack with the UUID, when several have it. Converted
+        /// copies keep the UUID of the original
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+/// The command line definition, named like the binary for completions and man pages
+fn cli() -> clap::Command {
+    Options::command().name(env!("CARGO_BIN_NAME"))
+}
+
+fn list_versions() {
+    println!(
+        "{:<10} {:<16} {}",
+        "Version".bold(),
+        "Binary format".bold(),
+        "Automatic fixes".bold()
+    );
+    for info in VERSION_TABLE {
+        println!(
+            "{:<10} {:<16} {}",
+            info.name.cyan(),
+            info.binary.to_string(),
+            info.version.fixes().join(", ")
+        );
+    }
+    let readable: Vec<String> = materialbin::ALL_VERSIONS
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    println!("\nReadable binary formats: {}", readable.join(", "));
+}
+
+fn print_stats(pack: OsString, top: usize) -> anyhow::Result<()> {
+    let (_, mut input) = open_input(pack, &Limits::default())?;
+    let stats = stats::pack_stats(&mut input)?;
+    println!(
+        "{:<48} {:>6} {:>8} {:>8} {:>12}",
+        "Material".bold(),
+        "Passes".bold(),
+        "Variants".bold(),
+        "Shaders".bold(),
+        "Code bytes".bold()
+    );
+    for material in &stats.materials {
+        println!(
+            "{:<48} {:>6} {:>8} {:>8} {:>12}",
+            material.entry.cyan(),
+            material.passes,
+            material.variants,
+            material.shaders,
+            material.code_bytes
+        );
+    }
+    println!(
+        "\n{} materials, {} shader code bytes",
+        stats.materials.len(),
+        stats.code_bytes()
+    );
+    for (title, breakdown) in [("Platform", &stats.by_platform), ("Stage", &stats.by_stage)] {
+        println!("\n{:<16} {:>8} {:>12}", title.bold(), "Shaders".bold(), "Code bytes".bold());
+        for (name, part) in breakdown {
+            println!("{:<16} {:>8} {:>12}", name, part.shaders, part.bytes);
+        }
+    }
+    if top > 0 && !stats.shaders.is_empty() {
+        println!("\n{}", "Largest shaders".bold());
+        for shader in stats.shaders.iter().take(top) {
+            println!(
+                "{:>10}  {} {} #{} {} {}",
+                shader.bytes,
+                shader.entry.cyan(),
+                shader.pass,
+                shader.variant,
+                shader.platform,
+                shader.stage
+            );
+        }
+    }
+    Ok(())
+}
+
+fn export_json(material: &Path, output: &Path, split_shaders: bool, force: bool) -> anyhow::Result<()> {
+    disk::check_overwrite(output, force)?;
+    let data = fs::read(material).with_context(|| format!("Error while reading {}", material.display()))?;
+    let (material, version) = detect_material(&data)?;
+    let shaders_dir_name = format!(
+        "{}_shaders",
+        output.file_stem().unwrap_or_default().to_string_lossy()
+    );
+    let shaders_dir = output.with_file_name(&shaders_dir_name);
+    let exported = json::export(&material, version, split_shaders.then_some((shaders_dir.as_path(), shaders_dir_name.as_str())))?;
+    let text = serde_json::to_vec_pretty(&exported)?;
+    disk::write_output(output, text.as_slice(), text.len() as u64)?;
+    println!("Exported {} ({version}) to {}", material.name.cyan(), output.display());
+    if split_shaders {
+        println!("Shader code is in {}", shaders_dir.display());
+    }
+    Ok(())
+}
+
+fn import_json(path: &Path, output: &Path, force: bool) -> anyhow::Result<()> {
+    disk::check_overwrite(output, force)?;
+    let text = fs::read(path).with_context(|| format!("Error while reading {}", path.display()))?;
+    let parsed: json::MaterialJson =
+        serde_json::from_slice(&text).with_context(|| format!("{} isn't valid material JSON", path.display()))?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let (material, version) = json::import(parsed, base_dir)?;
+    let mut data = Vec::new();
+    material
+        .write(&mut data, version)
+        .map_err(|err| anyhow::anyhow!("Error while writing {}: {err}", material.name))?;
+    disk::write_output(output, data.as_slice(), data.len() as u64)?;
+    println!("Rebuilt {} ({version}) as {}", material.name.cyan(), output.display());
+    Ok(())
+}
+
+fn validate(pack: OsString, levels: &LintLevels) -> anyhow::Result<()> {
+    let (_, mut input) = open_input(pack, &Limits::default())?;
+    let validation = validate_pack(&mut input, levels)?;
+    for (entry, problem) in &validation.problems {
+        println!("{} {entry}: {problem}", "Broken".red());
+    }
+    for (entry, lint) in &validation.lints {
+        let level = match lint.level {
+            LintLevel::Deny => "Defect".red().to_string(),
+            _ => "Warning".yellow().to_string(),
+        };
+        println!("{level} {entry}: {} {} {}", lint.location, lint.message, lint.code.to_string().dimmed());
+        println!("  {}", lint.code.suggestion().dimmed());
+    }
+    println!(
+        "Checked {} entries, {} materials",
+        validation.entries.to_string().cyan(),
+        validation.materials.to_string().cyan()
+    );
+    anyhow::ensure!(
+        validation.problems.is_empty(),
+        "{} broken entries",
+        validation.problems.len()
+    );
+    let denied = validation.lints.iter().filter(|(_, lint)| lint.level == LintLevel::Deny).count();
+    anyhow::ensure!(denied == 0, "{denied} structural defects, see --lint");
+    println!("{}", "Pack is valid".green());
+    Ok(())
+}
+
+fn detect(pack: OsString) -> anyhow::Result<()> {
+    let (_, mut input) = open_input(pack, &Limits::default())?;
+    let materials = detect::detect_materials(&mut input)?;
+    anyhow::ensure!(!materials.is_empty(), "No materials found");
+    for material in &materials {
+        let version = material.version.map_or("unreadable".to_string(), |version| version.to_string());
+        let fixes = if material.fixes.is_empty() {
+            "no known fixes".to_string()
+        } else {
+            material.fixes.join(", ")
+        };
+        println!("{}  {}  {}", material.entry.cyan(), version, fixes.dimmed());
+    }
+    match detect::likely_versions(&materials).as_slice() {
+        [] => println!("{}", "No material could be read, the version is unknown".red()),
+        [version] => println!("Built for {}", version.to_string().green()),
+        [version, others @ ..] => println!(
+            "Most likely built for {}, possibly {}",
+            version.to_string().green(),
+            others.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+        ),
+    }
+    Ok(())
+}
+
+fn doctor(pack: OsString, target: Option<&MVersion>, levels: &LintLevels) -> anyhow::Result<()> {
+    let (_, mut input) = open_input(pack, &Limits::default())?;
+    let findings = doctor::diagnose(&mut input, target, levels)?;
+    for finding in &findings {
+        match finding.severity {
+            Severity::Problem => println!("{} {}", "Problem".red(), finding.message),
+            Severity::Warning => println!("{} {}", "Warning".yellow(), finding.message),
+        }
+        println!("  {}", finding.suggestion.dimmed());
+    }
+    let problems = findings.iter().filter(|finding| finding.severity == Severity::Problem).count();
+    anyhow::ensure!(problems == 0, "{problems} problems found");
+    if findings.is_empty() {
+        println!("{}", "No problems found".green());
+    }
+    Ok(())
+}
+
+fn list_installed(game_dir: &Path) -> anyhow::Result<()> {
+    let packs = install::installed_packs(game_dir)?;
+    if packs.is_empty() {
+        println!("No packs installed in {}", game_dir.display());
+        return Ok(());
+    }
+    for pack in &packs {
+        let dir = pack.path.file_name().unwrap_or_default().to_string_lossy();
+        let sources = match pack.sources.as_slice() {
+            [] => "no materials".dimmed().to_string(),
+            sources => format_source_versions(sources),
+        };
+        println!(
+            "{} {} {} {}  {}  {}",
+            pack.folder.dir_name().dimmed(),
+            dir.cyan(),
+            pack.name,
+            pack.version,
+            pack.uuid.dimmed(),
+            sources
+        );
+    }
+    Ok(())
+}
+
+/// Remove the installed packs named `pack` or with UUID `pack`. Refuses when
+/// several have the UUID, unless `all` is set
+fn remove_installed(game_dir: &Path, pack: &str, all: bool) -> anyhow::Result<()> {
+    let packs = install::installed_packs(game_dir)?;
+    let named: Vec<_> = packs.iter().filter(|found| found.path.file_name().is_some_and(|name| name == pack)).collect();
+    let matching = if named.is_empty() {
+        packs.iter().filter(|found| found.uuid.eq_ignore_ascii_case(pack)).collect()
+    } else {
+        named
+    };
+    anyhow::ensure!(!matching.is_empty(), "No installed pack is named {pack} or has it as UUID, see `installed list`");
+    if matching.len() > 1 && !all {
+        let paths: Vec<String> = matching.iter().map(|found| found.path.display().to_string()).collect();
+        anyhow::bail!(
+            "{} packs have UUID {pack}: {}. Remove one by directory name or pass --all",
+            matching.len(),
+            paths.join(", ")
+        );
+    }
+    for found in matching {
+        fs::remove_dir_all(&found.path).with_context(|| format!("Error while removing {}", found.path.display()))?;
+        println!("Removed {}", found.path.display().to_string().cyan());
+    }
+    Ok(())
+}
+
+/// Read vanilla materials from a directory of material.bins, a pack or a url
+fn load_vanilla(source: OsString) -> anyhow::Result<VanillaMaterials> {
+    let mut vanilla = VanillaMaterials::new();
+    let dir = Path::new(&source);
+    if dir.is_dir() {
+        for entry in fs::read_dir(dir).with_context(|| format!("Error while reading {}", dir.display()))? {
+            let path = entry?.path();
+            if !path.to_string_lossy().ends_with(".material.bin") {
+                continue;
+            }
+            let (material, _) = detect_material(&fs::read(&path)?)
+                .with_context(|| format!("Error while reading {}", path.display()))?;
+            vanilla.add(&path.to_string_lossy(), &material);
+        }
+    } else {
+        let (_, mut input) = open_input(source.clone(), &Limits::default())?;
+        vanilla.add_pack(&mut input)?;
+    }
+    anyhow::ensure!(!vanilla.is_empty(), "No materials in {}", source.to_string_lossy());
+    Ok(vanilla)
+}
+
+fn compare_vanilla(pack: OsString, vanilla: OsString) -> anyhow::Result<()> {
+    let vanilla = load_vanilla(vanilla)?;
+    let (_, mut input) = open_input(pack, &Limits::default())?;
+    let results = vanilla::compare_pack(&mut input, &vanilla)?;
+    let (mut modified, mut identical, mut custom) = (0, 0, 0);
+    for (entry, baseline) in &results {
+        let status = match baseline {
+            Baseline::Identical => {
+                identical += 1;
+                "identical to vanilla, can be dropped".yellow().to_string()
+            }
+            Baseline::Modified { changed, shaders } => {
+                modified += 1;
+                format!("modified ({changed} of {shaders} shaders)").green().to_string()
+            }
+            Baseline::NotVanilla => {
+                custom += 1;
+                "not in vanilla".dimmed().to_string()
+            }
+        };
+        println!("{:<48} {status}", entry.cyan());
+    }
+    println!(
+        "\n{} modified, {} identical to vanilla, {} not in vanilla ({} vanilla materials compared against)",
+        modified.to_string().green(),
+        identical.to_string().yellow(),
+        custom,
+        vanilla.len()
+    );
+    Ok(())
+}
+
+fn print_grep(pack: OsString, pattern: &str, ignore_case: bool) -> anyhow::Result<()> {
+    let (_, mut input) = open_input(pack, &Limits::default())?;
+    let mut matches = 0;
+    grep::grep_shaders(&mut input, pattern, ignore_case, |found| {
+        matches += 1;
+        println!(
+            "{} {} [{}] {} {}:{}: {}",
+            found.entry.cyan(),
+            found.pass,
+            found.flags.join(", ").dimmed(),
+            found.platform,
+            found.stage,
+            found.line_number,
+            found.line
+        );
+    })?;
+    if matches == 0 {
+        println!("No shader contains {pattern:?}");
+    } else {
+        println!("{matches} matching lines");
+    }
+    Ok(())
+}
+
+fn parse_flag_filter(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("Expected KEY=VALUE, got {arg:?}")),
+    }
+}
+
+fn inspect(pack: OsString, filter: &[(String, String)], code: bool, pretty: bool) -> anyhow::Result<()> {
+    let (_, mut input) = open_input(pack, &Limits::default())?;
+    for_each_material(&mut input, |entry, material, version| {
+        println!("{} {}", entry.cyan(), format!("[{version}]").dimmed());
+        for (pass_name, pass) in &material.passes {
+            println!("  {}", pass_name.bold());
+            for (index, variant) in pass.variants.iter().enumerate() {
+                let matches = filter
+                    .iter()
+                    .all(|(key, value)| variant.flags.get(key) == Some(value));
+                if !matches {
+                    continue;
+                }
+                let flags: Vec<String> = variant
+                    .flags
+                    .iter()
+                    .map(|(key, value)| format!("{key}={value}"))
+                    .collect();
+                let supported = if variant.is_supported { "" } else { " (unsupported)" };
+                println!(
+                    "    #{index}{} {} shaders: {}",
+                    supported.yellow(),
+                    variant.shader_codes.len(),
+                    flags.join(", ")
+                );
+                if !code {
+                    continue;
+                }
+                for (stage, shader_code) in &variant.shader_codes {
+                    println!("      {} {}", stage.platform_name.cyan(), stage.stage_name);
+                    let shader: BgfxShader = match shader_code.bgfx_shader_data.pread(0) {
+                        Ok(shader) => shader,
+                        Err(err) => {
+                            println!("{}", format!("Unreadable shader: {err}").yellow());
+                            continue;
+                        }
+                    };
+                    match std::str::from_utf8(&shader.code) {
+                        Ok(text) if !text.contains('\0') => {
+                            let text = if pretty { pretty_glsl(text) } else { text.to_string() };
+                            println!("{}", text.trim_end());
+                        }
+                        _ => println!("{}", format!("<{} bytes of bytecode>", shader.code.len()).dimmed()),
+                    }
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+fn list_patches() {
+    println!(
+        "{:<32} {:<12} {}",
+        "Patch".bold(),
+        "Targets".bold(),
+        "Description".bold()
+    );
+    for patch in PATCHES {
+        let targets = match patch.targets() {
+            Some(targets) => targets
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+            None => "all".to_string(),
+        };
+        println!(
+            "{:<32} {:<12} {}",
+            patch.name().cyan(),
+            targets,
+            patch.description()
+        );
+    }
+}
+
+fn list_warnings() {
+    println!("{:<6} {:<24} {}", "Code".bold(), "Name".bold(), "Meaning".bold());
+    for code in WarningCode::ALL {
+        println!("{:<6} {:<24} {}", code.code(), code.name().cyan(), code.description());
+    }
+}
+
+fn explain(version: &str) -> anyhow::Result<()> {
+    let version = MVersion::resolve(version)
+        .with_context(|| format!("Unknown version {version:?}, see `material-updater list-versions`"))?;
+    let notes = changelog::notes(&version);
+    println!("{} (materials in the {} format)", version.to_string().cyan().bold(), version.info().binary);
+    println!("\n{}", "What changed".bold());
+    for change in notes.changes {
+        println!("  - {change}");
+    }
+    println!("\n{}", "Fixed when converting to it".bold());
+    println!("  - materials are rewritten in its binary format");
+    for patch in patches::default_patches(&version) {
+        println!("  - {}: {}", patch.name().cyan(), patch.description());
+    }
+    if !notes.limitations.is_empty() {
+        println!("\n{}", "Known limitations".bold());
+        for limitation in notes.limitations {
+            println!("  - {}", limitation.yellow());
+        }
+    }
+    println!(
+        "\nA pack that is still pink or broken after converting: `material-updater doctor PACK -t {version}` looks for the usual causes"
+    );
+    Ok(())
+}
+
+fn list_presets(config: &Config) {
+    for (name, preset) in config.presets() {
+        println!("{} {}", name.cyan().bold(), preset.description.as_deref().unwrap_or_default());
+        let mut options = Vec::new();
+        if let Some(targets) = &preset.target_version {
+            options.push(format!("-t {targets}"));
+        }
+        if let Some(level) = preset.zip_compression {
+            options.push(format!("-z {level}"));
+        }
+        if let Some(template) = &preset.output_template {
+            options.push(format!("--output-template {template}"));
+        }
+        for patch in &preset.enable_patch {
+            options.push(format!("--enable-patch {patch}"));
+        }
+        for patch in &preset.disable_patch {
+            options.push(format!("--disable-patch {patch}"));
+        }
+        if preset.minify_shaders {
+            options.push("--minify-shaders".to_string());
+        }
+        if preset.strip_conflicting_patches {
+            options.push("--strip-conflicting-patches".to_string());
+        }
+        if let Some(version) = &preset.add_essl {
+            options.push(format!("--add-essl {version}"));
+        }
+        if let Some(strategy) = &preset.on_incompat {
+            options.push(format!("--on-incompat {strategy}"));
+        }
+        if let Some(globs) = &preset.lightmap_materials {
+            options.push(format!("--lightmap-materials {}", globs.join(",")));
+        }
+        println!("    {}", options.join(" ").dimmed());
+    }
+}
+
+/// Fill the options `opts` doesn't set from `preset`
+fn apply_preset(opts: &mut ConvertArgs, preset: Preset) -> anyhow::Result<()> {
+    if opts.target_version.is_empty() {
+        if let Some(targets) = preset.target_version {
+            opts.target_version = targets.split(',').map(|t| t.trim().to_string()).collect();
+        }
+    }
+    opts.zip_compression = opts.zip_compression.or(preset.zip_compression);
+    opts.output_template = opts.output_template.take().or(preset.output_template);
+    if opts.enable_patch.is_empty() {
+        opts.enable_patch = preset.enable_patch;
+    }
+    if opts.disable_patch.is_empty() {
+        opts.disable_patch = preset.disable_patch;
+    }
+    opts.minify_shaders |= preset.minify_shaders;
+    opts.strip_conflicting_patches |= preset.strip_conflicting_patches;
+    if opts.add_essl.is_none() {
+        if let Some(version) = preset.add_essl {
+            opts.add_essl = Some(EsslVersion::from_str(&version, true).map_err(|err| anyhow::anyhow!("Invalid add_essl in preset: {err}"))?);
+        }
+    }
+    // The default can't be told apart from giving it, so only that is replaced
+    if opts.on_incompat == IncompatStrategy::default() {
+        if let Some(strategy) = preset.on_incompat {
+            opts.on_incompat =
+                IncompatStrategy::from_str(&strategy, true).map_err(|err| anyhow::anyhow!("Invalid on_incompat in preset: {err}"))?;
+        }
+    }
+    if opts.lightmap_materials.is_empty() {
+        opts.lightmap_materials = preset.lightmap_materials.unwrap_or_default();
+    }
+    Ok(())
+}
+
+/// Variable that turns a built-in patch on or off, e.g.
+/// `MATERIAL_UPDATER_PATCH_LIGHTMAP_CLEANUP`
+fn patch_env_var(patch: &str) -> String {
+    format!("MATERIAL_UPDATER_PATCH_{}", patch.to_ascii_uppercase().replace('-', "_"))
+}
+
+/// Enable or disable the patches set by `MATERIAL_UPDATER_PATCH_*` variables,
+/// unless --enable-patch or --disable-patch already names them
+fn apply_patch_env(opts: &mut ConvertArgs) -> anyhow::Result<()> {
+    for patch in PATCHES {
+        let name = patch.name();
+        let var = patch_env_var(name);
+        let Some(value) = std::env::var_os(&var) else {
+            continue;
+        };
+        if opts.enable_patch.iter().chain(&opts.disable_patch).any(|given| given.eq_ignore_ascii_case(name)) {
+            continue;
+        }
+        match value.to_string_lossy().to_ascii_lowercase().as_str() {
+            "1" | "on" | "true" | "yes" => opts.enable_patch.push(name.to_string()),
+            "0" | "off" | "false" | "no" => opts.disable_patch.push(name.to_string()),
+            other => anyhow::bail!("Invalid {var} {other:?}, use on or off"),
+        }
+    }
+    Ok(())
+}
+
+const DEFAULT_OUTPUT_TEMPLATE: &str = "{stem}_{target}{ext}";
+
+/// How outputs are named when no explicit path is given
+struct OutputNaming<'a> {
+    template: &'a str,
+    dir: Option<&'a Path>,
+    source: Option<String>,
+}
+
+fn resolve_target(arg: &str, config: &Config) -> anyhow::Result<MVersion> {
+    if arg.eq_ignore_ascii_case("auto") {
+        return detect_target();
+    }
+    let name = config.aliases.get(arg).map_or(arg, String::as_str);
+    MVersion::resolve(name).with_context(|| {
+        format!("Unknown target version {arg:?}, see --help for the accepted values")
+    })
+}
+
+fn detect_target() -> anyhow::Result<MVersion> {
+    let game = client::installed_version()?;
+    let version = MVersion::from_game_version(&game)
+        .with_context(|| format!("Installed Minecraft {game} is older than any supported version"))?;
+    println!("{}", tr!("detected-game", game = game.cyan(), target = version.to_string().cyan()));
+    Ok(version)
+}
+
+const fn get_style() -> Styles {
+    Styles::styled()
+        .header(AnsiColor::BrightYellow.on_default())
+        .usage(AnsiColor::Green.on_default())
+        .literal(Style::new().fg_color(None).bold())
+        .placeholder(AnsiColor::Green.on_default())
+}
+
+fn init_logger(verbose: u8, quiet: bool, log_file: Option<&Path>) -> anyhow::Result<()> {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Info,
+        (false, 2) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(level).parse_default_env();
+    if let Some(path) = log_file {
+        let file = File::create(path).with_context(|| "Error while creating log file")?;
+        builder
+            .target(env_logger::Target::Pipe(Box::new(file)))
+            .write_style(env_logger::WriteStyle::Never);
+    }
+    builder.try_init()?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let result = run();
+    if let Err(err) = &result {
+        eprintln!("{}", tr!("error", error = format!("{err:?}")));
+    }
+    // Started by double clicking or dropping files on the exe, keep the window
+    // open so the output can be read
+    if terminal::owns_console() {
+        terminal::wait_for_enter();
+    }
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        // What shells use for runs ended by Ctrl-C
+        Err(_) if cancel::is_cancelled() => ExitCode::from(130),
+        Err(_) => ExitCode::FAILURE,
+    }
+}
+
+fn run() -> anyhow::Result<()> {
+    #[cfg(feature = "gui")]
+    if std::env::args_os().len() == 1 {
+        return gui::run();
+    }
+    // Double clicked, or started without arguments from a terminal
+    let opts = if std::env::args_os().len() == 1 && io::stdin().is_terminal() {
+        i18n::set_language(None)?;
+        Options::parse_from(interactive::prompt_args()?)
+    } else {
+        Options::parse()
+    };
+    i18n::set_language(opts.lang.as_deref())?;
+    if opts.generate_man {
+        clap_mangen::Man::new(cli()).render(&mut io::stdout())?;
+        return Ok(());
+    }
+    init_logger(opts.verbose, opts.quiet, opts.log_file.as_deref())?;
+    temp::configure(opts.tempdir.as_deref(), opts.no_temp)?;
+    let mut convert_args = match opts.command {
+        Some(Command::Convert(args)) => *args,
+        #[cfg(feature = "gui")]
+        Some(Command::Gui) => return gui::run(),
+        Some(command) => return run_command(command, opts.config.as_deref()),
+        None => opts.convert,
+    };
+    convert_args.jobs = opts.threads;
+    let config = Config::load(opts.config.as_deref())?;
+    convert(convert_args, &config)
+}
+
+fn run_command(command: Command, config: Option<&Path>) -> anyhow::Result<()> {
+    match command {
+        Command::Convert(_) => unreachable!("handled by run"),
+        Command::ListVersions => list_versions(),
+        Command::ListPatches => list_patches(),
+        Command::ListWarnings => list_warnings(),
+        Command::Explain { version } => explain(&version)?,
+        Command::ListPresets => list_presets(&Config::load(config)?),
+        Command::Stats { pack, top } => print_stats(pack, top)?,
+        Command::Grep {
+            pack,
+            pattern,
+            ignore_case,
+        } => print_grep(pack, &pattern, ignore_case)?,
+        Command::Inspect {
+            pack,
+            flag,
+            code,
+            raw,
+        } => inspect(pack, &flag, code, !raw)?,
+        Command::Validate { pack, lint } => validate(pack, &lint.levels()?)?,
+        Command::Detect { pack } => detect(pack)?,
+        Command::Doctor { pack, target_version, lint } => {
+            let target = match target_version {
+                Some(arg) => Some(resolve_target(&arg, &Config::load(config)?)?),
+                None => None,
+            };
+            doctor(pack, target.as_ref(), &lint.levels()?)?
+        }
+        Command::EditFlags {
+            pack,
+            edits,
+            output,
+            zip_compression,
+            force,
+        } => {
+            disk::check_overwrite(&output, force)?;
+            let (_, mut input) = open_input(pack, &Limits::default())?;
+            let mut tmp_file = Scratch::new()?;
+            let changed = flags::edit_flags(&mut input, &mut tmp_file, &edits, zip_compression)?;
+            save_output(&mut tmp_file, &output)?;
+            println!("Changed flags on {} variants", changed.to_string().green());
+        }
+        Command::CompareVanilla { pack, vanilla } => {
+            let vanilla = match vanilla {
+                Some(vanilla) => vanilla,
+                None => Config::load(config)?
+                    .vanilla_materials
+                    .map(OsString::from)
+                    .with_context(|| "Pass --vanilla or set vanilla_materials in the config file")?,
+            };
+            compare_vanilla(pack, vanilla)?;
+        }
+        Command::Extract {
+            material,
+            from,
+            output,
+            force,
+        } => {
+            let (_, input) = open_input(from, &Limits::default())?;
+            let (name, data) = extract::extract(input, &material)?;
+            let output = if output.is_dir() {
+                // Only the file name, the entry's directories can't lead anywhere else
+                let file_name = Path::new(&name)
+                    .file_name()
+                    .with_context(|| format!("{name} has no file name to write it as"))?;
+                output.join(file_name)
+            } else {
+                output
+            };
+            disk::check_overwrite(&output, force)?;
+            disk::write_output(&output, data.as_slice(), data.len() as u64)?;
+            println!("Extracted {} to {}", name.cyan(), output.display());
+        }
+        Command::Inject {
+            material,
+            into,
+            entry,
+            output,
+            zip_compression,
+            force,
+        } => {
+            disk::check_overwrite(&output, force)?;
+            let data = std::fs::read(&material).with_context(|| format!("Error while reading {}", material.display()))?;
+            let wanted = match entry {
+                Some(entry) => entry,
+                None => material
+                    .file_name()
+                    .with_context(|| format!("{} has no file name", material.display()))?
+                    .to_string_lossy()
+                    .into_owned(),
+            };
+            let (_, input) = open_input(into, &Limits::default())?;
+            let mut tmp_file = Scratch::new()?;
+            let name = extract::inject(input, &mut tmp_file, &wanted, &data, zip_compression)?;
+            save_output(&mut tmp_file, &output)?;
+            println!("Replaced {} and wrote {}", name.cyan(), output.display());
+        }
+        Command::ExportJson {
+            material,
+            output,
+            split_shaders,
+            force,
+        } => export_json(&material, &output, split_shaders, force)?,
+        Command::ImportJson { json, output, force } => import_json(&json, &output, force)?,
+        Command::ApplyDelta {
+            original,
+            delta,
+            output,
+            force,
+        } => {
+            disk::check_overwrite(&output, force)?;
+            let (_, original) = open_input(original, &Limits::default())?;
+            let (_, delta) = open_input(delta, &Limits::default())?;
+            let mut tmp_file = Scratch::new()?;
+            let summary = delta::apply_delta(original, delta, &mut tmp_file)?;
+            save_output(&mut tmp_file, &output)?;
+            println!(
+                "Added {}, replaced {} and removed {} entries, wrote {}",
+                summary.added.to_string().green(),
+                summary.replaced.to_string().green(),
+                summary.removed.to_string().green(),
+                output.display()
+            );
+        }
+        Command::Merge {
+            base,
+            overlay,
+            output,
+            force,
+        } => {
+            disk::check_overwrite(&output, force)?;
+            let mut base = BufReader::new(File::open(&base).with_context(|| format!("Error while opening {}", base.display()))?);
+            let mut overlay = BufReader::new(
+                File::open(&overlay).with_context(|| format!("Error while opening {}", overlay.display()))?,
+            );
+            let mut tmp_file = Scratch::new()?;
+            let summary = merge::merge(&mut base, &mut overlay, &mut tmp_file)?;
+            save_output(&mut tmp_file, &output)?;
+            println!(
+                "Added {} passes and {} variants, replaced {} variants",
+                summary.added_passes.to_string().green(),
+                summary.added_variants.to_string().green(),
+                summary.replaced_variants.to_string().green()
+            );
+        }
+        Command::Installed { action, install_dir } => {
+            let install_dir = install_dir.or(Config::load(config)?.install_dir);
+            let game_dir = install::game_dir(install_dir.as_deref())?;
+            match action {
+                InstalledAction::List => list_installed(&game_dir)?,
+                InstalledAction::Remove { pack, all } => remove_installed(&game_dir, &pack, all)?,
+            }
+        }
+        Command::Rollback { output } => {
+            let conversion = history::rollback(&output)?;
+            match conversion.backup {
+                Some(_) => println!(
+                    "Restored {} from before the {} conversion of {}",
+                    output.display().to_string().cyan(),
+                    conversion.timestamp,
+                    conversion.input
+                ),
+                None => println!(
+                    "Removed {}, it did not exist before the {} conversion of {}",
+                    output.display().to_string().cyan(),
+                    conversion.timestamp,
+                    conversion.input
+                ),
+            }
+        }
+        Command::Completions { shell } => {
+            let mut cli = cli();
+            let name = cli.get_name().to_string();
+            clap_complete::generate(shell, &mut cli, name, &mut io::stdout());
+        }
+        #[cfg(windows)]
+        Command::Register { target_version } => {
+            register::register(&target_version)?;
+            println!("Right-click a .mcpack and pick {} to convert it for {}", "Update shaders…".cyan(), target_version.cyan());
+        }
+        #[cfg(feature = "gui")]
+        Command::Gui => unreachable!("handled by run"),
+        Command::Bench {
+            packs,
+            target_version,
+            iterations,
+            summary_file,
+        } => {
+            let target = resolve_target(&target_version, &Config::load(config)?)?;
+            run_bench(&packs, &target_version, &target, iterations, &summary_file)?;
+        }
+        #[cfg(windows)]
+        Command::Unregister => {
+            if register::unregister()? {
+                println!("Removed the .mcpack menu entry");
+            } else {
+                println!("No .mcpack menu entry to remove");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Time every phase of converting `packs` for `target` and write the timings
+/// with what the conversions did to `summary_file`
+fn run_bench(packs: &[PathBuf], requested: &str, target: &MVersion, iterations: usize, summary_file: &Path) -> anyhow::Result<()> {
+    let mut summary = RunSummary::new();
+    summary.targets.push(ResolvedTarget {
+        requested: requested.to_string(),
+        resolved: target.to_string(),
+    });
+    for path in packs {
+        let fixture = bench::Fixture::load(path)?;
+        let start = Instant::now();
+        let (phases, report) = bench::measure(&fixture, target, iterations)?;
+        println!("\n{} ({} materials, {} iterations)", fixture.name.cyan().bold(), fixture.materials.len(), iterations.max(1));
+        for timing in &phases {
+            println!(
+                "  {:<10} {:>10.2} ms  (min {:.2}, max {:.2}, {:.1} MB/s)",
+                format!("{:?}", timing.phase).to_lowercase(),
+                timing.mean_secs * 1000.0,
+                timing.min_secs * 1000.0,
+                timing.max_secs * 1000.0,
+                timing.bytes_per_sec / 1_000_000.0
+            );
+        }
+        let mut record = InputRecord::new(path.display().to_string(), sha256_reader(fixture.pack.as_slice())?);
+        record.outputs.push(OutputRecord::new(&report, None, None));
+        summary.inputs.push(record.finish(start.elapsed()));
+        summary.benchmarks.push(BenchRecord {
+            input: path.display().to_string(),
+            target: target.to_string(),
+            materials: fixture.materials.len(),
+            phases,
+        });
+    }
+    summary.write(summary_file, true)
+}
+
+/// Convert every input of the command line, writing --summary-file whether
+/// that worked, failed, crashed or was interrupted
+fn convert(opts: ConvertArgs, config: &Config) -> anyhow::Result<()> {
+    handle_interrupts();
+    let summary_file = opts.summary_file.clone();
+    let summary = summary_file.is_some().then(|| Mutex::new(RunSummary::new()));
+    // The panic itself was already printed by the hook
+    let result = panic::catch_unwind(AssertUnwindSafe(|| convert_inputs(opts, config, summary.as_ref())))
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("The conversion crashed")));
+    if let (Some(path), Some(summary)) = (summary_file, summary) {
+        let written = summary.into_inner().unwrap_or_else(PoisonError::into_inner).write(&path, result.is_ok());
+        return result.and(written);
+    }
+    result
+}
+
+/// Stop conversions before their next entry on Ctrl-C, so they fail like on an
+/// error: outputs aren't written half done and temporary files are removed.
+/// Pressing it again quits right away. Crashes name the entry they happened on
+fn handle_interrupts() {
+    let handled = ctrlc::set_handler(|| {
+        if cancel::is_cancelled() {
+            disk::remove_unfinished();
+            std::process::exit(130);
+        }
+        cancel::cancel();
+        match cancel::current_entry() {
+            Some(entry) => eprintln!("{}", tr!("stopping-entry", entry = entry).yellow()),
+            None => eprintln!("{}", tr!("stopping").yellow()),
+        }
+    });
+    if let Err(err) = handled {
+        log::debug!("Ctrl-C isn't handled: {err}");
+    }
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        if let Some(entry) = cancel::current_entry() {
+            eprintln!("{}", tr!("crashed-entry", entry = entry).red());
+        }
+    }));
+}
 
-use anyhow::Context;
-use clap::{
-    builder::{
-        styling::{AnsiColor, Style},
-        Styles,
-    },
-    Parser, ValueEnum,
-};
+fn convert_inputs(mut opts: ConvertArgs, config: &Config, summary: Option<&Mutex<RunSummary>>) -> anyhow::Result<()> {
+    if let Some(name) = &opts.preset {
+        let preset = config.preset(name)?;
+        apply_preset(&mut opts, preset)?;
+    }
+    apply_patch_env(&mut opts)?;
+    // Only the patches the command line, environment and preset leave alone
+    let unnamed = |patches: &[String]| -> Vec<String> {
+        patches
+            .iter()
+            .filter(|patch| !opts.enable_patch.iter().chain(&opts.disable_patch).any(|given| given.eq_ignore_ascii_case(patch)))
+            .cloned()
+            .collect()
+    };
+    let (enable, disable) = (unnamed(&config.enable_patch), unnamed(&config.disable_patch));
+    opts.enable_patch.extend(enable);
+    opts.disable_patch.extend(disable);
+    if opts.patch_file.is_empty() {
+        opts.patch_file.clone_from(&config.patch_files);
+    }
+    if opts.target_version.is_empty() {
+        if let Some(targets) = &config.target_version {
+            opts.target_version = targets.split(',').map(|t| t.trim().to_string()).collect();
+        }
+    }
+    opts.zip_compression = opts.zip_compression.or(config.zip_compression);
+    opts.cache |= !opts.no_cache && config.cache.unwrap_or(false);
+    let output_template = opts
+        .output_template
+        .clone()
+        .or(config.output_template.clone())
+        .unwrap_or_else(|| DEFAULT_OUTPUT_TEMPLATE.to_string());
 
-use materialbin::{
-    bgfx_shader::BgfxShader, CompiledMaterialDefinition, MinecraftVersion, WriteError,
-};
-use owo_colors::{colors::Yellow, OwoColorize};
-use scroll::Pread;
-use tempfile::tempfile;
-use zip::{
-    write::{ExtendedFileOptions, FileOptions},
-    ZipArchive, ZipWriter,
-};
+    let max_memory = opts.max_memory.map_or(0, |max| usize::try_from(max).unwrap_or(usize::MAX));
+    if let Some(max) = opts.max_memory {
+        // Every target keeps its own output buffer
+        log::info!("Keeping up to {max} bytes of each output in memory");
+    }
 
-#[derive(Parser)]
-#[clap(name = "Material Updater", version = "0.1.13")]
-#[command(version, about, long_about = None, styles = get_style())]
-struct Options {
-    /// Shader pack file to update
-    #[clap(required = true)]
-    file: String,
+    let mut renames: Vec<NameRename> = config
+        .uniform_renames
+        .iter()
+        .filter(|(from, _)| !opts.rename_uniform.iter().any(|r| r.from == **from))
+        .map(|(from, to)| format!("{from}={to}").parse().map_err(anyhow::Error::msg))
+        .collect::<anyhow::Result<_>>()
+        .with_context(|| "Invalid uniform_renames in config")?;
+    renames.extend(opts.rename_uniform.iter().cloned());
 
-    /// Output zip compression level
-    #[clap(short, long)]
-    zip_compression: Option<u32>,
+    let mut extra_patches: Vec<Arc<dyn Patch>> = Vec::new();
+    for path in &opts.plugin {
+        let plugin = PluginPatch::load(path)?;
+        println!("{}", tr!("loaded-plugin", name = plugin.name().cyan(), path = path.display()));
+        extra_patches.push(Arc::new(plugin));
+    }
+    for path in &opts.patch_file {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Error while reading patch file {}", path.display()))?;
+        let file: PatchFile = toml::from_str(&text)
+            .with_context(|| format!("Invalid patch file {}", path.display()))?;
+        extra_patches.extend(rules::compile_rules(file, opts.preview_rules)?);
+    }
+    if let Some(version) = opts.add_essl {
+        extra_patches.push(Arc::new(AddEsslVariants(version)));
+    }
+    if let Some(command) = &opts.post_process {
+        extra_patches.push(Arc::new(PostProcess::new(command.clone())));
+    }
+    // Last, so it also shrinks whatever the other patches inserted
+    if opts.minify_shaders {
+        extra_patches.push(Arc::new(MinifyShaders));
+    }
+    if opts.preview_rules {
+        opts.yeet = true;
+    }
+    let patches = PatchSelection {
+        enabled: opts.enable_patch.clone(),
+        disabled: opts.disable_patch.clone(),
+        extra: extra_patches,
+        strip_conflicts: opts.strip_conflicting_patches,
+        lightmap_materials: if opts.lightmap_materials.is_empty() {
+            config.lightmap_materials.clone().unwrap_or_default()
+        } else {
+            opts.lightmap_materials.clone()
+        },
+    };
 
-    /// Process the file, but dont write anything
-    #[clap(short, long)]
-    yeet: bool,
-    #[clap(short, long)]
-    verbose: bool,
+    let mut targets = Vec::new();
+    for arg in &opts.target_version {
+        let version = resolve_target(arg, config)?;
+        if !arg.eq_ignore_ascii_case("auto") && *arg != version.to_string() {
+            println!("{}", tr!("resolved-target", requested = arg, target = version.to_string().cyan()));
+        }
+        record_target(summary, arg, &version);
+        targets.push(version);
+    }
+    if targets.is_empty() {
+        let version = resolve_target("stable", config)?;
+        println!("{}", tr!("default-target", target = version));
+        record_target(summary, "stable", &version);
+        targets.push(version);
+    }
+    if targets.len() > 1 && opts.output.is_some() {
+        anyhow::bail!("--output can't be used with several target versions, use --output-template instead");
+    }
     
-    /// Output version
-    #[clap(short, long)]
-    target_version: Option<MVersion>,
+    if opts.files.len() > 1 && opts.output.is_some() {
+        anyhow::bail!("--output can't be used with several inputs, use --output-template instead");
+    }
+    if let (true, Some(output), None) = (opts.yeet, &opts.output, opts.install) {
+        println!("{}", tr!("dry-run-output-ignored", path = output.display()).yellow());
+    }
+    if opts.emit_delta.is_some() && (targets.len() > 1 || opts.files.len() > 1) {
+        anyhow::bail!("--emit-delta takes a single input and target version");
+    }
+    if opts.emit_debug_pack.is_some() && (targets.len() > 1 || opts.files.len() > 1) {
+        anyhow::bail!("--emit-debug-pack takes a single input and target version");
+    }
+    if opts.profile_json.is_some() && opts.files.len() > 1 {
+        anyhow::bail!("--profile-json takes a single input");
+    }
+    let vanilla = if opts.strip_vanilla {
+        let source = opts
+            .vanilla
+            .clone()
+            .or(config.vanilla_materials.clone().map(OsString::from))
+            .with_context(|| "--strip-vanilla needs --vanilla or vanilla_materials in the config file")?;
+        Some(load_vanilla(source)?)
+    } else {
+        None
+    };
+    let defaults = Limits::default();
+    let limits = Limits {
+        max_entry_size: opts.max_entry_size.unwrap_or(defaults.max_entry_size),
+        max_entries: opts.max_entries.unwrap_or(defaults.max_entries),
+        max_shader_size: opts
+            .max_shader_size
+            .map_or(defaults.max_shader_size, |max| usize::try_from(max).unwrap_or(usize::MAX)),
+    };
+    let options = ConvertOptions::builder()
+        .compression_level(opts.zip_compression)
+        .keep_going(opts.keep_going)
+        .renames(renames)
+        .patches(patches)
+        .on_incompat(opts.on_incompat)
+        .vanilla(vanilla.as_ref())
+        .limits(limits)
+        .lowercase_paths(opts.normalize_paths)
+        .duplicates(opts.duplicates)
+        .json_materials(opts.json_materials)
+        .zip_encoding(opts.zip_encoding)
+        .material_renames(opts.rename.clone())
+        .only_subpack(opts.only_subpack.clone())
+        .stamp(opts.stamp)
+        .hooks(&terminal::Output)
+        .build()?;
+    let conversion_settings = conversion_settings(&opts, options.renames(), options.patches())?;
+    let store = match opts.cache_dir.as_ref().or(config.cache_dir.as_ref()) {
+        Some(dir) => Some(ZstdStore::new(dir, conversion_settings.clone())?),
+        None => None,
+    };
+    let install = match opts.install {
+        Some(folder) => Some(install::game_dir(opts.install_dir.as_deref().or(config.install_dir.as_deref()))?.join(folder.dir_name())),
+        None => None,
+    };
+    let settings = Settings {
+        opts: &opts,
+        targets: &targets,
+        options: &options,
+        output_template: &output_template,
+        output_dir: config.output_dir.as_deref(),
+        install: install.as_deref(),
+        max_memory,
+        summary,
+        store: store.as_ref(),
+        conversion_settings: &conversion_settings,
+    };
+    if let Some(name) = &opts.from_stdin_name {
+        let result = read_stdin(name.clone(), &limits)
+            .and_then(|(input_name, input_file)| convert_input(settings, input_name, input_file));
+        if let Err(err) = &result {
+            run_summary::record_failure(summary, &name.to_string_lossy(), None, err);
+        }
+        return result;
+    }
+    let jobs = match opts.jobs {
+        0 => thread::available_parallelism().map_or(1, |cpus| cpus.get()),
+        jobs => jobs,
+    };
+    // Previewed replacements are printed in order
+    patches::set_threads(if opts.preview_rules { 1 } else { jobs });
+    if jobs > 1 && opts.files.len() * targets.len() > 1 {
+        return convert_parallel(settings, jobs);
+    }
+    let mut failed = Vec::new();
+    for file in &opts.files {
+        let result = open_input(file.clone(), &limits)
+            .and_then(|(input_name, input_file)| convert_input(settings, input_name, input_file));
+        if let Err(err) = result {
+            run_summary::record_failure(summary, &file.to_string_lossy(), None, &err);
+            // Keep going with the other dropped files
+            if opts.files.len() == 1 {
+                return Err(err);
+            }
+            println!("{}", tr!("input-failed", failed = tr!("failed").red(), input = file.to_string_lossy(), error = format!("{err:#}")));
+            failed.push(file.to_string_lossy().into_owned());
+        }
+    }
+    if !failed.is_empty() {
+        anyhow::bail!(
+            "{} of {} inputs failed: {}",
+            failed.len(),
+            opts.files.len(),
+            failed.join(", ")
+        );
+    }
+    Ok(())
+}
 
-    /// Output path
-    #[arg(short, long)]
-    output: Option<PathBuf>,
+/// Everything besides the target version that changes what a material
+/// converts to, so entries kept by --cache-dir are only reused for the same
+fn conversion_settings(opts: &ConvertArgs, renames: &[NameRename], patches: &PatchSelection) -> anyhow::Result<String> {
+    let mut settings = format!(
+        "material-updater {}\nenabled {:?}\ndisabled {:?}\nstrip {}\nlightmap {:?}\nrenames {renames:?}\nmaterials {:?}\nincompat {:?}\npost-process {:?}\n",
+        env!("CARGO_PKG_VERSION"),
+        patches.enabled,
+        patches.disabled,
+        patches.strip_conflicts,
+        patches.lightmap_materials,
+        opts.rename,
+        opts.on_incompat,
+        opts.post_process,
+    );
+    for patch in &patches.extra {
+        settings += &format!("extra {}\n", patch.name());
+    }
+    // Their contents, as a plugin or patch file may change under the same name
+    for path in opts.plugin.iter().chain(&opts.patch_file) {
+        let file = File::open(path).with_context(|| format!("Error while opening {}", path.display()))?;
+        settings += &format!("file {}\n", sha256_reader(BufReader::new(file))?);
+    }
+    Ok(settings)
 }
 
-#[derive(ValueEnum, Clone)]
-enum MVersion {
-    #[value(name = "26.10")]
-    V26_10,
-    #[value(name = "26.0.24")]
-    V26_0_24,
-    #[value(name = "1.21.110")]
-    V1_21_110,
-    #[value(name = "1.21.20")]
-    V1_21_20,
-    #[value(name = "1.20.80")]
-    V1_20_80,
-    #[value(name = "1.19.60")]
-    V1_19_60,
-    #[value(name = "1.18.30")]
-    V1_18_30,
-}
-
-impl std::fmt::Display for MVersion {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::V26_10 => write!(f, "26.10"),
-            Self::V26_0_24 => write!(f, "26.0.24"),
-            Self::V1_21_110 => write!(f, "1.21.110"),
-            Self::V1_21_20 => write!(f, "1.21.20"),
-            Self::V1_20_80 => write!(f, "1.20.80"),
-            Self::V1_19_60 => write!(f, "1.19.60"),
-            Self::V1_18_30 => write!(f, "1.18.30"),
+/// Convert every input for every target as separate tasks, `jobs` at a time,
+/// then print how each of them went
+fn convert_parallel(settings: Settings, jobs: usize) -> anyhow::Result<()> {
+    progress::set_enabled(false);
+    let files = &settings.opts.files;
+    let tasks: Vec<(&OsString, &MVersion)> = files
+        .iter()
+        .flat_map(|file| settings.targets.iter().map(move |target| (file, target)))
+        .collect();
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(tasks.len()));
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(tasks.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(&(file, target)) = tasks.get(index) else {
+                    break;
+                };
+                let name = file.to_string_lossy();
+                let counter = format!("[{}/{}]", index + 1, tasks.len());
+                println!("{}", tr!("task-started", counter = counter.dimmed(), input = name.cyan(), target = target));
+                let start = Instant::now();
+                let task_settings = Settings {
+                    targets: std::slice::from_ref(target),
+                    ..settings
+                };
+                let result = open_input(file.clone(), settings.options.limits())
+                    .and_then(|(input_name, input_file)| convert_input(task_settings, input_name, input_file));
+                let elapsed = start.elapsed();
+                match &result {
+                    Ok(()) => println!(
+                        "{}",
+                        tr!(
+                            "task-finished",
+                            counter = counter.dimmed(),
+                            input = name.cyan(),
+                            target = target,
+                            seconds = format!("{:.1}", elapsed.as_secs_f64())
+                        )
+                    ),
+                    Err(err) => {
+                        println!(
+                            "{}",
+                            tr!(
+                                "task-failed",
+                                counter = counter.dimmed(),
+                                failed = tr!("failed").red(),
+                                input = name,
+                                target = target,
+                                error = format!("{err:#}")
+                            )
+                        );
+                        run_summary::record_failure(settings.summary, &name, Some(target.to_string()), err);
+                    }
+                }
+                results.lock().unwrap().push((index, elapsed, result.err()));
+            });
         }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _, _)| *index);
+    println!(
+        "\n{:<48} {:<10} {:>8}  {}",
+        tr!("tasks-input").bold(),
+        tr!("tasks-target").bold(),
+        tr!("tasks-time").bold(),
+        tr!("tasks-result").bold()
+    );
+    let mut failed = 0;
+    for (index, elapsed, error) in &results {
+        let (file, target) = tasks[*index];
+        let result = match error {
+            None => tr!("result-converted").green().to_string(),
+            Some(err) => {
+                failed += 1;
+                format!("{err:#}").red().to_string()
+            }
+        };
+        println!(
+            "{:<48} {:<10} {:>7.1}s  {result}",
+            file.to_string_lossy().cyan(),
+            target.to_string(),
+            elapsed.as_secs_f64()
+        );
     }
+    anyhow::ensure!(failed == 0, "{failed} of {} conversions failed", tasks.len());
+    Ok(())
 }
 
-impl MVersion {
-    const fn as_version(&self) -> MinecraftVersion {
-        match self {
-            Self::V1_20_80 => MinecraftVersion::V1_20_80,
-            Self::V1_19_60 => MinecraftVersion::V1_19_60,
-            Self::V1_18_30 => MinecraftVersion::V1_18_30,
-            Self::V1_21_20 => MinecraftVersion::V1_21_20,
-            Self::V1_21_110 => MinecraftVersion::V1_21_110,
-            Self::V26_0_24 => MinecraftVersion::V26_0_24,
-            Self::V26_10 => MinecraftVersion::V1_21_110, // Uses 1.21.110 binary writer
-        }
+/// What every input of a run is converted with
+#[derive(Clone, Copy)]
+struct Settings<'a> {
+    opts: &'a ConvertArgs,
+    targets: &'a [MVersion],
+    options: &'a ConvertOptions<'a>,
+    output_template: &'a str,
+    output_dir: Option<&'a Path>,
+    /// Pack folder of the game --install extracts outputs to
+    install: Option<&'a Path>,
+    max_memory: usize,
+    summary: Option<&'a Mutex<RunSummary>>,
+    store: Option<&'a ZstdStore>,
+    /// [`conversion_settings`], which keys --cache entries
+    conversion_settings: &'a str,
+}
+
+fn record_target(summary: Option<&Mutex<RunSummary>>, requested: &str, resolved: &MVersion) {
+    if let Some(summary) = summary {
+        summary.lock().unwrap().targets.push(ResolvedTarget {
+            requested: requested.to_string(),
+            resolved: resolved.to_string(),
+        });
     }
 }
 
-const fn get_style() -> Styles {
-    Styles::styled()
-        .header(AnsiColor::BrightYellow.on_default())
-        .usage(AnsiColor::Green.on_default())
-        .literal(Style::new().fg_color(None).bold())
-        .placeholder(AnsiColor::Green.on_default())
+/// Add what became of one output to the --summary-file record of its input,
+/// `written` is the output and its data if it was saved
+fn record_output<F: io::Read + Seek>(
+    record: Option<&mut InputRecord>,
+    report: &ConversionReport,
+    written: Option<(&Path, &mut F)>,
+) -> anyhow::Result<()> {
+    let Some(record) = record else {
+        return Ok(());
+    };
+    let (path, sha256) = match written {
+        Some((path, data)) => {
+            data.rewind()?;
+            (Some(path.to_path_buf()), Some(sha256_reader(data)?))
+        }
+        None => (None, None),
+    };
+    record.outputs.push(OutputRecord::new(report, path, sha256));
+    Ok(())
 }
 
-fn main() -> anyhow::Result<()> {
-    let opts = Options::parse();
-    let target_mversion = match opts.target_version {
-        Some(version) => version,
-        None => {
-            println!("No target version specified, updating to latest stable: 1.21.110");
-            MVersion::V1_21_110
+fn convert_input(
+    settings: Settings,
+    input_name: OsString,
+    mut input_file: BufReader<Scratch>,
+) -> anyhow::Result<()> {
+    let Settings {
+        opts,
+        targets,
+        options,
+        output_template,
+        output_dir,
+        install: _,
+        max_memory,
+        summary,
+        store,
+        conversion_settings,
+    } = settings;
+    let start = Instant::now();
+    let input_display = input_name.to_string_lossy().into_owned();
+    let naming = OutputNaming {
+        template: output_template,
+        dir: output_dir,
+        source: if output_template.contains("{source}") && opts.output.is_none() {
+            match detect_source_versions(&mut input_file)?.as_slice() {
+                [] => None,
+                [version] => Some(version.to_string()),
+                _ => Some("mixed".to_string()),
+            }
+        } else {
+            None
+        },
+    };
+    // Mapped rather than read, so large inputs don't sit in memory next to their outputs.
+    // SAFETY: inputs aren't changed while they are converted, downloads and
+    // repacked archives are temporary files nothing else knows about
+    let mut mapped_file = None;
+    let mapped: &[u8] = match input_file.get_ref() {
+        Scratch::File(file) => {
+            mapped_file.insert(unsafe { Mmap::map(file) }.with_context(|| "Error while mapping the input")?)
         }
+        Scratch::Memory(data) => data.get_ref(),
+    };
+    let mut record = match summary {
+        Some(_) => Some(InputRecord::new(input_display.clone(), sha256_reader(mapped)?)),
+        None => None,
     };
-    
-    let mut input_file =
-        BufReader::new(File::open(&opts.file).with_context(|| "Error while opening input file")?);
         
-    if opts.file.ends_with(".material.bin") {
-        let output_filename: PathBuf = match &opts.output {
-            Some(output_name) => output_name.to_owned(),
-            None => {
-                let auto_name = update_filename(&opts.file, &target_mversion, ".material.bin")?;
-                println!("No output name specified, using {auto_name:?}");
-                auto_name
-            }
+    let lowercase_name = input_display.to_ascii_lowercase();
+    let other_archive = ARCHIVE_SUFFIXES
+        .iter()
+        .find(|suffix| lowercase_name.ends_with(*suffix));
+    let is_pack = input_display.ends_with(".zip") || input_display.ends_with(".mcpack") || other_archive.is_some();
+    let is_material = input_display.ends_with(".material.bin")
+        || opts.assume_material
+        || (!is_pack && material_updater::looks_like_material(mapped));
+    if is_material {
+        let postfix = material_postfix(&input_display);
+        if postfix != ".material.bin" {
+            log::info!("{input_display}: converting as a material.bin");
+        }
+        anyhow::ensure!(opts.emit_delta.is_none(), "--emit-delta only works on packs, not single materials");
+        anyhow::ensure!(opts.emit_debug_pack.is_none(), "--emit-debug-pack only works on packs, not single materials");
+        anyhow::ensure!(opts.install.is_none(), "--install only works on packs, not single materials");
+        let parts: Vec<(&[u8], Option<String>)> = match split_materials(mapped) {
+            parts if opts.split && parts.len() > 1 => parts
+                .into_iter()
+                .enumerate()
+                .map(|(index, part)| {
+                    let name = detect_material(part).map_or_else(|_| index.to_string(), |(material, _)| material.name);
+                    (part, Some(name))
+                })
+                .collect(),
+            _ => vec![(mapped, None)],
         };
-        let mut tmp_file = tempfile()?;
-        let mut output_file = file_to_shrodinger(&mut tmp_file, opts.yeet)?;
-        println!("Processing input {}", opts.file.cyan());
-        
-        file_update(&mut input_file, &mut output_file, &target_mversion, opts.verbose)?;
-        
-        tmp_file.rewind()?;
-        if !opts.yeet {
-            let mut output_file = File::create(output_filename)?;
-            io::copy(&mut tmp_file, &mut output_file)?;
+        let mut profiled = Vec::new();
+        for (target_mversion, (part, part_name)) in targets.iter().flat_map(|target| parts.iter().map(move |part| (target, part))) {
+            let output_filename = match part_name {
+                Some(name) => split_output_path(opts, &input_name, target_mversion, postfix, name, &naming)?,
+                None => output_path(opts, &input_name, target_mversion, postfix, ".material.bin", &naming)?,
+            };
+            // A dry run writes nowhere, not even to a temporary file
+            let mut tmp_file = (!opts.yeet).then(|| temp::spooled(max_memory));
+            let mut output_file = file_to_shrodinger(tmp_file.as_mut());
+            println!("{}", tr!("processing-input", name = input_display.cyan()));
+            
+            let report = file_update_bytes(part, &mut output_file, target_mversion, options)?;
+            if opts.profile || opts.profile_json.is_some() {
+                profiled.push((target_mversion.to_string(), report.entries.clone()));
+            }
+            check_denied(opts, &report)?;
+            if report.up_to_date {
+                println!("{}", tr!("material-up-to-date", target = target_mversion).green());
+                if opts.skip_up_to_date {
+                    println!("{}", tr!("skipping-output", path = output_filename.display()));
+                    record_output::<SpooledTempFile>(record.as_mut(), &report, None)?;
+                    continue;
+                }
+            }
+            
+            let Some(tmp_file) = tmp_file.as_mut() else {
+                println!("{}", tr!("dry-run", path = output_filename.display(), target = target_mversion));
+                record_output::<SpooledTempFile>(record.as_mut(), &report, None)?;
+                continue;
+            };
+            check_budget(opts, tmp_file, &output_filename)?;
+            save_converted(opts, tmp_file, &output_filename, &input_name, mapped, &report)?;
+            report_duplicates(opts, tmp_file, &output_filename)?;
+            record_output(record.as_mut(), &report, Some((&output_filename, &mut *tmp_file)))?;
+            if opts.emit_checksums {
+                tmp_file.rewind()?;
+                let name = output_filename.file_name().unwrap_or_default().to_string_lossy();
+                write_checksums(&output_filename, &file_checksums(&name, tmp_file)?)?;
+            }
+            if opts.verify_output {
+                verify_material(BufReader::new(File::open(&output_filename)?))
+                    .with_context(|| format!("Error in {}", output_filename.display()))?;
+                println!("{}", tr!("verified", path = output_filename.display()));
+            }
         }
+        show_profile(opts, profiled.iter().map(|(track, entries)| (track.clone(), entries.as_slice())))?;
+        finish_record(summary, record, start);
         return Ok(());
     }
     
-    if opts.file.ends_with(".zip") || opts.file.ends_with(".mcpack") {
-        let extension = Path::new(&opts.file)
-            .extension()
-            .with_context(|| "Input file does not have any extension??, weird")?
-            .to_str()
-            .unwrap();
-        let extension = ".".to_string() + extension;
-        let output_filename: PathBuf = match &opts.output {
-            Some(output_name) => output_name.to_owned(),
+    let mut in_place = None;
+    if is_pack {
+        if opts.in_place {
+            anyhow::ensure!(
+                other_archive.is_none() && opts.from_stdin_name.is_none() && Path::new(&input_name).is_file(),
+                "--in-place only works on .mcpack and .zip files, {input_display} isn't one"
+            );
+            anyhow::ensure!(targets.len() == 1, "--in-place takes a single target version");
+        }
+        let (postfix, extension) = match other_archive {
+            // Other archives are converted into an importable pack
+            Some(suffix) => (&input_display[input_display.len() - suffix.len()..], ".mcpack".to_string()),
             None => {
-                let auto_name = update_filename(&opts.file, &target_mversion, &extension)?;
-                println!("No output name specified, using {auto_name:?}");
-                auto_name
+                let extension = Path::new(&input_name)
+                    .extension()
+                    .with_context(|| "Input file does not have any extension??, weird")?
+                    .to_string_lossy();
+                let extension = ".".to_string() + &extension;
+                (&input_display[input_display.len() - extension.len()..], extension)
+            }
+        };
+        let mut output_filenames = Vec::new();
+        let mut caches = Vec::new();
+        let mut tmp_files = Vec::new();
+        for target_mversion in targets {
+            let output_filename = if opts.in_place {
+                PathBuf::from(&input_name)
+            } else {
+                output_path(opts, &input_name, target_mversion, postfix, &extension, &naming)?
+            };
+            caches.push(if opts.cache {
+                Some(ConversionCache::load(&output_filename, conversion_settings.to_string())?)
+            } else {
+                None
+            });
+            output_filenames.push(output_filename);
+            tmp_files.push((!opts.yeet).then(|| temp::spooled(max_memory)));
+        }
+        println!("{}", tr!("processing-zip", name = input_display.cyan()));
+        // History and deltas refer to the input as given, verifying to what was converted
+        let original = mapped;
+        let flattened_input;
+        let mapped = match &opts.flatten_subpack {
+            Some(tier) => {
+                let mut flattened = Cursor::new(Vec::new());
+                let summary = subpack::flatten_subpack(Cursor::new(mapped), &mut flattened, tier)
+                    .with_context(|| format!("Error while flattening {input_display}"))?;
+                println!(
+                    "{}",
+                    tr!(
+                        "flattened",
+                        tier = tier,
+                        promoted = summary.promoted,
+                        replaced = summary.replaced,
+                        dropped = summary.dropped
+                    )
+                );
+                flattened_input = flattened.into_inner();
+                &flattened_input[..]
             }
+            None => mapped,
         };
-        let mut tmp_file = tempfile()?;
-        let mut output_file = file_to_shrodinger(&mut tmp_file, opts.yeet)?;
-        println!("Processing input zip {}", opts.file.cyan());
+        if let Some(tier) = &opts.only_subpack {
+            let subpacks = subpack::list_subpacks(Cursor::new(mapped))?;
+            anyhow::ensure!(
+                subpacks.contains(tier),
+                "{input_display} has no subpack {tier}, it has {}",
+                if subpacks.is_empty() { "none".to_string() } else { subpacks.into_iter().collect::<Vec<_>>().join(", ") }
+            );
+        }
+        
+        let zip_targets = targets
+            .iter()
+            .zip(tmp_files.iter_mut())
+            .zip(caches.iter_mut())
+            .map(|((version, tmp_file), cache)| {
+                ZipTarget {
+                    version: version.clone(),
+                    output: file_to_shrodinger(tmp_file.as_mut()),
+                    cache: cache.as_mut(),
+                    store: store.map(|store| store as &dyn EntryStore),
+                }
+            })
+            .collect();
+        let mut reports = zip_update_targets(&mut Cursor::new(mapped), zip_targets, options)?;
+        if opts.flatten_subpack.is_some() {
+            // The materials may be, the pack isn't
+            for report in &mut reports {
+                report.up_to_date = false;
+            }
+        }
+        for report in &reports {
+            check_denied(opts, report)?;
+        }
+        if opts.require_uniform_source {
+            let sources = reports.first().map(ConversionReport::source_versions).unwrap_or_default();
+            anyhow::ensure!(
+                sources.len() <= 1,
+                "{input_display} mixes materials compiled for several versions ({}), it is probably assembled wrong",
+                format_source_versions(&sources)
+            );
+        }
         
-        zip_update(
-            &mut input_file,
-            &mut output_file,
-            &target_mversion,
-            opts.zip_compression,
-            opts.verbose,
-        )?;
+        show_profile(opts, reports.iter().map(|report| (report.version.to_string(), report.entries.as_slice())))?;
         
-        tmp_file.rewind()?;
-        if !opts.yeet {
-            let mut output_file = File::create(output_filename)?;
-            io::copy(&mut tmp_file, &mut output_file)?;
+        if opts.yeet {
+            for (output_filename, report) in output_filenames.iter().zip(&reports) {
+                if let Some(folder) = settings.install {
+                    println!("{}", tr!("dry-run-install", path = folder.join(installed_name(output_filename)).display()));
+                }
+                println!("{}", tr!("dry-run", path = output_filename.display(), target = &report.version));
+                record_output::<SpooledTempFile>(record.as_mut(), report, None)?;
+            }
+        } else {
+            for (((tmp_file, output_filename), cache), report) in tmp_files
+                .into_iter()
+                .zip(output_filenames)
+                .zip(caches)
+                .zip(reports)
+            {
+                let mut tmp_file = tmp_file.expect("only dry runs have no temporary file");
+                if opts.skip_up_to_date && report.up_to_date {
+                    println!("{}", tr!("skipping-output", path = output_filename.display()));
+                    record_output::<SpooledTempFile>(record.as_mut(), &report, None)?;
+                    continue;
+                }
+                check_budget(opts, &mut tmp_file, &output_filename)?;
+                if opts.in_place {
+                    // Written once the input isn't mapped anymore
+                    in_place = Some((tmp_file, report));
+                    continue;
+                }
+                save_converted(opts, &mut tmp_file, &output_filename, &input_name, original, &report)?;
+                report_duplicates(opts, &mut tmp_file, &output_filename)?;
+                record_output(record.as_mut(), &report, Some((&output_filename, &mut tmp_file)))?;
+                install_output(settings, &mut tmp_file, &output_filename)?;
+                if opts.emit_checksums {
+                    tmp_file.rewind()?;
+                    write_checksums(&output_filename, &archive_checksums(&mut tmp_file)?)?;
+                }
+                if opts.verify_output {
+                    let entries = verify_archive(BufReader::new(File::open(&output_filename)?), Cursor::new(mapped), settings.options, &report)
+                        .with_context(|| format!("Error in {}", output_filename.display()))?;
+                    println!("{}", tr!("verified-entries", count = entries, path = output_filename.display()));
+                }
+                if let Some(cache) = cache {
+                    let _lock = disk::lock(cache.path())?;
+                    cache.save()?;
+                }
+                if let Some(path) = &opts.emit_delta {
+                    disk::check_overwrite(path, opts.force)?;
+                    tmp_file.rewind()?;
+                    let mut delta = Scratch::new()?;
+                    let summary = delta::make_delta(Cursor::new(original), &mut tmp_file, &mut delta, &report.version.to_string())?;
+                    save_output(&mut delta, path)?;
+                    println!(
+                        "{}",
+                        tr!(
+                            "wrote-delta",
+                            path = path.display(),
+                            added = summary.added,
+                            replaced = summary.replaced,
+                            removed = summary.removed
+                        )
+                    );
+                }
+                if let Some(path) = &opts.emit_debug_pack {
+                    disk::check_overwrite(path, opts.force)?;
+                    tmp_file.rewind()?;
+                    let patched: HashSet<&str> = report
+                        .entries
+                        .iter()
+                        .filter(|entry| !entry.patches.is_empty())
+                        .map(|entry| entry.name.as_str())
+                        .collect();
+                    let mut debug = Scratch::new()?;
+                    let made = debug_pack::make_debug_pack(Cursor::new(original), &mut tmp_file, &mut debug, &patched)?;
+                    save_output(&mut debug, path)?;
+                    println!("{}", tr!("wrote-debug-pack", path = path.display(), count = made.patched));
+                }
+            }
         }
+    } else {
+        anyhow::bail!("{input_display} is neither a pack nor a material.bin, pass --assume-material if it is a material anyway");
+    }
+    if let Some((mut tmp_file, report)) = in_place {
+        let input_sha256 = sha256_reader(mapped)?;
+        // Files can't be resized while mapped on Windows
+        mapped_file = None;
+        let path = Path::new(&input_name);
+        save_in_place(opts, &mut tmp_file, path, input_sha256, &report)?;
+        install_output(settings, &mut tmp_file, path)?;
+        record_output(record.as_mut(), &report, Some((path, &mut File::open(path)?)))?;
     }
+    drop(mapped_file);
+    finish_record(summary, record, start);
     Ok(())
 }
 
-fn file_to_shrodinger<'a>(
-    file: &'a mut File,
-    dissapear: bool,
-) -> anyhow::Result<ShrodingerOutput<'a>> {
-    if dissapear {
-        Ok(ShrodingerOutput::Nothing)
-    } else {
-        Ok(ShrodingerOutput::File(file))
-    }
+/// Suffix of a material input that output names replace with `.material.bin`:
+/// `.material.bin` itself or a variation of it like `.material.bin.bak` or
+/// `.material_bin`, otherwise the extension, if any
+fn material_postfix(name: &str) -> &str {
+    let file_name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let start = file_name
+        .to_ascii_lowercase()
+        .find(".material")
+        .or_else(|| file_name.rfind('.'))
+        .unwrap_or(file_name.len());
+    &file_name[start..]
 }
 
-fn update_filename(
-    filename: &str,
-    version: &MVersion,
-    postfix: &str,
-) -> anyhow::Result<PathBuf> {
-    let stripped = filename
-        .strip_suffix(postfix)
-        .with_context(|| "String does not contain expected postfix")?;
-    Ok((stripped.to_string() + "_" + &version.to_string() + postfix).into())
+/// Directory --install extracts `output` to, named like it without the extension
+fn installed_name(output: &Path) -> String {
+    let name = output.file_name().map_or("pack".into(), |name| name.to_string_lossy());
+    ARCHIVE_SUFFIXES
+        .iter()
+        .chain(&[".mcpack", ".zip"])
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .unwrap_or(&name)
+        .to_string()
 }
 
-// INLINE SHADER PATCHES
-const LIGHTMAP_26_10_FIX: &[u8] = b"
-vec2 lightmapUtil_26_10_new(vec2 tc1) {
-    return fract(tc1.y * vec2(256.0, 4096.0));
+/// Extract a finished output into the game's pack folder with --install, in a
+/// directory named like the output file
+fn install_output<F: io::Read + Seek>(settings: Settings, tmp_file: &mut F, output: &Path) -> anyhow::Result<()> {
+    let Some(folder) = settings.install else {
+        return Ok(());
+    };
+    tmp_file.rewind()?;
+    let installed = install::install_pack(&mut *tmp_file, folder, &installed_name(output), settings.opts.force)?;
+    println!("{}", tr!("installed-pack", path = installed.display()));
+    Ok(())
 }
-#ifdef a_texcoord1
- #undef a_texcoord1
-#endif
-#define a_texcoord1 lightmapUtil_26_10_new(a_texcoord1)
-";
 
-fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
-    haystack.windows(needle.len()).position(|window| window == needle)
+/// Fail if `report` has warnings --deny turns into errors, listing them
+fn check_denied(opts: &ConvertArgs, report: &ConversionReport) -> anyhow::Result<()> {
+    let denied: Vec<_> = report.warnings.iter().filter(|warning| opts.deny.contains(&warning.code)).collect();
+    if denied.is_empty() {
+        return Ok(());
+    }
+    for warning in &denied {
+        match &warning.entry {
+            Some(entry) => println!("{} {}: {entry}: {}", warning.code.to_string().red(), report.version, warning.message),
+            None => println!("{} {}: {}", warning.code.to_string().red(), report.version, warning.message),
+        }
+    }
+    anyhow::bail!("{} warnings denied by --deny for {}, nothing was written", denied.len(), report.version)
 }
 
-fn replace_bytes(data: &mut Vec<u8>, from: &[u8], to: &[u8]) -> bool {
-    let mut changed = false;
-    while let Some(pos) = find_subsequence(data, from) {
-        data.splice(pos..pos + from.len(), to.iter().cloned());
-        changed = true;
+/// Print the timings of the entries with --profile and write them with --profile-json
+fn show_profile<'a>(
+    opts: &ConvertArgs,
+    tracks: impl IntoIterator<Item = (String, &'a [EntrySummary])>,
+) -> anyhow::Result<()> {
+    if !opts.profile && opts.profile_json.is_none() {
+        return Ok(());
+    }
+    let tracks: Vec<(String, &[EntrySummary])> = tracks.into_iter().collect();
+    if opts.profile {
+        let entries: Vec<EntrySummary> = tracks.iter().flat_map(|(_, entries)| entries.iter().cloned()).collect();
+        profile::print_profile(&entries, 10);
     }
-    changed
+    if let Some(path) = &opts.profile_json {
+        let text = serde_json::to_vec(&profile::trace_events(tracks))?;
+        disk::write_output(path, text.as_slice(), text.len() as u64)?;
+        println!("{}", tr!("wrote-profile", path = path.display()));
+    }
+    Ok(())
 }
 
-fn patch_material(material: &mut CompiledMaterialDefinition, target_version: &MVersion) {
-    let is_26_10 = matches!(target_version, MVersion::V26_10);
+fn finish_record(summary: Option<&Mutex<RunSummary>>, record: Option<InputRecord>, start: Instant) {
+    if let (Some(summary), Some(record)) = (summary, record) {
+        summary.lock().unwrap().inputs.push(record.finish(start.elapsed()));
+    }
+}
 
-    for (_, pass) in material.passes.iter_mut() {
-        for variant in pass.variants.iter_mut() {
-            for (stage, scode) in variant.shader_codes.iter_mut() {
-                let mut bgfx: BgfxShader = match scode.bgfx_shader_data.pread(0) {
-                    Ok(s) => s,
-                    Err(_) => continue,
-                };
+/// Open a local path or download a url, repacking 7z/tar.gz archives into a zip
+fn open_input(file: OsString, limits: &Limits) -> anyhow::Result<(OsString, BufReader<Scratch>)> {
+    if let Some(url) = file.to_str().filter(|file| download::is_url(file)) {
+        println!("{}", tr!("downloading", url = url.cyan()));
+        let (name, input) = download::download(url)?;
+        return repack_input(name.into(), input, limits);
+    }
+    if file.to_string_lossy().starts_with("content://") {
+        anyhow::bail!(
+            "content:// uris can't be opened directly, pipe the file in with --from-stdin-name <NAME> instead"
+        );
+    }
+    let input = File::open(&file).with_context(|| "Error while opening input file")?;
+    repack_input(file, Scratch::File(input), limits)
+}
 
-                let mut changed = false;
+/// Copy stdin into a temporary file and treat it as a file called `name`
+fn read_stdin(name: OsString, limits: &Limits) -> anyhow::Result<(OsString, BufReader<Scratch>)> {
+    let mut input = Scratch::new()?;
+    io::copy(&mut io::stdin().lock(), &mut input).with_context(|| "Error while reading stdin")?;
+    input.rewind()?;
+    repack_input(name, input, limits)
+}
 
-                // 1. Wipe out leftover 1.21.130 math so it doesn't collide with the new 26.10 math
-                if find_subsequence(&bgfx.code, b"65535").is_some() {
-                    if replace_bytes(&mut bgfx.code, b"a_texcoord1 * 65535.0", b"a_texcoord1          ") { changed = true; }
-                    if replace_bytes(&mut bgfx.code, b"a_texcoord1*65535.0", b"a_texcoord1        ") { changed = true; }
-                    if replace_bytes(&mut bgfx.code, b"a_texcoord1 * 65535.", b"a_texcoord1         ") { changed = true; }
-                }
+fn repack_input(
+    input_name: OsString,
+    input_file: Scratch,
+    limits: &Limits,
+) -> anyhow::Result<(OsString, BufReader<Scratch>)> {
+    let mut input_file = BufReader::new(input_file);
+    let display_name = input_name.to_string_lossy();
+    let repacked = match archive::source_for_name(&display_name, &mut input_file) {
+        Some(mut source) => {
+            println!("{}", tr!("extracting", name = display_name.cyan()));
+            let mut repacked = archive::repack_to_zip(source.as_mut(), Scratch::new()?, limits)?;
+            repacked.rewind()?;
+            Some(repacked)
+        }
+        None => None,
+    };
+    if let Some(repacked) = repacked {
+        input_file = BufReader::new(repacked);
+    }
+    Ok((input_name, input_file))
+}
 
-                // 2. Apply the 26.10+ Lightmap Patch
-                if is_26_10 
-                    && stage.stage == materialbin::pass::ShaderStage::Vertex 
-                    && (stage.platform == materialbin::pass::ShaderCodePlatform::Essl100 || stage.platform == materialbin::pass::ShaderCodePlatform::Essl300) 
-                {
-                    if find_subsequence(&bgfx.code, b"vec2(256.0, 4096.0)").is_none() {
-                        if let Some(pos) = find_subsequence(&bgfx.code, b"void main") {
-                            bgfx.code.splice(pos..pos, LIGHTMAP_26_10_FIX.iter().cloned());
-                            changed = true;
-                        }
-                    }
-                }
+/// Parse a byte size like 500, 64K, 256MB or 1G (binary multiples)
+fn parse_size(arg: &str) -> Result<u64, String> {
+    let arg = arg.trim();
+    let split = arg
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(arg.len());
+    let (number, unit) = arg.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("{arg:?} does not start with a number"))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        unit => return Err(format!("Unknown size unit {unit:?}")),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("{arg:?} is too large"))
+}
 
-                if changed {
-                    scode.bgfx_shader_data.clear();
-                    let _ = bgfx.write(&mut scode.bgfx_shader_data);
-                }
-            }
+/// Write `checksums` next to `output` as `<output>.checksums.json`
+fn write_checksums(output: &Path, checksums: &Checksums) -> anyhow::Result<()> {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".checksums.json");
+    let file = File::create(&path).with_context(|| "Error while creating checksum file")?;
+    serde_json::to_writer_pretty(file, checksums)?;
+    println!("{}", tr!("wrote-checksums", path = Path::new(&path).display()));
+    Ok(())
+}
+
+/// Explicit --output, or an automatic name from the output template
+fn output_path(
+    opts: &ConvertArgs,
+    input_name: &OsStr,
+    version: &MVersion,
+    postfix: &str,
+    extension: &str,
+    naming: &OutputNaming,
+) -> anyhow::Result<PathBuf> {
+    let path = match &opts.output {
+        Some(output_name) => output_name.to_owned(),
+        None => {
+            let auto_name = update_filename(input_name, version, postfix, extension, naming)?;
+            println!("{}", tr!("auto-output", path = format!("{:?}", auto_name.display())));
+            auto_name
         }
+    };
+    if !opts.yeet {
+        disk::check_overwrite(&path, opts.force)?;
     }
+    Ok(path)
 }
 
-fn file_update<R, W>(
-    input: &mut R,
-    output: &mut W,
+/// [`output_path`] of the material `name` of a file split with --split, which
+/// goes before the `.material.bin` of the usual name
+fn split_output_path(
+    opts: &ConvertArgs,
+    input_name: &OsStr,
     version: &MVersion,
-    verbose: bool,
-) -> anyhow::Result<()>
-where
-    R: Read + Seek,
-    W: Write + Seek,
-{
-    let mut data = Vec::new();
-    input.read_to_end(&mut data)?;
-    
-    let mut material = read_material(&data, verbose)?;
-    
-    patch_material(&mut material, version);
-    material.write(output, version.as_version())?;
-    
+    postfix: &str,
+    name: &str,
+    naming: &OutputNaming,
+) -> anyhow::Result<PathBuf> {
+    let path = match &opts.output {
+        Some(output_name) => output_name.to_owned(),
+        None => update_filename(input_name, version, postfix, ".material.bin", naming)?,
+    };
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = file_name.strip_suffix(".material.bin").unwrap_or(&file_name);
+    let path = path.with_file_name(format!("{stem}.{}.material.bin", name.replace(['/', '\\'], "_")));
+    println!("{}", tr!("auto-output", path = format!("{:?}", path.display())));
+    if !opts.yeet {
+        disk::check_overwrite(&path, opts.force)?;
+    }
+    Ok(path)
+}
+
+/// Save a finished conversion like [`save_output`], backing up the file it
+/// replaces and recording it for `rollback` unless --no-history is given
+fn save_converted<F: io::Read + Seek>(
+    opts: &ConvertArgs,
+    tmp_file: &mut F,
+    output: &Path,
+    input_name: &OsStr,
+    input: &[u8],
+    report: &ConversionReport,
+) -> anyhow::Result<()> {
+    if opts.no_history {
+        return save_output(tmp_file, output);
+    }
+    // Held until recorded, so a parallel run can't replace the output between
+    // its backup and the write
+    let lock = disk::lock(output)?;
+    let backup = history::backup(output)?;
+    let size = tmp_file.seek(SeekFrom::End(0))?;
+    tmp_file.rewind()?;
+    lock.replace(tmp_file, size)?;
+    record_history(input_name, sha256_reader(input)?, output, report, backup)
+}
+
+/// Measure an output against --size-budget before it is saved, failing with
+/// --strict when it doesn't fit
+fn check_budget<F: io::Read + Seek>(opts: &ConvertArgs, tmp_file: &mut F, output: &Path) -> anyhow::Result<()> {
+    let Some(size_budget) = opts.size_budget else {
+        return Ok(());
+    };
+    let report = budget::measure(tmp_file, size_budget)?;
+    budget::print_budget(&report, &output.display().to_string());
+    anyhow::ensure!(
+        !(opts.strict && report.over()),
+        "{} is over the size budget of {}, it wasn't written",
+        output.display(),
+        progress::format_bytes(size_budget)
+    );
     Ok(())
 }
 
-fn zip_update<R, W>(
-    input: &mut R,
-    output: &mut W,
-    version: &MVersion,
-    compression_level: Option<u32>,
-    verbose: bool,
-) -> anyhow::Result<()>
-where
-    R: Read + Seek,
-    W: Write + Seek,
-{
-    let mut input_zip = ZipArchive::new(input)?;
-    let mut output_zip = ZipWriter::new(output);
-    let mut translated_shaders = 0;
-    let mut warnings = 0;
-    let mut data = Vec::new();
-    
-    for index in 0..input_zip.len() {
-        let mut file = input_zip.by_index(index)?;
-        if !file.name().ends_with(".material.bin") {
-            output_zip.raw_copy_file(file)?;
-            continue;
-        }
-        print!("Processing file {}", file.name().green());
-        data.clear();
-        data.reserve(file.size().try_into()?);
-        file.read_to_end(&mut data)?;
-        
-        let mut material = match read_material(&data, verbose) {
-            Ok(material) => material,
-            Err(_) => {
-                anyhow::bail!("Material file {} is invalid for all versions", file.name());
-            }
-        };
-        
-        patch_material(&mut material, version);
-        sus(&material);
-        
-        let file_options = FileOptions::<ExtendedFileOptions>::default()
-            .compression_level(compression_level.map(|v| v.into()));
-        output_zip.start_file(file.name(), file_options)?;
-        
-        let result = material.write(&mut output_zip, version.as_version());
-        if let Err(err) = result {
-            match err {
-                WriteError::Compat(issue) => {
-                    println!(
-                        "{}:\n{}",
-                        "Ignoring materialbin because of compatibility error:"
-                            .fg::<Yellow>()
-                            .red(),
-                        issue
-                    );
-                    translated_shaders -= 1;
-                    warnings += 1;
-                }
-                _ => return Err(err.into()),
-            }
-            output_zip.abort_file()?;
-        }
-        translated_shaders += 1;
+/// List the shaders the materials of an output store more than once with
+/// --dedup-report
+fn report_duplicates<F: io::Read + Seek>(opts: &ConvertArgs, tmp_file: &mut F, output: &Path) -> anyhow::Result<()> {
+    if !opts.dedup_report {
+        return Ok(());
     }
-    
-    output_zip.finish()?;
-    if warnings != 0 {
-        println!("{}", format!("{warnings} warnings while updating").yellow());
+    tmp_file.rewind()?;
+    let report = dedup::dedup_report(tmp_file)?;
+    let path = output.display();
+    if report.groups.is_empty() {
+        println!("{}", tr!("dedup-none", path = path).dimmed());
+        return Ok(());
     }
-    
-    println!(
-        "Ported {} materials in zip to version {}",
-        translated_shaders.to_string().green(),
-        version.to_string().cyan() 
+    let summary = tr!(
+        "dedup-summary",
+        path = path,
+        duplicates = report.shaders - report.unique,
+        shaders = report.shaders,
+        wasted = progress::format_bytes(report.wasted_bytes() as u64),
+        total = progress::format_bytes(report.code_bytes as u64)
     );
+    println!("{}", summary.yellow());
+    for group in report.groups.iter().take(dedup::LISTED_GROUPS) {
+        let size = progress::format_bytes(group.bytes as u64);
+        println!("{}", tr!("dedup-group", copies = group.copies.len(), size = size, entry = group.entry.cyan()));
+        for copy in group.copies.iter().take(dedup::LISTED_COPIES) {
+            println!("  {copy}");
+        }
+        if group.copies.len() > dedup::LISTED_COPIES {
+            println!("  {}", tr!("dedup-more-copies", count = group.copies.len() - dedup::LISTED_COPIES).dimmed());
+        }
+    }
     Ok(())
 }
 
-fn read_material(data: &[u8], verbose: bool) -> anyhow::Result<CompiledMaterialDefinition> {
-    for version in materialbin::ALL_VERSIONS {
-        match data.pread_with(0, version) {
-            Ok(material) => {
-                print!("{}", format!(" [{version}]\n").dimmed());
-                return Ok(material);
-            }
-            Err(e) => {
-                if verbose {
-                    println!("Failed [{version}] {}", &e);
-                }
-            }
+/// Write the entries of the converted pack `tmp_file` that changed into the
+/// input pack `path` itself with --in-place, backed up like any output
+fn save_in_place<F: io::Read + Seek>(
+    opts: &ConvertArgs,
+    tmp_file: &mut F,
+    path: &Path,
+    input_sha256: String,
+    report: &ConversionReport,
+) -> anyhow::Result<()> {
+    let _lock = disk::lock(path)?;
+    let backup = if opts.no_history { None } else { history::backup(path)? };
+    // The pack is edited where it is, a failed update is undone from a copy.
+    // Without a history backup the copy is only kept until the update is done
+    let scratch = match backup {
+        Some(_) => None,
+        None => {
+            let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            let scratch = NamedTempFile::new_in(dir).with_context(|| format!("Error while creating a temporary file in {}", dir.display()))?;
+            fs::copy(path, scratch.path()).with_context(|| format!("Error while backing up {}", path.display()))?;
+            Some(scratch)
+        }
+    };
+    let copy = backup.as_deref().or(scratch.as_ref().map(NamedTempFile::path)).expect("copied when there is no backup");
+    let updated = (|| {
+        let mut archive = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Error while opening {}", path.display()))?;
+        tmp_file.rewind()?;
+        let update = in_place::update_in_place(&mut archive, tmp_file)
+            .with_context(|| format!("Error while updating {}", path.display()))?;
+        archive.set_len(update.length)?;
+        archive.sync_all()?;
+        anyhow::Ok(update)
+    })();
+    let update = match updated {
+        Ok(update) => update,
+        Err(err) => {
+            fs::copy(copy, path).with_context(|| format!("{err:#}, and restoring {} from {} failed", path.display(), copy.display()))?;
+            return Err(err);
         }
+    };
+    println!(
+        "{}",
+        tr!(
+            "updated-in-place",
+            path = path.display(),
+            replaced = update.replaced,
+            added = update.added,
+            removed = update.removed,
+            kept = update.kept
+        )
+    );
+    if opts.no_history {
+        return Ok(());
+    }
+    record_history(path.as_os_str(), input_sha256, path, report, backup)
+}
+
+/// Record a conversion that wrote `output` for `rollback`
+fn record_history(
+    input_name: &OsStr,
+    input_sha256: String,
+    output: &Path,
+    report: &ConversionReport,
+    backup: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let mut patches: Vec<String> = report
+        .entries
+        .iter()
+        .flat_map(|entry| entry.patches.iter().map(|(name, _)| name.to_string()))
+        .collect();
+    patches.sort();
+    patches.dedup();
+    history::record(&history::Conversion {
+        timestamp: chrono::Local::now().to_rfc3339(),
+        input: input_name.to_string_lossy().into_owned(),
+        input_sha256,
+        output: history::absolute(output)?,
+        target: report.version.to_string(),
+        patches,
+        backup,
+    })
+}
+
+/// Move a finished output from its temporary file to `output`
+fn save_output<F: io::Read + Seek>(tmp_file: &mut F, output: &Path) -> anyhow::Result<()> {
+    let size = tmp_file.seek(SeekFrom::End(0))?;
+    tmp_file.rewind()?;
+    disk::write_output(output, tmp_file, size)
+}
+
+/// Output writing to `file`, or discarding everything in a dry run without one
+fn file_to_shrodinger(file: Option<&mut SpooledTempFile>) -> ShrodingerOutput<'_> {
+    match file {
+        Some(file) => ShrodingerOutput::File(file),
+        None => ShrodingerOutput::Nothing,
+    }
+}
+
+/// `name` without `suffix`, keeping names that aren't valid UTF-8 intact
+fn strip_os_suffix<'a>(name: &'a OsStr, suffix: &str) -> Option<&'a OsStr> {
+    if suffix.is_empty() {
+        return Some(name);
     }
+    let stripped = name.as_encoded_bytes().strip_suffix(suffix.as_bytes())?;
+    // SAFETY: the bytes come from `as_encoded_bytes` and are split right before a
+    // non-empty valid UTF-8 substring
+    Some(unsafe { OsStr::from_encoded_bytes_unchecked(stripped) })
+}
 
-    anyhow::bail!("Material file is invalid");
+fn update_filename(
+    filename: &OsStr,
+    version: &MVersion,
+    postfix: &str,
+    extension: &str,
+    naming: &OutputNaming,
+) -> anyhow::Result<PathBuf> {
+    let stripped = Path::new(
+        strip_os_suffix(filename, postfix)
+            .with_context(|| "String does not contain expected postfix")?,
+    );
+    let stem = stripped
+        .file_name()
+        .with_context(|| "Input file has no name")?;
+    let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let target = version.to_string();
+    let name = render_template(
+        naming.template,
+        &[
+            ("stem", stem),
+            ("ext", extension.as_ref()),
+            ("target", target.as_ref()),
+            ("source", naming.source.as_deref().unwrap_or("unknown").as_ref()),
+            ("date", date.as_ref()),
+        ],
+    )?;
+    let dir = naming.dir.or(stripped.parent()).unwrap_or(Path::new(""));
+    Ok(dir.join(name))
 }
 
+/// Replace `{key}` placeholders in `template` with their values
+fn render_template(template: &str, values: &[(&str, &OsStr)]) -> anyhow::Result<OsString> {
+    let mut out = OsString::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .with_context(|| format!("Unclosed placeholder in template {template:?}"))?;
+        let key = &rest[start + 1..start + end];
+        let (_, value) = values
+            .iter()
+            .find(|(name, _)| *name == key)
+            .with_context(|| format!("Unknown placeholder {{{key}}} in template {template:?}"))?;
+        out.push(value);
+        rest = &rest[start + end + 1..];
+    }
+    out.push(rest);
+    Ok(out)
+}
 enum ShrodingerOutput<'a> {
-    File(&'a mut File),
+    File(&'a mut SpooledTempFile),
     Nothing,
 }
 
@@ -408,14 +2822,3 @@ impl<'a> Seek for ShrodingerOutput<'a> {
         }
     }
 }
-
-fn sus(mt: &CompiledMaterialDefinition) {
-    for (_, code) in mt
-        .passes
-        .iter()
-        .flat_map(|(_, pass)| &pass.variants)
-        .flat_map(|variants| &variants.shader_codes)
-    {
-        let _sh: BgfxShader = code.bgfx_shader_data.pread(0).unwrap();
-    }
-}
@@ -0,0 +1,81 @@
+use std::io::{Read, Write};
+
+use materialbin::CompiledMaterialDefinition;
+
+use crate::detect_material;
+
+/// What [`merge_materials`] took from the overlay
+#[derive(Default)]
+pub struct MergeSummary {
+    /// Passes only the overlay has
+    pub added_passes: usize,
+    /// Variants only the overlay has
+    pub added_variants: usize,
+    /// Variants both have, replaced by the overlay's shaders
+    pub replaced_variants: usize,
+}
+
+/// Merge the passes and variants of `overlay` into `base`, the overlay wins
+/// wherever both define something. Variants are matched by their flags, a
+/// matched variant keeps the shaders of platforms the overlay lacks
+pub fn merge_materials(base: &mut CompiledMaterialDefinition, overlay: CompiledMaterialDefinition) -> MergeSummary {
+    let mut summary = MergeSummary::default();
+    base.sampler_definitions.extend(overlay.sampler_definitions);
+    base.property_fields.extend(overlay.property_fields);
+    for (pass_name, overlay_pass) in overlay.passes {
+        let Some(base_pass) = base.passes.get_mut(&pass_name) else {
+            base.passes.insert(pass_name, overlay_pass);
+            summary.added_passes += 1;
+            continue;
+        };
+        base_pass.bitset = overlay_pass.bitset;
+        base_pass.fallback = overlay_pass.fallback;
+        base_pass.default_variant = overlay_pass.default_variant;
+        for overlay_variant in overlay_pass.variants {
+            match base_pass
+                .variants
+                .iter_mut()
+                .find(|variant| variant.flags == overlay_variant.flags)
+            {
+                Some(variant) => {
+                    variant.is_supported = overlay_variant.is_supported;
+                    variant.shader_codes.extend(overlay_variant.shader_codes);
+                    summary.replaced_variants += 1;
+                }
+                None => {
+                    base_pass.variants.push(overlay_variant);
+                    summary.added_variants += 1;
+                }
+            }
+        }
+    }
+    summary
+}
+
+/// Merge the material.bin `overlay` into `base` and write the result in the
+/// binary version `base` was read as
+pub fn merge<B, O, W>(base: &mut B, overlay: &mut O, output: &mut W) -> anyhow::Result<MergeSummary>
+where
+    B: Read,
+    O: Read,
+    W: Write,
+{
+    let mut data = Vec::new();
+    base.read_to_end(&mut data)?;
+    let (mut material, version) = detect_material(&data)?;
+    data.clear();
+    overlay.read_to_end(&mut data)?;
+    let (overlay, overlay_version) = detect_material(&data)?;
+    if overlay.name != material.name {
+        log::warn!("Merging material {} into {}", overlay.name, material.name);
+    }
+    if overlay_version.to_string() != version.to_string() {
+        log::warn!("Overlay is a {overlay_version} material, its shaders are written as {version}");
+    }
+    let summary = merge_materials(&mut material, overlay);
+    // Serialized in memory first, the writer makes many tiny writes
+    data.clear();
+    material.write(&mut data, version)?;
+    output.write_all(&data)?;
+    Ok(summary)
+}
@@ -0,0 +1,206 @@
+//! How to convert, for [`file_update`](crate::file_update),
+//! [`zip_update`](crate::zip_update) and the functions built on them. Made with
+//! [`ConvertOptions::builder`], so new settings can be added without breaking
+//! callers that don't set them
+
+use crate::{
+    hooks::ConversionHooks, limits::Limits, patches::PatchSelection, rename::NameRename, vanilla::VanillaMaterials,
+    zip_names::ZipEncoding, DuplicateEntries, IncompatStrategy, JsonMaterials,
+};
+
+/// Highest zip compression level the deflate writer takes
+pub const MAX_COMPRESSION_LEVEL: u32 = 9;
+
+/// Everything about a conversion but the target version, which one set of
+/// options can be used with several of. The default converts like the command
+/// line does without flags
+#[derive(Clone, Default)]
+pub struct ConvertOptions<'a> {
+    pub(crate) compression_level: Option<u32>,
+    pub(crate) keep_going: bool,
+    pub(crate) renames: Vec<NameRename>,
+    pub(crate) patches: PatchSelection,
+    pub(crate) on_incompat: IncompatStrategy,
+    pub(crate) vanilla: Option<&'a VanillaMaterials>,
+    pub(crate) limits: Limits,
+    pub(crate) lowercase_paths: bool,
+    pub(crate) duplicates: DuplicateEntries,
+    pub(crate) material_renames: Vec<NameRename>,
+    pub(crate) only_subpack: Option<String>,
+    pub(crate) stamp: bool,
+    pub(crate) json_materials: JsonMaterials,
+    pub(crate) zip_encoding: ZipEncoding,
+    pub(crate) hooks: Option<&'a dyn ConversionHooks>,
+}
+
+impl<'a> ConvertOptions<'a> {
+    pub fn builder() -> ConvertOptionsBuilder<'a> {
+        ConvertOptionsBuilder::default()
+    }
+
+    /// Zip compression level of converted archives, the writer's default if `None`
+    pub fn compression_level(&self) -> Option<u32> {
+        self.compression_level
+    }
+
+    pub fn renames(&self) -> &[NameRename] {
+        &self.renames
+    }
+
+    pub fn patches(&self) -> &PatchSelection {
+        &self.patches
+    }
+
+    pub fn on_incompat(&self) -> IncompatStrategy {
+        self.on_incompat
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn material_renames(&self) -> &[NameRename] {
+        &self.material_renames
+    }
+}
+
+/// Builds [`ConvertOptions`], checking them in [`build`](Self::build)
+#[derive(Clone, Default)]
+pub struct ConvertOptionsBuilder<'a> {
+    options: ConvertOptions<'a>,
+}
+
+impl<'a> ConvertOptionsBuilder<'a> {
+    /// Zip compression level of converted archives, 0 to 9
+    pub fn compression_level(mut self, level: Option<u32>) -> Self {
+        self.options.compression_level = level;
+        self
+    }
+
+    /// Copy materials that fail to convert instead of failing the archive
+    pub fn keep_going(mut self, keep_going: bool) -> Self {
+        self.options.keep_going = keep_going;
+        self
+    }
+
+    /// Uniforms and samplers to rename, on top of the renames known for the target
+    pub fn renames(mut self, renames: Vec<NameRename>) -> Self {
+        self.options.renames = renames;
+        self
+    }
+
+    pub fn patches(mut self, patches: PatchSelection) -> Self {
+        self.options.patches = patches;
+        self
+    }
+
+    /// Run `patch` even when the versions would not select it
+    pub fn enable_patch(mut self, patch: &str) -> Self {
+        self.options.patches.enabled.push(patch.to_string());
+        self
+    }
+
+    /// Never run `patch`
+    pub fn disable_patch(mut self, patch: &str) -> Self {
+        self.options.patches.disabled.push(patch.to_string());
+        self
+    }
+
+    pub fn on_incompat(mut self, strategy: IncompatStrategy) -> Self {
+        self.options.on_incompat = strategy;
+        self
+    }
+
+    /// Leave materials identical to these vanilla ones out of archives
+    pub fn vanilla(mut self, vanilla: Option<&'a VanillaMaterials>) -> Self {
+        self.options.vanilla = vanilla;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.options.limits = limits;
+        self
+    }
+
+    /// Write archive entry paths in lowercase
+    pub fn lowercase_paths(mut self, lowercase: bool) -> Self {
+        self.options.lowercase_paths = lowercase;
+        self
+    }
+
+    /// Which of several archive entries with the same name is kept
+    pub fn duplicates(mut self, duplicates: DuplicateEntries) -> Self {
+        self.options.duplicates = duplicates;
+        self
+    }
+
+    /// Materials to rename, both their name and their entry
+    pub fn material_renames(mut self, renames: Vec<NameRename>) -> Self {
+        self.options.material_renames = renames;
+        self
+    }
+
+    /// Only convert the materials of this subpack, copying the others
+    pub fn only_subpack(mut self, subpack: Option<String>) -> Self {
+        self.options.only_subpack = subpack;
+        self
+    }
+
+    /// Record the tool, target, source versions and patches in packs, see
+    /// [`stamp`](crate::stamp)
+    pub fn stamp(mut self, stamp: bool) -> Self {
+        self.options.stamp = stamp;
+        self
+    }
+
+    /// What to do with the JSON files next to the binary materials
+    pub fn json_materials(mut self, mode: JsonMaterials) -> Self {
+        self.options.json_materials = mode;
+        self
+    }
+
+    /// What entry names not marked as UTF-8 are in, see [`zip_names`](crate::zip_names)
+    pub fn zip_encoding(mut self, encoding: ZipEncoding) -> Self {
+        self.options.zip_encoding = encoding;
+        self
+    }
+
+    /// Called as the conversion goes, see [`hooks`](crate::hooks)
+    pub fn hooks(mut self, hooks: &'a dyn ConversionHooks) -> Self {
+        self.options.hooks = Some(hooks);
+        self
+    }
+
+    /// The options, if the compression level is in range and every patch named
+    /// exists
+    pub fn build(self) -> anyhow::Result<ConvertOptions<'a>> {
+        if let Some(level) = self.options.compression_level {
+            anyhow::ensure!(
+                level <= MAX_COMPRESSION_LEVEL,
+                "Compression level {level} is out of range, use 0 to {MAX_COMPRESSION_LEVEL}"
+            );
+        }
+        self.options.patches.validate()?;
+        if let Some(subpack) = &self.options.only_subpack {
+            anyhow::ensure!(!subpack.is_empty(), "The subpack to convert has no name");
+        }
+        Ok(self.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_and_validates() {
+        let options = ConvertOptions::builder().compression_level(Some(6)).keep_going(true).build().unwrap();
+        assert_eq!(options.compression_level(), Some(6));
+        assert!(options.keep_going);
+        assert_eq!(options.on_incompat(), IncompatStrategy::Skip);
+
+        assert!(ConvertOptions::builder().compression_level(Some(10)).build().is_err());
+        assert!(ConvertOptions::builder().enable_patch("no-such-patch").build().is_err());
+        assert!(ConvertOptions::builder().only_subpack(Some(String::new())).build().is_err());
+    }
+}
@@ -0,0 +1,1022 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+};
+
+use anyhow::Context;
+use materialbin::{
+    bgfx_shader::BgfxShader,
+    pass::{PlatformShaderStage, ShaderCodePlatform, ShaderStage, Variant},
+    CompiledMaterialDefinition, MinecraftVersion,
+};
+use rayon::prelude::*;
+use regex::bytes::Regex;
+use scroll::Pread;
+
+use crate::{find_subsequence, preamble::Preamble, pretty::minify_glsl, rules::rewrite, MVersion, UpdateError};
+
+/// A named shader fix applied while converting
+pub trait Patch: Send + Sync {
+    /// Name used by `--enable-patch`, `--disable-patch` and `list-patches`
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    /// Target versions the patch runs for, `None` for all of them
+    fn targets(&self) -> Option<&'static [MVersion]> {
+        None
+    }
+
+    /// Source binary versions the patch runs for, `None` for all of them
+    fn sources(&self) -> Option<&'static [MinecraftVersion]> {
+        None
+    }
+
+    /// Whether the patch should look at the material called `name`
+    fn matches_material(&self, _name: &str) -> bool {
+        true
+    }
+
+    /// Lightmap fixes only run on the materials picked by `--lightmap-materials`
+    fn fixes_lightmap(&self) -> bool {
+        false
+    }
+
+    /// Code only present in shaders the patch was applied to. Shaders that
+    /// contain it are skipped, and materials converted for a version the patch
+    /// isn't selected for get a conflict warning
+    fn fingerprint(&self) -> Option<&'static [u8]> {
+        None
+    }
+
+    /// Undo the patch in a shader it was applied to, returns whether `code` changed
+    fn strip(&self, _code: &mut Vec<u8>) -> bool {
+        false
+    }
+
+    /// Returns what happened to the shaders of the material
+    fn apply(&self, material: &mut CompiledMaterialDefinition) -> anyhow::Result<PatchOutcome>;
+}
+
+/// What a patch did to the shaders of one material
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PatchOutcome {
+    pub patched: usize,
+    /// Shaders that already contained the fix
+    pub already_patched: usize,
+    /// Shaders that needed the fix but didn't contain the code it hooks into
+    pub missing: usize,
+    /// Shaders in scope that hold bytecode or another language the patch can't edit
+    pub unsupported: usize,
+}
+
+impl PatchOutcome {
+    pub fn changed(&self) -> bool {
+        self.patched != 0
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl fmt::Display for PatchOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if self.patched != 0 {
+            parts.push(format!("{} patched", self.patched));
+        }
+        if self.already_patched != 0 {
+            parts.push(format!("{} already patched", self.already_patched));
+        }
+        if self.missing != 0 {
+            parts.push(format!("{} could not be fixed", self.missing));
+        }
+        if self.unsupported != 0 {
+            parts.push(format!("{} cannot be patched automatically", self.unsupported));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Outcome of every patch that touched a material
+#[derive(Default)]
+pub struct PatchReport {
+    pub outcomes: Vec<(&'static str, PatchOutcome)>,
+    /// Whether any shader changed, including stripped conflicting patches
+    pub changed: bool,
+}
+
+impl PatchReport {
+    /// Shaders some patch could not fix
+    pub fn missing(&self) -> usize {
+        self.outcomes.iter().map(|(_, outcome)| outcome.missing).sum()
+    }
+
+    /// Shaders some patch could not edit because of their language
+    pub fn unsupported(&self) -> usize {
+        self.outcomes.iter().map(|(_, outcome)| outcome.unsupported).sum()
+    }
+}
+
+/// Every built-in patch, in the order they are applied
+pub const PATCHES: &[&dyn Patch] = &[
+    &LightmapCleanup,
+    &LightmapPacking26_10,
+    &FragmentLightmapCleanup26_10,
+];
+
+pub fn find_patch(name: &str) -> Option<&'static dyn Patch> {
+    PATCHES
+        .iter()
+        .copied()
+        .find(|patch| patch.name().eq_ignore_ascii_case(name))
+}
+
+/// Built-in patches that run by default when converting to `target`
+pub fn default_patches(target: &MVersion) -> impl Iterator<Item = &'static dyn Patch> + '_ {
+    PATCHES
+        .iter()
+        .copied()
+        .filter(move |patch| patch.targets().is_none_or(|targets| targets.contains(target)))
+}
+
+/// Patches forced on or off by the user, on top of the version defaults
+#[derive(Clone, Default)]
+pub struct PatchSelection {
+    /// Run even when the versions would not select them
+    pub enabled: Vec<String>,
+    pub disabled: Vec<String>,
+    /// Extra patches that run after the built-in ones, e.g. plugins
+    pub extra: Vec<Arc<dyn Patch>>,
+    /// Undo conflicting patches left over from converting for another version
+    pub strip_conflicts: bool,
+    /// Material name globs the lightmap fixes are limited to, all materials if empty
+    pub lightmap_materials: Vec<String>,
+}
+
+impl PatchSelection {
+    /// Fail on names that are neither built-in nor extra patches
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for name in self.enabled.iter().chain(&self.disabled) {
+            let extra = self
+                .extra
+                .iter()
+                .any(|patch| patch.name().eq_ignore_ascii_case(name));
+            anyhow::ensure!(
+                extra || find_patch(name).is_some(),
+                "Unknown patch {name:?}, see list-patches"
+            );
+        }
+        Ok(())
+    }
+
+    fn contains(list: &[String], patch: &dyn Patch) -> bool {
+        list.iter().any(|name| name.eq_ignore_ascii_case(patch.name()))
+    }
+
+    /// Whether `patch` should look at the material called `name`
+    fn selects_material(&self, patch: &dyn Patch, name: &str) -> bool {
+        patch.matches_material(name)
+            && (!patch.fixes_lightmap()
+                || self.lightmap_materials.is_empty()
+                || self.lightmap_materials.iter().any(|glob| glob_matches(glob, name)))
+    }
+
+    /// Whether `patch` runs for a material read as `source` and written for `target`
+    pub fn selects(&self, patch: &dyn Patch, source: MinecraftVersion, target: &MVersion) -> bool {
+        if Self::contains(&self.disabled, patch) {
+            return false;
+        }
+        if Self::contains(&self.enabled, patch) {
+            return true;
+        }
+        patch.targets().is_none_or(|targets| targets.contains(target))
+            && patch.sources().is_none_or(|sources| sources.contains(&source))
+    }
+}
+
+/// Apply the selected patches for a material read as `source` and converted to
+/// `target`
+pub fn patch_material(
+    material: &mut CompiledMaterialDefinition,
+    source: MinecraftVersion,
+    target: &MVersion,
+    selection: &PatchSelection,
+) -> Result<PatchReport, UpdateError> {
+    let mut report = PatchReport::default();
+    let extra = selection.extra.iter().map(|patch| patch.as_ref());
+    let patches: Vec<&dyn Patch> = PATCHES.iter().copied().chain(extra).collect();
+    for &patch in &patches {
+        if !selection.selects(patch, source, target) {
+            report.changed |= resolve_conflict(material, patch, selection.strip_conflicts)
+                .map_err(|err| patch_failed(patch, &material.name, err))?;
+        }
+    }
+    for patch in patches {
+        if !selection.selects(patch, source, target) || !selection.selects_material(patch, &material.name) {
+            continue;
+        }
+        let outcome = patch
+            .apply(material)
+            .map_err(|err| patch_failed(patch, &material.name, err))?;
+        report.changed |= outcome.changed();
+        if !outcome.is_empty() {
+            report.outcomes.push((patch.name(), outcome));
+        }
+    }
+    Ok(report)
+}
+
+fn patch_failed(patch: &dyn Patch, material: &str, source: anyhow::Error) -> UpdateError {
+    let variant = source.downcast_ref::<ShaderLocation>().map(ToString::to_string);
+    UpdateError::PatchFailed {
+        patch: patch.name(),
+        material: material.to_string(),
+        variant,
+        source,
+    }
+}
+
+/// Pass and variant flags of a shader, attached by [`edit_shaders`] to the
+/// errors of the shader it was editing
+#[derive(Debug)]
+struct ShaderLocation {
+    pass: String,
+    flags: String,
+}
+
+impl fmt::Display for ShaderLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.pass, self.flags)
+    }
+}
+
+/// Match a material name against a glob where `*` is any run of characters and
+/// `?` a single one. A `.material.bin` suffix on the glob is ignored.
+fn glob_matches(glob: &str, name: &str) -> bool {
+    fn matches(glob: &[char], name: &[char]) -> bool {
+        match glob.split_first() {
+            None => name.is_empty(),
+            Some(('*', rest)) => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+            Some(('?', rest)) => !name.is_empty() && matches(rest, &name[1..]),
+            Some((c, rest)) => name.first() == Some(c) && matches(rest, &name[1..]),
+        }
+    }
+    let glob: Vec<char> = glob.trim_end_matches(".material.bin").chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches(&glob, &name)
+}
+
+/// Number of shaders in the material containing `fingerprint`
+pub(crate) fn count_fingerprint(material: &CompiledMaterialDefinition, fingerprint: &[u8]) -> usize {
+    material
+        .passes
+        .values()
+        .flat_map(|pass| &pass.variants)
+        .flat_map(|variant| variant.shader_codes.values())
+        .filter_map(|scode| scode.bgfx_shader_data.pread::<BgfxShader>(0).ok())
+        .filter(|bgfx| find_subsequence(&bgfx.code, fingerprint).is_some())
+        .count()
+}
+
+/// Warn about (or strip, if `strip`) a patch that isn't selected for this
+/// conversion but was applied to the material before, returns whether the
+/// material changed
+fn resolve_conflict(material: &mut CompiledMaterialDefinition, patch: &dyn Patch, strip: bool) -> anyhow::Result<bool> {
+    let Some(fingerprint) = patch.fingerprint() else {
+        return Ok(false);
+    };
+    let found = count_fingerprint(material, fingerprint);
+    if found == 0 {
+        return Ok(false);
+    }
+    if !strip {
+        log::warn!(
+            "{}: {found} shaders contain patch {}, which is not selected for this target, use --strip-conflicting-patches to remove it",
+            material.name,
+            patch.name()
+        );
+        return Ok(false);
+    }
+    let stripped = edit_shaders(material, |_, code| {
+        if patch.strip(code) {
+            ShaderEdit::Changed
+        } else {
+            ShaderEdit::Unchanged
+        }
+    })
+    .with_context(|| format!("Stripping patch {} failed", patch.name()))?
+    .patched;
+    log::info!("{}: stripped patch {} from {stripped} shaders", material.name, patch.name());
+    if stripped < found {
+        log::warn!(
+            "{}: patch {} could not be stripped from {} shaders",
+            material.name,
+            patch.name(),
+            found - stripped
+        );
+    }
+    Ok(stripped != 0)
+}
+
+/// Like [`edit_shaders`], skipping shaders that already contain the
+/// fingerprint of `patch`
+pub(crate) fn edit_unpatched_shaders<F>(
+    patch: &dyn Patch,
+    material: &mut CompiledMaterialDefinition,
+    edit: F,
+) -> anyhow::Result<PatchOutcome>
+where
+    F: Fn(&ShaderContext, &mut Vec<u8>) -> ShaderEdit + Sync,
+{
+    edit_shaders(material, |context, code| match patch.fingerprint() {
+        Some(fingerprint) if find_subsequence(code, fingerprint).is_some() => {
+            log::debug!(
+                "{}/{}: {} already applied ({:?})",
+                context.material,
+                context.pass,
+                patch.name(),
+                context.stage.platform
+            );
+            ShaderEdit::AlreadyPatched
+        }
+        _ => edit(context, code),
+    })
+}
+
+/// Where a shader handed to [`edit_shaders`] or
+/// [`ForEachShader::for_each_shader`] comes from
+pub struct ShaderContext<'a> {
+    pub material: &'a str,
+    pub pass: &'a str,
+    /// Flags of the variant, as `(flag, value)`
+    pub flags: &'a [(&'a str, &'a str)],
+    pub stage: &'a PlatformShaderStage,
+}
+
+impl ShaderContext<'_> {
+    pub fn language(&self) -> ShaderLanguage {
+        ShaderLanguage::of(&self.stage.platform)
+    }
+
+    /// Value of `flag` in the variant
+    pub fn flag(&self, flag: &str) -> Option<&str> {
+        self.flags.iter().find(|(name, _)| *name == flag).map(|(_, value)| *value)
+    }
+}
+
+/// Custom shader transforms for tools built on the library, going through the
+/// same code the built-in patches use
+pub trait ForEachShader {
+    /// Run `transform` on the code of every readable shader, returning whether
+    /// it changed it. Text shaders are handed over without BOM and with `\n`
+    /// line endings, which are restored when written back, and changed shaders
+    /// are checked to still be readable. Returns how many shaders changed
+    fn for_each_shader<F>(&mut self, transform: F) -> anyhow::Result<usize>
+    where
+        F: Fn(&ShaderContext, &mut Vec<u8>) -> bool + Sync;
+}
+
+impl ForEachShader for CompiledMaterialDefinition {
+    fn for_each_shader<F>(&mut self, transform: F) -> anyhow::Result<usize>
+    where
+        F: Fn(&ShaderContext, &mut Vec<u8>) -> bool + Sync,
+    {
+        let outcome = edit_shaders(self, |context, code| {
+            if transform(context, code) {
+                ShaderEdit::Changed
+            } else {
+                ShaderEdit::Unchanged
+            }
+        })?;
+        Ok(outcome.patched)
+    }
+}
+
+/// What a shader edit did to the code
+pub(crate) enum ShaderEdit {
+    Unchanged,
+    /// Left alone because the fix is already there
+    AlreadyPatched,
+    /// Left alone because the code the fix hooks into is missing
+    Missing,
+    /// Left alone because the shader isn't in a language the patch can edit
+    Unsupported,
+    Changed,
+    /// Changed by injecting code that contains this marker exactly once
+    Injected(&'static [u8]),
+}
+
+/// How a shader's text was stored. Edits see it without a BOM and with `\n`
+/// line endings, so byte patterns match however the shader was authored, and
+/// it is written back the way it was
+#[derive(Default)]
+struct TextStyle {
+    bom: bool,
+    crlf: bool,
+}
+
+impl TextStyle {
+    const BOM: &'static [u8] = b"\xEF\xBB\xBF";
+
+    fn normalize(code: &mut Vec<u8>) -> Self {
+        let bom = code.starts_with(Self::BOM);
+        if bom {
+            code.drain(..Self::BOM.len());
+        }
+        let crlf = find_subsequence(code, b"\r\n").is_some();
+        if crlf {
+            let mut normalized = Vec::with_capacity(code.len());
+            let mut bytes = code.iter().peekable();
+            while let Some(&byte) = bytes.next() {
+                if byte != b'\r' || bytes.peek() != Some(&&b'\n') {
+                    normalized.push(byte);
+                }
+            }
+            *code = normalized;
+        }
+        Self { bom, crlf }
+    }
+
+    /// Undo [`TextStyle::normalize`]. Code that mixed line endings comes back
+    /// with CRLF everywhere
+    fn restore(&self, code: &mut Vec<u8>) {
+        if self.crlf {
+            let mut restored = Vec::with_capacity(code.len() + code.len() / 32);
+            for &byte in code.iter() {
+                if byte == b'\n' {
+                    restored.push(b'\r');
+                }
+                restored.push(byte);
+            }
+            *code = restored;
+        }
+        if self.bom {
+            code.splice(..0, Self::BOM.iter().copied());
+        }
+    }
+}
+
+static PARALLEL: AtomicBool = AtomicBool::new(false);
+
+/// Patch the variants of a material on `threads` threads, the variants of
+/// large materials like RenderChunk being independent. Call it once, before
+/// converting
+pub fn set_threads(threads: usize) {
+    if threads > 1 {
+        if let Err(err) = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global() {
+            log::debug!("Thread pool already set up: {err}");
+        }
+    }
+    PARALLEL.store(threads > 1, Ordering::Relaxed);
+}
+
+impl std::ops::AddAssign for PatchOutcome {
+    fn add_assign(&mut self, other: Self) {
+        self.patched += other.patched;
+        self.already_patched += other.already_patched;
+        self.missing += other.missing;
+        self.unsupported += other.unsupported;
+    }
+}
+
+/// Run `edit` on the code of every readable shader, writing back and checking
+/// the ones it changed. Variants are edited in parallel after [`set_threads`],
+/// errors are those of the first failing variant either way
+pub(crate) fn edit_shaders<F>(material: &mut CompiledMaterialDefinition, edit: F) -> anyhow::Result<PatchOutcome>
+where
+    F: Fn(&ShaderContext, &mut Vec<u8>) -> ShaderEdit + Sync,
+{
+    let material_name = &material.name;
+    let mut variants: Vec<(&str, &mut Variant)> = material
+        .passes
+        .iter_mut()
+        .flat_map(|(pass_name, pass)| pass.variants.iter_mut().map(move |variant| (pass_name.as_str(), variant)))
+        .collect();
+    let edit_variant = |(pass_name, variant): &mut (&str, &mut Variant)| edit_variant(material_name, pass_name, variant, &edit);
+    let outcomes: Vec<_> = if PARALLEL.load(Ordering::Relaxed) && variants.len() > 1 {
+        variants.par_iter_mut().map(edit_variant).collect()
+    } else {
+        variants.iter_mut().map(edit_variant).collect()
+    };
+    let mut outcome = PatchOutcome::default();
+    for variant in outcomes {
+        outcome += variant?;
+    }
+    Ok(outcome)
+}
+
+/// [`edit_shaders`] for the shaders of one variant
+fn edit_variant<F>(material_name: &str, pass_name: &str, variant: &mut Variant, edit: &F) -> anyhow::Result<PatchOutcome>
+where
+    F: Fn(&ShaderContext, &mut Vec<u8>) -> ShaderEdit,
+{
+    let mut outcome = PatchOutcome::default();
+    let flags: Vec<(&str, &str)> = variant.flags.iter().map(|(flag, value)| (flag.as_str(), value.as_str())).collect();
+    for (stage, scode) in variant.shader_codes.iter_mut() {
+        let mut bgfx: BgfxShader = match scode.bgfx_shader_data.pread(0) {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        // Compiled bytecode may contain any bytes, only text is normalized
+        let style = match ShaderLanguage::of(&stage.platform) {
+            ShaderLanguage::Glsl | ShaderLanguage::Essl | ShaderLanguage::Metal => {
+                TextStyle::normalize(&mut bgfx.code)
+            }
+            _ => TextStyle::default(),
+        };
+        #[cfg(feature = "validate-glsl")]
+        let original = bgfx.code.clone();
+        let context = ShaderContext {
+            material: material_name,
+            pass: pass_name,
+            flags: &flags,
+            stage,
+        };
+        let injected = match edit(&context, &mut bgfx.code) {
+            ShaderEdit::Unchanged => continue,
+            ShaderEdit::AlreadyPatched => {
+                outcome.already_patched += 1;
+                continue;
+            }
+            ShaderEdit::Missing => {
+                outcome.missing += 1;
+                continue;
+            }
+            ShaderEdit::Unsupported => {
+                outcome.unsupported += 1;
+                continue;
+            }
+            ShaderEdit::Changed => None,
+            ShaderEdit::Injected(marker) => Some(marker),
+        };
+        outcome.patched += 1;
+        let location = || ShaderLocation {
+            pass: pass_name.to_string(),
+            flags: flags.iter().map(|(flag, value)| format!("{flag}={value}")).collect::<Vec<_>>().join(", "),
+        };
+        #[cfg(feature = "validate-glsl")]
+        crate::glsl_check::check_patched(&stage.platform, &stage.stage, &original, &bgfx.code)
+            .with_context(location)?;
+        style.restore(&mut bgfx.code);
+        scode.bgfx_shader_data.clear();
+        bgfx.write(&mut scode.bgfx_shader_data).with_context(location)?;
+        verify_patched_shader(&scode.bgfx_shader_data, injected)
+            .with_context(|| {
+                format!(
+                    "Patched {:?} {:?} shader of {material_name}/{pass_name} is broken",
+                    stage.platform, stage.stage
+                )
+            })
+            .with_context(location)?;
+    }
+    Ok(outcome)
+}
+
+/// Language of the code inside a shader blob
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShaderLanguage {
+    Glsl,
+    Essl,
+    /// Compiled DXBC/DXIL bytecode
+    Direct3D,
+    Metal,
+    /// Compiled SPIR-V
+    Vulkan,
+    Other,
+}
+
+impl ShaderLanguage {
+    pub fn of(platform: &ShaderCodePlatform) -> Self {
+        match platform {
+            ShaderCodePlatform::Glsl120 | ShaderCodePlatform::Glsl430 => Self::Glsl,
+            ShaderCodePlatform::Essl100 | ShaderCodePlatform::Essl300 | ShaderCodePlatform::Essl310 => Self::Essl,
+            ShaderCodePlatform::Direct3DSm40
+            | ShaderCodePlatform::Direct3DSm50
+            | ShaderCodePlatform::Direct3DSm60
+            | ShaderCodePlatform::Direct3DSm65
+            | ShaderCodePlatform::Direct3DXb1
+            | ShaderCodePlatform::Direct3DXb3 => Self::Direct3D,
+            ShaderCodePlatform::Metal => Self::Metal,
+            ShaderCodePlatform::Vulkan => Self::Vulkan,
+            _ => Self::Other,
+        }
+    }
+
+    /// GLSL source, which is all the built-in text patches understand
+    pub fn is_glsl(self) -> bool {
+        matches!(self, Self::Glsl | Self::Essl)
+    }
+
+    /// File extension for the code when written out on its own
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Glsl | Self::Essl => "glsl",
+            Self::Metal => "metal",
+            _ => "bin",
+        }
+    }
+}
+
+/// Whether a GLSL patch for `wanted` stage shaders should edit this one, the
+/// error is what to report when it shouldn't
+fn glsl_target(stage: &PlatformShaderStage, wanted: ShaderStage) -> Result<(), ShaderEdit> {
+    if stage.stage != wanted {
+        Err(ShaderEdit::Unchanged)
+    } else if !ShaderLanguage::of(&stage.platform).is_glsl() {
+        Err(ShaderEdit::Unsupported)
+    } else {
+        Ok(())
+    }
+}
+
+/// [`glsl_target`] for the fixes only mobile ESSL 1.00 and 3.00 shaders need,
+/// the other GLSL versions are left as they are
+fn essl_target(stage: &PlatformShaderStage, wanted: ShaderStage) -> Result<(), ShaderEdit> {
+    glsl_target(stage, wanted)?;
+    match stage.platform {
+        ShaderCodePlatform::Essl100 | ShaderCodePlatform::Essl300 => Ok(()),
+        _ => Err(ShaderEdit::Unchanged),
+    }
+}
+
+const LIGHTMAP_26_10_FUNCTION: &[u8] = b"vec2 lightmapUtil_26_10_new(vec2 tc1) {
+    return fract(tc1.y * vec2(256.0, 4096.0));
+}
+";
+const LIGHTMAP_26_10_REPLACEMENT: &[u8] = b"lightmapUtil_26_10_new(a_texcoord1)";
+/// How older releases injected the fix, all in one block before `void main`
+const LEGACY_LIGHTMAP_26_10_FIX: &[u8] = b"
+vec2 lightmapUtil_26_10_new(vec2 tc1) {
+    return fract(tc1.y * vec2(256.0, 4096.0));
+}
+#ifdef a_texcoord1
+ #undef a_texcoord1
+#endif
+#define a_texcoord1 lightmapUtil_26_10_new(a_texcoord1)
+";
+const LIGHTMAP_26_10_MARKER: &[u8] = b"vec2 lightmapUtil_26_10_new(vec2 tc1)";
+
+/// Replace `ident * 65535.0` style unpacking with plain `ident`, padded to keep offsets
+fn strip_unpack_math(code: &mut Vec<u8>, ident: &[u8]) -> bool {
+    if find_subsequence(code, b"65535").is_none() {
+        return false;
+    }
+    // Any identifier, so that one regex serves every shader and identifier
+    static UNPACK: OnceLock<Regex> = OnceLock::new();
+    let unpack = UNPACK.get_or_init(|| Regex::new(r"\b([A-Za-z_]\w*)\s*\*\s*65535\.0*").expect("valid regex"));
+    let mut stripped = false;
+    rewrite(code, unpack, |caps, out| {
+        if &caps[1] == ident {
+            out.extend_from_slice(ident);
+            out.resize(out.len() + caps[0].len() - ident.len(), b' ');
+            stripped = true;
+        } else {
+            out.extend_from_slice(&caps[0]);
+        }
+    });
+    stripped
+}
+
+/// Identifier a fragment shader samples the lightmap with, e.g. `v_lightmapUV`
+/// in `texture2D(s_LightMapTexture, v_lightmapUV)`
+fn lightmap_sample_uv(code: &[u8]) -> Option<Vec<u8>> {
+    for call in [&b"texture2D(s_LightMapTexture,"[..], b"texture(s_LightMapTexture,"] {
+        let Some(pos) = find_subsequence(code, call) else {
+            continue;
+        };
+        let ident: Vec<u8> = code[pos + call.len()..]
+            .iter()
+            .skip_while(|c| c.is_ascii_whitespace())
+            .take_while(|c| c.is_ascii_alphanumeric() || **c == b'_')
+            .copied()
+            .collect();
+        if !ident.is_empty() {
+            return Some(ident);
+        }
+    }
+    None
+}
+
+/// Wipes out leftover 1.21.130 lightmap math so it doesn't collide with newer packing
+pub struct LightmapCleanup;
+
+impl Patch for LightmapCleanup {
+    fn name(&self) -> &'static str {
+        "lightmap-cleanup"
+    }
+
+    fn description(&self) -> &'static str {
+        "remove leftover 1.21.130 lightmap unpacking (GLSL vertex)"
+    }
+
+    fn fixes_lightmap(&self) -> bool {
+        true
+    }
+
+    fn apply(&self, material: &mut CompiledMaterialDefinition) -> anyhow::Result<PatchOutcome> {
+        edit_shaders(material, |&ShaderContext { material, pass, stage, .. }, code| {
+            // a_texcoord1 is a vertex attribute
+            if let Err(skip) = glsl_target(stage, ShaderStage::Vertex) {
+                return skip;
+            }
+            if strip_unpack_math(code, b"a_texcoord1") {
+                log::info!("{material}/{pass}: removed 1.21.130 lightmap math ({:?})", stage.platform);
+                ShaderEdit::Changed
+            } else {
+                ShaderEdit::Unchanged
+            }
+        })
+    }
+}
+
+/// Repacks the lightmap uv the way 26.10 vertex shaders expect it
+pub struct LightmapPacking26_10;
+
+impl Patch for LightmapPacking26_10 {
+    fn name(&self) -> &'static str {
+        "lightmap-packing-26.10"
+    }
+
+    fn description(&self) -> &'static str {
+        "26.10 lightmap uv packing (ESSL vertex)"
+    }
+
+    fn fixes_lightmap(&self) -> bool {
+        true
+    }
+
+    fn targets(&self) -> Option<&'static [MVersion]> {
+        Some(&[MVersion::V26_10])
+    }
+
+    fn fingerprint(&self) -> Option<&'static [u8]> {
+        Some(b"vec2(256.0, 4096.0)")
+    }
+
+    fn strip(&self, code: &mut Vec<u8>) -> bool {
+        let define = Preamble::define_line(b"a_texcoord1", LIGHTMAP_26_10_REPLACEMENT);
+        let mut stripped = false;
+        for block in [LEGACY_LIGHTMAP_26_10_FIX, LIGHTMAP_26_10_FUNCTION, &define] {
+            if let Some(pos) = find_subsequence(code, block) {
+                code.drain(pos..pos + block.len());
+                stripped = true;
+            }
+        }
+        stripped
+    }
+
+    fn apply(&self, material: &mut CompiledMaterialDefinition) -> anyhow::Result<PatchOutcome> {
+        edit_unpatched_shaders(self, material, |&ShaderContext { material, pass, stage, .. }, code| {
+            if let Err(skip) = essl_target(stage, ShaderStage::Vertex) {
+                return skip;
+            }
+            let preamble = Preamble::new()
+                .item(LIGHTMAP_26_10_FUNCTION)
+                .remap(b"a_texcoord1", LIGHTMAP_26_10_REPLACEMENT);
+            if preamble.insert(code) {
+                log::info!("{material}/{pass}: applied 26.10 lightmap fix ({:?})", stage.platform);
+                ShaderEdit::Injected(LIGHTMAP_26_10_MARKER)
+            } else if find_subsequence(code, b"a_texcoord1").is_some() {
+                log::warn!("{material}/{pass}: no a_texcoord1 declaration found, skipping 26.10 lightmap fix ({:?})", stage.platform);
+                ShaderEdit::Missing
+            } else {
+                ShaderEdit::Unchanged
+            }
+        })
+    }
+}
+
+/// Packs that unpack the lightmap uv in the fragment shader need the same
+/// leftover math removed from the varying they sample with
+pub struct FragmentLightmapCleanup26_10;
+
+impl Patch for FragmentLightmapCleanup26_10 {
+    fn name(&self) -> &'static str {
+        "fragment-lightmap-cleanup-26.10"
+    }
+
+    fn description(&self) -> &'static str {
+        "strip lightmap unpacking of sampled uv (ESSL fragment)"
+    }
+
+    fn fixes_lightmap(&self) -> bool {
+        true
+    }
+
+    fn targets(&self) -> Option<&'static [MVersion]> {
+        Some(&[MVersion::V26_10])
+    }
+
+    fn apply(&self, material: &mut CompiledMaterialDefinition) -> anyhow::Result<PatchOutcome> {
+        edit_shaders(material, |&ShaderContext { material, pass, stage, .. }, code| {
+            if let Err(skip) = essl_target(stage, ShaderStage::Fragment) {
+                return skip;
+            }
+            let Some(uv) = lightmap_sample_uv(code) else {
+                return ShaderEdit::Unchanged;
+            };
+            if strip_unpack_math(code, &uv) {
+                log::info!(
+                    "{material}/{pass}: removed fragment lightmap math on {} ({:?})",
+                    String::from_utf8_lossy(&uv),
+                    stage.platform
+                );
+                ShaderEdit::Changed
+            } else {
+                ShaderEdit::Unchanged
+            }
+        })
+    }
+}
+
+/// Strips comments and whitespace from GLSL shaders, run last when
+/// `--minify-shaders` is given
+pub struct MinifyShaders;
+
+impl Patch for MinifyShaders {
+    fn name(&self) -> &'static str {
+        "minify-shaders"
+    }
+
+    fn description(&self) -> &'static str {
+        "strip comments and whitespace from GLSL and ESSL shaders"
+    }
+
+    fn apply(&self, material: &mut CompiledMaterialDefinition) -> anyhow::Result<PatchOutcome> {
+        edit_shaders(material, |&ShaderContext { stage, .. }, code| {
+            let text_platform = ShaderLanguage::of(&stage.platform).is_glsl();
+            // Some blobs end in NUL bytes, which are not part of the text
+            let text_len = code.iter().rposition(|&c| c != 0).map_or(0, |end| end + 1);
+            let Ok(text) = std::str::from_utf8(&code[..text_len]) else {
+                return ShaderEdit::Unchanged;
+            };
+            if !text_platform {
+                return ShaderEdit::Unchanged;
+            }
+            let minified = minify_glsl(text);
+            if minified.len() >= text_len {
+                return ShaderEdit::Unchanged;
+            }
+            code.splice(..text_len, minified.into_bytes());
+            ShaderEdit::Changed
+        })
+    }
+}
+
+/// Re-read a patched shader blob and make sure the result is still sane:
+/// it parses, an injected fix is present exactly once, and brackets balance
+fn verify_patched_shader(data: &[u8], injected: Option<&[u8]>) -> anyhow::Result<()> {
+    let bgfx: BgfxShader = data
+        .pread(0)
+        .map_err(|err| anyhow::anyhow!("Shader does not parse anymore: {err}"))?;
+    if let Some(marker) = injected {
+        let count = bgfx.code.windows(marker.len()).filter(|w| *w == marker).count();
+        anyhow::ensure!(
+            count == 1,
+            "Expected the injected fix once, found it {count} times"
+        );
+    }
+    check_brackets(&bgfx.code)
+}
+
+/// Lightweight GLSL syntax check: (), [] and {} must nest properly outside of comments
+fn check_brackets(code: &[u8]) -> anyhow::Result<()> {
+    let mut stack = Vec::new();
+    let mut i = 0;
+    while i < code.len() {
+        match &code[i..] {
+            [b'/', b'/', ..] => {
+                i += code[i..].iter().position(|&c| c == b'\n').unwrap_or(code.len() - i);
+                continue;
+            }
+            [b'/', b'*', ..] => {
+                i += find_subsequence(&code[i..], b"*/").map_or(code.len() - i, |end| end + 2);
+                continue;
+            }
+            [c @ (b'(' | b'[' | b'{'), ..] => stack.push(*c),
+            [c @ (b')' | b']' | b'}'), ..] => {
+                let open = match c {
+                    b')' => b'(',
+                    b']' => b'[',
+                    _ => b'{',
+                };
+                anyhow::ensure!(
+                    stack.pop() == Some(open),
+                    "Unbalanced '{}' at byte {i}",
+                    *c as char
+                );
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    anyhow::ensure!(stack.is_empty(), "{} unclosed brackets", stack.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_style_round_trips() {
+        let original = b"\xEF\xBB\xBFvoid main() {\r\n  gl_FragColor = c;\r\n}\r\n".to_vec();
+        let mut code = original.clone();
+        let style = TextStyle::normalize(&mut code);
+        assert_eq!(code, b"void main() {\n  gl_FragColor = c;\n}\n".to_vec());
+        style.restore(&mut code);
+        assert_eq!(code, original);
+
+        let mut plain = b"void main() {}\n".to_vec();
+        let style = TextStyle::normalize(&mut plain);
+        style.restore(&mut plain);
+        assert_eq!(plain, b"void main() {}\n".to_vec());
+    }
+
+    #[test]
+    fn strips_unpack_math_with_any_spacing() {
+        let mut code = b"vec2 a = a_texcoord1*65535.0; vec2 b = a_texcoord1  *  65535.; x_a_texcoord1 * 65535.0".to_vec();
+        assert!(strip_unpack_math(&mut code, b"a_texcoord1"));
+        assert_eq!(
+            code,
+            b"vec2 a = a_texcoord1        ; vec2 b = a_texcoord1           ; x_a_texcoord1 * 65535.0".to_vec()
+        );
+        assert!(!strip_unpack_math(&mut code, b"a_texcoord1"));
+        assert!(strip_unpack_math(&mut code, b"x_a_texcoord1"));
+        assert!(code.ends_with(b"x_a_texcoord1          "));
+    }
+
+    #[test]
+    fn selection_overrides_versions() {
+        let packing = find_patch("lightmap-packing-26.10").unwrap();
+        let source = MinecraftVersion::V1_21_110;
+        let default = PatchSelection::default();
+        assert!(default.selects(packing, source, &MVersion::V26_10));
+        assert!(!default.selects(packing, source, &MVersion::V1_21_110));
+
+        let enabled = PatchSelection {
+            enabled: vec!["Lightmap-Packing-26.10".to_string()],
+            ..Default::default()
+        };
+        assert!(enabled.selects(packing, source, &MVersion::V1_21_110));
+
+        let disabled = PatchSelection {
+            disabled: vec!["lightmap-packing-26.10".to_string()],
+            ..enabled
+        };
+        assert!(!disabled.selects(packing, source, &MVersion::V26_10));
+        assert!(PatchSelection {
+            enabled: vec!["nope".to_string()],
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn lightmap_materials_globs() {
+        assert!(glob_matches("RenderChunk*", "RenderChunk"));
+        assert!(glob_matches("RenderChunk*", "RenderChunkCustom"));
+        assert!(glob_matches("MyChunk.material.bin", "MyChunk"));
+        assert!(glob_matches("*Chunk?", "RenderChunkA"));
+        assert!(!glob_matches("RenderChunk", "RenderChunkCustom"));
+        assert!(!glob_matches("*Chunk?", "RenderChunk"));
+
+        let selection = PatchSelection {
+            lightmap_materials: vec!["RenderChunk*".to_string(), "MyChunk".to_string()],
+            ..Default::default()
+        };
+        let cleanup = find_patch("lightmap-cleanup").unwrap();
+        assert!(selection.selects_material(cleanup, "MyChunk"));
+        assert!(!selection.selects_material(cleanup, "Sky"));
+        assert!(PatchSelection::default().selects_material(cleanup, "Sky"));
+    }
+
+    #[test]
+    fn strips_injected_lightmap_fix() {
+        let packing = find_patch("lightmap-packing-26.10").unwrap();
+        let original = b"attribute vec2 a_texcoord1;\nvoid main() {}".to_vec();
+        let mut code = original.clone();
+        assert!(Preamble::new()
+            .item(LIGHTMAP_26_10_FUNCTION)
+            .remap(b"a_texcoord1", LIGHTMAP_26_10_REPLACEMENT)
+            .insert(&mut code));
+        assert!(find_subsequence(&code, b"return fract(tc1.y * vec2(256.0, 4096.0));").is_some());
+        assert!(find_subsequence(&code, b"#ifdef a_texcoord1\n #undef a_texcoord1\n#endif\n#define a_texcoord1").is_some());
+        assert!(find_subsequence(&code, packing.fingerprint().unwrap()).is_some());
+        assert!(packing.strip(&mut code));
+        assert_eq!(code, original);
+        assert!(!packing.strip(&mut code));
+
+        let mut legacy = original.clone();
+        let pos = find_subsequence(&legacy, b"void main").unwrap();
+        legacy.splice(pos..pos, LEGACY_LIGHTMAP_26_10_FIX.iter().copied());
+        assert!(packing.strip(&mut legacy));
+        assert_eq!(legacy, original);
+    }
+}
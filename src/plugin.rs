@@ -0,0 +1,144 @@
+//! Third-party patches loaded from dynamic libraries, see `include/material_updater_plugin.h`
+
+use std::{
+    ffi::{c_char, CStr, CString},
+    path::Path,
+    ptr, slice,
+    sync::Mutex,
+};
+
+use anyhow::Context;
+use libloading::{Library, Symbol};
+use materialbin::CompiledMaterialDefinition;
+
+use crate::patches::{edit_shaders, Patch, PatchOutcome, ShaderContext, ShaderEdit};
+
+pub const MU_PLUGIN_ABI_VERSION: u32 = 1;
+const MU_PLUGIN_UNCHANGED: i32 = 0;
+const MU_PLUGIN_CHANGED: i32 = 1;
+
+#[repr(C)]
+struct ShaderInfo {
+    material: *const c_char,
+    pass: *const c_char,
+    platform: *const c_char,
+    stage: *const c_char,
+}
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type NameFn = unsafe extern "C" fn() -> *const c_char;
+type PatchShaderFn = unsafe extern "C" fn(
+    *const ShaderInfo,
+    *const u8,
+    usize,
+    *mut *mut u8,
+    *mut usize,
+) -> i32;
+type FreeFn = unsafe extern "C" fn(*mut u8, usize);
+
+/// A patch implemented by a plugin library, which stays loaded for the life of the process
+pub struct PluginPatch {
+    name: &'static str,
+    description: &'static str,
+    patch_shader: PatchShaderFn,
+    free: FreeFn,
+    /// Plugins are called one shader at a time, they needn't be thread safe
+    lock: Mutex<()>,
+    // Keeps the function pointers above valid
+    _library: Library,
+}
+
+impl PluginPatch {
+    /// Load the plugin at `path`. Plugins run with the same rights as the
+    /// tool, only load ones you trust.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        // SAFETY: loading a library runs its initializers, which the user opted into
+        let library = unsafe { Library::new(path) }
+            .with_context(|| format!("Error while loading plugin {}", path.display()))?;
+        // SAFETY: the symbol types match include/material_updater_plugin.h
+        unsafe {
+            let abi_version: Symbol<AbiVersionFn> = library.get(b"mu_plugin_abi_version\0")?;
+            let abi_version = abi_version();
+            anyhow::ensure!(
+                abi_version == MU_PLUGIN_ABI_VERSION,
+                "Plugin {} uses ABI version {abi_version}, expected {MU_PLUGIN_ABI_VERSION}",
+                path.display()
+            );
+            let name: Symbol<NameFn> = library.get(b"mu_plugin_name\0")?;
+            let name = name();
+            anyhow::ensure!(!name.is_null(), "Plugin {} has no name", path.display());
+            let name = CStr::from_ptr(name).to_string_lossy().into_owned();
+            let patch_shader = *library.get::<PatchShaderFn>(b"mu_plugin_patch_shader\0")?;
+            let free = *library.get::<FreeFn>(b"mu_plugin_free\0")?;
+            Ok(Self {
+                name: Box::leak(name.into_boxed_str()),
+                description: Box::leak(format!("plugin {}", path.display()).into_boxed_str()),
+                patch_shader,
+                free,
+                lock: Mutex::new(()),
+                _library: library,
+            })
+        }
+    }
+}
+
+fn c_string(value: &str) -> CString {
+    CString::new(value.replace('\0', " ")).unwrap_or_default()
+}
+
+impl Patch for PluginPatch {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn apply(&self, material: &mut CompiledMaterialDefinition) -> anyhow::Result<PatchOutcome> {
+        let error = Mutex::new(None);
+        let outcome = edit_shaders(material, |&ShaderContext { material, pass, stage, .. }, code| {
+            let _call = self.lock.lock().unwrap();
+            if error.lock().unwrap().is_some() {
+                return ShaderEdit::Unchanged;
+            }
+            let (material, pass) = (c_string(material), c_string(pass));
+            let platform = c_string(&stage.platform_name);
+            let stage_name = c_string(&stage.stage_name);
+            let info = ShaderInfo {
+                material: material.as_ptr(),
+                pass: pass.as_ptr(),
+                platform: platform.as_ptr(),
+                stage: stage_name.as_ptr(),
+            };
+            let mut output = ptr::null_mut();
+            let mut output_len = 0;
+            // SAFETY: every pointer is valid for the duration of the call
+            let result = unsafe {
+                (self.patch_shader)(&info, code.as_ptr(), code.len(), &mut output, &mut output_len)
+            };
+            match result {
+                MU_PLUGIN_UNCHANGED => ShaderEdit::Unchanged,
+                MU_PLUGIN_CHANGED if !output.is_null() => {
+                    // SAFETY: the plugin handed over output_len bytes at output
+                    unsafe {
+                        *code = slice::from_raw_parts(output, output_len).to_vec();
+                        (self.free)(output, output_len);
+                    }
+                    ShaderEdit::Changed
+                }
+                code => {
+                    *error.lock().unwrap() = Some(format!(
+                        "Plugin {} failed on {:?} {:?} shader with code {code}",
+                        self.name, stage.platform, stage.stage
+                    ));
+                    ShaderEdit::Unchanged
+                }
+            }
+        })?;
+        match error.into_inner().unwrap() {
+            Some(error) => Err(anyhow::anyhow!(error)),
+            None => Ok(outcome),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! `--post-process`: hand every shader to an external command, e.g. an
+//! optimizer or linter, and take back what it leaves in the file
+
+use std::{
+    fs,
+    io::Write,
+    process::{Command, Stdio},
+    sync::Mutex,
+};
+
+use anyhow::Context;
+use materialbin::CompiledMaterialDefinition;
+
+use crate::patches::{edit_shaders, Patch, PatchOutcome, ShaderContext, ShaderEdit, ShaderLanguage};
+
+/// Placeholder in the command replaced by the path of the shader file
+pub const SHADER_FILE: &str = "{shader_file}";
+
+/// Runs a shell command on every shader, see [`PostProcess::new`]
+pub struct PostProcess {
+    command: String,
+}
+
+impl PostProcess {
+    /// `command` runs through the shell with `{shader_file}` replaced by the
+    /// path of a temporary file holding the shader code, or with the path
+    /// appended if it has no placeholder. Whatever the command leaves in the
+    /// file replaces the shader. The material, pass, platform and stage are in
+    /// the `MU_MATERIAL`, `MU_PASS`, `MU_PLATFORM` and `MU_STAGE` variables
+    pub fn new(command: String) -> Self {
+        Self { command }
+    }
+
+    fn command_for(&self, path: &str) -> String {
+        let path = shell_quote(path);
+        if self.command.contains(SHADER_FILE) {
+            self.command.replace(SHADER_FILE, &path)
+        } else {
+            format!("{} {path}", self.command)
+        }
+    }
+}
+
+#[cfg(windows)]
+fn shell_quote(path: &str) -> String {
+    format!("\"{path}\"")
+}
+
+#[cfg(not(windows))]
+fn shell_quote(path: &str) -> String {
+    format!("'{}'", path.replace('\'', r"'\''"))
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+impl Patch for PostProcess {
+    fn name(&self) -> &'static str {
+        "post-process"
+    }
+
+    fn description(&self) -> &'static str {
+        "run the --post-process command on every shader"
+    }
+
+    fn apply(&self, material: &mut CompiledMaterialDefinition) -> anyhow::Result<PatchOutcome> {
+        let error = Mutex::new(None);
+        let outcome = edit_shaders(material, |&ShaderContext { material, pass, stage, .. }, code| {
+            if error.lock().unwrap().is_some() {
+                return ShaderEdit::Unchanged;
+            }
+            let language = ShaderLanguage::of(&stage.platform);
+            // Trailing NUL bytes of text shaders aren't code, tools would choke on them
+            let text_len = match language {
+                ShaderLanguage::Glsl | ShaderLanguage::Essl | ShaderLanguage::Metal => {
+                    code.iter().rposition(|&c| c != 0).map_or(0, |end| end + 1)
+                }
+                _ => code.len(),
+            };
+            let run = || -> anyhow::Result<Option<Vec<u8>>> {
+                let mut file = tempfile::Builder::new()
+                    .prefix(&format!("{material}.{pass}.{}.", stage.stage_name))
+                    .suffix(&format!(".{}", language.extension()))
+                    .tempfile()
+                    .with_context(|| "Error while creating a temporary shader file")?;
+                file.write_all(&code[..text_len])?;
+                file.flush()?;
+                let path = file.path().to_string_lossy().into_owned();
+                let command = self.command_for(&path);
+                let status = shell(&command)
+                    .env("MU_MATERIAL", material)
+                    .env("MU_PASS", pass)
+                    .env("MU_PLATFORM", &stage.platform_name)
+                    .env("MU_STAGE", &stage.stage_name)
+                    .stdin(Stdio::null())
+                    .status()
+                    .with_context(|| format!("Error while running `{command}`"))?;
+                anyhow::ensure!(status.success(), "`{command}` failed with {status}");
+                let processed = fs::read(file.path()).with_context(|| format!("Error while reading back {path}"))?;
+                Ok((processed != code[..text_len]).then_some(processed))
+            };
+            match run() {
+                Ok(Some(processed)) => {
+                    code.splice(..text_len, processed);
+                    ShaderEdit::Changed
+                }
+                Ok(None) => ShaderEdit::Unchanged,
+                Err(err) => {
+                    *error.lock().unwrap() = Some(err.context(format!(
+                        "Post-processing {:?} {:?} shader of {material}/{pass}",
+                        stage.platform, stage.stage
+                    )));
+                    ShaderEdit::Unchanged
+                }
+            }
+        })?;
+        match error.into_inner().unwrap() {
+            Some(error) => Err(error),
+            None => Ok(outcome),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(windows))]
+    fn substitutes_the_shader_file() {
+        let patch = PostProcess::new("glslopt {shader_file} -o {shader_file}".to_string());
+        assert_eq!(patch.command_for("/tmp/a b.glsl"), "glslopt '/tmp/a b.glsl' -o '/tmp/a b.glsl'");
+        let patch = PostProcess::new("lint".to_string());
+        assert_eq!(patch.command_for("/tmp/it's.glsl"), r"lint '/tmp/it'\''s.glsl'");
+    }
+}
@@ -0,0 +1,131 @@
+use crate::find_subsequence;
+
+/// Code to inject into a GLSL shader, placed where the compiler accepts it
+/// instead of right before `void main`
+#[derive(Default)]
+pub struct Preamble {
+    /// Helper functions and declarations, inserted after the header
+    items: Vec<u8>,
+    /// `#define IDENT REPLACEMENT` lines, each inserted right after the
+    /// statement declaring `IDENT` so the declaration itself is left alone
+    remaps: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Preamble {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a helper function or declaration, should end with a newline
+    pub fn item(mut self, code: &[u8]) -> Self {
+        self.items.extend_from_slice(code);
+        self
+    }
+
+    /// Replace every use of `ident` after its declaration with `replacement`
+    pub fn remap(mut self, ident: &[u8], replacement: &[u8]) -> Self {
+        self.remaps.push((ident.to_vec(), replacement.to_vec()));
+        self
+    }
+
+    /// The `#define` a remap inserts, after undefining any macro of the same name
+    pub fn define_line(ident: &[u8], replacement: &[u8]) -> Vec<u8> {
+        [b"#ifdef ", ident, b"\n #undef ", ident, b"\n#endif\n#define ", ident, b" ", replacement, b"\n"].concat()
+    }
+
+    /// Insert into `code`, returns `false` without changing it when a remapped
+    /// identifier is never declared
+    pub fn insert(&self, code: &mut Vec<u8>) -> bool {
+        let header = header_end(code);
+        let mut positions = Vec::with_capacity(self.remaps.len());
+        for (ident, replacement) in &self.remaps {
+            let Some(pos) = after_declaration(code, header, ident) else {
+                return false;
+            };
+            positions.push((pos, Self::define_line(ident, replacement)));
+        }
+        // Back to front so earlier positions stay valid
+        positions.sort_by_key(|(pos, _)| std::cmp::Reverse(*pos));
+        for (pos, define) in positions {
+            code.splice(pos..pos, define);
+        }
+        code.splice(header..header, self.items.iter().copied());
+        true
+    }
+}
+
+/// Offset of the first line after the leading `#version`, `#extension`,
+/// `#define`, `#pragma` and `precision` lines, blank lines and line comments
+pub fn header_end(code: &[u8]) -> usize {
+    let mut pos = 0;
+    while pos < code.len() {
+        let end = code[pos..]
+            .iter()
+            .position(|&c| c == b'\n')
+            .map_or(code.len(), |end| pos + end + 1);
+        let line = code[pos..end].trim_ascii();
+        let header = line.is_empty()
+            || line.starts_with(b"//")
+            || line.starts_with(b"precision ")
+            || [&b"version"[..], b"extension", b"define", b"pragma"]
+                .iter()
+                .any(|directive| {
+                    line.strip_prefix(b"#")
+                        .is_some_and(|rest| rest.trim_ascii_start().starts_with(directive))
+                });
+        if !header {
+            break;
+        }
+        pos = end;
+    }
+    pos
+}
+
+fn is_identifier_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// Start of the line after the statement that first mentions `ident` past
+/// `from`, which is its declaration in any valid shader
+fn after_declaration(code: &[u8], from: usize, ident: &[u8]) -> Option<usize> {
+    let mut start = from;
+    let pos = loop {
+        let pos = start + find_subsequence(&code[start..], ident)?;
+        let end = pos + ident.len();
+        let bounded = (pos == 0 || !is_identifier_byte(code[pos - 1]))
+            && (end == code.len() || !is_identifier_byte(code[end]));
+        if bounded {
+            break pos;
+        }
+        start = end;
+    };
+    let semicolon = pos + code[pos..].iter().position(|&c| c == b';')?;
+    Some(
+        code[semicolon..]
+            .iter()
+            .position(|&c| c == b'\n')
+            .map_or(code.len(), |end| semicolon + end + 1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inserts_after_header_and_declaration() {
+        let mut code = b"#version 300 es\n#extension GL_EXT_foo : enable\nprecision highp float;\nin vec2 a_texcoord1;\nvec2 helper() { return a_texcoord1; }\nvoid main() {}\n".to_vec();
+        let preamble = Preamble::new()
+            .item(b"vec2 fix(vec2 v) { return v; }\n")
+            .remap(b"a_texcoord1", b"fix(a_texcoord1)");
+        assert!(preamble.insert(&mut code));
+        assert_eq!(
+            String::from_utf8(code).unwrap(),
+            "#version 300 es\n#extension GL_EXT_foo : enable\nprecision highp float;\nvec2 fix(vec2 v) { return v; }\nin vec2 a_texcoord1;\n#ifdef a_texcoord1\n #undef a_texcoord1\n#endif\n#define a_texcoord1 fix(a_texcoord1)\nvec2 helper() { return a_texcoord1; }\nvoid main() {}\n"
+        );
+
+        let mut code = b"void main() {}\n".to_vec();
+        assert!(!preamble.insert(&mut code));
+        assert_eq!(code, b"void main() {}\n".to_vec());
+    }
+}
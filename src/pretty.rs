@@ -0,0 +1,262 @@
+/// Re-indent minified GLSL: one statement per line, blocks indented by four
+/// spaces, preprocessor directives and comments kept on their own lines.
+/// Only whitespace changes, so the output compiles the same as the input.
+pub fn pretty_glsl(code: &str) -> String {
+    let mut out = String::with_capacity(code.len() * 2);
+    let mut line = String::new();
+    let mut depth = 0usize;
+    let mut parens = 0usize;
+    let mut chars = code.chars().peekable();
+
+    fn push_line(out: &mut String, line: &mut String, depth: usize) {
+        let trimmed = line.trim();
+        if !trimmed.is_empty() {
+            if !trimmed.starts_with('#') {
+                out.extend(std::iter::repeat_n("    ", depth));
+            }
+            out.push_str(trimmed);
+            out.push('\n');
+        }
+        line.clear();
+    }
+
+    while let Some(c) = chars.next() {
+        match c {
+            '#' if line.trim().is_empty() => {
+                line.push(c);
+                while let Some(&next) = chars.peek() {
+                    if next == '\n' {
+                        break;
+                    }
+                    line.push(next);
+                    chars.next();
+                }
+                push_line(&mut out, &mut line, depth);
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                line.push(c);
+                while let Some(&next) = chars.peek() {
+                    if next == '\n' {
+                        break;
+                    }
+                    line.push(next);
+                    chars.next();
+                }
+                push_line(&mut out, &mut line, depth);
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                line.push(c);
+                let mut previous = ' ';
+                for next in chars.by_ref() {
+                    line.push(next);
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    previous = next;
+                }
+            }
+            '{' => {
+                line.push_str(" {");
+                push_line(&mut out, &mut line, depth);
+                depth += 1;
+            }
+            '}' => {
+                push_line(&mut out, &mut line, depth);
+                depth = depth.saturating_sub(1);
+                line.push('}');
+                while chars.peek().is_some_and(|next| next.is_whitespace()) {
+                    chars.next();
+                }
+                // Keep `} else` and `};` together
+                let rest: String = chars.clone().take(4).collect();
+                if rest == "else" {
+                    line.push(' ');
+                } else if chars.peek() != Some(&';') {
+                    push_line(&mut out, &mut line, depth);
+                }
+            }
+            ';' => {
+                line.push(c);
+                if parens == 0 {
+                    push_line(&mut out, &mut line, depth);
+                }
+            }
+            '(' => {
+                parens += 1;
+                line.push(c);
+            }
+            ')' => {
+                parens = parens.saturating_sub(1);
+                line.push(c);
+            }
+            c if c.is_whitespace() => {
+                if !line.is_empty() && !line.ends_with(' ') {
+                    line.push(' ');
+                }
+            }
+            c => line.push(c),
+        }
+    }
+    push_line(&mut out, &mut line, depth);
+    out
+}
+
+/// Shrink GLSL without changing what it compiles to: comments are dropped,
+/// whitespace is only kept where tokens would merge without it, preprocessor
+/// directives stay on their own lines and float literals lose redundant zeros
+pub fn minify_glsl(code: &str) -> String {
+    let mut out = String::with_capacity(code.len());
+    let chars: Vec<char> = code.chars().collect();
+    let mut i = 0;
+    // Whitespace was skipped since the last token written
+    let mut pending_space = false;
+
+    fn is_word(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '_' || c == '.'
+    }
+    fn is_operator(c: char) -> bool {
+        "+-*/%<>=!&|^".contains(c)
+    }
+    fn push_token(out: &mut String, token: &str, pending_space: &mut bool) {
+        if let (Some(last), Some(first)) = (out.chars().last(), token.chars().next()) {
+            let merges = (is_word(last) && is_word(first)) || (is_operator(last) && is_operator(first));
+            if *pending_space && merges {
+                out.push(' ');
+            }
+        }
+        *pending_space = false;
+        out.push_str(token);
+    }
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '/' if chars.get(i + 1) == Some(&'/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                pending_space = true;
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i += 2;
+                pending_space = true;
+            }
+            '#' => {
+                let mut line = String::new();
+                while i < chars.len() && chars[i] != '\n' {
+                    match chars[i] {
+                        // Line continuation
+                        '\\' if chars.get(i + 1) == Some(&'\n') => {
+                            line.push_str("\\\n");
+                            i += 1;
+                        }
+                        '/' if chars.get(i + 1) == Some(&'/') => {
+                            while i < chars.len() && chars[i] != '\n' {
+                                i += 1;
+                            }
+                            break;
+                        }
+                        c if c.is_whitespace() => {
+                            if !line.ends_with(' ') {
+                                line.push(' ');
+                            }
+                        }
+                        c => line.push(c),
+                    }
+                    i += 1;
+                }
+                if !out.is_empty() && !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str(line.trim_end());
+                out.push('\n');
+                pending_space = false;
+                continue;
+            }
+            c if c.is_whitespace() => {
+                pending_space = true;
+                i += 1;
+            }
+            c if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit)) => {
+                let start = i;
+                while i < chars.len() && is_word(chars[i]) {
+                    i += 1;
+                }
+                let number: String = chars[start..i].iter().collect();
+                push_token(&mut out, &shorten_float(&number), &mut pending_space);
+            }
+            c if is_word(c) => {
+                let start = i;
+                while i < chars.len() && is_word(chars[i]) {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                push_token(&mut out, &word, &mut pending_space);
+            }
+            c => {
+                push_token(&mut out, c.encode_utf8(&mut [0; 4]), &mut pending_space);
+                i += 1;
+            }
+        }
+    }
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+/// `1.50` -> `1.5`, `0.5` -> `.5`, `2.0` -> `2.`; anything that isn't a plain
+/// decimal float literal is returned unchanged
+fn shorten_float(number: &str) -> String {
+    let Some((int, frac)) = number.split_once('.') else {
+        return number.to_string();
+    };
+    if !int.bytes().all(|c| c.is_ascii_digit()) || !frac.bytes().all(|c| c.is_ascii_digit()) {
+        return number.to_string();
+    }
+    let frac = frac.trim_end_matches('0');
+    let int = if frac.is_empty() { int } else { int.trim_start_matches('0') };
+    match (int, frac) {
+        ("", "") => "0.".to_string(),
+        _ => format!("{int}.{frac}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{minify_glsl, pretty_glsl};
+
+    #[test]
+    fn reindents_minified_code() {
+        let code = "#version 300 es\nprecision highp float;uniform vec4 u_a;void main(){for(int i=0;i<2;i++){if(u_a.x>0.0){gl_FragColor=u_a;}else{discard;}}}";
+        assert_eq!(
+            pretty_glsl(code),
+            "#version 300 es
+precision highp float;
+uniform vec4 u_a;
+void main() {
+    for(int i=0;i<2;i++) {
+        if(u_a.x>0.0) {
+            gl_FragColor=u_a;
+        } else {
+            discard;
+        }
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn minifies_without_merging_tokens() {
+        let code = "#version 300 es\n// comment\nprecision highp float; /* block */\nuniform vec4 u_a;\n#define SCALE 2.50\nvoid main() {\n    float x = u_a.x - -1.0 + 0.25;\n    gl_FragColor = vec4(x * SCALE, 1.000e2, 10.0, 0.0);\n}\n";
+        assert_eq!(
+            minify_glsl(code),
+            "#version 300 es\nprecision highp float;uniform vec4 u_a;\n#define SCALE 2.50\nvoid main(){float x=u_a.x- -1.+.25;gl_FragColor=vec4(x*SCALE,1.000e2,10.,0.);}\n"
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! Where conversion time goes, per material and phase, shown by `--profile`
+
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+use owo_colors::OwoColorize;
+use serde_json::{json, Value};
+
+use crate::summary::EntrySummary;
+
+/// Measures a phase. The clock isn't available on wasm, where every phase
+/// takes no time
+#[derive(Clone, Copy)]
+pub(crate) struct Stopwatch {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: Instant,
+}
+
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: Instant::now(),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn elapsed(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+/// Time a material entry took in each phase of its conversion. Reading and
+/// parsing happen once for all targets, so only the first target's entry has them
+#[derive(Clone, Copy, Default, Debug)]
+pub struct EntryTimings {
+    /// When the entry was started, since the conversion started
+    pub started: Duration,
+    /// Reading and decompressing the input entry
+    pub read: Duration,
+    pub parse: Duration,
+    /// Patching and renaming
+    pub patch: Duration,
+    /// Serializing the material
+    pub encode: Duration,
+    /// Compressing and writing the output entry
+    pub write: Duration,
+}
+
+impl EntryTimings {
+    pub fn phases(&self) -> [(&'static str, Duration); 5] {
+        [
+            ("read", self.read),
+            ("parse", self.parse),
+            ("patch", self.patch),
+            ("encode", self.encode),
+            ("write", self.write),
+        ]
+    }
+
+    pub fn total(&self) -> Duration {
+        self.phases().iter().map(|(_, duration)| *duration).sum()
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Print the `top` slowest entries with their phases, and the time spent in
+/// each phase over all entries
+pub fn print_profile(entries: &[EntrySummary], top: usize) {
+    let mut totals = EntryTimings::default();
+    for entry in entries {
+        totals.read += entry.timings.read;
+        totals.parse += entry.timings.parse;
+        totals.patch += entry.timings.patch;
+        totals.encode += entry.timings.encode;
+        totals.write += entry.timings.write;
+    }
+    let total = totals.total();
+    println!("{}", "Time per phase".bold());
+    for (phase, duration) in totals.phases() {
+        let share = if total.is_zero() {
+            0.0
+        } else {
+            duration.as_secs_f64() / total.as_secs_f64() * 100.0
+        };
+        println!("  {phase:<8} {:>10} {share:>5.1}%", millis(duration));
+    }
+    println!("  {:<8} {:>10}", "total", millis(total));
+
+    let mut slowest: Vec<&EntrySummary> = entries.iter().collect();
+    slowest.sort_by_key(|entry| std::cmp::Reverse(entry.timings.total()));
+    println!("{}", "Slowest materials".bold());
+    for entry in slowest.into_iter().take(top) {
+        let phases: Vec<String> = entry
+            .timings
+            .phases()
+            .iter()
+            .filter(|(_, duration)| !duration.is_zero())
+            .map(|(phase, duration)| format!("{phase} {}", millis(*duration)))
+            .collect();
+        println!(
+            "  {:<48} {:>10}  {}",
+            entry.name,
+            millis(entry.timings.total()),
+            phases.join(", ").dimmed()
+        );
+    }
+}
+
+/// The entries as Chrome trace events, which chrome://tracing, Perfetto and
+/// speedscope show as a flame chart: one track per `track` (e.g. the target
+/// version), a span per entry and its phases nested in it
+pub fn trace_events<'a>(tracks: impl IntoIterator<Item = (String, &'a [EntrySummary])>) -> Value {
+    let mut events = Vec::new();
+    for (tid, (track, entries)) in tracks.into_iter().enumerate() {
+        events.push(json!({ "name": "thread_name", "ph": "M", "pid": 1, "tid": tid, "args": { "name": track } }));
+        for entry in entries {
+            let timings = &entry.timings;
+            events.push(json!({
+                "name": entry.name,
+                "cat": "entry",
+                "ph": "X",
+                "pid": 1,
+                "tid": tid,
+                "ts": timings.started.as_micros() as u64,
+                "dur": timings.total().as_micros() as u64,
+                "args": { "result": entry.result.to_string() },
+            }));
+            let mut offset = timings.started;
+            for (phase, duration) in timings.phases() {
+                if !duration.is_zero() {
+                    events.push(json!({
+                        "name": phase,
+                        "cat": "phase",
+                        "ph": "X",
+                        "pid": 1,
+                        "tid": tid,
+                        "ts": offset.as_micros() as u64,
+                        "dur": duration.as_micros() as u64,
+                    }));
+                }
+                offset += duration;
+            }
+        }
+    }
+    json!({ "traceEvents": events, "displayTimeUnit": "ms" })
+}
@@ -0,0 +1,99 @@
+//! Progress of a pack conversion on the terminal: a bar with the throughput and
+//! the time left, for packs that take minutes to convert on a phone
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(not(target_arch = "wasm32"))]
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+use crate::hooks::{ArchiveStart, ConversionHooks, MaterialConverted};
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Show progress bars or not. Conversions running at once would draw over each
+/// other's bars, so the callers running them turn bars off
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Bar over the bytes of material entries to convert. It is only drawn on a
+/// terminal, and not with `--verbose`, whose lines per entry say as much, or
+/// `--quiet`
+pub(crate) struct Progress {
+    #[cfg(not(target_arch = "wasm32"))]
+    bar: ProgressBar,
+}
+
+impl Progress {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn new() -> Self {
+        let shown = ENABLED.load(Ordering::Relaxed)
+            && log::log_enabled!(log::Level::Warn)
+            && !log::log_enabled!(log::Level::Info);
+        let target = if shown {
+            ProgressDrawTarget::stderr()
+        } else {
+            ProgressDrawTarget::hidden()
+        };
+        let template = format!(
+            "{{bar:40.green}} {{binary_bytes}}/{{binary_total_bytes}} ({{binary_bytes_per_sec}}, {})",
+            crate::tr!("progress-left", eta = "{eta}")
+        );
+        let bar = ProgressBar::with_draw_target(Some(0), target)
+            .with_style(ProgressStyle::with_template(&template).unwrap_or_else(|_| ProgressStyle::default_bar()));
+        Self { bar }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn new() -> Self {
+        Self {}
+    }
+
+    pub(crate) fn finish(&self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.bar.finish_and_clear();
+    }
+}
+
+impl ConversionHooks for Progress {
+    fn on_archive_start(&self, archive: &ArchiveStart) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.bar.set_length(archive.material_bytes);
+        #[cfg(target_arch = "wasm32")]
+        let _ = archive;
+    }
+
+    fn on_material_converted(&self, converted: &MaterialConverted) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.bar.set_position(converted.done_bytes);
+        #[cfg(target_arch = "wasm32")]
+        let _ = converted;
+    }
+}
+
+/// `bytes` as e.g. "512 B", "3.4 KiB" or "1.2 GiB"
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(3 * 1024 + 410), "3.4 KiB");
+        assert_eq!(format_bytes(1288490189), "1.2 GiB");
+    }
+}
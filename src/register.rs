@@ -0,0 +1,69 @@
+use std::{io, path::Path, ptr};
+
+use anyhow::Context;
+use windows_sys::Win32::{
+    Foundation::{ERROR_FILE_NOT_FOUND, ERROR_SUCCESS, WIN32_ERROR},
+    System::Registry::{RegDeleteTreeW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ},
+};
+
+/// Explorer context menu entry for .mcpack files, per user so it needs no admin rights
+const MENU_KEY: &str = r"Software\Classes\SystemFileAssociations\.mcpack\shell\MaterialUpdater";
+
+/// Null terminated UTF-16 for the registry API
+fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain([0]).collect()
+}
+
+fn check(status: WIN32_ERROR, what: &str) -> anyhow::Result<()> {
+    if status == ERROR_SUCCESS {
+        return Ok(());
+    }
+    Err(io::Error::from_raw_os_error(status as i32)).with_context(|| format!("Error while {what}"))
+}
+
+/// Set the string value `name` (the default value if `None`) of `key` under
+/// HKEY_CURRENT_USER, creating the key if needed
+fn set_value(key: &str, name: Option<&str>, value: &str) -> anyhow::Result<()> {
+    let key_w = wide(key);
+    let name_w = name.map(wide);
+    let value_w = wide(value);
+    // SAFETY: all strings are null terminated and the data length matches value_w
+    let status = unsafe {
+        RegSetKeyValueW(
+            HKEY_CURRENT_USER,
+            key_w.as_ptr(),
+            name_w.as_ref().map_or(ptr::null(), |name| name.as_ptr()),
+            REG_SZ,
+            value_w.as_ptr().cast(),
+            (value_w.len() * 2) as u32,
+        )
+    };
+    check(status, &format!("writing HKEY_CURRENT_USER\\{key}"))
+}
+
+/// Command line Explorer runs for the entry, `%1` is the clicked pack
+fn menu_command(exe: &Path, target: &str) -> String {
+    format!("\"{}\" convert \"%1\" -t {target}", exe.display())
+}
+
+/// Add "Update shaders…" to the right-click menu of .mcpack files, converting
+/// the pack next to itself for `target`
+pub fn register(target: &str) -> anyhow::Result<()> {
+    let exe = std::env::current_exe().with_context(|| "Can't tell where this program is")?;
+    set_value(MENU_KEY, None, "Update shaders…")?;
+    set_value(MENU_KEY, Some("Icon"), &exe.display().to_string())?;
+    set_value(&format!(r"{MENU_KEY}\command"), None, &menu_command(&exe, target))?;
+    Ok(())
+}
+
+/// Remove the entry added by [`register`], returns `false` if there was none
+pub fn unregister() -> anyhow::Result<bool> {
+    let key_w = wide(MENU_KEY);
+    // SAFETY: key_w is null terminated
+    let status = unsafe { RegDeleteTreeW(HKEY_CURRENT_USER, key_w.as_ptr()) };
+    if status == ERROR_FILE_NOT_FOUND {
+        return Ok(false);
+    }
+    check(status, &format!("removing HKEY_CURRENT_USER\\{MENU_KEY}"))?;
+    Ok(true)
+}
@@ -0,0 +1,260 @@
+use std::str::FromStr;
+
+use anyhow::Context;
+use materialbin::{bgfx_shader::BgfxShader, CompiledMaterialDefinition};
+use scroll::Pread;
+
+use crate::{patches::ShaderLanguage, MVersion, VERSION_TABLE};
+
+/// A uniform or sampler renamed by a game update
+struct BuiltinRename {
+    /// First version using the new name
+    since: MVersion,
+    from: &'static str,
+    to: &'static str,
+}
+
+/// Uniforms and samplers renamed by game updates, newest first. Converting to
+/// `since` or newer renames `from` to `to`, converting to an older version
+/// renames it back.
+const BUILTIN_RENAMES: &[BuiltinRename] = &[
+    // The lightmap binding, renamed along with the new lightmap uv packing
+    BuiltinRename {
+        since: MVersion::V26_10,
+        from: "s_LightMapTexture",
+        to: "s_LightmapTexture",
+    },
+];
+
+/// Rename of a uniform or sampler, written `OLD=NEW`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NameRename {
+    pub from: String,
+    pub to: String,
+}
+
+impl FromStr for NameRename {
+    type Err = String;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        match arg.split_once('=') {
+            Some((from, to)) if is_identifier(from) && is_identifier(to) => Ok(Self {
+                from: from.to_string(),
+                to: to.to_string(),
+            }),
+            _ => Err(format!("Expected OLD=NEW identifiers, got {arg:?}")),
+        }
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(is_identifier_byte)
+}
+
+fn is_identifier_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// Position of `version` in [`VERSION_TABLE`], lower is newer
+pub(crate) fn age(version: &MVersion) -> usize {
+    VERSION_TABLE
+        .iter()
+        .position(|info| info.version == *version)
+        .unwrap_or(usize::MAX)
+}
+
+/// Renames to apply when converting to `target`: the built-in ones for its
+/// version, then `overrides`, which replace built-ins of the same name
+pub fn renames_for(target: &MVersion, overrides: &[NameRename]) -> Vec<NameRename> {
+    let mut renames: Vec<NameRename> = BUILTIN_RENAMES
+        .iter()
+        .map(|rename| {
+            let (from, to) = if age(target) <= age(&rename.since) {
+                (rename.from, rename.to)
+            } else {
+                (rename.to, rename.from)
+            };
+            NameRename {
+                from: from.to_string(),
+                to: to.to_string(),
+            }
+        })
+        .filter(|rename| !overrides.iter().any(|o| o.from == rename.from))
+        .collect();
+    renames.extend_from_slice(overrides);
+    renames
+}
+
+/// Replace whole-word occurrences of `from` with `to`
+pub(crate) fn replace_identifier(code: &mut Vec<u8>, from: &[u8], to: &[u8]) -> bool {
+    let mut changed = false;
+    let mut start = 0;
+    while let Some(offset) = code[start..]
+        .windows(from.len())
+        .position(|window| window == from)
+    {
+        let pos = start + offset;
+        let end = pos + from.len();
+        let bounded = (pos == 0 || !is_identifier_byte(code[pos - 1]))
+            && (end == code.len() || !is_identifier_byte(code[end]));
+        if bounded {
+            code.splice(pos..end, to.iter().copied());
+            start = pos + to.len();
+            changed = true;
+        } else {
+            start = end;
+        }
+    }
+    changed
+}
+
+fn rename_key<V>(
+    entries: impl IntoIterator<Item = (String, V)>,
+    renames: &[NameRename],
+    changed: &mut bool,
+) -> Vec<(String, V)> {
+    entries
+        .into_iter()
+        .map(|(name, value)| match renames.iter().find(|r| r.from == name) {
+            Some(rename) => {
+                *changed = true;
+                (rename.to.clone(), value)
+            }
+            None => (name, value),
+        })
+        .collect()
+}
+
+/// `entry` with its file name changed by the material rename whose `from` is
+/// the name without `.material.bin`, `None` if no rename applies
+pub fn rename_material_entry(entry: &str, renames: &[NameRename]) -> Option<String> {
+    let file_name = entry.rsplit('/').next()?;
+    let stem = file_name.strip_suffix(".material.bin")?;
+    let rename = renames.iter().find(|rename| rename.from == stem)?;
+    let dir = &entry[..entry.len() - file_name.len()];
+    Some(format!("{dir}{}.material.bin", rename.to))
+}
+
+/// Apply material renames to the name of `material` and to its parent, so
+/// materials inheriting from a renamed one keep finding it. Returns whether
+/// anything changed
+pub fn rename_material_definition(material: &mut CompiledMaterialDefinition, renames: &[NameRename]) -> bool {
+    let mut changed = false;
+    let names = std::iter::once(&mut material.name).chain(material.parent_name.as_mut());
+    for name in names {
+        if let Some(rename) = renames.iter().find(|rename| rename.from == *name) {
+            log::info!("Renamed material {} to {}", rename.from, rename.to);
+            name.clone_from(&rename.to);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Rename uniforms and samplers in the material definition and in the code
+/// and uniform tables of its shaders, returns whether anything changed. Only
+/// GLSL code is edited, the tables of bytecode shaders are renamed all the same
+pub fn rename_identifiers(
+    material: &mut CompiledMaterialDefinition,
+    renames: &[NameRename],
+) -> anyhow::Result<bool> {
+    if renames.is_empty() {
+        return Ok(false);
+    }
+    let mut any_changed = false;
+    let samplers = std::mem::take(&mut material.sampler_definitions);
+    material.sampler_definitions = rename_key(samplers, renames, &mut any_changed)
+        .into_iter()
+        .collect();
+    let properties = std::mem::take(&mut material.property_fields);
+    material.property_fields = rename_key(properties, renames, &mut any_changed)
+        .into_iter()
+        .collect();
+
+    for (pass_name, pass) in material.passes.iter_mut() {
+        for variant in pass.variants.iter_mut() {
+            for (stage, scode) in variant.shader_codes.iter_mut() {
+                let mut bgfx: BgfxShader = match scode.bgfx_shader_data.pread(0) {
+                    Ok(s) => s,
+                    Err(_) => continue,
+                };
+                let mut changed = false;
+                let uniforms = std::mem::take(&mut bgfx.uniforms);
+                bgfx.uniforms = rename_key(uniforms, renames, &mut changed)
+                    .into_iter()
+                    .collect();
+                // Names in bytecode aren't whole words to replace
+                let renames = if ShaderLanguage::of(&stage.platform).is_glsl() { renames } else { &[] };
+                for rename in renames {
+                    if replace_identifier(&mut bgfx.code, rename.from.as_bytes(), rename.to.as_bytes()) {
+                        changed = true;
+                        log::info!(
+                            "{}/{pass_name}: renamed {} to {} ({:?})",
+                            material.name,
+                            rename.from,
+                            rename.to,
+                            stage.platform
+                        );
+                    }
+                }
+                if changed {
+                    any_changed = true;
+                    scode.bgfx_shader_data.clear();
+                    bgfx.write(&mut scode.bgfx_shader_data).with_context(|| {
+                        format!("Error while writing renamed shader of {}/{pass_name}", material.name)
+                    })?;
+                }
+            }
+        }
+    }
+    Ok(any_changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renames_material_entries() {
+        let renames = ["RenderChunk=MyChunk".parse().unwrap()];
+        assert_eq!(
+            rename_material_entry("renderer/materials/RenderChunk.material.bin", &renames).as_deref(),
+            Some("renderer/materials/MyChunk.material.bin")
+        );
+        assert_eq!(rename_material_entry("RenderChunk.material.bin", &renames).as_deref(), Some("MyChunk.material.bin"));
+        assert_eq!(rename_material_entry("renderer/materials/RenderChunkPrepass.material.bin", &renames), None);
+        assert_eq!(rename_material_entry("textures/RenderChunk.png", &renames), None);
+    }
+
+    #[test]
+    fn replaces_whole_identifiers_only() {
+        let mut code = b"uniform vec4 u_fog;\nvec4 c = u_fog * u_fogColor;".to_vec();
+        assert!(replace_identifier(&mut code, b"u_fog", b"u_fogParams"));
+        assert_eq!(
+            code,
+            b"uniform vec4 u_fogParams;\nvec4 c = u_fogParams * u_fogColor;".to_vec()
+        );
+        assert!(!replace_identifier(&mut code, b"fog", b"mist"));
+    }
+
+    #[test]
+    fn builtins_follow_the_target() {
+        let lightmap = |from: &str, to: &str| NameRename { from: from.to_string(), to: to.to_string() };
+        assert!(renames_for(&MVersion::V26_10, &[]).contains(&lightmap("s_LightMapTexture", "s_LightmapTexture")));
+        assert!(renames_for(&MVersion::V1_21_110, &[]).contains(&lightmap("s_LightmapTexture", "s_LightMapTexture")));
+    }
+
+    #[test]
+    fn overrides_win_over_builtins() {
+        let overrides = ["s_LightMapTexture=s_MyLightmap".parse::<NameRename>().unwrap()];
+        let renames = renames_for(&MVersion::V26_10, &overrides);
+        assert_eq!(renames.iter().filter(|rename| rename.from == "s_LightMapTexture").collect::<Vec<_>>(), [&overrides[0]]);
+    }
+
+    #[test]
+    fn parses_renames() {
+        assert_eq!("s_A=s_B".parse(), Ok(NameRename { from: "s_A".to_string(), to: "s_B".to_string() }));
+        assert!("s_A=".parse::<NameRename>().is_err());
+        assert!("s A=s_B".parse::<NameRename>().is_err());
+    }
+}
@@ -0,0 +1,393 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use anyhow::Context;
+use materialbin::CompiledMaterialDefinition;
+use regex::bytes::{Captures, Regex};
+use serde::Deserialize;
+
+use crate::{
+    find_subsequence,
+    patches::{edit_shaders, edit_unpatched_shaders, Patch, PatchOutcome, ShaderContext, ShaderEdit, ShaderLanguage},
+};
+
+/// Contents of a patch file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PatchFile {
+    #[serde(default)]
+    pub rule: Vec<RuleSpec>,
+    #[serde(default)]
+    pub inject: Vec<InjectSpec>,
+}
+
+/// A user supplied regex rewrite as written in a patch file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleSpec {
+    pub name: String,
+    /// Regex matched against the shader code
+    pub pattern: String,
+    /// Replacement, `$1` or `${name}` insert capture groups
+    pub replacement: String,
+    /// Only rewrite materials whose name contains this
+    pub material: Option<String>,
+    /// Fail the material unless the rule matched exactly this many times in it
+    pub expect: Option<usize>,
+    /// Fail the material if the rule matched fewer times in it
+    pub expect_min: Option<usize>,
+    /// Fail the material if the rule matched more times in it
+    pub expect_max: Option<usize>,
+}
+
+/// A compiled regex rewrite, run as a patch on every shader of a material
+pub struct RewriteRule {
+    name: &'static str,
+    description: &'static str,
+    regex: Regex,
+    replacement: String,
+    material: Option<String>,
+    expect_min: Option<usize>,
+    expect_max: Option<usize>,
+    /// Print every replacement as it happens
+    preview: bool,
+}
+
+impl RewriteRule {
+    pub fn new(spec: RuleSpec, preview: bool) -> anyhow::Result<Self> {
+        let regex = Regex::new(&spec.pattern)
+            .with_context(|| format!("Invalid pattern in rule {}", spec.name))?;
+        Ok(Self {
+            description: Box::leak(format!("rewrite /{}/", spec.pattern).into_boxed_str()),
+            name: Box::leak(spec.name.into_boxed_str()),
+            regex,
+            replacement: spec.replacement,
+            material: spec.material,
+            expect_min: spec.expect.or(spec.expect_min),
+            expect_max: spec.expect.or(spec.expect_max),
+            preview,
+        })
+    }
+}
+
+/// Where an injection puts its snippet
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum InjectAt {
+    /// First thing in `main`
+    PrependToMain,
+    /// Last thing in `main`, code after an early `return` doesn't run
+    AppendToMain,
+    /// Right after the `#version` line
+    InsertAfterVersion,
+}
+
+/// A GLSL snippet to inject as written in a patch file, scoped like a rule and
+/// further by pass, stage and platform
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InjectSpec {
+    pub name: String,
+    pub at: InjectAt,
+    pub code: String,
+    /// Only inject into materials whose name contains this
+    pub material: Option<String>,
+    /// Only inject into this pass, e.g. "Transparent"
+    pub pass: Option<String>,
+    /// Only inject into this stage, e.g. "Fragment" (any case)
+    pub stage: Option<String>,
+    /// Only inject for this platform, e.g. "ESSL_300" (any case)
+    pub platform: Option<String>,
+}
+
+/// A compiled injection, run as a patch on the GLSL and ESSL shaders in scope.
+/// The snippet is wrapped in marker comments so it is injected only once and
+/// can be stripped again
+pub struct InjectRule {
+    name: &'static str,
+    description: &'static str,
+    at: InjectAt,
+    /// Snippet with its markers, the first line is the fingerprint
+    block: Vec<u8>,
+    begin: &'static [u8],
+    end: Vec<u8>,
+    material: Option<String>,
+    pass: Option<String>,
+    stage: Option<String>,
+    platform: Option<String>,
+}
+
+impl InjectRule {
+    pub fn new(spec: InjectSpec) -> anyhow::Result<Self> {
+        anyhow::ensure!(!spec.code.trim().is_empty(), "Injection {} has no code", spec.name);
+        let begin = format!("// >>> {}\n", spec.name);
+        let end = format!("// <<< {}\n", spec.name);
+        let block = format!("{begin}{}\n{end}", spec.code.trim_end_matches('\n'));
+        Ok(Self {
+            description: Box::leak(format!("inject {:?}", spec.at).to_lowercase().into_boxed_str()),
+            name: Box::leak(spec.name.into_boxed_str()),
+            at: spec.at,
+            block: block.into_bytes(),
+            begin: Box::leak(begin.into_bytes().into_boxed_slice()),
+            end: end.into_bytes(),
+            material: spec.material,
+            pass: spec.pass,
+            stage: spec.stage,
+            platform: spec.platform,
+        })
+    }
+
+    /// Insert the block into `code`, `false` if the place it goes isn't there
+    fn inject(&self, code: &mut Vec<u8>) -> bool {
+        let (pos, newline_before) = match self.at {
+            InjectAt::InsertAfterVersion => match version_line_end(code) {
+                Some(pos) => (pos, false),
+                None => return false,
+            },
+            InjectAt::PrependToMain => match main_body(code) {
+                Some((open, _)) if code.get(open + 1) == Some(&b'\n') => (open + 2, false),
+                Some((open, _)) => (open + 1, true),
+                None => return false,
+            },
+            InjectAt::AppendToMain => match main_body(code) {
+                Some((_, close)) => (close, code[close - 1] != b'\n'),
+                None => return false,
+            },
+        };
+        let mut block = Vec::with_capacity(self.block.len() + 1);
+        if newline_before || (pos == code.len() && !code.ends_with(b"\n")) {
+            block.push(b'\n');
+        }
+        block.extend_from_slice(&self.block);
+        code.splice(pos..pos, block);
+        true
+    }
+}
+
+/// Offset right after the `#version` line
+fn version_line_end(code: &[u8]) -> Option<usize> {
+    let mut pos = 0;
+    for line in code.split_inclusive(|&c| c == b'\n') {
+        if line.trim_ascii_start().starts_with(b"#version") {
+            return Some(pos + line.len());
+        }
+        pos += line.len();
+    }
+    None
+}
+
+/// Offsets of the opening and closing braces of `main`
+fn main_body(code: &[u8]) -> Option<(usize, usize)> {
+    let regex = Regex::new(r"\bvoid\s+main\s*\([^)]*\)\s*\{").expect("valid regex");
+    let open = regex.find(code)?.end() - 1;
+    let mut depth = 0;
+    for (offset, &c) in code[open..].iter().enumerate() {
+        match c {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((open, open + offset));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+impl Patch for InjectRule {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn matches_material(&self, name: &str) -> bool {
+        self.material.as_deref().is_none_or(|filter| name.contains(filter))
+    }
+
+    fn fingerprint(&self) -> Option<&'static [u8]> {
+        Some(self.begin)
+    }
+
+    fn strip(&self, code: &mut Vec<u8>) -> bool {
+        let Some(start) = find_subsequence(code, self.begin) else {
+            return false;
+        };
+        let Some(end) = find_subsequence(&code[start..], &self.end) else {
+            return false;
+        };
+        code.drain(start..start + end + self.end.len());
+        true
+    }
+
+    fn apply(&self, material: &mut CompiledMaterialDefinition) -> anyhow::Result<PatchOutcome> {
+        edit_unpatched_shaders(self, material, |&ShaderContext { pass, stage, .. }, code| {
+            let in_scope = self.pass.as_ref().is_none_or(|wanted| wanted == pass)
+                && self.stage.as_ref().is_none_or(|wanted| wanted.eq_ignore_ascii_case(&stage.stage_name))
+                && self.platform.as_ref().is_none_or(|wanted| wanted.eq_ignore_ascii_case(&stage.platform_name));
+            if !in_scope {
+                return ShaderEdit::Unchanged;
+            }
+            if !matches!(ShaderLanguage::of(&stage.platform), ShaderLanguage::Glsl | ShaderLanguage::Essl) {
+                return ShaderEdit::Unsupported;
+            }
+            if self.inject(code) {
+                ShaderEdit::Injected(self.begin)
+            } else {
+                ShaderEdit::Missing
+            }
+        })
+    }
+}
+
+/// Compile the rules and injections of a patch file
+pub fn compile_rules(file: PatchFile, preview: bool) -> anyhow::Result<Vec<Arc<dyn Patch>>> {
+    let rules = file
+        .rule
+        .into_iter()
+        .map(|spec| Ok(Arc::new(RewriteRule::new(spec, preview)?) as Arc<dyn Patch>));
+    let injections = file
+        .inject
+        .into_iter()
+        .map(|spec| Ok(Arc::new(InjectRule::new(spec)?) as Arc<dyn Patch>));
+    rules.chain(injections).collect()
+}
+
+/// Replace every match of `regex` in `code` with what `replace` builds from it,
+/// returns the number of replacements
+pub fn rewrite<F>(code: &mut Vec<u8>, regex: &Regex, mut replace: F) -> usize
+where
+    F: FnMut(&Captures, &mut Vec<u8>),
+{
+    let mut count = 0;
+    let mut out = Vec::with_capacity(code.len());
+    let mut last = 0;
+    for caps in regex.captures_iter(code) {
+        let whole = caps.get(0).expect("group 0 always matches");
+        out.extend_from_slice(&code[last..whole.start()]);
+        replace(&caps, &mut out);
+        last = whole.end();
+        count += 1;
+    }
+    if count != 0 {
+        out.extend_from_slice(&code[last..]);
+        *code = out;
+    }
+    count
+}
+
+impl Patch for RewriteRule {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn matches_material(&self, name: &str) -> bool {
+        self.material.as_deref().is_none_or(|filter| name.contains(filter))
+    }
+
+    fn apply(&self, material: &mut CompiledMaterialDefinition) -> anyhow::Result<PatchOutcome> {
+        let matches = AtomicUsize::new(0);
+        let outcome = edit_shaders(material, |&ShaderContext { material, pass, stage, .. }, code| {
+            let count = rewrite(code, &self.regex, |caps, out| {
+                let start = out.len();
+                caps.expand(self.replacement.as_bytes(), out);
+                if self.preview {
+                    println!(
+                        "{} {material}/{pass} {:?}: {:?} -> {:?}",
+                        self.name,
+                        stage.platform,
+                        String::from_utf8_lossy(&caps[0]),
+                        String::from_utf8_lossy(&out[start..])
+                    );
+                }
+            });
+            matches.fetch_add(count, Ordering::Relaxed);
+            if count == 0 {
+                ShaderEdit::Unchanged
+            } else {
+                ShaderEdit::Changed
+            }
+        })?;
+        let matches = matches.into_inner();
+        if let Some(min) = self.expect_min {
+            anyhow::ensure!(
+                matches >= min,
+                "Rule {} matched {matches} times, expected at least {min}",
+                self.name
+            );
+        }
+        if let Some(max) = self.expect_max {
+            anyhow::ensure!(
+                matches <= max,
+                "Rule {} matched {matches} times, expected at most {max}",
+                self.name
+            );
+        }
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn injection(at: InjectAt) -> InjectRule {
+        InjectRule::new(InjectSpec {
+            name: "tint".to_string(),
+            at,
+            code: "gl_FragColor.rgb *= 0.9;\n".to_string(),
+            material: None,
+            pass: None,
+            stage: None,
+            platform: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn injects_snippets_and_strips_them() {
+        let original = b"#version 300 es\nvoid main() {\n  if (x) { y(); }\n  gl_FragColor = c;\n}\n".to_vec();
+
+        let mut code = original.clone();
+        assert!(injection(InjectAt::AppendToMain).inject(&mut code));
+        assert_eq!(
+            String::from_utf8(code.clone()).unwrap(),
+            "#version 300 es\nvoid main() {\n  if (x) { y(); }\n  gl_FragColor = c;\n// >>> tint\ngl_FragColor.rgb *= 0.9;\n// <<< tint\n}\n"
+        );
+        assert!(injection(InjectAt::AppendToMain).strip(&mut code));
+        assert_eq!(code, original);
+
+        let mut code = original.clone();
+        assert!(injection(InjectAt::PrependToMain).inject(&mut code));
+        assert!(code.starts_with(b"#version 300 es\nvoid main() {\n// >>> tint\n"));
+
+        let mut code = original.clone();
+        assert!(injection(InjectAt::InsertAfterVersion).inject(&mut code));
+        assert!(code.starts_with(b"#version 300 es\n// >>> tint\ngl_FragColor"));
+
+        let mut code = b"void helper() {}".to_vec();
+        assert!(!injection(InjectAt::AppendToMain).inject(&mut code));
+        assert!(!injection(InjectAt::InsertAfterVersion).inject(&mut code));
+    }
+
+    #[test]
+    fn rewrite_expands_captures() {
+        let regex = Regex::new(r"texture2D\(\s*(\w+)\s*,").unwrap();
+        let mut code = b"texture2D( s_A, uv); texture2D(s_B ,uv)".to_vec();
+        let count = rewrite(&mut code, &regex, |caps, out| {
+            caps.expand(b"texture($1,", out);
+        });
+        assert_eq!(count, 2);
+        assert_eq!(code, b"texture(s_A, uv); texture(s_B,uv)".to_vec());
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use material_updater::{bench::PhaseTiming, summary::EntrySummary, tr, ConversionReport};
+use serde::Serialize;
+
+/// Everything a run did, written by `--summary-file` for release automation
+#[derive(Serialize)]
+pub struct RunSummary {
+    pub tool_version: &'static str,
+    pub started: String,
+    pub duration_secs: f64,
+    pub success: bool,
+    /// Target versions as given on the command line and what they resolved to
+    pub targets: Vec<ResolvedTarget>,
+    pub inputs: Vec<InputRecord>,
+    /// Inputs, or inputs for one target, that failed before finishing
+    pub failures: Vec<Failure>,
+    /// Phase timings of every input, only written by `bench`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub benchmarks: Vec<BenchRecord>,
+    #[serde(skip)]
+    start: Instant,
+}
+
+#[derive(Serialize)]
+pub struct ResolvedTarget {
+    pub requested: String,
+    pub resolved: String,
+}
+
+/// One converted input, for every target it was converted to in one go
+#[derive(Serialize)]
+pub struct InputRecord {
+    pub input: String,
+    pub input_sha256: String,
+    pub duration_secs: f64,
+    pub outputs: Vec<OutputRecord>,
+}
+
+#[derive(Serialize)]
+pub struct OutputRecord {
+    pub target: String,
+    /// `None` when nothing was written, see `written`
+    pub path: Option<PathBuf>,
+    pub sha256: Option<String>,
+    /// False for --yeet, and for outputs --skip-up-to-date left out
+    pub written: bool,
+    pub up_to_date: bool,
+    pub translated_materials: usize,
+    pub warnings: usize,
+    /// Every warning with its stable code, e.g. `W002`
+    pub warning_codes: Vec<WarningRecord>,
+    pub unfixed_shaders: usize,
+    pub unpatchable_shaders: usize,
+    /// How many materials each patch changed
+    pub patches: BTreeMap<&'static str, usize>,
+    /// How many materials were read as each binary version
+    pub sources: BTreeMap<String, usize>,
+    pub entries: Vec<EntryRecord>,
+}
+
+#[derive(Serialize)]
+pub struct WarningRecord {
+    pub code: String,
+    pub name: &'static str,
+    pub entry: Option<String>,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct EntryRecord {
+    pub name: String,
+    pub source: Option<String>,
+    pub result: String,
+    /// Patches that changed the material, with the shaders they couldn't fix
+    pub patches: BTreeMap<&'static str, usize>,
+    pub size_delta: i64,
+}
+
+#[derive(Serialize)]
+pub struct BenchRecord {
+    pub input: String,
+    pub target: String,
+    pub materials: usize,
+    pub phases: Vec<PhaseTiming>,
+}
+
+#[derive(Serialize)]
+pub struct Failure {
+    pub input: String,
+    /// `None` if the input failed for every target
+    pub target: Option<String>,
+    pub error: String,
+}
+
+impl RunSummary {
+    pub fn new() -> Self {
+        Self {
+            tool_version: env!("CARGO_PKG_VERSION"),
+            started: chrono::Local::now().to_rfc3339(),
+            duration_secs: 0.0,
+            success: false,
+            targets: Vec::new(),
+            inputs: Vec::new(),
+            failures: Vec::new(),
+            benchmarks: Vec::new(),
+            start: Instant::now(),
+        }
+    }
+
+    /// Finish the summary and write it to `path` as JSON
+    pub fn write(mut self, path: &Path, success: bool) -> anyhow::Result<()> {
+        self.duration_secs = self.start.elapsed().as_secs_f64();
+        self.success = success;
+        let file = File::create(path).with_context(|| format!("Error while creating {}", path.display()))?;
+        serde_json::to_writer_pretty(file, &self)?;
+        println!("{}", tr!("wrote-run-summary", path = path.display()));
+        Ok(())
+    }
+}
+
+/// Record `failure` in `summary`, if there is one
+pub fn record_failure(summary: Option<&Mutex<RunSummary>>, input: &str, target: Option<String>, error: &anyhow::Error) {
+    if let Some(summary) = summary {
+        summary.lock().unwrap().failures.push(Failure {
+            input: input.to_string(),
+            target,
+            error: format!("{error:#}"),
+        });
+    }
+}
+
+impl InputRecord {
+    pub fn new(input: String, input_sha256: String) -> Self {
+        Self {
+            input,
+            input_sha256,
+            duration_secs: 0.0,
+            outputs: Vec::new(),
+        }
+    }
+
+    pub fn finish(mut self, duration: Duration) -> Self {
+        self.duration_secs = duration.as_secs_f64();
+        self
+    }
+}
+
+impl OutputRecord {
+    pub fn new(report: &ConversionReport, path: Option<PathBuf>, sha256: Option<String>) -> Self {
+        let mut patches = BTreeMap::new();
+        for entry in &report.entries {
+            for (name, _) in &entry.patches {
+                *patches.entry(*name).or_default() += 1;
+            }
+        }
+        Self {
+            target: report.version.to_string(),
+            written: path.is_some(),
+            path,
+            sha256,
+            up_to_date: report.up_to_date,
+            translated_materials: report.translated_materials,
+            warnings: report.warnings.len(),
+            warning_codes: report
+                .warnings
+                .iter()
+                .map(|warning| WarningRecord {
+                    code: warning.code.code(),
+                    name: warning.code.name(),
+                    entry: warning.entry.clone(),
+                    message: warning.message.clone(),
+                })
+                .collect(),
+            unfixed_shaders: report.unfixed_shaders,
+            unpatchable_shaders: report.unpatchable_shaders,
+            patches,
+            sources: report
+                .source_versions()
+                .into_iter()
+                .map(|(version, count)| (version.to_string(), count))
+                .collect(),
+            entries: report.entries.iter().map(EntryRecord::from).collect(),
+        }
+    }
+}
+
+impl From<&EntrySummary> for EntryRecord {
+    fn from(entry: &EntrySummary) -> Self {
+        Self {
+            name: entry.name.clone(),
+            source: entry.source.map(|version| version.to_string()),
+            result: entry.result.to_string(),
+            patches: entry.patches.iter().copied().collect(),
+            size_delta: entry.size_delta,
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! `--stamp`: a record of how a pack was converted, kept in the pack itself so
+//! whoever gets a broken one can tell what produced it
+
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use crate::{summary::EntrySummary, MVersion};
+
+/// Name of the record, next to the pack's manifest.json
+pub const STAMP_ENTRY: &str = "material_updater.json";
+
+/// Start of the line added to the manifest's description
+const DESCRIPTION_PREFIX: &str = "Converted for ";
+
+/// The record of converting to `target`, given the entries of its report
+pub fn stamp_json(target: &MVersion, entries: &[EntrySummary]) -> String {
+    let mut sources = BTreeMap::new();
+    let mut patches = BTreeMap::new();
+    for entry in entries {
+        if let Some(source) = entry.source {
+            *sources.entry(source.to_string()).or_insert(0) += 1;
+        }
+        for (patch, _) in &entry.patches {
+            *patches.entry(*patch).or_insert(0) += 1;
+        }
+    }
+    let stamp = json!({
+        "tool": env!("CARGO_PKG_NAME"),
+        "tool_version": env!("CARGO_PKG_VERSION"),
+        "target": target.to_string(),
+        "sources": sources,
+        "patches": patches,
+    });
+    serde_json::to_string_pretty(&stamp).expect("the stamp is plain JSON")
+}
+
+/// `manifest` with a line saying it was converted for `target` at the end of
+/// its description, in place of one an earlier conversion added. `None` if it
+/// isn't JSON with a header, e.g. because it has comments
+pub fn stamp_manifest(manifest: &[u8], target: &MVersion) -> Option<Vec<u8>> {
+    let mut manifest: Value = serde_json::from_slice(manifest).ok()?;
+    let header = manifest.get_mut("header")?.as_object_mut()?;
+    let description = header.get("description").and_then(Value::as_str).unwrap_or_default();
+    let kept: Vec<&str> = description.lines().filter(|line| !line.starts_with(DESCRIPTION_PREFIX)).collect();
+    let line = format!("{DESCRIPTION_PREFIX}{target} by {} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let description = if kept.is_empty() { line } else { format!("{}\n{line}", kept.join("\n")) };
+    header.insert("description".to_string(), Value::String(description));
+    serde_json::to_vec_pretty(&manifest).ok()
+}
+
+/// Directory of the pack's own manifest.json among `names`, the least nested
+/// one, e.g. `MyPack/`. `None` if there is no manifest
+pub fn pack_prefix<'a>(names: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    names
+        .into_iter()
+        .filter(|name| name.rsplit('/').next() == Some("manifest.json"))
+        .min_by_key(|name| name.matches('/').count())
+        .map(|name| name[..name.len() - "manifest.json".len()].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamps_once() {
+        let manifest = br#"{"header": {"description": "Shaders", "uuid": "a"}}"#;
+        let stamped = stamp_manifest(manifest, &MVersion::V1_21_110).unwrap();
+        let again = stamp_manifest(&stamped, &MVersion::V26_10).unwrap();
+        let description = serde_json::from_slice::<Value>(&again).unwrap()["header"]["description"].as_str().unwrap().to_string();
+        assert!(description.starts_with("Shaders\nConverted for 26.10 by material-updater"));
+        assert_eq!(description.lines().count(), 2);
+        assert!(stamp_manifest(b"// comment\n{}", &MVersion::V26_10).is_none());
+
+        assert_eq!(pack_prefix(["MyPack/subpacks/high/manifest.json", "MyPack/manifest.json"]), Some("MyPack/".to_string()));
+        assert_eq!(pack_prefix(["manifest.json"]), Some(String::new()));
+        let stamp: Value = serde_json::from_str(&stamp_json(&MVersion::V26_10, &[])).unwrap();
+        assert_eq!(stamp["target"], "26.10");
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    io::{Read, Seek},
+};
+
+use materialbin::CompiledMaterialDefinition;
+
+use crate::for_each_material;
+
+/// Shader count and code size for one platform or stage
+#[derive(Default, Clone, Copy)]
+pub struct Breakdown {
+    pub shaders: usize,
+    pub bytes: usize,
+}
+
+impl Breakdown {
+    fn add(&mut self, bytes: usize) {
+        self.shaders += 1;
+        self.bytes += bytes;
+    }
+}
+
+pub struct MaterialStats {
+    /// Zip entry (or file) the material was read from
+    pub entry: String,
+    pub name: String,
+    pub passes: usize,
+    pub variants: usize,
+    pub shaders: usize,
+    pub code_bytes: usize,
+}
+
+pub struct ShaderSize {
+    pub entry: String,
+    pub pass: String,
+    pub variant: usize,
+    pub platform: String,
+    pub stage: String,
+    pub bytes: usize,
+}
+
+#[derive(Default)]
+pub struct PackStats {
+    pub materials: Vec<MaterialStats>,
+    pub by_platform: BTreeMap<String, Breakdown>,
+    pub by_stage: BTreeMap<String, Breakdown>,
+    /// Every shader in the pack, largest first
+    pub shaders: Vec<ShaderSize>,
+}
+
+impl PackStats {
+    pub fn code_bytes(&self) -> usize {
+        self.materials.iter().map(|m| m.code_bytes).sum()
+    }
+
+    fn add_material(&mut self, entry: &str, material: &CompiledMaterialDefinition) {
+        let mut stats = MaterialStats {
+            entry: entry.to_string(),
+            name: material.name.clone(),
+            passes: material.passes.len(),
+            variants: 0,
+            shaders: 0,
+            code_bytes: 0,
+        };
+        for (pass_name, pass) in &material.passes {
+            stats.variants += pass.variants.len();
+            for (index, variant) in pass.variants.iter().enumerate() {
+                for (stage, code) in &variant.shader_codes {
+                    let bytes = code.bgfx_shader_data.len();
+                    stats.shaders += 1;
+                    stats.code_bytes += bytes;
+                    self.by_platform
+                        .entry(stage.platform_name.clone())
+                        .or_default()
+                        .add(bytes);
+                    self.by_stage
+                        .entry(stage.stage_name.clone())
+                        .or_default()
+                        .add(bytes);
+                    self.shaders.push(ShaderSize {
+                        entry: entry.to_string(),
+                        pass: pass_name.clone(),
+                        variant: index,
+                        platform: stage.platform_name.clone(),
+                        stage: stage.stage_name.clone(),
+                        bytes,
+                    });
+                }
+            }
+        }
+        self.materials.push(stats);
+    }
+}
+
+/// Collect statistics for every material in a zip, or for a single material.bin
+pub fn pack_stats<R: Read + Seek>(input: &mut R) -> anyhow::Result<PackStats> {
+    let mut stats = PackStats::default();
+    for_each_material(input, |entry, material, _| {
+        stats.add_material(entry, &material);
+        Ok(())
+    })?;
+    stats.shaders.sort_by_key(|shader| Reverse(shader.bytes));
+    Ok(stats)
+}
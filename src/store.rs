@@ -0,0 +1,95 @@
+//! `--cache-dir`: converted material entries kept zstd compressed in a
+//! directory, so packs that barely changed convert almost instantly
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
+
+use crate::{cache::EntryStore, checksums::hex, MVersion};
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Entries under `<dir>/<first two key digits>/<key>.zst`, the key being the
+/// SHA-256 of the conversion settings, target version and input entry
+pub struct ZstdStore {
+    dir: PathBuf,
+    settings: String,
+}
+
+impl ZstdStore {
+    /// `settings` describes everything besides the target version that makes
+    /// a conversion come out differently, e.g. the selected patches. Runs with
+    /// other settings never see each other's entries
+    pub fn new(dir: &Path, settings: String) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("Error while creating cache directory {}", dir.display()))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            settings,
+        })
+    }
+
+    fn path(&self, data: &[u8], version: &MVersion) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(self.settings.as_bytes());
+        hasher.update([0]);
+        hasher.update(version.to_string().as_bytes());
+        hasher.update([0]);
+        hasher.update(data);
+        let key = hex(&hasher.finalize());
+        self.dir.join(&key[..2]).join(format!("{key}.zst"))
+    }
+
+    fn write(&self, path: &Path, encoded: &[u8]) -> anyhow::Result<()> {
+        let dir = path.parent().unwrap_or(&self.dir);
+        fs::create_dir_all(dir)?;
+        // Renamed into place, so other runs sharing the directory never read half an entry
+        let mut tmp = NamedTempFile::new_in(dir)?;
+        let compressed = zstd::bulk::compress(encoded, ZSTD_LEVEL)?;
+        tmp.write_all(&compressed)?;
+        tmp.persist(path)?;
+        Ok(())
+    }
+}
+
+impl EntryStore for ZstdStore {
+    fn load(&self, data: &[u8], version: &MVersion) -> Option<Vec<u8>> {
+        let path = self.path(data, version);
+        let compressed = fs::read(&path).ok()?;
+        match zstd::decode_all(compressed.as_slice()) {
+            Ok(encoded) => Some(encoded),
+            Err(err) => {
+                log::warn!("Ignoring broken cache entry {}: {err}", path.display());
+                None
+            }
+        }
+    }
+
+    fn save(&self, data: &[u8], version: &MVersion, encoded: &[u8]) {
+        let path = self.path(data, version);
+        if let Err(err) = self.write(&path, encoded) {
+            log::warn!("Error while writing cache entry {}: {err:#}", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_kept_per_settings_and_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ZstdStore::new(dir.path(), "default".to_string()).unwrap();
+        store.save(b"input", &MVersion::V1_21_110, b"converted");
+        assert_eq!(store.load(b"input", &MVersion::V1_21_110).as_deref(), Some(&b"converted"[..]));
+        assert_eq!(store.load(b"input", &MVersion::V26_10), None);
+        let other = ZstdStore::new(dir.path(), "--disable-patch x".to_string()).unwrap();
+        assert_eq!(other.load(b"input", &MVersion::V1_21_110), None);
+    }
+}
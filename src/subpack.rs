@@ -0,0 +1,214 @@
+//! Subpacks: tiers of a pack the player picks between, each with its own
+//! `subpacks/<tier>/` directory that can hold materials of its own
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    io::{Read, Seek, Write},
+};
+
+use anyhow::Context;
+use owo_colors::OwoColorize;
+use serde_json::Value;
+use zip::{result::ZipResult, write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use crate::{summary::EntrySummary, tr};
+
+/// An entry path under `subpacks/` split into the directory of the pack, the
+/// tier and the path inside the tier, which are empty for the directories
+/// themselves. The pack may sit in a directory of the archive
+fn split_subpack(entry: &str) -> Option<(&str, &str, &str)> {
+    let mut start = 0;
+    for segment in entry.split('/') {
+        if segment.eq_ignore_ascii_case("subpacks") {
+            let rest = entry.get(start + segment.len() + 1..).unwrap_or_default();
+            let (tier, path) = rest.split_once('/').unwrap_or((rest, ""));
+            return Some((&entry[..start], tier, path));
+        }
+        start += segment.len() + 1;
+    }
+    None
+}
+
+/// Subpack tier an entry path belongs to, `None` for entries of the main pack.
+/// The pack may sit in a directory of the archive
+pub fn subpack_of(entry: &str) -> Option<&str> {
+    match split_subpack(entry)? {
+        // A tier directory with something in it, not the directory entry itself
+        (_, tier, path) if !tier.is_empty() && !path.is_empty() => Some(tier),
+        _ => None,
+    }
+}
+
+/// Tiers of the subpacks in an archive, sorted
+pub fn list_subpacks<R: Read + Seek>(input: R) -> ZipResult<BTreeSet<String>> {
+    let zip = ZipArchive::new(input)?;
+    Ok(zip.file_names().filter_map(subpack_of).map(str::to_string).collect())
+}
+
+/// What [`flatten_subpack`] did
+pub struct Flattened {
+    /// Files of the tier moved to the main pack
+    pub promoted: usize,
+    /// Files of the main pack the tier's files replaced
+    pub replaced: usize,
+    /// Files of the other tiers left out
+    pub dropped: usize,
+}
+
+/// Copy `input` to `output` with the files of subpack `tier` moved to the main
+/// pack, replacing the ones already there, and all subpacks left out. The
+/// manifest's `subpacks` declarations are removed too, so the game doesn't
+/// offer tiers that are gone
+pub fn flatten_subpack<R, W>(input: R, output: W, tier: &str) -> anyhow::Result<Flattened>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let mut zip = ZipArchive::new(input)?;
+    let mut flattened = Flattened {
+        promoted: 0,
+        replaced: 0,
+        dropped: 0,
+    };
+    let mut promoted = Vec::new();
+    let mut tiers = BTreeSet::new();
+    for name in zip.file_names() {
+        match split_subpack(name) {
+            Some((pack, found, path)) if found == tier && !path.is_empty() => {
+                promoted.push((name.to_string(), format!("{pack}{path}")));
+            }
+            Some((_, found, path)) => {
+                if !found.is_empty() {
+                    tiers.insert(found.to_string());
+                }
+                if !path.is_empty() && !path.ends_with('/') {
+                    flattened.dropped += 1;
+                }
+            }
+            None => {}
+        }
+    }
+    anyhow::ensure!(
+        !promoted.is_empty(),
+        "The pack has no subpack {tier}, it has {}",
+        if tiers.is_empty() { "none".to_string() } else { tiers.into_iter().collect::<Vec<_>>().join(", ") }
+    );
+    let replacements: HashSet<&str> = promoted.iter().map(|(_, name)| name.as_str()).collect();
+
+    let mut output = ZipWriter::new(output);
+    for index in 0..zip.len() {
+        let file = zip.by_index_raw(index)?;
+        let name = file.name().to_string();
+        if split_subpack(&name).is_some() {
+            continue;
+        }
+        if replacements.contains(name.as_str()) {
+            flattened.replaced += 1;
+            continue;
+        }
+        if name.rsplit('/').next() == Some("manifest.json") {
+            drop(file);
+            let mut manifest = String::new();
+            zip.by_index(index)?.read_to_string(&mut manifest)?;
+            match without_subpacks(&manifest) {
+                Ok(Some(manifest)) => {
+                    output.start_file(name, SimpleFileOptions::default())?;
+                    output.write_all(manifest.as_bytes())?;
+                    continue;
+                }
+                Ok(None) => {}
+                // E.g. comments, which the game accepts
+                Err(err) => log::warn!("{name} can't be read ({err}), its subpack declarations are left in"),
+            }
+            output.raw_copy_file(zip.by_index_raw(index)?)?;
+            continue;
+        }
+        output.raw_copy_file(file)?;
+    }
+    for (from, to) in &promoted {
+        let index = zip.index_for_name(from).with_context(|| format!("{from} vanished"))?;
+        output.raw_copy_file_rename(zip.by_index_raw(index)?, to)?;
+        flattened.promoted += 1;
+    }
+    output.finish()?;
+    Ok(flattened)
+}
+
+/// `manifest` without its `subpacks` declarations, `None` if it has none
+fn without_subpacks(manifest: &str) -> anyhow::Result<Option<String>> {
+    let mut manifest: Value = serde_json::from_str(manifest)?;
+    let removed = manifest.as_object_mut().and_then(|manifest| manifest.remove("subpacks"));
+    Ok(match removed {
+        Some(_) => Some(serde_json::to_string_pretty(&manifest)?),
+        None => None,
+    })
+}
+
+/// How the materials of every subpack went, printed after the summary table
+/// of packs that have subpacks
+pub fn print_subpack_summaries(entries: &[EntrySummary]) {
+    let mut tiers: BTreeMap<Option<&str>, BTreeMap<String, usize>> = BTreeMap::new();
+    for entry in entries {
+        *tiers
+            .entry(subpack_of(&entry.name))
+            .or_default()
+            .entry(entry.result.localized())
+            .or_default() += 1;
+    }
+    if tiers.keys().all(Option::is_none) {
+        return;
+    }
+    for (tier, results) in tiers {
+        let total: usize = results.values().sum();
+        let results: Vec<String> = results.iter().map(|(result, count)| format!("{count} {result}")).collect();
+        let tier = match tier {
+            Some(tier) => tr!("subpack", tier = tier),
+            None => tr!("main-pack"),
+        };
+        println!("{}", tr!("subpack-summary", tier = tier.bold(), count = total, results = results.join(", ")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_tier() {
+        assert_eq!(subpack_of("subpacks/high/renderer/materials/Sky.material.bin"), Some("high"));
+        assert_eq!(subpack_of("MyPack/subpacks/low/renderer/materials/Sky.material.bin"), Some("low"));
+        assert_eq!(subpack_of("subpacks/high/"), None);
+        assert_eq!(subpack_of("renderer/materials/Sky.material.bin"), None);
+    }
+
+    #[test]
+    fn flattens_a_tier() {
+        let mut zip = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let entries = [
+            ("manifest.json", r#"{"header": {}, "subpacks": [{"folder_name": "high", "name": "High"}]}"#),
+            ("renderer/materials/Sky.material.bin", "main"),
+            ("renderer/materials/Water.material.bin", "main"),
+            ("subpacks/high/renderer/materials/Sky.material.bin", "high"),
+            ("subpacks/low/renderer/materials/Sky.material.bin", "low"),
+        ];
+        for (name, contents) in entries {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        let input = zip.finish().unwrap();
+        let mut output = std::io::Cursor::new(Vec::new());
+        let flattened = flatten_subpack(input, &mut output, "high").unwrap();
+        assert_eq!((flattened.promoted, flattened.replaced, flattened.dropped), (1, 1, 1));
+
+        let mut zip = ZipArchive::new(output).unwrap();
+        let mut names: Vec<&str> = zip.file_names().collect();
+        names.sort();
+        assert_eq!(names, ["manifest.json", "renderer/materials/Sky.material.bin", "renderer/materials/Water.material.bin"]);
+        let mut sky = String::new();
+        zip.by_name("renderer/materials/Sky.material.bin").unwrap().read_to_string(&mut sky).unwrap();
+        assert_eq!(sky, "high");
+        let mut manifest = String::new();
+        zip.by_name("manifest.json").unwrap().read_to_string(&mut manifest).unwrap();
+        assert!(!manifest.contains("subpacks"));
+    }
+}
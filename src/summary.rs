@@ -0,0 +1,185 @@
+use std::fmt;
+
+use materialbin::MinecraftVersion;
+use owo_colors::OwoColorize;
+
+use crate::{profile::EntryTimings, tr};
+
+/// What happened to a material entry of an archive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryResult {
+    Converted,
+    /// Copied from the previous output by `--cache`
+    Cached,
+    /// Copied unchanged from the input
+    Copied,
+    /// Written for this older version because the target rejected it
+    Downgraded(&'static str),
+    /// Left out of the output
+    Skipped,
+    /// Copied unchanged because patching it failed
+    Failed,
+    /// Left out of the output because it is the same as vanilla
+    Vanilla,
+    /// Copied unchanged, it belongs to a subpack other than `--only-subpack`
+    OtherSubpack,
+}
+
+impl fmt::Display for EntryResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Converted => f.write_str("converted"),
+            Self::Cached => f.write_str("cached"),
+            Self::Copied => f.write_str("copied"),
+            Self::Downgraded(version) => write!(f, "downgraded to {version}"),
+            Self::Skipped => f.write_str("skipped"),
+            Self::Failed => f.write_str("failed"),
+            Self::Vanilla => f.write_str("vanilla, stripped"),
+            Self::OtherSubpack => f.write_str("other subpack, unchanged"),
+        }
+    }
+}
+
+impl EntryResult {
+    /// What happened in the language of the messages, [`Display`](fmt::Display)
+    /// is the same in English for records
+    pub fn localized(&self) -> String {
+        match self {
+            Self::Converted => tr!("result-converted"),
+            Self::Cached => tr!("result-cached"),
+            Self::Copied => tr!("result-copied"),
+            Self::Downgraded(version) => tr!("result-downgraded", version = version),
+            Self::Skipped => tr!("result-skipped"),
+            Self::Failed => tr!("result-failed"),
+            Self::Vanilla => tr!("result-vanilla"),
+            Self::OtherSubpack => tr!("result-other-subpack"),
+        }
+    }
+}
+
+/// One row of the per-archive summary
+#[derive(Clone)]
+pub struct EntrySummary {
+    pub name: String,
+    /// Binary version the material was read as, if it was parsed
+    pub source: Option<MinecraftVersion>,
+    /// Patches that changed the material, with the shaders they couldn't fix
+    pub patches: Vec<(&'static str, usize)>,
+    pub result: EntryResult,
+    /// Uncompressed output size minus input size
+    pub size_delta: i64,
+    pub timings: EntryTimings,
+}
+
+impl EntrySummary {
+    pub(crate) fn new(name: &str, source: Option<MinecraftVersion>, result: EntryResult) -> Self {
+        Self {
+            name: name.to_string(),
+            source,
+            patches: Vec::new(),
+            result,
+            size_delta: 0,
+            timings: EntryTimings::default(),
+        }
+    }
+}
+
+/// Binary versions the entries were read as, with how many entries each, in the
+/// order they first appear
+pub fn source_versions(entries: &[EntrySummary]) -> Vec<(MinecraftVersion, usize)> {
+    let mut versions: Vec<(MinecraftVersion, usize)> = Vec::new();
+    for source in entries.iter().filter_map(|entry| entry.source) {
+        match versions.iter_mut().find(|(version, _)| *version == source) {
+            Some((_, count)) => *count += 1,
+            None => versions.push((source, 1)),
+        }
+    }
+    versions
+}
+
+/// `versions` as "1.20.80 (12), 1.21.20 (3)"
+pub fn format_source_versions(versions: &[(MinecraftVersion, usize)]) -> String {
+    versions
+        .iter()
+        .map(|(version, count)| format!("{version} ({count})"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn size_delta(delta: i64) -> String {
+    match delta {
+        0 => "0".to_string(),
+        delta if delta.unsigned_abs() >= 1024 => format!("{:+.1}K", delta as f64 / 1024.0),
+        delta => format!("{delta:+}"),
+    }
+}
+
+/// Print the entries of a converted archive as an aligned table
+pub fn print_summary(entries: &[EntrySummary]) {
+    if entries.is_empty() {
+        return;
+    }
+    let rows: Vec<[String; 5]> = entries
+        .iter()
+        .map(|entry| {
+            let patches: Vec<String> = entry
+                .patches
+                .iter()
+                .map(|(name, unfixed)| match unfixed {
+                    0 => name.to_string(),
+                    unfixed => format!("{name} ({unfixed} unfixed)"),
+                })
+                .collect();
+            [
+                entry.name.clone(),
+                entry.source.map_or("-".to_string(), |source| source.to_string()),
+                if patches.is_empty() { "-".to_string() } else { patches.join(", ") },
+                entry.result.localized(),
+                size_delta(entry.size_delta),
+            ]
+        })
+        .collect();
+    let header = [
+        tr!("summary-entry"),
+        tr!("summary-source"),
+        tr!("summary-patches"),
+        tr!("summary-result"),
+        tr!("summary-size"),
+    ];
+    let mut widths = header.each_ref().map(|title| title.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line: Vec<String> = header
+        .iter()
+        .zip(widths)
+        .map(|(title, width)| format!("{title:<width$}"))
+        .collect();
+    // Printed at once so tables of parallel conversions don't interleave
+    let mut table = format!("{}\n", line.join("  ").trim_end().bold());
+    for (row, entry) in rows.iter().zip(entries) {
+        let [name, source, patches, result, size] = row;
+        let [name_w, source_w, patches_w, result_w, _] = widths;
+        let result = format!("{result:<result_w$}");
+        let result = match entry.result {
+            EntryResult::Converted | EntryResult::Cached => result.green().to_string(),
+            EntryResult::Vanilla | EntryResult::OtherSubpack => result.dimmed().to_string(),
+            EntryResult::Copied | EntryResult::Downgraded(_) => result.yellow().to_string(),
+            EntryResult::Skipped | EntryResult::Failed => result.red().to_string(),
+        };
+        let patches = format!("{patches:<patches_w$}");
+        let patches = if entry.patches.iter().any(|(_, unfixed)| *unfixed != 0) {
+            patches.yellow().to_string()
+        } else {
+            patches
+        };
+        table += &format!(
+            "{}  {}  {patches}  {result}  {size}\n",
+            format!("{name:<name_w$}").cyan(),
+            format!("{source:<source_w$}").dimmed(),
+        );
+    }
+    print!("{table}");
+}
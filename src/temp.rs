@@ -0,0 +1,119 @@
+//! Where scratch data goes: temporary files in the system temp directory or
+//! `--tempdir`, or memory with `--no-temp`
+
+use std::{
+    fs::{self, File},
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::Context;
+use tempfile::{tempfile, SpooledTempFile};
+
+static IN_MEMORY: AtomicBool = AtomicBool::new(false);
+
+/// Put temporary files in `dir`, or keep everything in memory if `no_temp`.
+/// Without `dir`, an unwritable system temp directory (as on some Android
+/// setups) is swapped for one in the cache directory
+pub fn configure(dir: Option<&Path>, no_temp: bool) -> anyhow::Result<()> {
+    IN_MEMORY.store(no_temp, Ordering::Relaxed);
+    if no_temp {
+        return Ok(());
+    }
+    let dir = match dir {
+        Some(dir) => dir.to_path_buf(),
+        None if writable(&std::env::temp_dir()) => return Ok(()),
+        None => {
+            let fallback = fallback_dir().with_context(|| {
+                format!(
+                    "The temp directory {} isn't writable, give another one with --tempdir or use --no-temp",
+                    std::env::temp_dir().display()
+                )
+            })?;
+            log::warn!(
+                "The temp directory {} isn't writable, using {}",
+                std::env::temp_dir().display(),
+                fallback.display()
+            );
+            fallback
+        }
+    };
+    fs::create_dir_all(&dir).with_context(|| format!("Error while creating temp directory {}", dir.display()))?;
+    anyhow::ensure!(writable(&dir), "The temp directory {} isn't writable", dir.display());
+    // Only fails when it was set before, which happens once per process
+    let _ = tempfile::env::override_temp_dir(&dir);
+    Ok(())
+}
+
+fn writable(dir: &Path) -> bool {
+    tempfile::tempfile_in(dir).is_ok()
+}
+
+fn fallback_dir() -> Option<PathBuf> {
+    let dir = dirs::cache_dir()?.join("material-updater").join("tmp");
+    fs::create_dir_all(&dir).ok()?;
+    writable(&dir).then_some(dir)
+}
+
+/// Whether `--no-temp` keeps everything in memory
+pub fn in_memory() -> bool {
+    IN_MEMORY.load(Ordering::Relaxed)
+}
+
+/// Output buffer that moves to a temporary file past `max_memory` bytes, unless
+/// everything stays in memory
+pub fn spooled(max_memory: usize) -> SpooledTempFile {
+    SpooledTempFile::new(if in_memory() { usize::MAX } else { max_memory })
+}
+
+/// An input file, or scratch data held in a temporary file or memory
+pub enum Scratch {
+    File(File),
+    Memory(Cursor<Vec<u8>>),
+}
+
+impl Scratch {
+    /// Empty scratch space, a temporary file unless everything stays in memory
+    pub fn new() -> io::Result<Self> {
+        if in_memory() {
+            Ok(Self::Memory(Cursor::default()))
+        } else {
+            tempfile().map(Self::File)
+        }
+    }
+}
+
+impl Read for Scratch {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::File(file) => file.read(buf),
+            Self::Memory(data) => data.read(buf),
+        }
+    }
+}
+
+impl Write for Scratch {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::File(file) => file.write(buf),
+            Self::Memory(data) => data.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::File(file) => file.flush(),
+            Self::Memory(data) => data.flush(),
+        }
+    }
+}
+
+impl Seek for Scratch {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::File(file) => file.seek(pos),
+            Self::Memory(data) => data.seek(pos),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::io::{self, BufRead, Write};
+
+use material_updater::{
+    hooks::{ConversionHooks, Message, MessageKind},
+    subpack, summary,
+    summary::EntrySummary,
+    tr, MVersion,
+};
+use owo_colors::OwoColorize;
+
+/// Prints what conversions have to say, the library leaves that to its callers
+pub struct Output;
+
+impl ConversionHooks for Output {
+    fn on_summary(&self, _target: &MVersion, entries: &[EntrySummary]) {
+        summary::print_summary(entries);
+        subpack::print_subpack_summaries(entries);
+    }
+
+    fn on_message(&self, message: &Message) {
+        let text = message.text;
+        match message.kind {
+            MessageKind::Plain => println!("{text}"),
+            MessageKind::Heading => println!("{}", text.bold()),
+            MessageKind::Detail => println!("{}", text.dimmed()),
+            MessageKind::Warning => println!("{}", text.yellow()),
+            MessageKind::Done => println!("{}", text.green()),
+        }
+    }
+}
+
+/// Whether this process is the only one attached to its console, which is the
+/// case when Explorer opened a new window for it instead of a shell running it
+#[cfg(windows)]
+pub fn owns_console() -> bool {
+    use windows_sys::Win32::System::Console::GetConsoleProcessList;
+
+    let mut processes = [0u32; 2];
+    // SAFETY: the buffer is valid for the length passed along with it
+    let count = unsafe { GetConsoleProcessList(processes.as_mut_ptr(), processes.len() as u32) };
+    count == 1
+}
+
+#[cfg(not(windows))]
+pub fn owns_console() -> bool {
+    false
+}
+
+pub fn wait_for_enter() {
+    print!("\n{}", tr!("press-enter"));
+    let _ = io::stdout().flush();
+    let _ = io::stdin().lock().read_line(&mut String::new());
+}
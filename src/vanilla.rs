@@ -0,0 +1,110 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Seek},
+};
+
+use materialbin::CompiledMaterialDefinition;
+use sha2::{Digest, Sha256};
+
+use crate::for_each_material;
+
+/// How a material of a pack relates to the vanilla material of the same name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Baseline {
+    /// Every shader matches vanilla, the pack doesn't need to ship it
+    Identical,
+    /// Shaders that differ from vanilla or that vanilla doesn't have, out of all of the material's
+    Modified { changed: usize, shaders: usize },
+    /// No vanilla material with this name
+    NotVanilla,
+}
+
+/// Shader digests of a material, keyed by pass, variant flags, platform and stage
+type ShaderDigests = HashMap<String, [u8; 32]>;
+
+fn shader_digests(material: &CompiledMaterialDefinition) -> ShaderDigests {
+    let mut digests = HashMap::new();
+    for (pass_name, pass) in &material.passes {
+        for variant in &pass.variants {
+            let mut flags: Vec<String> = variant.flags.iter().map(|(key, value)| format!("{key}={value}")).collect();
+            flags.sort();
+            for (stage, code) in &variant.shader_codes {
+                let key = format!("{pass_name} [{}] {} {}", flags.join(","), stage.platform_name, stage.stage_name);
+                digests.insert(key, Sha256::digest(&code.bgfx_shader_data).into());
+            }
+        }
+    }
+    digests
+}
+
+/// File name of an entry, vanilla and pack materials are matched by it alone
+fn file_name(entry: &str) -> &str {
+    entry.rsplit(['/', '\\']).next().unwrap_or(entry)
+}
+
+/// Vanilla materials of one game version to compare packs against. Shaders
+/// are compared after parsing, so the binary versions don't have to match
+#[derive(Default)]
+pub struct VanillaMaterials {
+    materials: HashMap<String, ShaderDigests>,
+}
+
+impl VanillaMaterials {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add one vanilla material, `entry` is its file or entry name
+    pub fn add(&mut self, entry: &str, material: &CompiledMaterialDefinition) {
+        self.materials
+            .insert(file_name(entry).to_string(), shader_digests(material));
+    }
+
+    /// Add every material of a zip, or a single material.bin
+    pub fn add_pack<R: Read + Seek>(&mut self, input: &mut R) -> anyhow::Result<()> {
+        for_each_material(input, |entry, material, _| {
+            self.add(entry, &material);
+            Ok(())
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+
+    pub fn compare(&self, entry: &str, material: &CompiledMaterialDefinition) -> Baseline {
+        let Some(vanilla) = self.materials.get(file_name(entry)) else {
+            return Baseline::NotVanilla;
+        };
+        let digests = shader_digests(material);
+        let changed = digests
+            .iter()
+            .filter(|(key, digest)| vanilla.get(*key) != Some(*digest))
+            .count();
+        if changed == 0 {
+            Baseline::Identical
+        } else {
+            Baseline::Modified {
+                changed,
+                shaders: digests.len(),
+            }
+        }
+    }
+}
+
+/// Compare every material of a pack against `vanilla`, in entry order
+pub fn compare_pack<R: Read + Seek>(
+    input: &mut R,
+    vanilla: &VanillaMaterials,
+) -> anyhow::Result<Vec<(String, Baseline)>> {
+    let mut results = Vec::new();
+    for_each_material(input, |entry, material, _| {
+        results.push((entry.to_string(), vanilla.compare(entry, &material)));
+        Ok(())
+    })?;
+    Ok(results)
+}
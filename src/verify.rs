@@ -0,0 +1,217 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+use anyhow::Context;
+use materialbin::bgfx_shader::BgfxShader;
+use scroll::Pread;
+use zip::ZipArchive;
+
+use crate::{
+    detect_material,
+    in_place::FirstDuplicates,
+    kept_entries,
+    lint::{lint_material, Lint, LintLevels},
+    split_materials,
+    zip_names::entry_names,
+    ConversionReport, ConvertOptions, DuplicateEntries, UpdateError,
+};
+
+/// Decompress entry `index` and make sure its data matches the CRC stored for it
+pub(crate) fn check_entry_crc<R: Read + Seek>(zip: &mut ZipArchive<R>, index: usize) -> Result<(), UpdateError> {
+    let mut file = zip.by_index(index)?;
+    if file.is_dir() {
+        return Ok(());
+    }
+    // The zip reader checks the CRC once the entry is read to the end
+    io::copy(&mut file, &mut io::sink()).map_err(|source| UpdateError::CorruptEntry {
+        entry: file.name().to_string(),
+        source,
+    })?;
+    Ok(())
+}
+
+/// Number of entries the end of central directory record of a zip claims,
+/// `None` if there is none or the count is only in a zip64 record. The zip
+/// reader keeps one entry per name, so a larger count means duplicate names
+pub(crate) fn declared_entries<R: Read + Seek>(input: &mut R) -> io::Result<Option<u64>> {
+    let len = input.seek(SeekFrom::End(0))?;
+    // The record is 22 bytes and a comment of up to 64 KiB
+    let tail_len = len.min(22 + 0xFFFF);
+    input.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = Vec::new();
+    input.take(tail_len).read_to_end(&mut tail)?;
+    input.rewind()?;
+    let Some(start) = tail.windows(4).rposition(|window| window == b"PK\x05\x06") else {
+        return Ok(None);
+    };
+    let Some(&[low, high]) = tail.get(start + 10..start + 12) else {
+        return Ok(None);
+    };
+    let count = u16::from_le_bytes([low, high]);
+    Ok((count != u16::MAX).then_some(u64::from(count)))
+}
+
+/// Reopen a finished output archive and make sure every entry is readable,
+/// entries copied unchanged still match `source` and none of them went missing.
+/// Those are looked for under the names `options` has them written as, and
+/// the ones `report` says were rewritten on purpose are left alone. Returns the
+/// number of entries checked
+pub fn verify_archive<R, S>(output: R, source: S, options: &ConvertOptions, report: &ConversionReport) -> anyhow::Result<usize>
+where
+    R: Read + Seek,
+    S: Read + Seek,
+{
+    let mut output = ZipArchive::new(output).with_context(|| "Output is not a readable zip")?;
+    let mut source = ZipArchive::new(FirstDuplicates::new(source, options.duplicates == DuplicateEntries::First)?)?;
+    let names = entry_names(&mut source, options.zip_encoding)?.names;
+    let mut copied = HashMap::new();
+    for (name, index) in kept_entries(&names, options) {
+        if !name.ends_with(".material.bin") {
+            copied.insert(name, source.by_index_raw(index)?.crc32());
+        }
+    }
+    for name in &report.rewritten {
+        copied.remove(name);
+    }
+
+    for index in 0..output.len() {
+        check_entry_crc(&mut output, index).with_context(|| "Output verification failed")?;
+        let file = output.by_index_raw(index)?;
+        if let Some(crc) = copied.remove(file.name()) {
+            anyhow::ensure!(
+                crc == file.crc32(),
+                "Output verification failed: {} differs from the input (CRC {:08x}, expected {crc:08x})",
+                file.name(),
+                file.crc32()
+            );
+        }
+    }
+    if !copied.is_empty() {
+        let mut missing: Vec<_> = copied.into_keys().collect();
+        missing.sort();
+        anyhow::bail!("Output verification failed: missing {}", missing.join(", "));
+    }
+    Ok(output.len())
+}
+
+/// Make sure a written material.bin still parses
+pub fn verify_material<R: Read>(mut output: R) -> anyhow::Result<()> {
+    let mut data = Vec::new();
+    output.read_to_end(&mut data)?;
+    for part in split_materials(&data) {
+        detect_material(part).with_context(|| "Output verification failed")?;
+    }
+    Ok(())
+}
+
+/// Result of [`validate_pack`]
+#[derive(Default)]
+pub struct Validation {
+    pub entries: usize,
+    pub materials: usize,
+    /// Entry name and what is wrong with it
+    pub problems: Vec<(String, String)>,
+    /// Entry name and the structural defects of its material that aren't allowed
+    pub lints: Vec<(String, Lint)>,
+}
+
+fn check_material(data: &[u8], levels: &LintLevels) -> anyhow::Result<Vec<Lint>> {
+    let (material, _) = detect_material(data)?;
+    for (pass_name, pass) in &material.passes {
+        for variant in &pass.variants {
+            for (stage, code) in &variant.shader_codes {
+                code.bgfx_shader_data.pread::<BgfxShader>(0).map_err(|err| {
+                    anyhow::anyhow!("{pass_name}: {:?} {:?} shader does not parse: {err}", stage.platform, stage.stage)
+                })?;
+            }
+        }
+    }
+    Ok(lint_material(&material, levels))
+}
+
+/// Check a pack without converting it: every entry must decompress with the
+/// right CRC and every material, with all its shaders, must parse. Materials
+/// are linted with `levels`
+pub fn validate_pack<R: Read + Seek>(input: &mut R, levels: &LintLevels) -> anyhow::Result<Validation> {
+    let mut validation = Validation::default();
+    let mut data = Vec::new();
+    let mut magic = [0; 4];
+    let is_zip = input.read_exact(&mut magic).is_ok() && magic == *b"PK\x03\x04";
+    input.rewind()?;
+    if !is_zip {
+        input.read_to_end(&mut data)?;
+        validation.entries = 1;
+        validation.materials = 1;
+        match check_material(&data, levels) {
+            Ok(lints) => validation.lints.extend(lints.into_iter().map(|lint| ("input".to_string(), lint))),
+            Err(err) => validation.problems.push(("input".to_string(), format!("{err:#}"))),
+        }
+        return Ok(validation);
+    }
+    let mut zip = ZipArchive::new(input)?;
+    for index in 0..zip.len() {
+        validation.entries += 1;
+        let name = zip.name_for_index(index).unwrap_or_default().to_string();
+        if let Err(err) = check_entry_crc(&mut zip, index) {
+            validation.problems.push((name, format!("{err:#}")));
+            continue;
+        }
+        if !name.ends_with(".material.bin") {
+            continue;
+        }
+        validation.materials += 1;
+        data.clear();
+        zip.by_index(index)?.read_to_end(&mut data)?;
+        match check_material(&data, levels) {
+            Ok(lints) => validation.lints.extend(lints.into_iter().map(|lint| (name.clone(), lint))),
+            Err(err) => validation.problems.push((name, format!("{err:#}"))),
+        }
+    }
+    Ok(validation)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::*;
+    use crate::{zip_update, MVersion};
+
+    fn convert(pack: &mut Cursor<Vec<u8>>, options: &ConvertOptions) -> (Cursor<Vec<u8>>, ConversionReport) {
+        let mut output = Cursor::new(Vec::new());
+        let report = zip_update(pack, &mut output, &MVersion::V26_10, None, options).unwrap();
+        output.set_position(0);
+        (output, report)
+    }
+
+    #[test]
+    fn finds_copied_entries_under_their_normalized_names() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in [("manifest.json", r#"{"header": {}}"#), ("textures\\a.png", "png")] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data.as_bytes()).unwrap();
+        }
+        let mut pack = zip.finish().unwrap();
+        let options = ConvertOptions::builder().build().unwrap();
+        let (mut output, report) = convert(&mut pack, &options);
+        assert_eq!(ZipArchive::new(&mut output).unwrap().name_for_index(1), Some("textures/a.png"));
+        assert_eq!(verify_archive(&mut output, &mut pack, &options, &report).unwrap(), 2);
+
+        // Left out of the output
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("manifest.json", SimpleFileOptions::default()).unwrap();
+        zip.write_all(br#"{"header": {}}"#).unwrap();
+        let err = verify_archive(zip.finish().unwrap(), &mut pack, &options, &report).unwrap_err();
+        assert!(err.to_string().contains("missing textures/a.png"));
+
+        // The stamp changes the manifest
+        let options = ConvertOptions::builder().stamp(true).build().unwrap();
+        let (mut output, report) = convert(&mut pack, &options);
+        assert_eq!(report.rewritten, ["manifest.json"]);
+        assert_eq!(verify_archive(&mut output, &mut pack, &options, &report).unwrap(), 3);
+    }
+}
@@ -0,0 +1,165 @@
+//! Stable codes for the warnings of a conversion, so automation can allow the
+//! ones it knows about and fail on new ones (`--deny`)
+
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+/// What a warning is about. The codes never change meaning and new ones are
+/// only added at the end
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WarningCode {
+    /// A material incompatible with the target was left out
+    CompatSkip,
+    /// A patch didn't find what it fixes in some shaders
+    PatternMissing,
+    /// A material incompatible with the target was copied unchanged
+    CompatKeptOriginal,
+    /// A material incompatible with the target was written for an older version
+    CompatDowngraded,
+    /// Bytecode shaders a patch would have to edit
+    UnpatchableShaders,
+    /// A patch failed on a material, which was left out
+    PatchFailed,
+    /// A material no version can read was copied unchanged
+    UnreadableMaterial,
+    /// An entry that doesn't match its CRC was copied anyway
+    CorruptEntry,
+    /// An entry whose name escapes the pack was left out
+    UnsafePath,
+    /// An entry with the same path as another was left out
+    DuplicateEntry,
+    /// Entries with exactly the same name as a later one were unreadable
+    DuplicateHidden,
+    /// The materials were compiled for several versions
+    MixedSources,
+    /// A material was read as the wrong version and read again as another
+    SourceMisdetected,
+    /// A JSON file among the materials that doesn't read or convert was copied unchanged
+    JsonMaterialUnchanged,
+}
+
+impl WarningCode {
+    pub const ALL: [Self; 14] = [
+        Self::CompatSkip,
+        Self::PatternMissing,
+        Self::CompatKeptOriginal,
+        Self::CompatDowngraded,
+        Self::UnpatchableShaders,
+        Self::PatchFailed,
+        Self::UnreadableMaterial,
+        Self::CorruptEntry,
+        Self::UnsafePath,
+        Self::DuplicateEntry,
+        Self::DuplicateHidden,
+        Self::MixedSources,
+        Self::SourceMisdetected,
+        Self::JsonMaterialUnchanged,
+    ];
+
+    /// `W001` and so on
+    pub fn code(self) -> String {
+        let number = Self::ALL.iter().position(|code| *code == self).unwrap_or_default() + 1;
+        format!("W{number:03}")
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::CompatSkip => "compat-skip",
+            Self::PatternMissing => "pattern-missing",
+            Self::CompatKeptOriginal => "compat-kept-original",
+            Self::CompatDowngraded => "compat-downgraded",
+            Self::UnpatchableShaders => "unpatchable-shaders",
+            Self::PatchFailed => "patch-failed",
+            Self::UnreadableMaterial => "unreadable-material",
+            Self::CorruptEntry => "corrupt-entry",
+            Self::UnsafePath => "unsafe-path",
+            Self::DuplicateEntry => "duplicate-entry",
+            Self::DuplicateHidden => "duplicate-hidden",
+            Self::MixedSources => "mixed-sources",
+            Self::SourceMisdetected => "source-misdetected",
+            Self::JsonMaterialUnchanged => "json-material-unchanged",
+        }
+    }
+}
+
+impl WarningCode {
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::CompatSkip => "a material incompatible with the target was left out",
+            Self::PatternMissing => "a patch found nothing to fix in some shaders",
+            Self::CompatKeptOriginal => "a material incompatible with the target was copied unchanged",
+            Self::CompatDowngraded => "a material incompatible with the target was written for an older version",
+            Self::UnpatchableShaders => "a patch can't edit some compiled shaders",
+            Self::PatchFailed => "a patch failed on a material, which was copied unchanged",
+            Self::UnreadableMaterial => "a material no version can read was copied unchanged",
+            Self::CorruptEntry => "an entry that doesn't match its CRC was copied anyway",
+            Self::UnsafePath => "an entry whose name escapes the pack was left out",
+            Self::DuplicateEntry => "an entry with the same path as another was left out",
+            Self::DuplicateHidden => "entries with exactly the same name as another were dropped",
+            Self::MixedSources => "the materials were compiled for several versions",
+            Self::SourceMisdetected => "a material read as the wrong version was read again as another",
+            Self::JsonMaterialUnchanged => "a JSON file among the materials that doesn't read or convert was copied unchanged",
+        }
+    }
+}
+
+/// `W001 compat-skip`
+impl fmt::Display for WarningCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.name())
+    }
+}
+
+/// Either the code (`W001`, case-insensitive) or the name (`compat-skip`)
+impl FromStr for WarningCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|code| code.code().eq_ignore_ascii_case(s) || code.name() == s)
+            .ok_or_else(|| format!("Unknown warning {s:?}, see `material-updater list-warnings`"))
+    }
+}
+
+/// A warning of a conversion
+#[derive(Clone, Debug)]
+pub struct Warning {
+    pub code: WarningCode,
+    /// Entry it is about, `None` for the pack as a whole
+    pub entry: Option<String>,
+    pub message: String,
+}
+
+impl Warning {
+    pub(crate) fn new(code: WarningCode, entry: Option<&str>, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            entry: entry.map(str::to_string),
+            message: message.into(),
+        }
+    }
+}
+
+/// How many warnings of each code there are, in code order
+pub fn count_codes(warnings: &[Warning]) -> BTreeMap<WarningCode, usize> {
+    let mut counts = BTreeMap::new();
+    for warning in warnings {
+        *counts.entry(warning.code).or_default() += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_round_trip() {
+        assert_eq!(WarningCode::PatternMissing.to_string(), "W002 pattern-missing");
+        for code in WarningCode::ALL {
+            assert_eq!(code.code().parse::<WarningCode>(), Ok(code));
+            assert_eq!(code.name().parse::<WarningCode>(), Ok(code));
+        }
+        assert!("W999".parse::<WarningCode>().is_err());
+    }
+}
@@ -0,0 +1,11 @@
+use clap::ValueEnum;
+use wasm_bindgen::prelude::*;
+
+use crate::MVersion;
+
+/// Convert a shader pack or material.bin to `target` (e.g. "1.21.110")
+#[wasm_bindgen]
+pub fn convert_bytes(input: &[u8], target: &str) -> Result<Vec<u8>, JsError> {
+    let version = MVersion::from_str(target, true).map_err(|err| JsError::new(&err))?;
+    crate::convert_bytes(input, &version, None).map_err(|err| JsError::new(&format!("{:#}", anyhow::Error::new(err))))
+}
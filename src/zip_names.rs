@@ -0,0 +1,163 @@
+//! Entry names of zips that don't say they are UTF-8. The zip reader takes
+//! those as CP437, the format's old default, but packs zipped on Chinese or
+//! Japanese Windows have them in GBK or Shift-JIS, which comes out garbled
+//! and, with Shift-JIS, even split at the wrong `\`. The names are decoded
+//! here instead and written back as UTF-8, which is what the pack's JSON files
+//! refer to them in
+
+use std::io::{Read, Seek};
+
+use chardetng::EncodingDetector;
+use clap::ValueEnum;
+use encoding_rs::{Encoding, BIG5, EUC_KR, GBK, SHIFT_JIS};
+use zip::{result::ZipResult, ZipArchive};
+
+/// What entry names not marked as UTF-8 are written in
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ZipEncoding {
+    /// Guess from the names themselves
+    #[default]
+    Auto,
+    /// UTF-8 without the flag saying so, as many tools write it
+    Utf8,
+    /// The zip format's default
+    Cp437,
+    /// Simplified Chinese
+    Gbk,
+    /// Japanese
+    ShiftJis,
+    /// Traditional Chinese
+    Big5,
+    /// Korean
+    EucKr,
+}
+
+impl ZipEncoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Utf8 => "UTF-8",
+            Self::Cp437 => "CP437",
+            Self::Gbk => "GBK",
+            Self::ShiftJis => "Shift-JIS",
+            Self::Big5 => "Big5",
+            Self::EucKr => "EUC-KR",
+        }
+    }
+
+    fn encoding(self) -> Option<&'static Encoding> {
+        match self {
+            Self::Gbk => Some(GBK),
+            Self::ShiftJis => Some(SHIFT_JIS),
+            Self::Big5 => Some(BIG5),
+            Self::EucKr => Some(EUC_KR),
+            Self::Auto | Self::Utf8 | Self::Cp437 => None,
+        }
+    }
+
+    /// The encoding of `names`, the raw names not marked as UTF-8. CP437 unless
+    /// they are valid UTF-8 or look like one of the East Asian encodings
+    pub fn detect(names: &[&[u8]]) -> Self {
+        if names.iter().all(|name| std::str::from_utf8(name).is_ok()) {
+            return Self::Utf8;
+        }
+        let mut detector = EncodingDetector::new();
+        for name in names {
+            detector.feed(name, false);
+            // Keeps a multi-byte character from spanning two names
+            detector.feed(b"\n", false);
+        }
+        detector.feed(b"", true);
+        let guess = detector.guess(None, false);
+        [Self::Gbk, Self::ShiftJis, Self::Big5, Self::EucKr]
+            .into_iter()
+            .find(|encoding| encoding.encoding() == Some(guess))
+            .unwrap_or(Self::Cp437)
+    }
+}
+
+/// The names of the entries of a zip, decoded
+pub struct EntryNames {
+    pub names: Vec<String>,
+    /// What the names not marked as UTF-8 were decoded as, `None` if there
+    /// were none with anything but ASCII
+    pub decoded_as: Option<ZipEncoding>,
+    /// How many names that was
+    pub decoded: usize,
+    /// Names with bytes that aren't valid in that encoding
+    pub invalid: usize,
+}
+
+/// The name of every entry of `zip` by index, decoding those not marked as
+/// UTF-8 as `encoding`
+pub fn entry_names<R: Read + Seek>(zip: &mut ZipArchive<R>, encoding: ZipEncoding) -> ZipResult<EntryNames> {
+    let mut names = Vec::with_capacity(zip.len());
+    // Index and raw bytes of the names to decode
+    let mut undecoded = Vec::new();
+    for index in 0..zip.len() {
+        let file = zip.by_index_raw(index)?;
+        let raw = file.name_raw();
+        // The reader only took the raw name as is when the zip flags it as UTF-8
+        if !raw.is_ascii() && std::str::from_utf8(raw) != Ok(file.name()) {
+            undecoded.push((index, raw.to_vec()));
+        }
+        names.push(file.name().to_string());
+    }
+    if undecoded.is_empty() {
+        return Ok(EntryNames { names, decoded_as: None, decoded: 0, invalid: 0 });
+    }
+    let encoding = match encoding {
+        ZipEncoding::Auto => ZipEncoding::detect(&undecoded.iter().map(|(_, raw)| raw.as_slice()).collect::<Vec<_>>()),
+        encoding => encoding,
+    };
+    let decoded = undecoded.len();
+    let mut invalid = 0;
+    for (index, raw) in undecoded {
+        let (name, had_errors) = match (encoding, encoding.encoding()) {
+            // Already what the reader gave
+            (ZipEncoding::Cp437, _) => continue,
+            (_, Some(decoder)) => {
+                let (name, had_errors) = decoder.decode_without_bom_handling(&raw);
+                (name.into_owned(), had_errors)
+            }
+            _ => match String::from_utf8(raw) {
+                Ok(name) => (name, false),
+                Err(err) => (String::from_utf8_lossy(err.as_bytes()).into_owned(), true),
+            },
+        };
+        invalid += usize::from(had_errors);
+        names[index] = name;
+    }
+    Ok(EntryNames { names, decoded_as: Some(encoding), decoded, invalid })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn detects_east_asian_names() {
+        let (gbk, _, _) = GBK.encode("textures/blocks/草方块.png");
+        let (sjis, _, _) = SHIFT_JIS.encode("textures/blocks/表示ソース.png");
+        assert_eq!(ZipEncoding::detect(&[&gbk]), ZipEncoding::Gbk);
+        assert_eq!(ZipEncoding::detect(&[&sjis]), ZipEncoding::ShiftJis);
+        assert_eq!(ZipEncoding::detect(&["textures/ü.png".as_bytes()]), ZipEncoding::Utf8);
+
+        // A zip with one GBK name, without the UTF-8 flag
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("manifest.json", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.start_file("ab.png", zip::write::SimpleFileOptions::default()).unwrap();
+        let mut bytes = zip.finish().unwrap().into_inner();
+        let (name, _, _) = GBK.encode("草.png");
+        for at in (0..bytes.len() - 5).filter(|&at| &bytes[at..at + 6] == b"ab.png").collect::<Vec<_>>() {
+            bytes.splice(at..at + 6, name.iter().copied());
+        }
+        let mut zip = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let names = entry_names(&mut zip, ZipEncoding::Gbk).unwrap();
+        assert_eq!(names.names, ["manifest.json", "草.png"]);
+        assert_eq!(names.decoded_as, Some(ZipEncoding::Gbk));
+        assert_eq!(entry_names(&mut zip, ZipEncoding::Cp437).unwrap().names[1], zip.by_index_raw(1).unwrap().name());
+    }
+}
@@ -0,0 +1,159 @@
+//! Converts every material in `tests/golden/fixtures` for every target
+//! version, with the default patches and with each patch on its own, and
+//! compares the results with the outputs checked in under
+//! `tests/golden/expected`. Packs there are converted with the default patches
+//! and compared by a listing of what ends up in them. After a change that is meant to alter outputs, run
+//! `UPDATE_GOLDEN=1 cargo test --test golden` and review the changed files
+
+use std::{
+    env, fs,
+    fmt::Write,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use material_updater::{
+    bench, detect_material, file_update, json,
+    patches::{PatchSelection, PATCHES},
+    zip_update, ConvertOptions, MVersion,
+};
+use zip::ZipArchive;
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+/// Every fixture material and pack, sorted by name
+fn fixtures() -> Vec<PathBuf> {
+    bench::fixture_paths(&golden_dir().join("fixtures"), &[".material.bin", ".mcpack"])
+}
+
+/// Only `patch`, whatever the versions would select
+fn only(patch: &str) -> PatchSelection {
+    PatchSelection {
+        enabled: vec![patch.to_string()],
+        disabled: PATCHES.iter().map(|other| other.name().to_string()).filter(|other| other != patch).collect(),
+        ..PatchSelection::default()
+    }
+}
+
+/// `data` converted for `target`, or the error as text, so that materials a
+/// target rejects are pinned down too
+fn convert(data: &[u8], target: &MVersion, patches: PatchSelection) -> Vec<u8> {
+    let mut output = Cursor::new(Vec::new());
+    let options = ConvertOptions::builder().patches(patches).build().unwrap();
+    match file_update(&mut Cursor::new(data), &mut output, target, &options) {
+        Ok(_) => output.into_inner(),
+        Err(err) => format!("error: {err}\n").into_bytes(),
+    }
+}
+
+/// The pack `data` converted for `target`, as the name, CRC and size of every
+/// entry followed by the entry results and warnings, or the error as text
+fn convert_pack(data: &[u8], target: &MVersion) -> Vec<u8> {
+    let mut output = Cursor::new(Vec::new());
+    let options = ConvertOptions::builder().build().unwrap();
+    let report = match zip_update(&mut Cursor::new(data), &mut output, target, None, &options) {
+        Ok(report) => report,
+        Err(err) => return format!("error: {err}\n").into_bytes(),
+    };
+    let mut zip = ZipArchive::new(output).unwrap();
+    let mut listing = String::new();
+    for index in 0..zip.len() {
+        let file = zip.by_index_raw(index).unwrap();
+        writeln!(listing, "{} {:08x} {}", file.name(), file.crc32(), file.size()).unwrap();
+    }
+    for entry in &report.entries {
+        writeln!(listing, "entry {} {}", entry.name, entry.result).unwrap();
+    }
+    for warning in &report.warnings {
+        writeln!(listing, "warning {}", warning.code.name()).unwrap();
+    }
+    listing.into_bytes()
+}
+
+/// Compare `actual` with the golden file `name`, or write it with
+/// UPDATE_GOLDEN. Mismatches are written next to it as `.actual` for diffing
+fn check(name: &str, actual: &[u8], bless: bool, mismatches: &mut Vec<String>) {
+    let expected = golden_dir().join("expected").join(name);
+    let actual_path = expected.with_file_name(format!("{name}.actual"));
+    if bless {
+        fs::write(&expected, actual).unwrap();
+        let _ = fs::remove_file(actual_path);
+        return;
+    }
+    match fs::read(&expected) {
+        Ok(golden) if golden == actual => {
+            let _ = fs::remove_file(actual_path);
+        }
+        Ok(_) => {
+            fs::write(&actual_path, actual).unwrap();
+            mismatches.push(format!("{name} differs, see {}", actual_path.display()));
+        }
+        Err(_) => mismatches.push(format!("{name} has no golden output, run with UPDATE_GOLDEN=1")),
+    }
+}
+
+#[test]
+fn outputs_match_golden_files() {
+    let bless = env::var_os("UPDATE_GOLDEN").is_some_and(|value| value != "0");
+    let fixtures = fixtures();
+    assert!(!fixtures.is_empty(), "No fixtures in {}", golden_dir().join("fixtures").display());
+    let mut mismatches = Vec::new();
+    let mut sources = Vec::new();
+    for fixture in &fixtures {
+        let data = fs::read(fixture).unwrap();
+        if let Some(stem) = fixture.file_name().unwrap().to_string_lossy().strip_suffix(".mcpack") {
+            for target in MVersion::value_variants() {
+                check(&format!("{stem}.{target}.txt"), &convert_pack(&data, target), bless, &mut mismatches);
+            }
+            continue;
+        }
+        let stem = fixture.file_name().unwrap().to_string_lossy().trim_end_matches(".material.bin").to_string();
+        let (_, source) = detect_material(&data).unwrap_or_else(|err| panic!("{}: {err}", fixture.display()));
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+        for target in MVersion::value_variants() {
+            check(&format!("{stem}.{target}.material.bin"), &convert(&data, target, PatchSelection::default()), bless, &mut mismatches);
+            for patch in PATCHES {
+                let name = format!("{stem}.{target}.{}.material.bin", patch.name());
+                check(&name, &convert(&data, target, only(patch.name())), bless, &mut mismatches);
+            }
+        }
+    }
+    let uncovered: Vec<String> = materialbin::ALL_VERSIONS
+        .iter()
+        .filter(|version| !sources.contains(version))
+        .map(ToString::to_string)
+        .collect();
+    assert!(mismatches.is_empty(), "Outputs changed:\n{}", mismatches.join("\n"));
+    assert!(uncovered.is_empty(), "No fixture material is in the {} format, see tests/golden/fixtures/README.md", uncovered.join(", "));
+}
+
+/// Every fixture material exported to JSON, with its code inline and in a
+/// shader directory, imports back as the material it was
+#[test]
+fn json_round_trips() {
+    let encode = |material: &materialbin::CompiledMaterialDefinition, version| {
+        let mut data = Vec::new();
+        material.write(&mut data, version).unwrap();
+        data
+    };
+    let materials: Vec<PathBuf> = fixtures().into_iter().filter(|path| path.to_string_lossy().ends_with(".material.bin")).collect();
+    assert!(!materials.is_empty(), "No fixture materials to round trip, see tests/golden/fixtures/README.md");
+    for fixture in &materials {
+        let (material, version) = detect_material(&fs::read(fixture).unwrap()).unwrap();
+        let expected = encode(&material, version);
+        // code_file paths start where the JSON is, next to the shader directory
+        let json_dir = tempfile::tempdir().unwrap();
+        let shader_dir = json_dir.path().join("shaders");
+        for dir in [None, Some((shader_dir.as_path(), "shaders"))] {
+            let exported = serde_json::to_string(&json::export(&material, version, dir).unwrap()).unwrap();
+            let (imported, imported_version) = json::import(serde_json::from_str(&exported).unwrap(), json_dir.path()).unwrap();
+            assert_eq!(imported_version, version, "{}", fixture.display());
+            assert!(encode(&imported, version) == expected, "{} changed in a JSON round trip", fixture.display());
+        }
+    }
+}